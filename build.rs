@@ -0,0 +1,40 @@
+use std::process::Command;
+
+/// Expose build information (git commit, build date, target and enabled features) to the crate
+/// through environment variables, for use in `oxidizr version --verbose`.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    println!("cargo:rustc-env=OXIDIZR_GIT_COMMIT={commit}");
+
+    // Honour SOURCE_DATE_EPOCH so that reproducible builds report a stable build date.
+    let date = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => command_output("date", &["-u", "-d", &format!("@{epoch}"), "+%Y-%m-%d"]),
+        Err(_) => command_output("date", &["-u", "+%Y-%m-%d"]),
+    };
+    println!("cargo:rustc-env=OXIDIZR_BUILD_DATE={date}");
+
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    println!("cargo:rustc-env=OXIDIZR_TARGET={target}");
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase()))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=OXIDIZR_FEATURES={}", features.join(","));
+}
+
+/// Run a command and return its trimmed output, or "unknown" if it fails.
+fn command_output(command: &str, args: &[&str]) -> String {
+    Command::new(command)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -47,6 +47,11 @@
             src = lib.cleanSource ./.;
             cargoLock.lockFile = ./Cargo.lock;
 
+            postInstall = ''
+              mkdir -p $out/share/man/man1
+              $out/bin/oxidizr generate man --output-dir $out/share/man/man1
+            '';
+
             meta = {
               description = "Replace system utilities with Rust alternatives on Ubuntu";
               homepage = "https://github.com/jnsgruk/oxidizr";
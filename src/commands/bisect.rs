@@ -0,0 +1,262 @@
+use crate::experiments::{Experiment, Implication, all_experiments, implicate};
+use crate::utils::{Command, ManagedTarget, OutputFormat, Worker, structured};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// A file replaced by an enabled experiment, which may be the cause of a failure.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Suspect {
+    pub experiment: String,
+    pub file: PathBuf,
+}
+
+/// What bisecting a failure found.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", tag = "outcome")]
+pub enum BisectOutcome {
+    /// The command succeeds with every replacement in place, so there is nothing to bisect.
+    Passes,
+    /// The command fails with the GNU binaries too, so the replacements aren't the cause.
+    FailsWithGnu,
+    /// The command fails with this replacement alone in place, and succeeds with all the others.
+    Culprit(Suspect),
+    /// No single replacement makes the command fail, such as when it takes two of them together.
+    Inconclusive,
+}
+
+/// The report of `oxidizr bisect`.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct BisectReport {
+    pub command: Vec<String>,
+    #[serde(flatten)]
+    pub outcome: BisectOutcome,
+    /// The number of times the command was run.
+    pub runs: usize,
+    /// Whether the culprit was quarantined, which only the experiments replacing GNU utilities
+    /// support.
+    pub quarantined: bool,
+}
+
+/// Find which replaced binary makes `command` fail: the binaries replaced by the enabled
+/// experiments are restored from their backups half at a time, running the command each time,
+/// until one is left that makes it fail on its own. The rest are re-applied, and the culprit is
+/// quarantined, so that it is left as GNU until the quarantine is cleared.
+pub fn bisect(system: &dyn Worker, command: &[String], format: OutputFormat) -> Result<()> {
+    let experiments: Vec<Experiment> = all_experiments(system)
+        .into_iter()
+        .filter(|e| e.status().enabled)
+        .collect();
+    let mut suspects = Vec::new();
+    for e in &experiments {
+        for (file, linked) in e.check_replaced()? {
+            if linked {
+                suspects.push(Suspect {
+                    experiment: e.name(),
+                    file,
+                });
+            }
+        }
+    }
+    if suspects.is_empty() {
+        anyhow::bail!(
+            "No files are replaced by enabled experiments, so there is nothing to bisect"
+        );
+    }
+
+    let (program, args) = command
+        .split_first()
+        .context("No command was given to bisect")?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut runs = 0;
+    let result = search(&suspects, |replaced| {
+        runs += 1;
+        replace_only(system, &experiments, &suspects, replaced)?;
+        let failed = system.run(&Command::build(program, &args)).is_err();
+        info!(
+            "With {} of {} replacement(s) in place, the command {}",
+            replaced.len(),
+            suspects.len(),
+            match failed {
+                true => "fails",
+                false => "succeeds",
+            }
+        );
+        Ok(failed)
+    });
+
+    // Whatever was found, everything but the culprit is replaced again.
+    let reapplied = replace_only(system, &experiments, &suspects, &suspects);
+    let outcome = result?;
+    reapplied?;
+    let quarantined = match &outcome {
+        BisectOutcome::Culprit(culprit) => quarantine(system, &experiments, culprit, command)?,
+        _ => false,
+    };
+
+    let report = BisectReport {
+        command: command.to_vec(),
+        outcome,
+        runs,
+        quarantined,
+    };
+    match format {
+        OutputFormat::Human => print_report(&report),
+        format => print!("{}", structured(format, &report)?),
+    }
+    Ok(())
+}
+
+/// Search for the single suspect that makes the command fail, given a test reporting whether it
+/// fails with only the suspects given replaced. Each round keeps the half of the suspects whose
+/// replacement alone makes it fail.
+fn search(
+    suspects: &[Suspect],
+    mut fails: impl FnMut(&[Suspect]) -> Result<bool>,
+) -> Result<BisectOutcome> {
+    if !fails(suspects)? {
+        return Ok(BisectOutcome::Passes);
+    }
+    if fails(&[])? {
+        return Ok(BisectOutcome::FailsWithGnu);
+    }
+
+    let mut remaining = suspects;
+    while remaining.len() > 1 {
+        let (first, second) = remaining.split_at(remaining.len() / 2);
+        remaining = match fails(first)? {
+            true => first,
+            false => second,
+        };
+    }
+    // The half that was never run on its own is only presumed to hold the culprit.
+    match fails(remaining)? {
+        true => Ok(BisectOutcome::Culprit(remaining[0].clone())),
+        false => Ok(BisectOutcome::Inconclusive),
+    }
+}
+
+/// Replace the files of the experiments again, then restore from their backups each suspect that
+/// isn't in `replaced`.
+fn replace_only(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    suspects: &[Suspect],
+    replaced: &[Suspect],
+) -> Result<()> {
+    for e in experiments {
+        e.enable()?;
+    }
+    for suspect in suspects.iter().filter(|s| !replaced.contains(s)) {
+        system.restore_file(ManagedTarget::new(suspect.file.clone())?)?;
+    }
+    Ok(())
+}
+
+/// Quarantine the culprit and restore it, reporting whether its experiment supports pinning
+/// binaries to GNU.
+fn quarantine(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    culprit: &Suspect,
+    command: &[String],
+) -> Result<bool> {
+    let Some(experiment) = experiments
+        .iter()
+        .find(|e| e.name() == culprit.experiment && matches!(e, Experiment::Uutils(_)))
+    else {
+        warn!(
+            "'{}' can't be pinned to GNU, so it has been left replaced",
+            culprit.file.display()
+        );
+        return Ok(false);
+    };
+    let reason = format!("'{}' fails with it replaced alone", command.join(" "));
+    implicate(
+        system,
+        &culprit.experiment,
+        &culprit.file,
+        Implication::Bisect,
+        &reason,
+    )?;
+    experiment.restore_pinned()?;
+    Ok(true)
+}
+
+fn print_report(report: &BisectReport) {
+    let command = report.command.join(" ");
+    match &report.outcome {
+        BisectOutcome::Passes => {
+            println!("'{command}' succeeds with every replacement in place: nothing to bisect.")
+        }
+        BisectOutcome::FailsWithGnu => println!(
+            "'{command}' fails with the GNU binaries too, so the replacements aren't the cause."
+        ),
+        BisectOutcome::Inconclusive => println!(
+            "No single replacement makes '{command}' fail; it may take several together. Every \
+            replacement is in place again."
+        ),
+        BisectOutcome::Culprit(culprit) => {
+            println!(
+                "'{command}' fails because of the replacement of {}, from the {} experiment \
+                ({} run(s)).",
+                culprit.file.display(),
+                culprit.experiment,
+                report.runs
+            );
+            match report.quarantined {
+                true => println!(
+                    "It has been restored to GNU and quarantined, and the other replacements are \
+                    in place again. Once it is fixed, replace it again with 'oxidizr quarantine \
+                    clear {}' and 'oxidizr reapply'.",
+                    culprit.file.file_name().unwrap_or_default().display()
+                ),
+                false => println!(
+                    "Every replacement is in place again; disable {} to stop using it.",
+                    culprit.experiment
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suspects(count: usize) -> Vec<Suspect> {
+        (0..count)
+            .map(|i| Suspect {
+                experiment: "coreutils".to_string(),
+                file: PathBuf::from(format!("/usr/bin/{i}")),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let all = suspects(11);
+        let culprit = all[6].clone();
+        let mut runs = 0;
+        let outcome = search(&all, |replaced| {
+            runs += 1;
+            Ok(replaced.contains(&culprit))
+        })
+        .unwrap();
+        assert_eq!(outcome, BisectOutcome::Culprit(culprit));
+        // Each round halves the suspects, after checking with all and none replaced.
+        assert_eq!(runs, 2 + 4 + 1);
+
+        assert_eq!(search(&all, |_| Ok(false)).unwrap(), BisectOutcome::Passes);
+        assert_eq!(
+            search(&all, |_| Ok(true)).unwrap(),
+            BisectOutcome::FailsWithGnu
+        );
+        // It fails only with two of the replacements together.
+        let (first, last) = (all[0].clone(), all[10].clone());
+        let outcome = search(&all, |r| Ok(r.contains(&first) && r.contains(&last))).unwrap();
+        assert_eq!(outcome, BisectOutcome::Inconclusive);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::config::NotificationsConfig;
+use crate::experiments::{
+    ExperimentStatus, Trial, all_experiments, calendar_time, count_fallbacks,
+};
+use crate::journal::Journal;
+use crate::notify::{Notification, Urgency, notify};
+use crate::utils::{OutputFormat, Worker, structured};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    fmt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The state of a check, ordered from best to worst, with the exit codes of monitoring plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckState {
+    Ok,
+    /// The check couldn't be made, such as for lack of privileges.
+    Unknown,
+    Warning,
+    Critical,
+}
+
+impl CheckState {
+    /// The exit code of a Nagios or Icinga plugin in this state.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Ok => 0,
+            Self::Warning => 1,
+            Self::Critical => 2,
+            Self::Unknown => 3,
+        }
+    }
+}
+
+impl fmt::Display for CheckState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Ok => "OK",
+            Self::Unknown => "UNKNOWN",
+            Self::Warning => "WARNING",
+            Self::Critical => "CRITICAL",
+        };
+        write!(f, "{state}")
+    }
+}
+
+/// A problem found by a check.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CheckFinding {
+    pub state: CheckState,
+    pub message: String,
+}
+
+/// The outcome of checking the health of the experiments on the system.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CheckReport {
+    /// The worst state of the findings, or `ok` if there are none.
+    pub state: CheckState,
+    pub findings: Vec<CheckFinding>,
+    pub experiments: usize,
+    pub enabled: usize,
+    /// The number of files that have drifted from their replacements.
+    pub drifted: usize,
+    /// The binaries of the enabled experiments that are quarantined, failures having implicated
+    /// them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quarantined: Vec<String>,
+}
+
+/// Check the health of the experiments on the system: an operation left interrupted is critical,
+/// and files that have drifted from their replacements, or a trial that should have ended, are
+/// warnings, as is a binary quarantined for falling back to GNU repeatedly. Anything found is
+/// notified through the channels enabled in `notifications`. With `nagios`, a single line of
+/// output is printed in the format of a Nagios or Icinga plugin, with performance data. Returns
+/// the exit code: that of the state for a plugin, and otherwise 1 if anything was found.
+pub fn check(
+    system: &dyn Worker,
+    notifications: &NotificationsConfig,
+    journal_path: &Path,
+    nagios: bool,
+    format: OutputFormat,
+) -> Result<i32> {
+    let experiments = all_experiments(system);
+    // Only root can record the quarantine, so others only see the binaries already quarantined.
+    let quarantined = match uzers::get_current_uid() {
+        0 => count_fallbacks(system, &experiments),
+        _ => Ok(Vec::new()),
+    };
+    let statuses: Vec<ExperimentStatus> = experiments.iter().map(|e| e.status()).collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let report = evaluate(system, journal_path, &statuses, &quarantined, now);
+    if report.state != CheckState::Ok {
+        let messages: Vec<&str> = report.findings.iter().map(|f| f.message.as_str()).collect();
+        let urgency = match report.state {
+            CheckState::Critical => Urgency::Critical,
+            _ => Urgency::Normal,
+        };
+        let summary = format!("oxidizr check: {}", report.state);
+        let notification = Notification::new(urgency, summary, messages.join("\n"))
+            .with_report("check.json", &report);
+        notify(system, notifications, &notification);
+    }
+
+    if nagios {
+        println!("{}", plugin_output(&report));
+        return Ok(report.state.exit_code());
+    }
+    match format {
+        OutputFormat::Human if report.findings.is_empty() => println!("{}", summary(&report)),
+        OutputFormat::Human => report
+            .findings
+            .iter()
+            .for_each(|f| println!("{}: {}", f.state, f.message)),
+        format => print!("{}", structured(format, &report)?),
+    }
+    Ok(match report.state {
+        CheckState::Ok => 0,
+        _ => 1,
+    })
+}
+
+/// Evaluate the state of the system from the status of each experiment, and the binaries just
+/// quarantined for falling back to GNU.
+fn evaluate(
+    system: &dyn Worker,
+    journal_path: &Path,
+    statuses: &[ExperimentStatus],
+    quarantined: &Result<Vec<String>>,
+    now: u64,
+) -> CheckReport {
+    let mut findings = Vec::new();
+    let mut find =
+        |state: CheckState, message: String| findings.push(CheckFinding { state, message });
+
+    match Journal::load(journal_path) {
+        Ok(Some(journal)) => find(
+            CheckState::Critical,
+            format!(
+                "an interrupted '{}' of {} has been left part way through: run 'oxidizr resume'",
+                journal.command,
+                journal.experiments.join(", ")
+            ),
+        ),
+        Ok(None) => {}
+        Err(e) => find(
+            CheckState::Unknown,
+            format!("the journal could not be read: {e:#}"),
+        ),
+    }
+
+    for s in statuses {
+        if s.enabled && !s.drift.is_empty() {
+            find(
+                CheckState::Warning,
+                format!(
+                    "{}: {} file(s) no longer replaced, run 'oxidizr reapply'",
+                    s.name,
+                    s.drift.len()
+                ),
+            );
+        }
+        for unchecked in &s.unchecked {
+            find(CheckState::Unknown, format!("{}: {unchecked}", s.name));
+        }
+    }
+
+    match quarantined {
+        Ok(quarantined) => {
+            for binary in quarantined {
+                find(
+                    CheckState::Warning,
+                    format!(
+                        "{binary} has fallen back to GNU repeatedly, so it is quarantined: run \
+                        'oxidizr reapply' to restore GNU {binary}"
+                    ),
+                );
+            }
+        }
+        Err(e) => find(
+            CheckState::Unknown,
+            format!("the fallbacks to GNU could not be counted: {e:#}"),
+        ),
+    }
+
+    match Trial::load(system) {
+        Ok(Some(trial)) if trial.expires < now => find(
+            CheckState::Warning,
+            format!(
+                "the trial of {} should have ended at {}",
+                trial.experiments.join(", "),
+                calendar_time(trial.expires)
+            ),
+        ),
+        Ok(_) => {}
+        Err(e) => find(
+            CheckState::Unknown,
+            format!("the trial could not be read: {e:#}"),
+        ),
+    }
+
+    CheckReport {
+        state: findings
+            .iter()
+            .map(|f| f.state)
+            .max()
+            .unwrap_or(CheckState::Ok),
+        findings,
+        experiments: statuses.len(),
+        enabled: statuses.iter().filter(|s| s.enabled).count(),
+        drifted: statuses
+            .iter()
+            .filter(|s| s.enabled)
+            .map(|s| s.drift.len())
+            .sum(),
+        quarantined: statuses
+            .iter()
+            .filter(|s| s.enabled)
+            .flat_map(|s| s.quarantined.clone())
+            .collect(),
+    }
+}
+
+/// Summarize a report with no findings.
+fn summary(report: &CheckReport) -> String {
+    format!(
+        "{} of {} experiment(s) enabled, no drift",
+        report.enabled, report.experiments
+    )
+}
+
+/// Render a report as the output of a monitoring plugin: the state and a summary, led by the
+/// worst findings, then the performance data.
+fn plugin_output(report: &CheckReport) -> String {
+    let mut findings = report.findings.clone();
+    findings.sort_by_key(|f| std::cmp::Reverse(f.state));
+    let text = match findings.is_empty() {
+        true => summary(report),
+        false => findings
+            .iter()
+            .map(|f| f.message.as_str())
+            .collect::<Vec<&str>>()
+            .join("; "),
+    };
+    format!(
+        "OXIDIZR {} - {text} | enabled={};;;0;{} drifted={};0;;0 quarantined={};;;0",
+        report.state,
+        report.enabled,
+        report.experiments,
+        report.drifted,
+        report.quarantined.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+    use std::path::PathBuf;
+
+    fn status(runner: &MockSystem, drift: &[&str]) -> ExperimentStatus {
+        let mut status = all_experiments(runner)[0].status();
+        status.enabled = true;
+        status.drift = drift.iter().map(PathBuf::from).collect();
+        status
+    }
+
+    #[test]
+    fn test_check() {
+        let runner = MockSystem::default();
+        let journal = Path::new("/nonexistent/journal.json");
+
+        let report = evaluate(
+            &runner,
+            journal,
+            &[status(&runner, &[])],
+            &Ok(Vec::new()),
+            0,
+        );
+        assert_eq!(report.state, CheckState::Ok);
+        assert_eq!(
+            plugin_output(&report),
+            "OXIDIZR OK - 1 of 1 experiment(s) enabled, no drift | enabled=1;;;0;1 drifted=0;0;;0 \
+            quarantined=0;;;0"
+        );
+
+        let mut statuses = vec![status(&runner, &["/usr/bin/ls", "/usr/bin/cp"])];
+        statuses[0].unchecked = vec!["replaced files could not be checked".to_string()];
+        statuses[0].quarantined = vec!["date".to_string()];
+        let quarantined = Ok(vec!["date".to_string()]);
+        let report = evaluate(&runner, journal, &statuses, &quarantined, 0);
+        assert_eq!(report.state, CheckState::Warning);
+        assert_eq!(report.state.exit_code(), 1);
+        let output = plugin_output(&report);
+        assert!(output.starts_with("OXIDIZR WARNING - "));
+        assert!(output.contains("2 file(s) no longer replaced"));
+        assert!(output.contains("date has fallen back to GNU repeatedly"));
+        assert!(output.ends_with("drifted=2;0;;0 quarantined=1;;;0"));
+    }
+
+    #[test]
+    fn test_check_interrupted() {
+        let runner = MockSystem::default();
+        let dir = std::env::temp_dir().join(format!("oxidizr-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("journal.json");
+        Journal::begin(&path, "enable", vec!["coreutils".to_string()]).unwrap();
+
+        let report = evaluate(&runner, &path, &[], &Ok(Vec::new()), 0);
+        assert_eq!(report.state, CheckState::Critical);
+        assert_eq!(report.state.exit_code(), 2);
+        assert!(report.findings[0].message.contains("'enable' of coreutils"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use crate::experiments::all_experiments;
+use crate::utils::{Command, ErrorCode, OutputFormat, Worker, backup_filename, structured};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    ffi::OsStr,
+    io::{IsTerminal, Read, Write},
+    process::Stdio,
+    thread,
+};
+
+/// The width of the side-by-side diff when the width of the terminal isn't known.
+const DEFAULT_WIDTH: usize = 120;
+
+/// The most pairs of lines compared to find the differences between two outputs. Longer outputs
+/// are compared line by line instead.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// What an invocation of a binary, GNU or its replacement, printed and exited with.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Invocation {
+    pub command: String,
+    /// The exit code, or `None` if the binary was killed by a signal.
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// The outcome of running the same invocation against a GNU binary and its replacement.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct BinaryComparison {
+    pub binary: String,
+    pub experiment: String,
+    pub gnu: Invocation,
+    pub replacement: Invocation,
+    /// Whether both printed the same and exited with the same code.
+    pub identical: bool,
+}
+
+/// Run `binary` with `args` as both the GNU binary, from its backup once replaced, and its
+/// replacement, and show how their output and exit codes differ. Standard input, unless it is a
+/// terminal, is read once and given to both. Returns whether they behaved identically.
+pub fn compare_one(
+    system: &dyn Worker,
+    binary: &str,
+    args: &[String],
+    format: OutputFormat,
+) -> Result<bool> {
+    let (experiment, gnu, replacement) = commands(system, binary)?;
+    let input = match std::io::stdin().is_terminal() {
+        true => None,
+        false => {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input)?;
+            Some(input)
+        }
+    };
+
+    let gnu = invoke(gnu, args, input.as_deref())?;
+    let replacement = invoke(replacement, args, input.as_deref())?;
+    let comparison = BinaryComparison {
+        binary: binary.to_string(),
+        experiment,
+        identical: gnu.status == replacement.status
+            && gnu.stdout == replacement.stdout
+            && gnu.stderr == replacement.stderr,
+        gnu,
+        replacement,
+    };
+
+    match format {
+        OutputFormat::Human => {
+            let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            let width = std::env::var("COLUMNS")
+                .ok()
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(DEFAULT_WIDTH);
+            print!("{}", render(&comparison, width, color));
+        }
+        format => print!("{}", structured(format, &comparison)?),
+    }
+    Ok(comparison.identical)
+}
+
+/// Find the experiment replacing `binary`, and build the commands running the GNU binary and its
+/// replacement. Once replaced, the GNU binary is only available as the backup.
+fn commands(system: &dyn Worker, binary: &str) -> Result<(String, Command, Command)> {
+    for experiment in all_experiments(system) {
+        let Ok(replacements) = experiment.replacements() else {
+            continue;
+        };
+        let Some((source, target)) = replacements
+            .into_iter()
+            .find(|(_, target)| target.file_name() == Some(OsStr::new(binary)))
+        else {
+            continue;
+        };
+
+        let backup = backup_filename(&target)?;
+        let gnu = match system.file_checksum(&backup)? {
+            Some(_) => backup,
+            None => target,
+        };
+        let replacement = experiment.replacement_command(&source, OsStr::new(binary));
+        return Ok((experiment.name(), Command::build(gnu, &[]), replacement));
+    }
+    Err(ErrorCode::NotReplaced.error(format!(
+        "'{binary}' isn't replaced by any experiment whose replacement is installed"
+    )))
+}
+
+/// Run a command with arguments appended, collecting whatever it prints, however it exits.
+fn invoke(command: Command, args: &[String], input: Option<&[u8]>) -> Result<Invocation> {
+    let command = args.iter().fold(command, |c, a| c.arg(a));
+    let mut child = std::process::Command::new(&command.command)
+        .args(&command.args)
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::inherit(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("unable to run '{}'", command.command()))?;
+    if let (Some(input), Some(mut pipe)) = (input, child.stdin.take()) {
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = pipe.write_all(&input);
+        });
+    }
+    let output = child.wait_with_output()?;
+
+    Ok(Invocation {
+        command: command.command(),
+        status: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+/// A row of a side-by-side diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Row<'a> {
+    Same(&'a str),
+    Changed(&'a str, &'a str),
+    Gnu(&'a str),
+    Replacement(&'a str),
+}
+
+/// Pair up the lines of two outputs, matching the longest common subsequence of lines, and
+/// pairing the lines removed and added between them as changed.
+fn diff<'a>(gnu: &[&'a str], replacement: &[&'a str]) -> Vec<Row<'a>> {
+    let (n, m) = (gnu.len(), replacement.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        return (0..n.max(m))
+            .map(|i| match (gnu.get(i), replacement.get(i)) {
+                (Some(g), Some(r)) if g == r => Row::Same(g),
+                (Some(g), Some(r)) => Row::Changed(g, r),
+                (Some(g), None) => Row::Gnu(g),
+                (None, r) => Row::Replacement(r.copied().unwrap_or_default()),
+            })
+            .collect();
+    }
+
+    // The length of the longest common subsequence of the lines from i and j onwards.
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = match gnu[i] == replacement[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut rows = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let flush = |rows: &mut Vec<Row<'a>>, removed: &mut Vec<&'a str>, added: &mut Vec<&'a str>| {
+        for i in 0..removed.len().max(added.len()) {
+            rows.push(match (removed.get(i), added.get(i)) {
+                (Some(g), Some(r)) => Row::Changed(g, r),
+                (Some(g), None) => Row::Gnu(g),
+                (None, r) => Row::Replacement(r.copied().unwrap_or_default()),
+            });
+        }
+        removed.clear();
+        added.clear();
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && gnu[i] == replacement[j] {
+            flush(&mut rows, &mut removed, &mut added);
+            rows.push(Row::Same(gnu[i]));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            removed.push(gnu[i]);
+            i += 1;
+        } else {
+            added.push(replacement[j]);
+            j += 1;
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// Render a comparison for a terminal of `width` columns: the commands run, their exit codes,
+/// and a side-by-side diff of what each printed, GNU on the left, coloured if `color`.
+fn render(comparison: &BinaryComparison, width: usize, color: bool) -> String {
+    let paint = |text: String, code: &str| match color {
+        true => format!("{code}{text}{RESET}"),
+        false => text,
+    };
+    let status = |i: &Invocation| match i.status {
+        Some(code) => code.to_string(),
+        None => "killed by a signal".to_string(),
+    };
+
+    let (gnu, replacement) = (&comparison.gnu, &comparison.replacement);
+    let mut out = format!(
+        "GNU:         {}\nReplacement: {}\n\n",
+        gnu.command, replacement.command
+    );
+    let statuses = format!("Exit code: {} | {}", status(gnu), status(replacement));
+    out.push_str(&match gnu.status == replacement.status {
+        true => statuses,
+        false => paint(statuses, YELLOW),
+    });
+    out.push('\n');
+
+    let column = width.saturating_sub(3) / 2;
+    let cell = |line: &str| {
+        let line = line.replace('\t', "    ");
+        let line: String = line.chars().take(column).collect();
+        format!("{line:<column$}")
+    };
+    for (stream, gnu, replacement) in [
+        ("stdout", &gnu.stdout, &replacement.stdout),
+        ("stderr", &gnu.stderr, &replacement.stderr),
+    ] {
+        if gnu.is_empty() && replacement.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n{stream}:\n"));
+        if gnu == replacement {
+            out.push_str("(identical)\n");
+            continue;
+        }
+
+        let gnu: Vec<&str> = gnu.lines().collect();
+        let replacement: Vec<&str> = replacement.lines().collect();
+        for row in diff(&gnu, &replacement) {
+            let line = match row {
+                Row::Same(line) => format!("{} | {}", cell(line), cell(line)),
+                Row::Changed(g, r) => paint(format!("{} ~ {}", cell(g), cell(r)), YELLOW),
+                Row::Gnu(g) => paint(format!("{} < {}", cell(g), cell("")), RED),
+                Row::Replacement(r) => paint(format!("{} > {}", cell(""), cell(r)), GREEN),
+            };
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+
+    out.push_str(match comparison.identical {
+        true => "\nThe replacement behaved identically.\n",
+        false => "\nThe replacement behaved differently.\n",
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_diff() {
+        let gnu = ["a", "b", "c", "d"];
+        let replacement = ["a", "x", "c", "d", "e"];
+        assert_eq!(
+            diff(&gnu, &replacement),
+            vec![
+                Row::Same("a"),
+                Row::Changed("b", "x"),
+                Row::Same("c"),
+                Row::Same("d"),
+                Row::Replacement("e"),
+            ]
+        );
+        assert_eq!(diff(&["a"], &[]), vec![Row::Gnu("a")]);
+    }
+
+    #[test]
+    fn test_render() {
+        let invocation = |command: &str, status, stdout: &str| Invocation {
+            command: command.to_string(),
+            status,
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        };
+        let comparison = BinaryComparison {
+            binary: "sort".to_string(),
+            experiment: "coreutils".to_string(),
+            gnu: invocation("/usr/bin/.sort.oxidizr.bak -n", Some(0), "1\n2\n10\n"),
+            replacement: invocation("/usr/bin/coreutils sort -n", Some(0), "1\n10\n2\n"),
+            identical: false,
+        };
+
+        let rendered = render(&comparison, 23, false);
+        assert!(rendered.starts_with(
+            "GNU:         /usr/bin/.sort.oxidizr.bak -n\n\
+            Replacement: /usr/bin/coreutils sort -n\n\nExit code: 0 | 0\n\nstdout:\n"
+        ));
+        assert!(
+            rendered.contains("\n1          | 1\n2          <\n10         | 10\n           > 2\n")
+        );
+        assert!(rendered.ends_with("\nThe replacement behaved differently.\n"));
+
+        // Differences are coloured for a terminal.
+        assert!(render(&comparison, 23, true).contains(&format!("{RED}2          < ")));
+    }
+
+    #[test]
+    fn test_compare_commands() {
+        let runner = MockSystem::default();
+        let err = commands(&runner, "frobnicate").map(|_| ()).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::NotReplaced));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::experiments::{Channel, Experiment, replace_symlink};
+use crate::utils::{OutputFormat, Worker, structured};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// The prefixes whose `bin` directory holds the files of the system, which a deployment mustn't
+/// write over: replacing those is what `enable` is for.
+const SYSTEM_PREFIXES: &[&str] = &["/", "/usr"];
+
+/// An experiment deployed under a prefix, or the reason it was skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct DeployedExperiment {
+    pub name: String,
+    /// The upstream release deployed, or `None` for the binaries of the installed package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    /// The binaries linked into the `bin` directory of the prefix.
+    pub binaries: Vec<String>,
+    pub skip_reason: Option<String>,
+}
+
+/// The binaries to link into a prefix for an experiment, by name, and the upstream release they
+/// come from, if any.
+struct Sources {
+    release: Option<String>,
+    binaries: Vec<(String, PathBuf)>,
+}
+
+/// The replacements deployed under a prefix, and the environment that puts them to use.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Deployment {
+    pub prefix: PathBuf,
+    pub experiments: Vec<DeployedExperiment>,
+    /// The variables to export, as a POSIX shell would expand them.
+    pub environment: BTreeMap<String, String>,
+}
+
+/// Deploy the replacements of experiments under `prefix`, without root and without touching the
+/// files of the system: each replacement binary is linked into `bin` under the prefix, from the
+/// installed package or from an upstream release unpacked into `lib/oxidizr`. The environment
+/// putting them first on the PATH is printed, and written to `env` under the prefix to be sourced.
+pub fn deploy(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    prefix: &Path,
+    channel: Channel,
+    format: OutputFormat,
+) -> Result<()> {
+    let deployment = deploy_into(system, experiments, prefix, channel)?;
+    match format {
+        OutputFormat::Human => {
+            for e in &deployment.experiments {
+                match (&e.skip_reason, &e.release) {
+                    (Some(reason), _) => println!("Skipped {}: {reason}", e.name),
+                    (None, Some(release)) => println!(
+                        "Deployed {} {release} ({} binaries)",
+                        e.name,
+                        e.binaries.len()
+                    ),
+                    (None, None) => println!("Deployed {} ({} binaries)", e.name, e.binaries.len()),
+                }
+            }
+            println!(
+                "\nTo use the replacements, run the following, or source {}:\n",
+                deployment.prefix.join("env").display()
+            );
+            print!("{}", env_script(&deployment.environment));
+        }
+        format => print!("{}", structured(format, &deployment)?),
+    }
+    Ok(())
+}
+
+/// Deploy the replacements of experiments under `prefix`, as described for `deploy`.
+fn deploy_into(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    prefix: &Path,
+    channel: Channel,
+) -> Result<Deployment> {
+    let prefix = std::path::absolute(prefix)
+        .with_context(|| format!("'{}' isn't a valid prefix", prefix.display()))?;
+    check_prefix(&prefix)?;
+    let bin = prefix.join("bin");
+    fs::create_dir_all(&bin).with_context(|| format!("unable to create {}", bin.display()))?;
+
+    let mut deployed = Vec::new();
+    for e in experiments {
+        let mut planned = DeployedExperiment {
+            name: e.name(),
+            release: None,
+            binaries: Vec::new(),
+            skip_reason: None,
+        };
+        let sources = match sources(system, e, &prefix, channel)? {
+            Ok(sources) => {
+                planned.release = sources.release;
+                sources.binaries
+            }
+            Err(reason) => {
+                planned.skip_reason = Some(reason);
+                deployed.push(planned);
+                continue;
+            }
+        };
+        for (name, source) in sources {
+            replace_symlink(&source, &bin.join(&name))?;
+            planned.binaries.push(name);
+        }
+        info!(
+            "Deployed {} replacement(s) for {} under {}",
+            planned.binaries.len(),
+            planned.name,
+            prefix.display()
+        );
+        deployed.push(planned);
+    }
+
+    let environment = environment(&prefix);
+    let script = prefix.join("env");
+    fs::write(&script, env_script(&environment))
+        .with_context(|| format!("unable to write {}", script.display()))?;
+    Ok(Deployment {
+        prefix,
+        experiments: deployed,
+        environment,
+    })
+}
+
+/// Refuse to deploy under a prefix whose `bin` directory is that of the system.
+fn check_prefix(prefix: &Path) -> Result<()> {
+    let resolved = fs::canonicalize(prefix).unwrap_or_else(|_| prefix.to_path_buf());
+    if SYSTEM_PREFIXES.iter().any(|p| resolved == Path::new(p)) {
+        anyhow::bail!(
+            "{} holds the binaries of the system: use 'oxidizr enable' to replace those",
+            resolved.display()
+        );
+    }
+    Ok(())
+}
+
+/// The binaries to link into the prefix for an experiment, or the reason it can't be deployed.
+fn sources(
+    system: &dyn Worker,
+    experiment: &Experiment,
+    prefix: &Path,
+    channel: Channel,
+) -> Result<Result<Sources, String>> {
+    let name = experiment.name();
+    match (experiment, channel) {
+        (Experiment::SudoRs(_), _) => Ok(Err(
+            "its binaries only work when installed setuid root, which a prefix can't provide"
+                .to_string(),
+        )),
+        (Experiment::Uutils(uutils), Channel::Latest | Channel::Nightly) => {
+            let directory = prefix.join("lib/oxidizr").join(&name);
+            let release = uutils
+                .unpack_release(channel, &directory)
+                .with_context(|| format!("unable to deploy the {channel} release of {name}"))?;
+            let mut binaries = Vec::new();
+            for file in system.list_files(directory.join("bin"))? {
+                if let Some(binary) = file.file_name().and_then(|n| n.to_str()) {
+                    binaries.push((binary.to_string(), file.clone()));
+                }
+            }
+            Ok(Ok(Sources {
+                release: release.map(|r| r.release),
+                binaries,
+            }))
+        }
+        (_, Channel::Latest | Channel::Nightly) => {
+            Ok(Err("it has no upstream releases to deploy".to_string()))
+        }
+        (_, Channel::Stable) if !experiment.check_installed() => Ok(Err(format!(
+            "{} isn't installed: have it installed, or deploy an upstream release with \
+            --channel latest",
+            experiment.package()
+        ))),
+        (_, Channel::Stable) => {
+            let mut binaries = Vec::new();
+            for (source, target) in experiment.replacements()? {
+                if let Some(binary) = target.file_name().and_then(|n| n.to_str()) {
+                    binaries.push((binary.to_string(), source));
+                }
+            }
+            Ok(Ok(Sources {
+                release: None,
+                binaries,
+            }))
+        }
+    }
+}
+
+/// The environment variables putting the deployment under `prefix` to use: its `bin` directory
+/// first on the PATH, and its libraries, if it has any, first on the library path.
+fn environment(prefix: &Path) -> BTreeMap<String, String> {
+    let mut environment = BTreeMap::from([(
+        "PATH".to_string(),
+        format!("{}:$PATH", prefix.join("bin").display()),
+    )]);
+    let lib = prefix.join("lib");
+    let has_libraries = fs::read_dir(&lib).is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().contains(".so"))
+    });
+    if has_libraries {
+        environment.insert(
+            "LD_LIBRARY_PATH".to_string(),
+            format!("{}${{LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}}", lib.display()),
+        );
+    }
+    environment
+}
+
+/// Write the environment as the commands of a POSIX shell exporting it.
+fn env_script(environment: &BTreeMap<String, String>) -> String {
+    environment
+        .iter()
+        .map(|(name, value)| format!("export {name}=\"{value}\"\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{SudoRsExperiment, all_experiments};
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_deploy() {
+        let prefix = std::env::temp_dir().join(format!("oxidizr-deploy-{}", std::process::id()));
+        let runner = MockSystem::default();
+        runner.mock_install_package("rust-findutils");
+        runner.mock_files(vec![("/usr/lib/cargo/bin/findutils/find", "", true)]);
+        let mut experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["findutils", "diffutils"].contains(&e.name().as_str()))
+            .collect();
+        experiments.push(Experiment::SudoRs(SudoRsExperiment::new(&runner)));
+
+        let deployment = deploy_into(&runner, &experiments, &prefix, Channel::Stable).unwrap();
+        let deployed: Vec<_> = deployment
+            .experiments
+            .iter()
+            .map(|e| (e.name.as_str(), e.skip_reason.is_none(), e.binaries.len()))
+            .collect();
+        assert_eq!(
+            deployed,
+            vec![
+                ("diffutils", false, 0),
+                ("findutils", true, 1),
+                ("sudo-rs", false, 0)
+            ]
+        );
+        assert_eq!(
+            fs::read_link(prefix.join("bin/find")).unwrap(),
+            Path::new("/usr/lib/cargo/bin/findutils/find")
+        );
+        assert_eq!(
+            fs::read_to_string(prefix.join("env")).unwrap(),
+            format!("export PATH=\"{}/bin:$PATH\"\n", prefix.display())
+        );
+
+        // A library deployed under the prefix goes on the library path too.
+        fs::create_dir_all(prefix.join("lib")).unwrap();
+        fs::write(prefix.join("lib/libexample.so.1"), "").unwrap();
+        assert!(environment(&prefix).contains_key("LD_LIBRARY_PATH"));
+        fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn test_check_prefix() {
+        assert!(check_prefix(Path::new("/usr")).is_err());
+        assert!(check_prefix(Path::new("/")).is_err());
+        assert!(check_prefix(Path::new("/opt/oxidizr")).is_ok());
+    }
+}
@@ -0,0 +1,408 @@
+use super::RESCUE_DIRECTORIES;
+use crate::config::RetentionConfig;
+use crate::experiments::{
+    CHANNELS_DIRECTORY, Channel, Channels, Directories, Displaced, Generations, MAX_GENERATIONS,
+    PACKAGE_CACHE_DIRECTORY, PackageCache, RecordedVersions, SHIMS_DIRECTORY, all_experiments,
+};
+use crate::utils::{
+    ManagedTarget, OutputFormat, Table, Worker, backup_original, format_size, structured,
+    update_state,
+};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// Something left behind by oxidizr that nothing needs any more.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Artifact {
+    /// The backup of a file that is no longer replaced, which the file itself has superseded.
+    Backup { path: PathBuf, size: u64 },
+    /// A generation older than those kept.
+    Generation { number: u32, size: u64 },
+    /// A package file downloaded for a rollback that no experiment can need.
+    CachedPackage {
+        package: String,
+        path: PathBuf,
+        size: u64,
+    },
+    /// A shim written to the state directory before being copied into place, which the copy has
+    /// superseded.
+    StagedShim { path: PathBuf, size: u64 },
+    /// A release unpacked from an upstream channel, and the links to its utilities, for an
+    /// experiment no longer installed from that channel.
+    ChannelRelease {
+        experiment: String,
+        path: PathBuf,
+        size: u64,
+    },
+}
+
+impl Artifact {
+    /// The space the artifact takes up, in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            Artifact::Backup { size, .. }
+            | Artifact::Generation { size, .. }
+            | Artifact::CachedPackage { size, .. }
+            | Artifact::StagedShim { size, .. }
+            | Artifact::ChannelRelease { size, .. } => *size,
+        }
+    }
+
+    /// Describe the kind of the artifact, and where it is, for display.
+    fn describe(&self) -> (&'static str, String) {
+        match self {
+            Artifact::Backup { path, .. } => ("backup", path.display().to_string()),
+            Artifact::Generation { number, .. } => ("generation", format!("generation {number}")),
+            Artifact::CachedPackage { path, .. } => ("cached package", path.display().to_string()),
+            Artifact::StagedShim { path, .. } => ("staged shim", path.display().to_string()),
+            Artifact::ChannelRelease { path, .. } => {
+                ("channel release", path.display().to_string())
+            }
+        }
+    }
+}
+
+/// Find the artifacts that nothing needs any more: backups of files that aren't replaced, the
+/// generations beyond those kept, the package files cached for experiments that can no longer
+/// be rolled back, the shims staged before being copied into place, and the upstream releases of
+/// experiments no longer on their channel.
+pub fn find_garbage(system: &dyn Worker, retention: &RetentionConfig) -> Result<Vec<Artifact>> {
+    let mut garbage = orphaned_backups(system)?;
+    garbage.extend(stale_generations(system, retention)?);
+    garbage.extend(unused_packages(system)?);
+    garbage.extend(staged_shims(system)?);
+    garbage.extend(unused_releases(system)?);
+    Ok(garbage)
+}
+
+/// Print the artifacts found, and how much space removing them reclaims.
+pub fn print_garbage(garbage: &[Artifact], dry_run: bool, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Human if garbage.is_empty() => println!("Nothing to collect."),
+        OutputFormat::Human => {
+            print!("{}", garbage_table(garbage));
+            let total = format_size(garbage.iter().map(Artifact::size).sum());
+            match dry_run {
+                true => println!("\nRemoving them would reclaim {total}."),
+                false => println!("\nRemoving them reclaims {total}."),
+            }
+        }
+        format => print!("{}", structured(format, &garbage)?),
+    }
+    Ok(())
+}
+
+/// Remove the artifacts found, forgetting them in the records they are kept in, and report the
+/// space reclaimed. An artifact that can't be removed is warned about, and the rest removed.
+pub fn collect_garbage(system: &dyn Worker, garbage: &[Artifact]) -> Result<u64> {
+    let mut reclaimed = 0;
+    for artifact in garbage {
+        match collect(system, artifact) {
+            Ok(()) => reclaimed += artifact.size(),
+            Err(e) => warn!("Unable to remove {}: {e:#}", artifact.describe().1),
+        }
+    }
+    info!("Reclaimed {}", format_size(reclaimed));
+    Ok(reclaimed)
+}
+
+/// Remove an artifact.
+fn collect(system: &dyn Worker, artifact: &Artifact) -> Result<()> {
+    match artifact {
+        Artifact::Backup { path, .. } | Artifact::StagedShim { path, .. } => {
+            system.remove_file(path)
+        }
+        Artifact::Generation { number, .. } => {
+            update_state(system, |g: &mut Generations| {
+                g.generations.retain(|g| g.number != *number);
+                Ok(())
+            })?;
+            Ok(())
+        }
+        Artifact::CachedPackage { package, path, .. } => {
+            if system.file_size(path)?.is_some() {
+                system.remove_file(path)?;
+            }
+            update_state(system, |cache: &mut PackageCache| {
+                cache
+                    .packages
+                    .retain(|p, cached| p != package || cached.file != *path);
+                Ok(())
+            })?;
+            Ok(())
+        }
+        Artifact::ChannelRelease {
+            experiment, path, ..
+        } => {
+            fs::remove_dir_all(path)
+                .with_context(|| format!("unable to remove {}", path.display()))?;
+            update_state(system, |channels: &mut Channels| {
+                channels.releases.remove(experiment);
+                Ok(())
+            })?;
+            Ok(())
+        }
+    }
+}
+
+/// Build a human-readable table of artifacts.
+fn garbage_table(garbage: &[Artifact]) -> Table {
+    let mut table = Table::new(&["KIND", "ARTIFACT", "SIZE"]);
+    for artifact in garbage {
+        let (kind, name) = artifact.describe();
+        table.add_row(vec![kind.to_string(), name, format_size(artifact.size())]);
+    }
+    table
+}
+
+/// Find the backups whose original file is in place, neither replaced by a link or a copy nor
+/// drifted from an enabled experiment, which `reapply` would back up afresh. A backup whose
+/// original is missing is kept, as it may be the only copy of the file.
+fn orphaned_backups(system: &dyn Worker) -> Result<Vec<Artifact>> {
+    let drifted: BTreeSet<PathBuf> = all_experiments(system)
+        .iter()
+        .flat_map(|e| e.status().drift)
+        .collect();
+    let recorded = Directories::load(system)?;
+    let directories: BTreeSet<PathBuf> = RESCUE_DIRECTORIES
+        .iter()
+        .map(PathBuf::from)
+        .chain(recorded.experiments.into_values().flatten())
+        .map(|d| fs::canonicalize(&d).unwrap_or(d))
+        .collect();
+
+    let mut orphaned = Vec::new();
+    for directory in directories {
+        for path in system.list_files(directory.clone())?.into_iter() {
+            let Some(original) = backup_original(&path) else {
+                continue;
+            };
+            let checksum = ManagedTarget::new(&original)?.sibling("oxidizr.sha256");
+            let in_place = system.read_symlink(&original)?.is_none()
+                && system.file_size(&checksum)?.is_none()
+                && system.file_size(&original)?.is_some();
+            if in_place && !drifted.contains(&original) {
+                let size = system.file_size(&path)?.unwrap_or_default();
+                orphaned.push(Artifact::Backup { path, size });
+            }
+        }
+    }
+    Ok(orphaned)
+}
+
+/// The file name of a path, for display.
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Find the generations older than the latest ones kept.
+fn stale_generations(system: &dyn Worker, retention: &RetentionConfig) -> Result<Vec<Artifact>> {
+    let generations = Generations::load(system)?;
+    let keep = retention.generations.unwrap_or(MAX_GENERATIONS);
+    let stale = generations.generations.len().saturating_sub(keep);
+    generations.generations[..stale]
+        .iter()
+        .map(|g| {
+            Ok(Artifact::Generation {
+                number: g.number,
+                size: serde_json::to_vec(g)?.len() as u64,
+            })
+        })
+        .collect()
+}
+
+/// Find the cached package files that no rollback can use: those of packages that are neither
+/// recorded for `rollback --to-recorded`, nor displaced by a replacement, nor replaced by an
+/// enabled experiment, and those no longer recorded in the cache at all.
+fn unused_packages(system: &dyn Worker) -> Result<Vec<Artifact>> {
+    let cache = PackageCache::load(system)?;
+    let mut needed: BTreeSet<String> = RecordedVersions::load(system)?
+        .experiments
+        .into_values()
+        .map(|r| r.replaced.package)
+        .collect();
+    needed.extend(
+        Displaced::load(system)?
+            .experiments
+            .into_values()
+            .map(|d| d.package),
+    );
+    needed.extend(
+        all_experiments(system)
+            .iter()
+            .filter(|e| e.status().enabled)
+            .map(|e| e.metadata().replaces.to_string()),
+    );
+
+    let mut unused = Vec::new();
+    for (package, cached) in &cache.packages {
+        if !needed.contains(package) {
+            unused.push(Artifact::CachedPackage {
+                package: package.clone(),
+                path: cached.file.clone(),
+                size: system.file_size(&cached.file)?.unwrap_or_default(),
+            });
+        }
+    }
+    let recorded: BTreeSet<&PathBuf> = cache.packages.values().map(|c| &c.file).collect();
+    let directory = Path::new(PACKAGE_CACHE_DIRECTORY);
+    for file in system.list_files(directory.to_path_buf())? {
+        if file.parent() == Some(directory) && !recorded.contains(&file) {
+            unused.push(Artifact::CachedPackage {
+                package: name_of(&file),
+                size: system.file_size(&file)?.unwrap_or_default(),
+                path: file,
+            });
+        }
+    }
+    Ok(unused)
+}
+
+/// Find the shims staged for copying into place. Only an operation in progress needs them, and
+/// shims are staged afresh whenever they are installed.
+fn staged_shims(system: &dyn Worker) -> Result<Vec<Artifact>> {
+    let directory = Path::new(SHIMS_DIRECTORY);
+    let mut staged = Vec::new();
+    for path in system.list_files(directory.to_path_buf())? {
+        if path.parent() == Some(directory) {
+            let size = system.file_size(&path)?.unwrap_or_default();
+            staged.push(Artifact::StagedShim { path, size });
+        }
+    }
+    Ok(staged)
+}
+
+/// Find the releases unpacked from upstream channels for experiments now installed from the
+/// distribution.
+fn unused_releases(system: &dyn Worker) -> Result<Vec<Artifact>> {
+    let channels = Channels::load(system)?;
+    let entries = match fs::read_dir(CHANNELS_DIRECTORY) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut unused = Vec::new();
+    for entry in entries.flatten() {
+        let experiment = entry.file_name().to_string_lossy().into_owned();
+        if channels.of(&experiment) == Channel::Stable {
+            unused.push(Artifact::ChannelRelease {
+                experiment,
+                size: directory_size(&entry.path()),
+                path: entry.path(),
+            });
+        }
+    }
+    Ok(unused)
+}
+
+/// The total size of the files in a directory, and those below it, not following links.
+fn directory_size(directory: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => directory_size(&e.path()),
+            Ok(_) => e.metadata().map_or(0, |m| m.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{CachedPackage, Generation};
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_find_garbage() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            // GNU date is back in place of the replacement, so its backup is no longer needed.
+            ("/usr/bin/date", "GNU date", true),
+            ("/usr/bin/.date.oxidizr.bak", "GNU date", false),
+            // The backup of a file that is missing is kept.
+            ("/usr/bin/.gone.oxidizr.bak", "gone", false),
+            (
+                "/var/lib/oxidizr/packages/diffutils_3.10-1_amd64.deb",
+                "diffutils",
+                false,
+            ),
+            ("/var/lib/oxidizr/packages/stray.deb", "stray", false),
+            ("/var/lib/oxidizr/shims/date", "#!/bin/sh", true),
+            // Backups are also found in the directories configured for an experiment.
+            ("/opt/tools/ls", "GNU ls", false),
+            ("/opt/tools/.ls.oxidizr.bak", "GNU ls", false),
+        ]);
+        let mut directories = Directories::default();
+        directories.set("coreutils", &[PathBuf::from("/opt/tools")]);
+        directories.save(&runner).unwrap();
+        let mut cache = PackageCache::default();
+        cache.packages.insert(
+            "diffutils".to_string(),
+            CachedPackage {
+                version: "3.10-1".to_string(),
+                file: PathBuf::from("/var/lib/oxidizr/packages/diffutils_3.10-1_amd64.deb"),
+                sha256: String::new(),
+            },
+        );
+        cache.save(&runner).unwrap();
+        Generations {
+            generations: (1..=3)
+                .map(|number| Generation {
+                    number,
+                    ..Generation::default()
+                })
+                .collect(),
+        }
+        .save(&runner)
+        .unwrap();
+
+        let retention = RetentionConfig {
+            generations: Some(2),
+        };
+        let garbage = find_garbage(&runner, &retention).unwrap();
+        let described: Vec<_> = garbage.iter().map(|a| a.describe().1).collect();
+        let backup = match fs::canonicalize("/usr/bin") {
+            Ok(bin) => bin.join(".date.oxidizr.bak").display().to_string(),
+            Err(_) => "/usr/bin/.date.oxidizr.bak".to_string(),
+        };
+        assert_eq!(
+            described,
+            vec![
+                "/opt/tools/.ls.oxidizr.bak",
+                backup.as_str(),
+                "generation 1",
+                "/var/lib/oxidizr/packages/diffutils_3.10-1_amd64.deb",
+                "/var/lib/oxidizr/packages/stray.deb",
+                "/var/lib/oxidizr/shims/date",
+            ]
+        );
+
+        let size = garbage[2..].iter().map(Artifact::size).sum::<u64>();
+        assert_eq!(collect_garbage(&runner, &garbage[2..]).unwrap(), size);
+        assert_eq!(Generations::load(&runner).unwrap().generations.len(), 2);
+        assert_eq!(
+            PackageCache::load(&runner).unwrap(),
+            PackageCache::default()
+        );
+        assert_eq!(
+            runner
+                .read_file(Path::new("/var/lib/oxidizr/packages/stray.deb"))
+                .unwrap(),
+            None
+        );
+    }
+}
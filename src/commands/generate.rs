@@ -0,0 +1,76 @@
+use super::Provisioning;
+use anyhow::Result;
+use clap::Subcommand;
+use std::{fs, path::PathBuf};
+use tracing::info;
+
+/// Supporting files that can be generated from oxidizr's CLI definition.
+#[derive(Subcommand, Debug)]
+pub enum GenerateTarget {
+    /// Generate man pages for oxidizr and each of its subcommands.
+    Man {
+        #[arg(
+            long,
+            default_value = ".",
+            help = "Directory to write the generated man pages to"
+        )]
+        output_dir: PathBuf,
+    },
+    /// Generate a cloud-config snippet that installs oxidizr and enables the selected experiments
+    /// when an instance is first booted.
+    CloudInit {
+        #[arg(
+            long,
+            default_value = env!("CARGO_PKG_VERSION"),
+            help = "Release of oxidizr to install"
+        )]
+        release: String,
+    },
+    /// Generate an Anaconda kickstart %post section that installs oxidizr and enables the
+    /// selected experiments at the end of an installation.
+    Kickstart {
+        #[arg(
+            long,
+            default_value = env!("CARGO_PKG_VERSION"),
+            help = "Release of oxidizr to install"
+        )]
+        release: String,
+    },
+    /// Generate debian-installer preseed directives that install oxidizr and enable the selected
+    /// experiments at the end of an installation.
+    Preseed {
+        #[arg(
+            long,
+            default_value = env!("CARGO_PKG_VERSION"),
+            help = "Release of oxidizr to install"
+        )]
+        release: String,
+    },
+}
+
+/// Generate the requested files from the definition of the command line interface. Provisioning
+/// snippets run oxidizr with `enable_args`, which pin the experiments and flags given to this
+/// invocation.
+pub fn generate(
+    target: &GenerateTarget,
+    cli: clap::Command,
+    enable_args: Vec<String>,
+) -> Result<()> {
+    match target {
+        GenerateTarget::Man { output_dir } => {
+            fs::create_dir_all(output_dir)?;
+            clap_mangen::generate_to(cli, output_dir)?;
+            info!("Generated man pages in {}", output_dir.display());
+        }
+        GenerateTarget::CloudInit { release } => {
+            print!("{}", Provisioning::new(release, enable_args).cloud_config());
+        }
+        GenerateTarget::Kickstart { release } => {
+            print!("{}", Provisioning::new(release, enable_args).kickstart());
+        }
+        GenerateTarget::Preseed { release } => {
+            print!("{}", Provisioning::new(release, enable_args).preseed());
+        }
+    }
+    Ok(())
+}
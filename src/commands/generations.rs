@@ -0,0 +1,131 @@
+use crate::experiments::{Generation, GenerationDiff, Generations, calendar_time};
+use crate::utils::{OutputFormat, Table, Worker, structured};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// A generation as listed by `generations`, with how it differs from the one before it.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct GenerationListing {
+    pub number: u32,
+    /// When the generation was recorded, in seconds since the Unix epoch.
+    pub created: u64,
+    pub command: String,
+    pub experiments: Vec<String>,
+    /// Whether it is the latest generation, which the system is in.
+    pub current: bool,
+    pub changes: GenerationDiff,
+}
+
+/// Print the generations recorded, oldest first, with the changes each made.
+pub fn generations(system: &dyn Worker, format: OutputFormat) -> Result<()> {
+    let listings = listings(&Generations::load(system)?);
+    match format {
+        OutputFormat::Human if listings.is_empty() => {
+            println!("No generations have been recorded.")
+        }
+        OutputFormat::Human => print!("{}", generations_table(&listings)),
+        format => print!("{}", structured(format, &listings)?),
+    }
+    Ok(())
+}
+
+/// List the generations recorded with how each differs from the one before it.
+fn listings(generations: &Generations) -> Vec<GenerationListing> {
+    let latest = generations.generations.last().map(|g| g.number);
+    generations
+        .generations
+        .iter()
+        .map(|g: &Generation| GenerationListing {
+            number: g.number,
+            created: g.created,
+            command: g.command.clone(),
+            experiments: g.experiments.clone(),
+            current: Some(g.number) == latest,
+            changes: g.diff(generations.previous(g)),
+        })
+        .collect()
+}
+
+/// Summarise a change between generations, such as `+findutils -sudo-rs, 5 linked`.
+fn describe_changes(diff: &GenerationDiff) -> String {
+    let mut parts = Vec::new();
+    let experiments: Vec<String> = diff
+        .enabled
+        .iter()
+        .map(|e| format!("+{e}"))
+        .chain(diff.disabled.iter().map(|e| format!("-{e}")))
+        .collect();
+    if !experiments.is_empty() {
+        parts.push(experiments.join(" "));
+    }
+    if !diff.linked.is_empty() {
+        parts.push(format!("{} linked", diff.linked.len()));
+    }
+    if !diff.unlinked.is_empty() {
+        parts.push(format!("{} unlinked", diff.unlinked.len()));
+    }
+    match parts.is_empty() {
+        true => "state only".to_string(),
+        false => parts.join(", "),
+    }
+}
+
+/// Build a human-readable table of the generations.
+fn generations_table(listings: &[GenerationListing]) -> Table {
+    let mut table = Table::new(&["GENERATION", "CREATED", "COMMAND", "EXPERIMENTS", "CHANGES"]);
+    for l in listings {
+        let number = match l.current {
+            true => format!("{} (current)", l.number),
+            false => l.number.to_string(),
+        };
+        let experiments = match l.experiments.is_empty() {
+            true => "-".to_string(),
+            false => l.experiments.join(", "),
+        };
+        table.add_row(vec![
+            number,
+            calendar_time(l.created),
+            l.command.clone(),
+            experiments,
+            describe_changes(&l.changes),
+        ]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_listings() {
+        let generation = |number: u32, experiments: &[&str], links: &[&str]| Generation {
+            number,
+            command: "enable".to_string(),
+            experiments: experiments.iter().map(|e| e.to_string()).collect(),
+            links: links
+                .iter()
+                .map(|t| (PathBuf::from(t), PathBuf::from("/usr/lib/cargo/bin/x")))
+                .collect(),
+            ..Generation::default()
+        };
+        let generations = Generations {
+            generations: vec![
+                generation(3, &["sudo-rs"], &["/usr/bin/sudo", "/usr/bin/su"]),
+                generation(4, &["coreutils"], &["/usr/bin/ls"]),
+                generation(5, &["coreutils"], &["/usr/bin/ls"]),
+            ],
+        };
+
+        let listings = listings(&generations);
+        assert!(!listings[0].current && listings[2].current);
+        assert_eq!(describe_changes(&listings[0].changes), "+sudo-rs, 2 linked");
+        assert_eq!(
+            describe_changes(&listings[1].changes),
+            "+coreutils -sudo-rs, 1 linked, 2 unlinked"
+        );
+        assert_eq!(describe_changes(&listings[2].changes), "state only");
+    }
+}
@@ -0,0 +1,477 @@
+use crate::config::InventoryConfig;
+use crate::experiments::{RecordedVersions, all_experiments};
+use crate::utils::{OutputFormat, Table, Worker, structured};
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use hmac::{Hmac, KeyInit, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+/// The file identifying the host, which stays the same across changes of hostname.
+const MACHINE_ID_PATH: &str = "/etc/machine-id";
+
+/// The file holding the hostname of the host.
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+
+/// The prefix of a signature, naming the algorithm it was made with.
+const SIGNATURE_PREFIX: &str = "hmac-sha256:";
+
+/// Operations on the inventories of hosts.
+#[derive(Subcommand, Debug)]
+pub enum InventoryAction {
+    /// Aggregate the inventories collected from many hosts into a summary of the fleet.
+    Merge {
+        #[arg(required = true, help = "Inventories written by 'oxidizr inventory'")]
+        files: Vec<PathBuf>,
+    },
+}
+
+/// The state of oxidizr on a host, for collection alongside those of the other hosts in a fleet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Inventory {
+    /// The machine id of the host.
+    pub host: String,
+    pub hostname: String,
+    /// When the inventory was taken, in seconds since the Unix epoch.
+    pub taken: u64,
+    /// The version of oxidizr that took the inventory.
+    pub oxidizr: String,
+    /// The distribution release, such as `Ubuntu 24.04 (noble, server, amd64)`.
+    pub distribution: Option<String>,
+    /// The experiments whose replacements are installed.
+    pub experiments: Vec<InventoryExperiment>,
+}
+
+/// The state of an experiment on a host.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct InventoryExperiment {
+    pub name: String,
+    pub enabled: bool,
+    /// The installed version of the replacement package.
+    pub version: Option<String>,
+    /// Files that should link to a replacement binary, but don't.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<PathBuf>,
+    /// Binaries quarantined after failures implicated them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quarantined: Vec<String>,
+    /// When the experiment was last enabled, in seconds since the Unix epoch, if recorded.
+    pub enabled_at: Option<u64>,
+}
+
+/// An inventory as emitted, signed with the key shared by the fleet if one is configured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SignedInventory {
+    pub inventory: Inventory,
+    /// The HMAC-SHA256 of the inventory, such as `hmac-sha256:5f2b...`.
+    pub signature: Option<String>,
+}
+
+/// The state of oxidizr across a fleet, aggregated from the inventories of its hosts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub struct FleetSummary {
+    pub hosts: usize,
+    /// Whether the signature of each inventory was checked against the configured key.
+    pub verified: bool,
+    /// The number of hosts running each distribution release.
+    pub distributions: BTreeMap<String, usize>,
+    /// The state of each experiment across the fleet, by experiment name.
+    pub experiments: BTreeMap<String, ExperimentSummary>,
+    /// Inventories left out of the summary, and why.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<RejectedInventory>,
+}
+
+/// The state of an experiment across a fleet.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub struct ExperimentSummary {
+    /// The number of hosts on which the experiment is enabled.
+    pub enabled: usize,
+    /// The hosts on which files have drifted from their replacements.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drifted: Vec<String>,
+    /// The number of hosts with each version of the replacement package installed.
+    pub versions: BTreeMap<String, usize>,
+    /// The number of hosts on which each binary is quarantined.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub quarantined: BTreeMap<String, usize>,
+}
+
+/// An inventory that couldn't be included in a summary.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct RejectedInventory {
+    pub file: PathBuf,
+    pub reason: String,
+}
+
+/// Print the inventory of this host as a single line of JSON, or aggregate the inventories of
+/// many hosts into a summary of the fleet.
+pub fn inventory(
+    system: &dyn Worker,
+    config: &InventoryConfig,
+    action: Option<&InventoryAction>,
+    format: OutputFormat,
+) -> Result<()> {
+    let key = signing_key(system, config)?;
+    match action {
+        None => {
+            let inventory = take_inventory(system)?;
+            let signature = key.as_deref().map(|k| sign(&inventory, k)).transpose()?;
+            println!(
+                "{}",
+                serde_json::to_string(&SignedInventory {
+                    inventory,
+                    signature
+                })?
+            );
+        }
+        Some(InventoryAction::Merge { files }) => {
+            let summary = merge(system, files, key.as_deref())?;
+            match format {
+                OutputFormat::Human => print!("{}", fleet_report(&summary)),
+                format => print!("{}", structured(format, &summary)?),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Take the inventory of this host.
+fn take_inventory(system: &dyn Worker) -> Result<Inventory> {
+    let read = |path: &str| -> Result<String> {
+        Ok(system
+            .read_file(Path::new(path))?
+            .with_context(|| format!("{path} doesn't exist"))?
+            .trim()
+            .to_string())
+    };
+    let recorded = RecordedVersions::load(system).unwrap_or_default();
+    let experiments = all_experiments(system)
+        .iter()
+        .map(|e| e.status())
+        .filter(|s| s.installed)
+        .map(|s| InventoryExperiment {
+            enabled_at: recorded
+                .experiments
+                .get(&s.name)
+                .filter(|_| s.enabled)
+                .map(|r| r.recorded),
+            name: s.name,
+            enabled: s.enabled,
+            version: s.version.map(|v| v.to_string()),
+            drift: s.drift,
+            quarantined: s.quarantined,
+        })
+        .collect();
+
+    Ok(Inventory {
+        host: read(MACHINE_ID_PATH)?,
+        hostname: read(HOSTNAME_PATH)?,
+        taken: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        oxidizr: env!("CARGO_PKG_VERSION").to_string(),
+        distribution: system.distribution().ok().map(|d| d.describe()),
+        experiments,
+    })
+}
+
+/// Read the key that inventories are signed with, if one is configured.
+fn signing_key(system: &dyn Worker, config: &InventoryConfig) -> Result<Option<Vec<u8>>> {
+    let Some(path) = &config.key_file else {
+        return Ok(None);
+    };
+    let key = system
+        .read_file(path)?
+        .with_context(|| format!("the inventory key {} doesn't exist", path.display()))?;
+    let key = key.trim();
+    anyhow::ensure!(
+        !key.is_empty(),
+        "the inventory key {} is empty",
+        path.display()
+    );
+    Ok(Some(key.as_bytes().to_vec()))
+}
+
+/// Sign an inventory. The signature covers the inventory as JSON, with its fields in a canonical
+/// order, so that it can be checked however the document has been reformatted since.
+fn sign(inventory: &Inventory, key: &[u8]) -> Result<String> {
+    let digest: String = mac(inventory, key)?
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(format!("{SIGNATURE_PREFIX}{digest}"))
+}
+
+/// Check the signature of an inventory, in constant time.
+fn verify(inventory: &Inventory, key: &[u8], signature: &str) -> Result<bool> {
+    let Some(digest) = signature
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(decode_hex)
+    else {
+        return Ok(false);
+    };
+    Ok(mac(inventory, key)?.verify_slice(&digest).is_ok())
+}
+
+fn mac(inventory: &Inventory, key: &[u8]) -> Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(serde_json::to_value(inventory)?.to_string().as_bytes());
+    Ok(mac)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Aggregate inventories into a summary of the fleet. When a key is given, inventories that
+/// aren't signed with it are rejected; inventories that can't be read are rejected either way.
+/// Only the latest inventory of each host is counted.
+fn merge(system: &dyn Worker, files: &[PathBuf], key: Option<&[u8]>) -> Result<FleetSummary> {
+    let mut summary = FleetSummary {
+        verified: key.is_some(),
+        ..FleetSummary::default()
+    };
+    let mut latest: BTreeMap<String, Inventory> = BTreeMap::new();
+
+    for file in files {
+        match read_inventory(system, file, key) {
+            Ok(inventory) => {
+                if latest
+                    .get(&inventory.host)
+                    .is_none_or(|i| i.taken < inventory.taken)
+                {
+                    latest.insert(inventory.host.clone(), inventory);
+                }
+            }
+            Err(e) => {
+                warn!("Leaving out the inventory {}: {e:#}", file.display());
+                summary.rejected.push(RejectedInventory {
+                    file: file.clone(),
+                    reason: format!("{e:#}"),
+                });
+            }
+        }
+    }
+
+    summary.hosts = latest.len();
+    for inventory in latest.into_values() {
+        let distribution = inventory.distribution.unwrap_or("unknown".to_string());
+        *summary.distributions.entry(distribution).or_default() += 1;
+        for e in inventory.experiments {
+            let experiment = summary.experiments.entry(e.name).or_default();
+            if e.enabled {
+                experiment.enabled += 1;
+            }
+            if !e.drift.is_empty() {
+                experiment.drifted.push(inventory.hostname.clone());
+            }
+            for binary in e.quarantined {
+                *experiment.quarantined.entry(binary).or_default() += 1;
+            }
+            let version = e.version.unwrap_or("unknown".to_string());
+            *experiment.versions.entry(version).or_default() += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Read an inventory, checking its signature if a key is given.
+fn read_inventory(system: &dyn Worker, file: &Path, key: Option<&[u8]>) -> Result<Inventory> {
+    let contents = system.read_file(file)?.context("the file doesn't exist")?;
+    let signed: SignedInventory =
+        serde_json::from_str(&contents).context("the file isn't an inventory")?;
+    if let Some(key) = key {
+        let signature = signed.signature.as_deref().context("it isn't signed")?;
+        anyhow::ensure!(
+            verify(&signed.inventory, key, signature)?,
+            "its signature doesn't match the configured key"
+        );
+    }
+    Ok(signed.inventory)
+}
+
+/// Render a summary of the fleet as a table of experiments.
+fn fleet_report(summary: &FleetSummary) -> String {
+    let mut table = Table::new(&["EXPERIMENT", "ENABLED", "DRIFTED", "VERSIONS"]);
+    for (name, e) in &summary.experiments {
+        let versions: Vec<String> = e
+            .versions
+            .iter()
+            .map(|(version, hosts)| format!("{version} ({hosts})"))
+            .collect();
+        table.add_row(vec![
+            name.clone(),
+            format!("{}/{}", e.enabled, summary.hosts),
+            e.drifted.len().to_string(),
+            versions.join(", "),
+        ]);
+    }
+
+    let distributions: Vec<String> = summary
+        .distributions
+        .iter()
+        .map(|(distribution, hosts)| format!("{distribution} ({hosts})"))
+        .collect();
+    let mut report = format!(
+        "{} host(s){}: {}\n\n{table}",
+        summary.hosts,
+        match summary.verified {
+            true => ", signatures verified",
+            false => "",
+        },
+        distributions.join(", ")
+    );
+    for e in summary
+        .experiments
+        .iter()
+        .filter(|(_, e)| !e.drifted.is_empty())
+    {
+        report.push_str(&format!("\nDrift in {}: {}", e.0, e.1.drifted.join(", ")));
+    }
+    for (name, e) in summary
+        .experiments
+        .iter()
+        .filter(|(_, e)| !e.quarantined.is_empty())
+    {
+        let quarantined: Vec<String> = e
+            .quarantined
+            .iter()
+            .map(|(binary, hosts)| format!("{binary} ({hosts})"))
+            .collect();
+        report.push_str(&format!(
+            "\nQuarantined in {name}: {}",
+            quarantined.join(", ")
+        ));
+    }
+    if !summary.rejected.is_empty() {
+        report.push_str(&format!(
+            "\n{} inventory file(s) left out, see the warnings above\n",
+            summary.rejected.len()
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    fn inventory(host: &str, taken: u64, version: &str, drift: &[&str]) -> Inventory {
+        Inventory {
+            host: host.to_string(),
+            hostname: format!("{host}.example.com"),
+            taken,
+            oxidizr: "1.1.0".to_string(),
+            distribution: Some("Ubuntu 24.04".to_string()),
+            experiments: vec![InventoryExperiment {
+                name: "coreutils".to_string(),
+                enabled: true,
+                version: Some(version.to_string()),
+                drift: drift.iter().map(PathBuf::from).collect(),
+                quarantined: Vec::new(),
+                enabled_at: Some(taken - 60),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_take_inventory() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            (MACHINE_ID_PATH, "0123456789abcdef\n", false),
+            (HOSTNAME_PATH, "web-1\n", false),
+        ]);
+        runner.mock_install_package("sudo-rs");
+
+        let inventory = take_inventory(&runner).unwrap();
+        assert_eq!(inventory.host, "0123456789abcdef");
+        assert_eq!(inventory.hostname, "web-1");
+        let names: Vec<&str> = inventory
+            .experiments
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, ["sudo-rs"]);
+        assert!(!inventory.experiments[0].enabled);
+    }
+
+    #[test]
+    fn test_sign() {
+        let inventory = inventory("a", 1000, "0.2.0-1", &[]);
+        let signature = sign(&inventory, b"fleet").unwrap();
+        assert!(signature.starts_with(SIGNATURE_PREFIX));
+        assert!(verify(&inventory, b"fleet", &signature).unwrap());
+        assert!(!verify(&inventory, b"other", &signature).unwrap());
+        assert!(!verify(&inventory, b"fleet", "hmac-sha256:zz").unwrap());
+
+        let mut tampered = inventory.clone();
+        tampered.experiments[0].enabled = false;
+        assert!(!verify(&tampered, b"fleet", &signature).unwrap());
+    }
+
+    #[test]
+    fn test_merge() {
+        let runner = MockSystem::default();
+        let key = b"fleet".as_slice();
+        let write = |file: &str, inventory: Inventory, key: &[u8]| {
+            let signature = Some(sign(&inventory, key).unwrap());
+            let signed = SignedInventory {
+                inventory,
+                signature,
+            };
+            runner
+                .write_file(Path::new(file), &serde_json::to_string(&signed).unwrap())
+                .unwrap();
+            PathBuf::from(file)
+        };
+        let files = vec![
+            write("/a-old.json", inventory("a", 1000, "0.1.0-1", &[]), key),
+            write("/a.json", inventory("a", 2000, "0.2.0-1", &[]), key),
+            write(
+                "/b.json",
+                inventory("b", 2000, "0.2.0-1", &["/usr/bin/ls"]),
+                key,
+            ),
+            write("/c.json", inventory("c", 2000, "0.2.0-1", &[]), b"other"),
+            PathBuf::from("/missing.json"),
+        ];
+
+        let summary = merge(&runner, &files, Some(key)).unwrap();
+        assert_eq!(summary.hosts, 2);
+        assert!(summary.verified);
+        assert_eq!(summary.distributions["Ubuntu 24.04"], 2);
+        let coreutils = &summary.experiments["coreutils"];
+        assert_eq!(coreutils.enabled, 2);
+        assert_eq!(coreutils.drifted, ["b.example.com"]);
+        assert_eq!(
+            coreutils.versions,
+            BTreeMap::from([("0.2.0-1".to_string(), 2)])
+        );
+        let rejected: Vec<&Path> = summary.rejected.iter().map(|r| r.file.as_path()).collect();
+        assert_eq!(rejected, [Path::new("/c.json"), Path::new("/missing.json")]);
+
+        // Without a key, signatures aren't checked.
+        let summary = merge(&runner, &files, None).unwrap();
+        assert_eq!(summary.hosts, 3);
+        assert!(!summary.verified);
+
+        let report = fleet_report(&summary);
+        assert!(report.starts_with("3 host(s): Ubuntu 24.04 (3)\n"));
+        assert!(report.contains("Drift in coreutils: b.example.com"));
+    }
+}
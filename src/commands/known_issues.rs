@@ -0,0 +1,51 @@
+use crate::experiments::{KnownIssue, KnownIssues};
+use crate::utils::{OutputFormat, Table, Worker, structured};
+use anyhow::Result;
+use tracing::info;
+
+/// Print the database of known issues in replacement packages. When `refresh` is given, the
+/// latest database is first downloaded from that URL, and used from then on.
+pub fn known_issues(
+    system: &dyn Worker,
+    format: OutputFormat,
+    refresh: Option<&str>,
+) -> Result<()> {
+    let issues = match refresh {
+        Some(url) => {
+            let issues = KnownIssues::refresh(system, url)?;
+            info!("Refreshed the known issues database from {}", url);
+            issues
+        }
+        None => KnownIssues::load(system),
+    };
+
+    match format {
+        OutputFormat::Human => print!("{}", issues_table(&issues.issue)),
+        format => print!("{}", structured(format, &issues.issue)?),
+    }
+    Ok(())
+}
+
+/// Build a human-readable table of known issues.
+fn issues_table(issues: &[KnownIssue]) -> Table {
+    let mut table = Table::new(&["PACKAGE", "BINARY", "AFFECTED", "SUMMARY"]);
+    for issue in issues {
+        table.add_row(vec![
+            issue.package.clone(),
+            issue.binary.clone().unwrap_or("-".to_string()),
+            affected_versions(issue),
+            issue.summary.clone(),
+        ]);
+    }
+    table
+}
+
+/// Describe the range of versions affected by an issue.
+fn affected_versions(issue: &KnownIssue) -> String {
+    match (&issue.introduced, &issue.fixed) {
+        (Some(introduced), Some(fixed)) => format!(">= {introduced}, < {fixed}"),
+        (Some(introduced), None) => format!(">= {introduced}"),
+        (None, Some(fixed)) => format!("< {fixed}"),
+        (None, None) => "all".to_string(),
+    }
+}
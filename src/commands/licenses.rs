@@ -0,0 +1,97 @@
+use crate::experiments::{Experiment, all_experiments};
+use crate::utils::{OutputFormat, Table, Worker, structured};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The change of license implied by enabling an experiment.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct LicenseChange {
+    pub experiment: String,
+    /// The package providing the original binaries, and its SPDX license expression.
+    pub replaced_package: String,
+    pub replaced_license: String,
+    /// The package providing the replacement binaries, and its SPDX license expression.
+    pub package: String,
+    pub license: String,
+    pub upstream: String,
+}
+
+impl LicenseChange {
+    fn new(experiment: &Experiment) -> Self {
+        let metadata = experiment.metadata();
+        Self {
+            experiment: experiment.name(),
+            replaced_package: metadata.replaces.to_string(),
+            replaced_license: metadata.replaced_license.to_string(),
+            package: experiment.package(),
+            license: metadata.license.to_string(),
+            upstream: metadata.upstream.to_string(),
+        }
+    }
+}
+
+/// Print the change of license implied by each experiment enabled on the system, for review
+/// before rolling oxidizr out. With `markdown`, the report is rendered as a Markdown table for
+/// inclusion in documents.
+pub fn licenses(system: &dyn Worker, format: OutputFormat, markdown: bool) -> Result<()> {
+    let changes: Vec<LicenseChange> = all_experiments(system)
+        .iter()
+        .filter(|e| e.status().enabled)
+        .map(LicenseChange::new)
+        .collect();
+
+    match format {
+        OutputFormat::Human if changes.is_empty() => println!("No experiments are enabled."),
+        OutputFormat::Human if markdown => print!("{}", license_table(&changes).markdown()),
+        OutputFormat::Human => print!("{}", license_table(&changes)),
+        format => print!("{}", structured(format, &changes)?),
+    }
+
+    Ok(())
+}
+
+/// Build a table of license changes.
+fn license_table(changes: &[LicenseChange]) -> Table {
+    let mut table = Table::new(&[
+        "EXPERIMENT",
+        "REPLACES",
+        "LICENSE",
+        "REPLACEMENT",
+        "LICENSE",
+    ]);
+    for c in changes {
+        table.add_row(vec![
+            c.experiment.clone(),
+            c.replaced_package.clone(),
+            c.replaced_license.clone(),
+            c.package.clone(),
+            c.license.clone(),
+        ]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_license_changes() {
+        let runner = MockSystem::default();
+        let changes: Vec<LicenseChange> = all_experiments(&runner)
+            .iter()
+            .map(LicenseChange::new)
+            .collect();
+
+        assert_eq!(changes[0].replaced_license, "GPL-3.0-or-later");
+        assert_eq!(changes[0].license, "MIT");
+        assert_eq!(
+            license_table(&changes[..1]).markdown(),
+            "| EXPERIMENT | REPLACES | LICENSE | REPLACEMENT | LICENSE |\n\
+            | --- | --- | --- | --- | --- |\n\
+            | coreutils | coreutils | GPL-3.0-or-later | rust-coreutils | MIT |\n"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::experiments::{
+    Channel, ExperimentStatus, Installs, LinkStrategy, Trial, all_experiments, calendar_time,
+};
+use crate::utils::{OutputFormat, Table, Worker, Wsl, detect_wsl, structured, yes_no};
+use anyhow::Result;
+
+/// Print the status of all known experiments on the system.
+///
+/// This doesn't require root: checks that need privileges the user lacks are skipped, and noted
+/// alongside the results, so that the state of a host can be audited from a normal account.
+pub fn list(system: &dyn Worker, format: OutputFormat) -> Result<()> {
+    let statuses: Vec<ExperimentStatus> =
+        all_experiments(system).iter().map(|e| e.status()).collect();
+
+    match format {
+        OutputFormat::Human => {
+            print!("{}", status_table(&statuses));
+            let mut notes: Vec<String> = derivative_note(system).into_iter().collect();
+            notes.extend(detect_wsl(system).map(wsl_note));
+            notes.extend(statuses.iter().filter_map(|s| {
+                let reason = s.incompatibility.as_ref()?;
+                Some(format!("{}: incompatible: {reason}", s.name))
+            }));
+            notes.extend(
+                statuses
+                    .iter()
+                    .filter(|s| !s.pinned.is_empty())
+                    .map(|s| format!("{}: pinned to GNU: {}", s.name, s.pinned.join(", "))),
+            );
+            notes.extend(
+                statuses
+                    .iter()
+                    .filter(|s| !s.quarantined.is_empty())
+                    .map(|s| {
+                        format!(
+                            "{}: quarantined after failures: {}. See 'oxidizr quarantine'.",
+                            s.name,
+                            s.quarantined.join(", ")
+                        )
+                    }),
+            );
+            notes.extend(
+                statuses
+                    .iter()
+                    .filter(|s| s.channel != Channel::Stable)
+                    .map(|s| {
+                        format!(
+                            "{}: installed from the {} upstream channel",
+                            s.name, s.channel
+                        )
+                    }),
+            );
+            notes.extend(
+                statuses
+                    .iter()
+                    .filter(|s| s.enabled && s.strategy == LinkStrategy::Shim)
+                    .map(|s| format!("{}: replaced with shims that fall back to GNU", s.name)),
+            );
+            notes.extend(
+                Installs::load(system)
+                    .map(|i| i.experiments.into_keys().collect::<Vec<_>>())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|name| {
+                        format!(
+                            "{name}: installed without being activated. Run 'oxidizr activate' to \
+                            put it in place."
+                        )
+                    }),
+            );
+            notes.extend(Trial::load(system).ok().flatten().map(|t| {
+                format!(
+                    "On trial until {}: {}. Run 'oxidizr keep' to keep them enabled.",
+                    calendar_time(t.expires),
+                    t.experiments.join(", ")
+                )
+            }));
+            notes.extend(unchecked_notes(&statuses, uzers::get_current_uid() == 0));
+            if !notes.is_empty() {
+                println!();
+                notes.iter().for_each(|note| println!("{note}"));
+            }
+        }
+        format => print!("{}", structured(format, &statuses)?),
+    }
+
+    Ok(())
+}
+
+/// Describe the derivative the system runs, if any, as compatibility is judged against the release
+/// it is based on, noting when that isn't supported at all.
+fn derivative_note(system: &dyn Worker) -> Option<String> {
+    let distribution = system.distribution().ok()?;
+    let derivative = distribution.derivative.as_ref()?;
+    Some(match distribution.id.as_str() {
+        "Ubuntu" => format!(
+            "This system runs {derivative}, a derivative of {}: compatibility is that of {0}.",
+            distribution.base()
+        ),
+        _ => format!(
+            "This system runs {derivative}, an unsupported derivative of {}.",
+            distribution.base()
+        ),
+    })
+}
+
+/// Describe how running under WSL affects oxidizr.
+fn wsl_note(wsl: Wsl) -> String {
+    match wsl {
+        Wsl::Wsl1 => "This system runs under WSL 1, which has no systemd: replacements can't be \
+            scheduled for the next boot, and services aren't reported for restart."
+            .to_string(),
+        Wsl::Wsl2 => "This system runs under WSL 2.".to_string(),
+    }
+}
+
+/// Describe the checks that could not be made for each experiment, suggesting that they be run
+/// as root when they were made without privileges.
+fn unchecked_notes(statuses: &[ExperimentStatus], privileged: bool) -> Vec<String> {
+    let mut notes: Vec<String> = statuses
+        .iter()
+        .flat_map(|s| {
+            s.unchecked
+                .iter()
+                .map(|note| format!("{}: {}", s.name, note))
+        })
+        .collect();
+    if !notes.is_empty() && !privileged {
+        notes.push("Some checks need root: run 'sudo oxidizr list' for complete results.".into());
+    }
+    notes
+}
+
+/// Build a human-readable table from a list of experiment statuses.
+fn status_table(statuses: &[ExperimentStatus]) -> Table {
+    let mut table = Table::new(&[
+        "EXPERIMENT",
+        "MATURITY",
+        "COMPATIBLE",
+        "INSTALLED",
+        "ENABLED",
+        "VERSION",
+        "DRIFT",
+    ]);
+
+    for s in statuses {
+        let drift = match s.drift.len() {
+            0 => "-".to_string(),
+            1 => "1 file".to_string(),
+            n => format!("{n} files"),
+        };
+
+        table.add_row(vec![
+            s.name.clone(),
+            s.metadata.maturity.to_string(),
+            yes_no(s.compatible),
+            yes_no(s.installed),
+            yes_no(s.enabled),
+            s.version
+                .as_ref()
+                .map_or("-".to_string(), |v| v.to_string()),
+            drift,
+        ]);
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{Maturity, Metadata};
+
+    fn status(name: &str, unchecked: Vec<String>) -> ExperimentStatus {
+        ExperimentStatus {
+            name: name.to_string(),
+            metadata: Metadata {
+                upstream: "https://github.com/uutils/coreutils",
+                license: "MIT",
+                maturity: Maturity::Stable,
+                maintainer: "uutils",
+                replaces: "coreutils",
+                replaced_license: "GPL-3.0-or-later",
+            },
+            compatible: true,
+            incompatibility: None,
+            installed: true,
+            enabled: true,
+            version: None,
+            channel: Channel::Stable,
+            drift: Vec::new(),
+            strategy: LinkStrategy::Symlink,
+            pinned: Vec::new(),
+            quarantined: Vec::new(),
+            unchecked,
+        }
+    }
+
+    #[test]
+    fn test_unchecked_notes() {
+        let statuses = vec![
+            status("coreutils", Vec::new()),
+            status(
+                "sudo-rs",
+                vec!["replaced files could not be checked: Permission denied".to_string()],
+            ),
+        ];
+        assert_eq!(
+            unchecked_notes(&statuses, false),
+            vec![
+                "sudo-rs: replaced files could not be checked: Permission denied",
+                "Some checks need root: run 'sudo oxidizr list' for complete results.",
+            ]
+        );
+        assert_eq!(unchecked_notes(&statuses, true).len(), 1);
+        assert!(unchecked_notes(&statuses[..1], false).is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::experiments::all_experiments;
+use crate::utils::{Distribution, OutputFormat, Table, Worker, structured, yes_no};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// The distribution releases on which a single experiment is supported.
+#[derive(Debug, Serialize)]
+struct ExperimentSupport {
+    name: String,
+    supported_releases: Vec<Distribution>,
+}
+
+/// Print which experiments are supported on which distribution releases.
+pub fn matrix(system: &dyn Worker, format: OutputFormat) -> Result<()> {
+    // Experiments that aren't tied to a release, such as overrides, are supported everywhere.
+    let support: Vec<ExperimentSupport> = all_experiments(system)
+        .iter()
+        .filter(|e| !e.supported_releases().is_empty())
+        .map(|e| ExperimentSupport {
+            name: e.name(),
+            supported_releases: e.supported_releases(),
+        })
+        .collect();
+
+    match format {
+        OutputFormat::Human => print!("{}", matrix_table(&support)),
+        format => print!("{}", structured(format, &support)?),
+    }
+
+    Ok(())
+}
+
+/// Build a table with a row per known distribution release, and a column per experiment.
+fn matrix_table(support: &[ExperimentSupport]) -> Table {
+    let releases: BTreeSet<&Distribution> = support
+        .iter()
+        .flat_map(|s| s.supported_releases.iter())
+        .collect();
+
+    let mut headers = vec!["DISTRIBUTION", "RELEASE"];
+    headers.extend(support.iter().map(|s| s.name.as_str()));
+    let mut table = Table::new(&headers);
+
+    for release in releases {
+        let mut row = vec![release.id.clone(), release.release.clone()];
+        row.extend(
+            support
+                .iter()
+                .map(|s| yes_no(s.supported_releases.contains(release))),
+        );
+        table.add_row(row);
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_table() {
+        let support = vec![
+            ExperimentSupport {
+                name: "coreutils".to_string(),
+                supported_releases: vec![
+                    Distribution::new("Ubuntu", "24.10"),
+                    Distribution::new("Ubuntu", "24.04"),
+                ],
+            },
+            ExperimentSupport {
+                name: "diffutils".to_string(),
+                supported_releases: vec![Distribution::new("Ubuntu", "24.10")],
+            },
+        ];
+
+        let expected = "\
+DISTRIBUTION  RELEASE  coreutils  diffutils
+Ubuntu        24.04    yes        no
+Ubuntu        24.10    yes        yes
+";
+        assert_eq!(matrix_table(&support).to_string(), expected);
+    }
+}
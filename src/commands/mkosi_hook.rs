@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The environment variable through which mkosi passes the root of the image being built to its
+/// postinstall scripts.
+const BUILDROOT_ENV: &str = "BUILDROOT";
+
+/// Determine the root of the image to modify: the `--root` argument if given, otherwise the
+/// image root passed by mkosi.
+pub fn image_root(root: Option<PathBuf>) -> Result<PathBuf> {
+    root.or_else(|| std::env::var_os(BUILDROOT_ENV).map(PathBuf::from))
+        .filter(|root| !root.as_os_str().is_empty())
+        .with_context(|| format!("no image root to modify: pass --root or set ${BUILDROOT_ENV}"))
+}
+
+/// Confine the rest of the process to the root of an image, so that packages are installed into
+/// the image and its files are replaced, rather than the host's. A root of `/` is left as is,
+/// for hooks that already run inside the image (such as through `mkosi-chroot`).
+pub fn enter_root(root: &Path) -> Result<()> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("image root {} does not exist", root.display()))?;
+    if root == Path::new("/") {
+        return Ok(());
+    }
+
+    info!("Entering image root {}", root.display());
+    std::os::unix::fs::chroot(&root)
+        .with_context(|| format!("unable to change root to {}", root.display()))?;
+    std::env::set_current_dir("/")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_root() {
+        assert_eq!(
+            image_root(Some(PathBuf::from("/work/root"))).unwrap(),
+            PathBuf::from("/work/root")
+        );
+        assert!(image_root(Some(PathBuf::new())).is_err());
+    }
+}
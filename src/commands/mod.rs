@@ -0,0 +1,41 @@
+mod bisect;
+mod check;
+mod compare;
+mod deploy;
+mod gc;
+mod generate;
+mod generations;
+mod inventory;
+mod known_issues;
+mod licenses;
+mod list;
+mod matrix;
+mod mkosi_hook;
+mod provision;
+mod quarantine;
+mod rescue;
+mod schema;
+mod self_update;
+mod usage;
+mod version;
+
+pub use bisect::{BisectReport, bisect};
+pub use check::{CheckReport, CheckState, check};
+pub use compare::{BinaryComparison, compare_one};
+pub use deploy::{Deployment, deploy};
+pub use gc::{Artifact, collect_garbage, find_garbage, print_garbage};
+pub use generate::{GenerateTarget, generate};
+pub use generations::{GenerationListing, generations};
+pub use inventory::{FleetSummary, InventoryAction, SignedInventory, inventory};
+pub use known_issues::known_issues;
+pub use licenses::{LicenseChange, licenses};
+pub use list::list;
+pub use matrix::matrix;
+pub use mkosi_hook::{enter_root, image_root};
+pub use provision::Provisioning;
+pub use quarantine::{QuarantineAction, quarantine};
+pub use rescue::{RESCUE_DIRECTORIES, rescue, rescue_directories, update_rescue_script};
+pub use schema::{SchemaTarget, schema};
+pub use self_update::self_update;
+pub use usage::usage;
+pub use version::version;
@@ -0,0 +1,155 @@
+/// The base URL from which release assets are downloaded.
+const RELEASES_URL: &str = concat!(env!("CARGO_PKG_REPOSITORY"), "/releases/download");
+
+/// A provisioning step that installs a pinned release of oxidizr and enables experiments with a
+/// fixed set of flags, rendered into the snippet formats understood by provisioning tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provisioning {
+    /// The release of oxidizr to install, such as `1.1.0`.
+    pub release: String,
+    /// The arguments passed to oxidizr to enable the experiments.
+    pub enable_args: Vec<String>,
+}
+
+impl Provisioning {
+    /// Create a new `Provisioning` step.
+    pub fn new(release: &str, enable_args: Vec<String>) -> Self {
+        Self {
+            release: release.trim_start_matches('v').to_string(),
+            enable_args,
+        }
+    }
+
+    /// The packages that must be installed before the script runs: the tools used to download
+    /// the release, and a rescue toolset if coreutils is to be replaced.
+    pub fn packages(&self) -> Vec<&'static str> {
+        let mut packages = vec!["ca-certificates", "curl"];
+        if self.enable_args.iter().any(|a| a == "coreutils") {
+            packages.push(crate::experiments::RESCUE_PACKAGE);
+        }
+        packages
+    }
+
+    /// A POSIX shell script that downloads the release for the running architecture, verifies
+    /// its checksum, installs it to /usr/bin and enables the experiments.
+    pub fn script(&self) -> String {
+        format!(
+            "set -e\n\
+            arch=\"$(uname -m)\"\n\
+            base=\"{RELEASES_URL}/v{release}\"\n\
+            cd \"$(mktemp -d)\"\n\
+            curl -fsSLO \"$base/oxidizr_Linux_$arch.tar.gz\"\n\
+            curl -fsSLO \"$base/checksums.txt\"\n\
+            sha256sum --ignore-missing -c checksums.txt\n\
+            tar -xzf \"oxidizr_Linux_$arch.tar.gz\" oxidizr\n\
+            install -Dm755 oxidizr /usr/bin/oxidizr\n\
+            oxidizr {args}\n",
+            release = self.release,
+            args = self.enable_args.join(" "),
+        )
+    }
+
+    /// A cloud-config document that installs the required packages and runs the script on first
+    /// boot.
+    pub fn cloud_config(&self) -> String {
+        let mut config = format!(
+            "#cloud-config\n# Generated by oxidizr {}\npackages:\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        for package in self.packages() {
+            config.push_str(&format!("  - {package}\n"));
+        }
+        config.push_str("runcmd:\n  - |\n");
+        for line in self.script().lines() {
+            config.push_str(&format!("    {line}\n"));
+        }
+        config
+    }
+
+    /// An Anaconda kickstart `%post` section that runs the script in the installed system. A
+    /// kickstart may only have one `%packages` section, so the packages required are listed in a
+    /// comment for adding to it.
+    pub fn kickstart(&self) -> String {
+        format!(
+            "# Generated by oxidizr {}\n\
+            # Add these packages to the %packages section: {}\n\
+            %post --erroronfail --log=/var/log/oxidizr-post.log\n\
+            {}%end\n",
+            env!("CARGO_PKG_VERSION"),
+            self.packages().join(" "),
+            self.script(),
+        )
+    }
+
+    /// debian-installer preseed directives that install the required packages and run the script
+    /// in the target system at the end of the installation.
+    pub fn preseed(&self) -> String {
+        let commands: Vec<String> = self
+            .script()
+            .lines()
+            .map(|line| format!("    {line}"))
+            .collect();
+        format!(
+            "# Generated by oxidizr {}\n\
+            # pkgsel/include replaces any existing value: merge the packages if one is set.\n\
+            d-i pkgsel/include string {}\n\
+            d-i preseed/late_command string in-target sh -c ' \\\n{}'\n",
+            env!("CARGO_PKG_VERSION"),
+            self.packages().join(" "),
+            commands.join("; \\\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provisioning() -> Provisioning {
+        Provisioning::new(
+            "v1.1.0",
+            ["enable", "--yes", "--experiments", "coreutils", "sudo-rs"]
+                .map(String::from)
+                .to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_script() {
+        let script = provisioning().script();
+        assert!(
+            script
+                .contains("base=\"https://github.com/jnsgruk/oxidizr/releases/download/v1.1.0\"\n")
+        );
+        assert!(script.ends_with("oxidizr enable --yes --experiments coreutils sudo-rs\n"));
+    }
+
+    #[test]
+    fn test_cloud_config() {
+        let config = provisioning().cloud_config();
+        assert!(config.starts_with("#cloud-config\n"));
+        assert!(config.contains(
+            "packages:\n  - ca-certificates\n  - curl\n  - busybox-static\nruncmd:\n  - |\n    set -e\n"
+        ));
+        assert!(config.ends_with("    oxidizr enable --yes --experiments coreutils sudo-rs\n"));
+    }
+
+    #[test]
+    fn test_kickstart() {
+        let kickstart = provisioning().kickstart();
+        assert!(kickstart.contains("# Add these packages to the %packages section: ca-certificates curl busybox-static\n%post --erroronfail --log=/var/log/oxidizr-post.log\nset -e\n"));
+        assert!(
+            kickstart.ends_with("oxidizr enable --yes --experiments coreutils sudo-rs\n%end\n")
+        );
+    }
+
+    #[test]
+    fn test_preseed() {
+        let preseed = provisioning().preseed();
+        assert!(
+            preseed.contains("d-i pkgsel/include string ca-certificates curl busybox-static\n")
+        );
+        assert!(preseed.contains("d-i preseed/late_command string in-target sh -c ' \\\n    set -e; \\\n    arch=\"$(uname -m)\"; \\\n"));
+        assert!(preseed.ends_with("    oxidizr enable --yes --experiments coreutils sudo-rs'\n"));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::experiments::{QUARANTINE_STRIKES, Quarantine, calendar_time};
+use crate::utils::{OutputFormat, Table, Worker, structured, update_state};
+use anyhow::Result;
+use clap::Subcommand;
+
+/// Operations on the binaries quarantined on this host.
+#[derive(Subcommand, Debug)]
+pub enum QuarantineAction {
+    /// Clear the quarantine of binaries, forgetting the failures that implicated them, so that
+    /// the next reapply replaces them again.
+    Clear {
+        #[arg(
+            required_unless_present = "all",
+            help = "The binaries to clear, such as 'sort'"
+        )]
+        binaries: Vec<String>,
+        #[arg(long, default_value_t = false, help = "Clear every binary")]
+        all: bool,
+    },
+}
+
+/// Print the binaries implicated in failures on this host, and which of them are quarantined, or
+/// clear their quarantine.
+pub fn quarantine(
+    system: &dyn Worker,
+    action: Option<&QuarantineAction>,
+    format: OutputFormat,
+) -> Result<()> {
+    let Some(QuarantineAction::Clear { binaries, all }) = action else {
+        let quarantine = Quarantine::load(system)?;
+        match format {
+            OutputFormat::Human if quarantine.binaries.is_empty() => {
+                println!("No binaries have been implicated in failures.")
+            }
+            OutputFormat::Human => print!("{}", quarantine_table(&quarantine)),
+            format => print!("{}", structured(format, &quarantine)?),
+        }
+        return Ok(());
+    };
+
+    let mut cleared = Vec::new();
+    update_state(system, |q: &mut Quarantine| {
+        let binaries = match all {
+            true => q.binaries.keys().cloned().collect(),
+            false => binaries.clone(),
+        };
+        for binary in binaries {
+            match q.clear(&binary) {
+                true => cleared.push(binary),
+                false => println!("'{binary}' isn't implicated in any failures."),
+            }
+        }
+        Ok(())
+    })?;
+    if !cleared.is_empty() {
+        println!(
+            "Cleared {}. Run 'oxidizr reapply' to replace them again.",
+            cleared.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Build a human-readable table of the binaries implicated in failures.
+fn quarantine_table(quarantine: &Quarantine) -> Table {
+    let mut table = Table::new(&[
+        "BINARY",
+        "EXPERIMENT",
+        "STRIKES",
+        "QUARANTINED",
+        "IMPLICATED BY",
+        "LATEST FAILURE",
+    ]);
+    for (binary, s) in &quarantine.binaries {
+        let implicated_by: Vec<String> = s.implicated_by.iter().map(|i| i.to_string()).collect();
+        table.add_row(vec![
+            binary.clone(),
+            s.experiment.clone(),
+            format!("{}/{QUARANTINE_STRIKES}", s.strikes.min(QUARANTINE_STRIKES)),
+            s.quarantined.map_or("-".to_string(), calendar_time),
+            implicated_by.join(", "),
+            s.reason.clone(),
+        ]);
+    }
+    table
+}
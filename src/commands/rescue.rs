@@ -0,0 +1,385 @@
+use crate::experiments::{BOOT_SCHEDULE_PATH, DIRECTORIES_PATH, Directories};
+use crate::journal::JOURNAL_PATH;
+use crate::utils::{BACKUP_MANIFEST, Worker, backup_original, manifest_key};
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// The directories that may contain files replaced by oxidizr, and their backups, unless others
+/// are configured.
+pub const RESCUE_DIRECTORIES: &[&str] = &["/usr/bin", "/usr/sbin", "/bin", "/sbin"];
+
+/// The directories searched for backups: `RESCUE_DIRECTORIES`, along with those configured for
+/// any experiment in the record at `record`, such as `/usr/local/bin`. The record is read with
+/// nothing but the standard library, so that it works however broken the system is; a record
+/// that can't be read adds none.
+pub fn rescue_directories(record: &Path) -> Vec<PathBuf> {
+    let recorded = fs::read_to_string(record)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Directories>(&contents).ok())
+        .unwrap_or_default();
+    let mut directories: Vec<PathBuf> = RESCUE_DIRECTORIES.iter().map(PathBuf::from).collect();
+    for directory in recorded.experiments.into_values().flatten() {
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    directories
+}
+
+/// The location of the generated shell script that restores every backup.
+pub const RESCUE_SCRIPT_PATH: &str = "/var/lib/oxidizr/rescue.sh";
+
+/// Restore every file replaced by oxidizr from its backup, for use on a system broken badly
+/// enough that the replaced utilities themselves don't work.
+///
+/// Unlike `disable`, this runs no external commands and doesn't consult the package manager, the
+/// journal or the experiments: it finds the backups by name in each directory, and those listed in
+/// its backup manifest where there is one, and moves them back into place with `rename(2)`.
+/// Replacements scheduled for the next boot, and the journal of any interrupted operation, are
+/// removed so that they can't undo the restore, along with the rescue script, which no longer has
+/// anything to restore.
+pub fn rescue(directories: &[PathBuf]) -> Result<()> {
+    let backups = find_backups(directories)?;
+    if backups.is_empty() {
+        println!("No files replaced by oxidizr were found.");
+    }
+
+    let failures = restore(&backups);
+    for path in [BOOT_SCHEDULE_PATH, JOURNAL_PATH, RESCUE_SCRIPT_PATH] {
+        if fs::exists(path)? {
+            fs::remove_file(path).with_context(|| format!("unable to remove {path}"))?;
+            println!("Removed {path}");
+        }
+    }
+
+    anyhow::ensure!(failures == 0, "{failures} file(s) could not be restored");
+    if !backups.is_empty() {
+        println!(
+            "The packages installed by oxidizr are left in place. Once the system is working, \
+            run 'oxidizr disable --all' to remove them."
+        );
+    }
+    Ok(())
+}
+
+/// Find the backups made by oxidizr in each directory, paired with the file each was backed up
+/// from. Where a directory has a backup manifest, only the backups it lists are, so that a file
+/// that merely has the name of a backup is left alone; without one, as for backups made by
+/// versions predating it, every file named as a backup is. Directories that resolve to the same
+/// place, such as `/bin` and `/usr/bin` on merged-usr systems, are only searched once.
+fn find_backups(directories: &[PathBuf]) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let directories: BTreeSet<PathBuf> = directories
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .collect();
+
+    let mut backups = Vec::new();
+    for dir in directories {
+        let manifest = read_manifest(&dir);
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = manifest_key(path.file_name().unwrap_or_default());
+            if manifest.as_ref().is_some_and(|m| !m.contains_key(&name)) {
+                continue;
+            }
+            if let Some(original) = backup_original(&path) {
+                backups.push((path, original));
+            }
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Read the backup manifest of a directory: the backups made by oxidizr in it, by file name, or
+/// `None` if it has none. A manifest that can't be read is warned about and treated as missing,
+/// so that the backups can still be restored.
+fn read_manifest(dir: &Path) -> Option<BTreeMap<String, String>> {
+    let path = dir.join(BACKUP_MANIFEST);
+    let contents = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            eprintln!("Ignoring {}, which can't be parsed: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Forget a restored backup in the manifest of its directory, removing the manifest once it
+/// records none.
+fn forget_backup(backup: &Path) {
+    let Some(dir) = backup.parent() else {
+        return;
+    };
+    let Some(mut manifest) = read_manifest(dir) else {
+        return;
+    };
+    let name = manifest_key(backup.file_name().unwrap_or_default());
+    if manifest.remove(&name).is_none() {
+        return;
+    }
+    let path = dir.join(BACKUP_MANIFEST);
+    let result = match manifest.is_empty() {
+        true => fs::remove_file(&path),
+        false => serde_json::to_string_pretty(&manifest)
+            .map_err(std::io::Error::other)
+            .and_then(|contents| fs::write(&path, contents)),
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to update {}: {}", path.display(), e);
+    }
+}
+
+/// Move each backup back over the file it was backed up from, returning the number of files that
+/// could not be restored.
+fn restore(backups: &[(PathBuf, PathBuf)]) -> usize {
+    let mut failures = 0;
+    for (backup, original) in backups {
+        match fs::rename(backup, original) {
+            Ok(()) => {
+                println!("Restored {}", original.display());
+                let _ = fs::remove_file(checksum_path(original));
+                forget_backup(backup);
+            }
+            Err(e) => {
+                eprintln!("Failed to restore {}: {}", original.display(), e);
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// Write a plain shell script to `RESCUE_SCRIPT_PATH` that restores every backup currently in
+/// place with literal `mv` commands, so that the system can be recovered without running oxidizr
+/// at all - such as from a live CD, by passing the mount point of the broken system's root. The
+/// script is removed once there are no backups left to restore.
+///
+/// Failures are logged rather than returned, so that they don't fail an otherwise successful
+/// operation.
+pub fn update_rescue_script(system: &dyn Worker) {
+    let directories = rescue_directories(Path::new(DIRECTORIES_PATH));
+    let result = find_backups(&directories).and_then(|backups| {
+        let path = Path::new(RESCUE_SCRIPT_PATH);
+        match backups.is_empty() {
+            true if fs::exists(path)? => Ok(fs::remove_file(path)?),
+            true => Ok(()),
+            false => system.write_file(path, &rescue_script(&backups)),
+        }
+    });
+
+    if let Err(e) = result {
+        tracing::warn!("Unable to update the rescue script: {:#}", e);
+    }
+}
+
+/// Generate a shell script that moves each backup back over the file it was backed up from.
+fn rescue_script(backups: &[(PathBuf, PathBuf)]) -> String {
+    let mut script = String::from(
+        "#!/bin/sh\n\
+        # Generated by oxidizr: restores every file replaced by oxidizr from its backup.\n\
+        # Usage: sh rescue.sh [ROOT], where ROOT is the mount point of the system to restore,\n\
+        # such as when run from a live CD. Defaults to the running system.\n\
+        ROOT=\"${1:-}\"\n\n",
+    );
+    for (backup, original) in backups {
+        script.push_str(&format!(
+            "mv -f \"$ROOT\"{} \"$ROOT\"{}\n",
+            shell_quote(backup),
+            shell_quote(original)
+        ));
+        script.push_str(&format!(
+            "rm -f \"$ROOT\"{}\n",
+            shell_quote(&checksum_path(original))
+        ));
+    }
+    // Every backup a manifest lists is restored, so the manifests are left with none.
+    let manifests: BTreeSet<PathBuf> = backups
+        .iter()
+        .filter_map(|(backup, _)| backup.parent())
+        .map(|dir| dir.join(BACKUP_MANIFEST))
+        .filter(|manifest| manifest.exists())
+        .collect();
+    for manifest in manifests {
+        script.push_str(&format!("rm -f \"$ROOT\"{}\n", shell_quote(&manifest)));
+    }
+    script.push_str(&format!(
+        "rm -f \"$ROOT\"{} \"$ROOT\"{}\n",
+        shell_quote(Path::new(BOOT_SCHEDULE_PATH)),
+        shell_quote(Path::new(JOURNAL_PATH))
+    ));
+    script
+}
+
+/// Quote a path for use as a single shell word. Bytes that aren't valid UTF-8 are written with
+/// `printf`, so that the script names the file byte for byte while staying valid text.
+fn shell_quote(path: &Path) -> String {
+    let mut quoted = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            quoted.push_str(&format!("'{}'", chunk.valid().replace('\'', "'\\''")));
+        }
+        for byte in chunk.invalid() {
+            quoted.push_str(&format!("\"$(printf '\\{byte:03o}')\""));
+        }
+    }
+    match quoted.is_empty() {
+        true => "''".to_string(),
+        false => quoted,
+    }
+}
+
+/// The path of the checksum recorded for a file replaced with a copy.
+fn checksum_path(file: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(file.file_name().unwrap_or_default());
+    name.push(".oxidizr.sha256");
+    file.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_and_restore_backups() {
+        let root = std::env::temp_dir().join(format!("oxidizr-rescue-{}", std::process::id()));
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join(".date.oxidizr.bak"), "GNU date").unwrap();
+        fs::write(bin.join(".sudo.oxidizr.bak"), "sudo").unwrap();
+        fs::write(bin.join("sudo"), "sudo-rs").unwrap();
+        fs::write(bin.join(".sudo.oxidizr.sha256"), "0a1b2c").unwrap();
+        fs::write(bin.join("ls"), "GNU ls").unwrap();
+        std::os::unix::fs::symlink("/usr/lib/cargo/bin/coreutils/date", bin.join("date")).unwrap();
+        let link = root.join("link");
+        std::os::unix::fs::symlink(&bin, &link).unwrap();
+
+        let directories = vec![bin.clone(), link];
+        let backups = find_backups(&directories).unwrap();
+        assert_eq!(
+            backups,
+            vec![
+                (bin.join(".date.oxidizr.bak"), bin.join("date")),
+                (bin.join(".sudo.oxidizr.bak"), bin.join("sudo")),
+            ]
+        );
+
+        assert_eq!(restore(&backups), 0);
+        assert_eq!(fs::read_to_string(bin.join("date")).unwrap(), "GNU date");
+        assert_eq!(fs::read_to_string(bin.join("sudo")).unwrap(), "sudo");
+        assert!(!fs::exists(bin.join(".sudo.oxidizr.sha256")).unwrap());
+        assert!(find_backups(&directories).unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_backups_in_manifest() {
+        let root =
+            std::env::temp_dir().join(format!("oxidizr-rescue-manifest-{}", std::process::id()));
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join(".date.oxidizr.bak"), "GNU date").unwrap();
+        fs::write(bin.join(".sort.oxidizr.bak"), "unrelated").unwrap();
+        fs::write(bin.join("sort"), "GNU sort").unwrap();
+        fs::write(
+            bin.join(BACKUP_MANIFEST),
+            "{\".date.oxidizr.bak\": \"0a1b2c\"}",
+        )
+        .unwrap();
+
+        // Only the backups the manifest lists are restored, and it is removed once it lists none.
+        let backups = find_backups(std::slice::from_ref(&bin)).unwrap();
+        assert_eq!(
+            backups,
+            vec![(bin.join(".date.oxidizr.bak"), bin.join("date"))]
+        );
+        assert!(rescue_script(&backups).contains(&format!(
+            "rm -f \"$ROOT\"'{}'\n",
+            bin.join(BACKUP_MANIFEST).display()
+        )));
+        assert_eq!(restore(&backups), 0);
+        assert_eq!(fs::read_to_string(bin.join("sort")).unwrap(), "GNU sort");
+        assert!(!fs::exists(bin.join(BACKUP_MANIFEST)).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rescue_non_utf8_backup() {
+        use crate::utils::{ManagedTarget, System};
+        use std::ffi::OsStr;
+
+        let root =
+            std::env::temp_dir().join(format!("oxidizr-rescue-non-utf8-{}", std::process::id()));
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9");
+        let target = ManagedTarget::new(bin.join(name)).unwrap();
+        fs::write(&target, "GNU").unwrap();
+
+        let system = System::default().with_managed_directories(std::slice::from_ref(&bin));
+        system.backup_file(target.clone()).unwrap();
+        fs::write(&target, "replacement").unwrap();
+        let manifest = fs::read_to_string(bin.join(BACKUP_MANIFEST)).unwrap();
+        assert!(manifest.contains("\"hex:2e636166e92e6f786964697a722e62616b\""));
+
+        let backups = find_backups(std::slice::from_ref(&bin)).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            backups[0].1.file_name().unwrap().as_bytes(),
+            b"caf\xe9".as_slice()
+        );
+        assert!(rescue_script(&backups).contains("/caf'\"$(printf '\\351')\"\n"));
+        assert_eq!(restore(&backups), 0);
+        assert_eq!(fs::read_to_string(bin.join(name)).unwrap(), "GNU");
+        assert!(!fs::exists(bin.join(BACKUP_MANIFEST)).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rescue_directories() {
+        let record =
+            std::env::temp_dir().join(format!("oxidizr-directories-{}.json", std::process::id()));
+        assert_eq!(rescue_directories(&record).len(), RESCUE_DIRECTORIES.len());
+
+        fs::write(
+            &record,
+            "{\"experiments\":{\"coreutils\":[\"/usr/local/bin\",\"/usr/bin\"]}}",
+        )
+        .unwrap();
+        let directories = rescue_directories(&record);
+        assert_eq!(directories.len(), RESCUE_DIRECTORIES.len() + 1);
+        assert_eq!(directories.last().unwrap(), Path::new("/usr/local/bin"));
+
+        fs::remove_file(&record).unwrap();
+    }
+
+    #[test]
+    fn test_rescue_script() {
+        let backups = vec![(
+            PathBuf::from("/usr/bin/.date.oxidizr.bak"),
+            PathBuf::from("/usr/bin/date"),
+        )];
+        let script = rescue_script(&backups);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(
+            script.contains(
+                "\nmv -f \"$ROOT\"'/usr/bin/.date.oxidizr.bak' \"$ROOT\"'/usr/bin/date'\n"
+            )
+        );
+        assert!(script.ends_with(
+            "rm -f \"$ROOT\"'/etc/tmpfiles.d/oxidizr.conf' \"$ROOT\"'/var/lib/oxidizr/journal.json'\n"
+        ));
+        assert_eq!(shell_quote(Path::new("/tmp/it's")), "'/tmp/it'\\''s'");
+    }
+}
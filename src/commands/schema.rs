@@ -0,0 +1,116 @@
+use super::{
+    Artifact, BinaryComparison, BisectReport, CheckReport, Deployment, FleetSummary,
+    GenerationListing, LicenseChange, SignedInventory,
+};
+use crate::experiments::{ExperimentStatus, Plan, Quarantine, Usage};
+use crate::progress::ProgressEvent;
+use crate::summary::RunSummary;
+use crate::utils::ErrorReport;
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::{Schema, schema_for};
+
+/// The structured outputs of oxidizr for which a JSON Schema is published.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SchemaTarget {
+    /// The status of each experiment, as reported by `list`.
+    Status,
+    /// The plan printed before an enable or disable operation.
+    Plan,
+    /// The report emitted in place of an error message.
+    Report,
+    /// The license changes reported by `licenses`.
+    Licenses,
+    /// The invocation counts of shims reported by `usage`.
+    Usage,
+    /// The summary printed once an enable, disable or reapply operation ends.
+    Summary,
+    /// The inventory of a host emitted by `inventory`.
+    Inventory,
+    /// The summary of a fleet reported by `inventory merge`.
+    Fleet,
+    /// The findings reported by `check`.
+    Check,
+    /// The comparison of a binary with its replacement reported by `compare-one`.
+    Comparison,
+    /// The replacement found to cause a failure by `bisect`.
+    Bisect,
+    /// The binaries implicated in failures reported by `quarantine`.
+    Quarantine,
+    /// The replacements deployed under a prefix by `deploy`.
+    Deployment,
+    /// The generations listed by `generations`.
+    Generations,
+    /// The artifacts found by `gc`.
+    Garbage,
+    /// The events streamed by an operation with `--progress json-lines`, one per line.
+    Progress,
+}
+
+/// Print the JSON Schema that the structured output of a command follows, in either of the
+/// structured output formats.
+pub fn schema(target: SchemaTarget) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&json_schema(target))?);
+    Ok(())
+}
+
+/// Generate the JSON Schema for a structured output from the types it is serialized from, so that
+/// the schema can't drift from the output.
+fn json_schema(target: SchemaTarget) -> Schema {
+    match target {
+        SchemaTarget::Status => schema_for!(Vec<ExperimentStatus>),
+        SchemaTarget::Plan => schema_for!(Plan),
+        SchemaTarget::Report => schema_for!(ErrorReport),
+        SchemaTarget::Licenses => schema_for!(Vec<LicenseChange>),
+        SchemaTarget::Usage => schema_for!(Usage),
+        SchemaTarget::Summary => schema_for!(RunSummary),
+        SchemaTarget::Inventory => schema_for!(SignedInventory),
+        SchemaTarget::Fleet => schema_for!(FleetSummary),
+        SchemaTarget::Check => schema_for!(CheckReport),
+        SchemaTarget::Comparison => schema_for!(BinaryComparison),
+        SchemaTarget::Bisect => schema_for!(BisectReport),
+        SchemaTarget::Quarantine => schema_for!(Quarantine),
+        SchemaTarget::Deployment => schema_for!(Deployment),
+        SchemaTarget::Generations => schema_for!(Vec<GenerationListing>),
+        SchemaTarget::Garbage => schema_for!(Vec<Artifact>),
+        SchemaTarget::Progress => schema_for!(ProgressEvent<'static>),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{Maturity, PlannedExperiment};
+    use serde_json::Value;
+
+    #[test]
+    fn test_plan_schema_matches_output() {
+        let plan = Plan {
+            command: "enable".to_string(),
+            experiments: vec![PlannedExperiment {
+                name: "coreutils".to_string(),
+                package: "rust-coreutils".to_string(),
+                maturity: Maturity::Stable,
+                skip_reason: None,
+                option_coverage: Vec::new(),
+                known_issues: Vec::new(),
+                pinned: Vec::new(),
+                risk: None,
+                removal: None,
+            }],
+            warnings: Vec::new(),
+        };
+
+        let schema = json_schema(SchemaTarget::Plan).to_value();
+        let Value::Object(output) = serde_json::to_value(&plan).unwrap() else {
+            panic!("plan is not serialized as an object");
+        };
+        let mut properties: Vec<&String> =
+            schema["properties"].as_object().unwrap().keys().collect();
+        let mut fields: Vec<&String> = output.keys().collect();
+        properties.sort();
+        fields.sort();
+        assert_eq!(properties, fields);
+        assert_eq!(schema["properties"]["experiments"]["type"], "array");
+    }
+}
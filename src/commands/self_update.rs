@@ -0,0 +1,298 @@
+use crate::utils::{ErrorCode, hex_digest};
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use ring::signature::{ED25519, UnparsedPublicKey};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info};
+
+/// The GitHub repository oxidizr is released from, as recorded in the crate metadata.
+const REPOSITORY: &str = env!("CARGO_PKG_REPOSITORY");
+
+/// The name of the checksum file published alongside each release.
+const CHECKSUMS_ASSET: &str = "checksums.txt";
+
+/// The name of the detached Ed25519 signature of the checksum file, made by the release workflow.
+const SIGNATURE_ASSET: &str = "checksums.txt.sig";
+
+/// The Ed25519 public key whose private half signs the checksums of each release.
+const RELEASE_PUBLIC_KEY: [u8; 32] = [
+    0xbe, 0xda, 0x42, 0xcc, 0x4b, 0x1f, 0xa3, 0xba, 0x49, 0x44, 0x60, 0xd5, 0x92, 0x1a, 0xe3, 0xf9,
+    0x93, 0x65, 0x5b, 0xf5, 0x9c, 0xaf, 0x8f, 0x4a, 0xb2, 0xab, 0x4d, 0xe7, 0x7d, 0x06, 0xc1, 0xf3,
+];
+
+/// The maximum size of a release asset that will be downloaded.
+const MAX_DOWNLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+/// A subset of the release information returned by the GitHub API.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a GitHub release.
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// Find the download URL of an asset by name.
+    fn asset_url(&self, name: &str) -> Result<&str> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.as_str())
+            .with_context(|| format!("release {} has no asset named '{}'", self.tag_name, name))
+    }
+}
+
+/// Replace the running executable with the latest released version of oxidizr, after verifying
+/// the signature of the release's checksums and the checksum of the downloaded archive. If `check`
+/// is set, only report whether an update is available.
+pub fn self_update(check: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+
+    info!("Checking for new releases of oxidizr");
+    let release: Release = ureq::get(&latest_release_url(REPOSITORY)?)
+        .header("User-Agent", concat!("oxidizr/", env!("CARGO_PKG_VERSION")))
+        .call()?
+        .body_mut()
+        .read_json()?;
+
+    let latest = release.tag_name.trim_start_matches('v');
+    if !is_newer(latest, current)? {
+        info!("oxidizr {} is already the latest version", current);
+        return Ok(());
+    }
+
+    if check {
+        info!("oxidizr {} is available (installed: {})", latest, current);
+        return Ok(());
+    }
+
+    let archive_name = archive_name(std::env::consts::ARCH);
+    let archive = download(release.asset_url(&archive_name)?)?;
+    let checksums = download(release.asset_url(CHECKSUMS_ASSET)?)?;
+    let signature = download(release.asset_url(SIGNATURE_ASSET)?)?;
+    verify_signature(&RELEASE_PUBLIC_KEY, &checksums, &signature)?;
+    debug!("Verified signature of {}", CHECKSUMS_ASSET);
+    let checksums = String::from_utf8(checksums)?;
+
+    let expected = expected_checksum(&checksums, &archive_name)?;
+    let actual = hex_digest(&archive);
+    if actual != expected {
+        return Err(ErrorCode::ChecksumMismatch.error(format!(
+            "checksum mismatch for {archive_name}: expected {expected}, got {actual}"
+        )));
+    }
+    debug!("Verified sha256 checksum of {}", archive_name);
+
+    let binary = extract_binary(&archive)?;
+    let exe = std::env::current_exe()?;
+    replace_executable(&exe, &binary)?;
+
+    info!(
+        "Updated oxidizr {} -> {} at {}",
+        current,
+        latest,
+        exe.display()
+    );
+    Ok(())
+}
+
+/// The GitHub API endpoint describing the latest release published in a GitHub repository.
+fn latest_release_url(repository: &str) -> Result<String> {
+    let name = repository
+        .strip_prefix("https://github.com/")
+        .map(|name| name.trim_end_matches('/'))
+        .with_context(|| format!("{repository} is not a GitHub repository"))?;
+    Ok(format!(
+        "https://api.github.com/repos/{name}/releases/latest"
+    ))
+}
+
+/// Check that `signature` is a valid Ed25519 signature of `message` by `public_key`, failing with
+/// `ErrorCode::SignatureInvalid` otherwise.
+fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(message, signature)
+        .map_err(|_| {
+            ErrorCode::SignatureInvalid.error(format!(
+                "the signature of {CHECKSUMS_ASSET} doesn't match the release signing key"
+            ))
+        })
+}
+
+/// Download the contents of a URL into memory.
+fn download(url: &str) -> Result<Vec<u8>> {
+    debug!("Downloading {}", url);
+    let body = ureq::get(url)
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_SIZE)
+        .read_to_vec()?;
+    Ok(body)
+}
+
+/// The name of the release archive for the given architecture, as published by goreleaser.
+fn archive_name(arch: &str) -> String {
+    let arch = match arch {
+        "powerpc64" => "ppc64le",
+        other => other,
+    };
+    format!("oxidizr_Linux_{arch}.tar.gz")
+}
+
+/// Find the expected sha256 checksum of a file in the contents of a `sha256sum`-style file.
+fn expected_checksum(checksums: &str, filename: &str) -> Result<String> {
+    checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim_start_matches([' ', '*']) == filename)
+        .map(|(sum, _)| sum.to_lowercase())
+        .with_context(|| format!("no checksum published for {filename}"))
+}
+
+/// Extract the `oxidizr` binary from a gzipped release tarball.
+fn extract_binary(archive: &[u8]) -> Result<Vec<u8>> {
+    let mut tarball = tar::Archive::new(GzDecoder::new(archive));
+    for entry in tarball.entries()? {
+        let mut entry = entry?;
+        if entry.path()? == Path::new("oxidizr") {
+            let mut binary = Vec::new();
+            entry.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
+    }
+    anyhow::bail!("release archive does not contain an oxidizr binary")
+}
+
+/// Atomically replace an executable: the new contents are written to a temporary file in the
+/// same directory, synced to disk, then renamed over the original.
+fn replace_executable(exe: &Path, contents: &[u8]) -> Result<()> {
+    let mut staged = PathBuf::from(exe);
+    staged.set_file_name(".oxidizr.update");
+
+    let mut file = fs::File::create(&staged)
+        .with_context(|| format!("unable to write to {}", staged.display()))?;
+    file.write_all(contents)?;
+    file.set_permissions(fs::Permissions::from_mode(0o755))?;
+    file.sync_all()?;
+
+    if let Err(e) = fs::rename(&staged, exe) {
+        fs::remove_file(&staged)?;
+        return Err(e).with_context(|| format!("unable to replace {}", exe.display()));
+    }
+    Ok(())
+}
+
+/// Report whether version `candidate` is newer than version `current`. Versions are compared
+/// numerically, component by component; pre-release suffixes are ignored.
+fn is_newer(candidate: &str, current: &str) -> Result<bool> {
+    Ok(version_components(candidate)? > version_components(current)?)
+}
+
+/// Split a version string such as `1.2.3-rc1` into its numeric components.
+fn version_components(version: &str) -> Result<Vec<u64>> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|c| {
+            c.parse::<u64>()
+                .with_context(|| format!("invalid version '{version}'"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("1.2.0", "1.1.0").unwrap());
+        assert!(is_newer("1.10.0", "1.9.3").unwrap());
+        assert!(is_newer("2.0.0-rc1", "1.9.0").unwrap());
+        assert!(!is_newer("1.1.0", "1.1.0").unwrap());
+        assert!(!is_newer("1.0.9", "1.1.0").unwrap());
+        assert!(is_newer("nightly", "1.1.0").is_err());
+    }
+
+    #[test]
+    fn test_archive_name() {
+        assert_eq!(archive_name("x86_64"), "oxidizr_Linux_x86_64.tar.gz");
+        assert_eq!(archive_name("aarch64"), "oxidizr_Linux_aarch64.tar.gz");
+        assert_eq!(archive_name("powerpc64"), "oxidizr_Linux_ppc64le.tar.gz");
+    }
+
+    #[test]
+    fn test_latest_release_url() {
+        assert_eq!(
+            latest_release_url("https://github.com/jnsgruk/oxidizr").unwrap(),
+            "https://api.github.com/repos/jnsgruk/oxidizr/releases/latest"
+        );
+        assert_eq!(
+            latest_release_url(REPOSITORY).unwrap(),
+            format!(
+                "https://api.github.com/repos/{}/releases/latest",
+                REPOSITORY.trim_start_matches("https://github.com/")
+            )
+        );
+        assert!(latest_release_url("https://gitlab.com/jnsgruk/oxidizr").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let key = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let public_key = key.public_key().as_ref();
+        let checksums = b"0a1b2c  oxidizr_Linux_x86_64.tar.gz\n";
+        let signature = key.sign(checksums);
+        assert!(verify_signature(public_key, checksums, signature.as_ref()).is_ok());
+
+        // Checksums tampered with, or signed by another key, are refused.
+        let err = verify_signature(
+            public_key,
+            b"deadbeef  oxidizr_Linux_x86_64.tar.gz\n",
+            signature.as_ref(),
+        )
+        .unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::SignatureInvalid));
+        let err = verify_signature(&RELEASE_PUBLIC_KEY, checksums, signature.as_ref()).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::SignatureInvalid));
+        assert!(verify_signature(public_key, checksums, b"").is_err());
+    }
+
+    #[test]
+    fn test_expected_checksum() {
+        let checksums = "\
+0a1b2c  oxidizr_Linux_aarch64.tar.gz
+DEADBEEF  oxidizr_Linux_x86_64.tar.gz
+";
+        assert_eq!(
+            expected_checksum(checksums, "oxidizr_Linux_x86_64.tar.gz").unwrap(),
+            "deadbeef"
+        );
+        assert!(expected_checksum(checksums, "oxidizr_Linux_ppc64le.tar.gz").is_err());
+    }
+
+    #[test]
+    fn test_hex_digest() {
+        assert_eq!(
+            hex_digest(b"oxidizr"),
+            "6a1ba65eb50581759efed85068c410a465ee4ce48b1a18c2b6c7e21c03fe2761"
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use crate::experiments::Usage;
+use crate::utils::{OutputFormat, Table, Worker, structured};
+use anyhow::Result;
+use std::cmp::Reverse;
+
+/// Print how often each binary replaced with a shim has been run, and how often it fell back to
+/// GNU. When `save` is given, the events logged by the shims since are folded into the recorded
+/// counts.
+pub fn usage(system: &dyn Worker, format: OutputFormat, save: bool) -> Result<()> {
+    let usage = Usage::load(system)?;
+    if save {
+        usage.save(system)?;
+    }
+
+    match format {
+        OutputFormat::Human if usage.binaries.is_empty() => {
+            println!("No shim invocations have been recorded.")
+        }
+        OutputFormat::Human => print!("{}", usage_table(&usage)),
+        format => print!("{}", structured(format, &usage)?),
+    }
+    Ok(())
+}
+
+/// Build a human-readable table of the usage of each binary, most frequently run first.
+fn usage_table(usage: &Usage) -> Table {
+    let mut binaries: Vec<_> = usage.binaries.iter().collect();
+    binaries.sort_by_key(|(_, u)| Reverse(u.invocations));
+
+    let mut table = Table::new(&["BINARY", "INVOCATIONS", "FALLBACKS"]);
+    for (binary, u) in binaries {
+        table.add_row(vec![
+            binary.clone(),
+            u.invocations.to_string(),
+            u.fallbacks.to_string(),
+        ]);
+    }
+    table
+}
@@ -0,0 +1,98 @@
+use crate::utils::{BuildInfo, OutputFormat, Worker, structured};
+use anyhow::Result;
+
+/// Print the version of oxidizr. When `verbose` is set, also print build information and details
+/// of the environment oxidizr is running in.
+pub fn version(system: &dyn Worker, format: OutputFormat, verbose: bool) -> Result<()> {
+    let info = BuildInfo::gather(system);
+
+    match format {
+        OutputFormat::Human if !verbose => println!("oxidizr {}", info.version),
+        OutputFormat::Human => print!("{}", verbose_version(&info)),
+        format => print!("{}", structured(format, &info)?),
+    }
+
+    Ok(())
+}
+
+/// Render build information as a human-readable list of fields.
+fn verbose_version(info: &BuildInfo) -> String {
+    let features = match info.features.is_empty() {
+        true => "none".to_string(),
+        false => info.features.join(", "),
+    };
+
+    let fields = [
+        ("commit", info.git_commit.clone()),
+        ("build date", info.build_date.clone()),
+        ("target", info.target.clone()),
+        ("features", features),
+        (
+            "distribution",
+            info.distribution
+                .as_ref()
+                .map(|d| d.describe())
+                .unwrap_or("unknown".to_string()),
+        ),
+        (
+            "kernel",
+            info.kernel.clone().unwrap_or("unknown".to_string()),
+        ),
+        (
+            "container",
+            info.container.clone().unwrap_or("none".to_string()),
+        ),
+        (
+            "wsl",
+            info.wsl.map_or("none".to_string(), |w| w.to_string()),
+        ),
+        (
+            "ephemeral",
+            info.ephemeral_root
+                .as_ref()
+                .map_or("no".to_string(), |fs| format!("yes ({fs} root)")),
+        ),
+    ];
+
+    let mut out = format!("oxidizr {}\n", info.version);
+    for (name, value) in fields {
+        out.push_str(&format!("{:<14}{}\n", format!("{name}:"), value));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Distribution;
+
+    #[test]
+    fn test_verbose_version() {
+        let info = BuildInfo {
+            version: "1.1.0".to_string(),
+            git_commit: "abc1234".to_string(),
+            build_date: "2025-01-01".to_string(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            features: Vec::new(),
+            distribution: Some(Distribution::new("Ubuntu", "24.04")),
+            kernel: None,
+            container: Some("lxc".to_string()),
+            wsl: None,
+            ephemeral_root: Some("overlay".to_string()),
+        };
+
+        let expected = "\
+oxidizr 1.1.0
+commit:       abc1234
+build date:   2025-01-01
+target:       x86_64-unknown-linux-gnu
+features:     none
+distribution: Ubuntu 24.04
+kernel:       unknown
+container:    lxc
+wsl:          none
+ephemeral:    yes (overlay root)
+";
+        assert_eq!(verbose_version(&info), expected);
+    }
+}
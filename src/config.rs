@@ -0,0 +1,327 @@
+use crate::experiments::PolicyName;
+use crate::utils::ErrorCode;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The default location of the oxidizr configuration file.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/oxidizr/config.toml";
+
+/// Configuration for oxidizr, read from a TOML file. Every setting is optional.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub telemetry: TelemetryConfig,
+    pub known_issues: KnownIssuesConfig,
+    pub compatibility: CompatibilityConfig,
+    pub labels: LabelsConfig,
+    pub maintenance: MaintenanceConfig,
+    pub scratch: ScratchConfig,
+    pub retention: RetentionConfig,
+    pub inventory: InventoryConfig,
+    pub notifications: NotificationsConfig,
+    /// Commands run before and after each experiment is enabled or disabled, by experiment name.
+    pub hooks: BTreeMap<String, ExperimentHooks>,
+    pub restart: RestartConfig,
+    pub coreutils: ExperimentConfig,
+    pub diffutils: ExperimentConfig,
+    pub findutils: ExperimentConfig,
+    pub sed: ExperimentConfig,
+    pub tar: ExperimentConfig,
+    /// Binaries replaced by the `overrides` experiment, each mapped to the file replacing it.
+    pub overrides: BTreeMap<String, PathBuf>,
+}
+
+/// Configuration for anonymous usage telemetry, which is disabled unless explicitly enabled.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    pub endpoint: Option<String>,
+}
+
+/// Configuration for the database of known issues in replacement packages.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KnownIssuesConfig {
+    /// The URL from which `oxidizr known-issues --refresh` downloads the database.
+    pub url: Option<String>,
+}
+
+/// Configuration for the rules deciding which experiments may be enabled on a release.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompatibilityConfig {
+    /// Policies applied in addition to the supported releases of each experiment.
+    pub policies: Vec<PolicyName>,
+}
+
+/// Configuration for the labels describing this host, and the labels each experiment requires
+/// of the hosts it is enabled on.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LabelsConfig {
+    /// A file of `key=value` labels, `/etc/oxidizr/labels` by default.
+    pub file: Option<PathBuf>,
+    /// A shell command printing `key=value` labels, such as one querying an inventory.
+    pub command: Option<String>,
+    /// Labels set in the configuration itself.
+    pub values: BTreeMap<String, String>,
+    /// The labels a host must have for each experiment to be enabled on it, by experiment name.
+    pub selectors: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Configuration for the notices put up while an operation is underway, so that the humans and
+/// automation using the host can see that core utilities are being replaced.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    /// Show a notice in the message of the day and on consoles.
+    pub notice: bool,
+    /// A file that exists only while an operation is underway, for other automation to check.
+    pub flag: Option<PathBuf>,
+}
+
+/// Configuration for the scratch space used while an operation runs, such as to build packages.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScratchConfig {
+    /// The directory under which scratch directories are created, `$TMPDIR` or `/tmp` by default.
+    pub directory: Option<PathBuf>,
+}
+
+/// Configuration for what `oxidizr gc` keeps of the artifacts no longer needed.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// The number of the latest generations kept, 20 by default.
+    pub generations: Option<usize>,
+}
+
+/// Configuration for the inventories collected from each host of a fleet.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InventoryConfig {
+    /// A file holding the key shared by the fleet, which inventories are signed and checked with.
+    pub key_file: Option<PathBuf>,
+}
+
+/// Configuration for notifying the people using the host of what oxidizr did unattended, such as
+/// re-applying experiments after a package upgrade reverted them, or a failed `oxidizr check`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// Show a notification to the users logged in to a graphical desktop session.
+    pub desktop: bool,
+    pub email: EmailConfig,
+}
+
+/// Configuration for emailing notifications, such as from servers without other means of
+/// alerting. Nothing is emailed unless there are recipients.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    pub to: Vec<String>,
+    /// The sender, by default that chosen by sendmail, usually root at the host.
+    pub from: Option<String>,
+    /// The sendmail-compatible program messages are handed to, `/usr/sbin/sendmail` by default.
+    pub sendmail: Option<PathBuf>,
+}
+
+/// Shell commands run before and after an experiment is enabled or disabled.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentHooks {
+    pub pre_enable: Option<String>,
+    pub post_enable: Option<String>,
+    pub pre_disable: Option<String>,
+    pub post_disable: Option<String>,
+}
+
+/// Configuration for restarting the services that run replaced binaries after enabling, with
+/// `oxidizr enable --restart-services`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestartConfig {
+    /// Only restart these services, if any are listed.
+    pub include: Vec<String>,
+    /// Never restart these services, such as those whose restart would interrupt users.
+    pub exclude: Vec<String>,
+}
+
+/// Configuration for an experiment replacing GNU utilities.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentConfig {
+    /// The binaries to leave as GNU while the rest of the experiment is enabled, such as `dd`.
+    pub pin_gnu: Vec<String>,
+    /// The directories searched, in order, for the files the experiment replaces, such as
+    /// `/usr/sbin`. By default, those of the layout of the distribution.
+    pub directories: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Load configuration from a file. A missing file results in the default configuration.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read config file {}", path.display()))?;
+        Self::parse(&contents).with_context(|| {
+            ErrorCode::InvalidConfig.context(format!("invalid config file {}", path.display()))
+        })
+    }
+
+    /// Find the configuration of an experiment replacing GNU utilities.
+    fn experiment(&self, experiment: &str) -> Option<&ExperimentConfig> {
+        match experiment {
+            "coreutils" => Some(&self.coreutils),
+            "diffutils" => Some(&self.diffutils),
+            "findutils" => Some(&self.findutils),
+            "sed" => Some(&self.sed),
+            "tar" => Some(&self.tar),
+            _ => None,
+        }
+    }
+
+    /// Report the binaries pinned to GNU for an experiment. Only experiments replacing GNU
+    /// utilities have any.
+    pub fn pin_gnu(&self, experiment: &str) -> &[String] {
+        self.experiment(experiment).map_or(&[], |e| &e.pin_gnu)
+    }
+
+    /// Report the directories configured for the files an experiment replaces, or none if those
+    /// of the layout of the distribution are used. Only experiments replacing GNU utilities have
+    /// any.
+    pub fn directories(&self, experiment: &str) -> &[PathBuf] {
+        self.experiment(experiment).map_or(&[], |e| &e.directories)
+    }
+
+    /// Report the directories configured for the files of every experiment, which oxidizr
+    /// manages along with the usual ones.
+    pub fn all_directories(&self) -> Vec<PathBuf> {
+        [
+            &self.coreutils,
+            &self.diffutils,
+            &self.findutils,
+            &self.sed,
+            &self.tar,
+        ]
+        .into_iter()
+        .flat_map(|e| e.directories.iter().cloned())
+        .collect()
+    }
+
+    /// Parse configuration from the contents of a TOML file.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        let relative = config
+            .all_directories()
+            .into_iter()
+            .find(|d| d.is_relative());
+        if let Some(directory) = relative {
+            anyhow::bail!("directory '{}' is not absolute", directory.display());
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config, Config::default());
+        assert!(!config.telemetry.enabled);
+    }
+
+    #[test]
+    fn test_config_compatibility() {
+        let config = Config::parse(
+            r#"
+            [compatibility]
+            policies = ["lts-only"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.compatibility.policies, vec![PolicyName::LtsOnly]);
+        assert!(Config::parse("[compatibility]\npolicies = [\"weekends\"]").is_err());
+    }
+
+    #[test]
+    fn test_config_telemetry() {
+        let config = Config::parse(
+            r#"
+            [telemetry]
+            enabled = true
+            endpoint = "https://telemetry.example.com/v1/report"
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.telemetry.enabled);
+        assert_eq!(
+            config.telemetry.endpoint.as_deref(),
+            Some("https://telemetry.example.com/v1/report")
+        );
+    }
+
+    #[test]
+    fn test_config_pin_gnu() {
+        let config =
+            Config::parse("coreutils.pin_gnu = [\"dd\", \"install\", \"stat\"]\n").unwrap();
+        assert_eq!(config.pin_gnu("coreutils"), ["dd", "install", "stat"]);
+        assert!(config.pin_gnu("findutils").is_empty());
+        assert!(config.pin_gnu("sudo-rs").is_empty());
+    }
+
+    #[test]
+    fn test_config_directories() {
+        let config =
+            Config::parse("coreutils.directories = [\"/usr/sbin\", \"/usr/bin\"]\n").unwrap();
+        assert_eq!(
+            config.directories("coreutils"),
+            [PathBuf::from("/usr/sbin"), PathBuf::from("/usr/bin")]
+        );
+        assert!(config.directories("sed").is_empty());
+        assert!(Config::parse("sed.directories = [\"bin\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let config = Config::parse("[overrides]\ngrep = \"/usr/local/bin/rg-wrapper\"\n").unwrap();
+        assert_eq!(
+            config.overrides.get("grep"),
+            Some(&PathBuf::from("/usr/local/bin/rg-wrapper"))
+        );
+    }
+
+    #[test]
+    fn test_config_retention() {
+        let config = Config::parse("[retention]\ngenerations = 5\n").unwrap();
+        assert_eq!(config.retention.generations, Some(5));
+        assert_eq!(Config::default().retention.generations, None);
+    }
+
+    #[test]
+    fn test_config_scratch() {
+        let config = Config::parse("[scratch]\ndirectory = \"/var/tmp/oxidizr\"\n").unwrap();
+        assert_eq!(
+            config.scratch.directory,
+            Some(PathBuf::from("/var/tmp/oxidizr"))
+        );
+    }
+
+    #[test]
+    fn test_config_unknown_field() {
+        assert!(Config::parse("[telemetry]\nenabeld = true\n").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::DEFAULT_CONFIG_PATH;
+use crate::experiments::{
+    BOOT_SCHEDULE_PATH, SCHEDULE_SERVICE_PATH, SCHEDULE_TIMER_PATH, TRIAL_SERVICE_PATH,
+    TRIAL_TIMER_PATH,
+};
+use crate::journal::Step;
+use crate::utils::{Command, Worker};
+use anyhow::Result;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+/// The directory whose history is kept.
+const ETC: &str = "/etc";
+
+/// The file etckeeper creates in /etc once it keeps its history.
+const ETCKEEPER_MARKER: &str = "/etc/.etckeeper";
+
+/// How the history of /etc is kept on the system, if it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EtcHistory {
+    /// etckeeper commits the whole of /etc, in whichever version control system it uses.
+    Etckeeper,
+    /// /etc is a git repository maintained by hand, so only the files oxidizr manages in it are
+    /// committed, leaving the administrator's own changes alone.
+    Git,
+}
+
+impl EtcHistory {
+    /// Detect how the history of /etc is kept, if it is.
+    pub fn detect(system: &dyn Worker) -> Option<Self> {
+        let etckeeper = system.which("etckeeper".as_ref()).is_ok()
+            && matches!(system.read_file(Path::new(ETCKEEPER_MARKER)), Ok(Some(_)));
+        if etckeeper {
+            return Some(Self::Etckeeper);
+        }
+        let toplevel = system
+            .run(&git(&["rev-parse", "--show-toplevel"], &[]))
+            .ok()?;
+        (String::from_utf8_lossy(&toplevel.stdout).trim() == ETC).then_some(Self::Git)
+    }
+}
+
+/// The files in /etc written by oxidizr, or read by it as its configuration.
+fn managed_paths() -> Vec<String> {
+    let config = Path::new(DEFAULT_CONFIG_PATH)
+        .parent()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    [
+        config.as_str(),
+        BOOT_SCHEDULE_PATH,
+        SCHEDULE_TIMER_PATH,
+        SCHEDULE_SERVICE_PATH,
+        TRIAL_TIMER_PATH,
+        TRIAL_SERVICE_PATH,
+    ]
+    .iter()
+    .filter_map(|p| p.strip_prefix("/etc/").map(str::to_string))
+    .collect()
+}
+
+/// Build a git command on the repository of /etc, limited to `paths` if there are any.
+fn git(args: &[&str], paths: &[String]) -> Command {
+    let mut command = Command::build("git", &[&["-C", ETC], args].concat());
+    if !paths.is_empty() {
+        command = command.arg("--");
+    }
+    paths
+        .iter()
+        .fold(command, |command, path| command.arg(path))
+}
+
+/// Commit the changes left in /etc by the administrator before `command` starts, as etckeeper
+/// does before apt runs, so that the commit made once it completes holds only its own changes.
+/// Only etckeeper is committed to, as /etc maintained by hand is only ever partly committed.
+///
+/// Failures are logged rather than returned: the history is a record, and must not fail an
+/// otherwise successful operation.
+pub fn commit_before(system: &dyn Worker, command: &str) {
+    if EtcHistory::detect(system) != Some(EtcHistory::Etckeeper) {
+        return;
+    }
+    // `etckeeper unclean` fails when there is nothing to commit.
+    if system
+        .run(&Command::build("etckeeper", &["unclean"]))
+        .is_err()
+    {
+        return;
+    }
+    let message = format!("saving uncommitted changes in /etc prior to oxidizr {command} run");
+    if let Err(e) = system.run(&Command::build("etckeeper", &["commit", &message])) {
+        warn!("Unable to commit the changes in /etc: {:#}", e);
+    }
+}
+
+/// Commit the changes `command` made to /etc, when its history is kept, with a message
+/// describing the operation, so that the history of the host reflects the switch. With
+/// etckeeper, the whole of /etc is committed, as with apt; in a git repository maintained by hand,
+/// only the files oxidizr manages are.
+///
+/// Failures are logged rather than returned: the history is a record, and must not fail an
+/// otherwise successful operation.
+pub fn commit_after(system: &dyn Worker, command: &str, experiments: &[String], steps: &[Step]) {
+    let result = match EtcHistory::detect(system) {
+        Some(EtcHistory::Etckeeper) => commit_etckeeper(system, command, experiments, steps),
+        Some(EtcHistory::Git) => commit_git(system, command, experiments, steps),
+        None => return,
+    };
+    if let Err(e) = result {
+        warn!("Unable to commit the changes in /etc: {:#}", e);
+    }
+}
+
+fn commit_etckeeper(
+    system: &dyn Worker,
+    command: &str,
+    experiments: &[String],
+    steps: &[Step],
+) -> Result<()> {
+    if system
+        .run(&Command::build("etckeeper", &["unclean"]))
+        .is_err()
+    {
+        debug!("Nothing changed in /etc, so nothing is committed");
+        return Ok(());
+    }
+    let message = commit_message(command, experiments, steps);
+    system.run(&Command::build("etckeeper", &["commit", &message]))?;
+    info!("Committed the changes in /etc with etckeeper");
+    Ok(())
+}
+
+fn commit_git(
+    system: &dyn Worker,
+    command: &str,
+    experiments: &[String],
+    steps: &[Step],
+) -> Result<()> {
+    let status = system.run(&git(&["status", "--porcelain"], &managed_paths()))?;
+    let changed = changed_paths(&String::from_utf8_lossy(&status.stdout));
+    if changed.is_empty() {
+        debug!("Nothing oxidizr manages changed in /etc, so nothing is committed");
+        return Ok(());
+    }
+    let message = commit_message(command, experiments, steps);
+    system.run(&git(&["add", "--all"], &changed))?;
+    system.run(&git(&["commit", "--quiet", "-m", &message], &changed))?;
+    info!("Committed the changes oxidizr made to /etc with git");
+    Ok(())
+}
+
+/// The paths changed in the output of `git status --porcelain`, such as ` M oxidizr/config.toml`,
+/// taking the new path of those renamed.
+fn changed_paths(status: &str) -> Vec<String> {
+    status
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| path.rsplit(" -> ").next().unwrap_or(path).to_string())
+        .collect()
+}
+
+/// Describe an operation for the history of /etc, such as `oxidizr enable: coreutils`, followed
+/// by what it changed.
+fn commit_message(command: &str, experiments: &[String], steps: &[Step]) -> String {
+    let mut installed = Vec::new();
+    let mut removed = Vec::new();
+    let (mut replaced, mut restored) = (0, 0);
+    for step in steps {
+        match step {
+            Step::InstallPackage { package } => installed.push(package.as_str()),
+            Step::RemovePackage { package } => removed.push(package.as_str()),
+            Step::ReplaceWithSymlink { .. } | Step::ReplaceWithCopy { .. } => replaced += 1,
+            Step::RestoreFile { .. } => restored += 1,
+        }
+    }
+
+    let mut message = format!("oxidizr {command}: {}\n", experiments.join(", "));
+    let mut details = Vec::new();
+    if !installed.is_empty() {
+        details.push(format!("Installed {}.", installed.join(", ")));
+    }
+    if !removed.is_empty() {
+        details.push(format!("Removed {}.", removed.join(", ")));
+    }
+    if replaced > 0 {
+        details.push(format!(
+            "Replaced {replaced} file(s) with their replacements."
+        ));
+    }
+    if restored > 0 {
+        details.push(format!("Restored {restored} file(s) from their backups."));
+    }
+    if !details.is_empty() {
+        message.push('\n');
+        message.push_str(&details.join("\n"));
+        message.push('\n');
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_commit_after_git() {
+        let runner = MockSystem::default();
+        assert_eq!(EtcHistory::detect(&runner), None);
+        runner.mock_command("git -C /etc rev-parse --show-toplevel", "/etc\n");
+        runner.mock_command(
+            "git -C /etc status --porcelain -- oxidizr tmpfiles.d/oxidizr.conf \
+            systemd/system/oxidizr-scheduled.timer systemd/system/oxidizr-scheduled.service \
+            systemd/system/oxidizr-trial.timer systemd/system/oxidizr-trial.service",
+            " M oxidizr/config.toml\n?? tmpfiles.d/oxidizr.conf\n",
+        );
+        assert_eq!(EtcHistory::detect(&runner), Some(EtcHistory::Git));
+
+        let steps = vec![
+            Step::InstallPackage {
+                package: "rust-coreutils".to_string(),
+            },
+            Step::ReplaceWithSymlink {
+                source: PathBuf::from("/usr/lib/cargo/bin/coreutils/date"),
+                target: PathBuf::from("/usr/bin/date"),
+            },
+        ];
+        commit_after(&runner, "enable", &["coreutils".to_string()], &steps);
+        let commands = runner.commands.borrow();
+        assert_eq!(
+            commands[commands.len() - 2..],
+            [
+                "git -C /etc add --all -- oxidizr/config.toml tmpfiles.d/oxidizr.conf",
+                "git -C /etc commit --quiet -m oxidizr enable: coreutils\n\n\
+                Installed rust-coreutils.\nReplaced 1 file(s) with their replacements.\n \
+                -- oxidizr/config.toml tmpfiles.d/oxidizr.conf",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commit_after_etckeeper() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/bin/etckeeper", "", true),
+            ("/etc/.etckeeper", "", false),
+        ]);
+        assert_eq!(EtcHistory::detect(&runner), Some(EtcHistory::Etckeeper));
+
+        commit_after(&runner, "disable", &["sudo-rs".to_string()], &[]);
+        assert_eq!(
+            runner.commands.borrow().last().unwrap(),
+            "etckeeper commit oxidizr disable: sudo-rs\n"
+        );
+    }
+}
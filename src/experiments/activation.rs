@@ -0,0 +1,144 @@
+use super::Experiment;
+use crate::utils::{StateFile, Worker, read_state, update_state, write_state};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use tracing::info;
+
+/// The location of the record of the experiments installed without being activated.
+pub const INSTALLS_PATH: &str = "/var/lib/oxidizr/installs.json";
+
+/// A system file staged to be replaced once its experiment is activated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StagedReplacement {
+    /// The replacement binary, which is installed.
+    pub source: PathBuf,
+    /// The system file it will replace.
+    pub target: PathBuf,
+}
+
+/// An experiment whose replacements are installed, but not yet in place.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StagedInstall {
+    /// When the replacements were installed, in seconds since the Unix epoch.
+    pub installed: u64,
+    pub replacements: Vec<StagedReplacement>,
+}
+
+/// The experiments installed with `oxidizr install --no-activate`, by experiment name, which
+/// `oxidizr activate` puts in place. An experiment is forgotten once it is activated, enabled or
+/// disabled.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Installs {
+    pub experiments: BTreeMap<String, StagedInstall>,
+}
+
+impl StateFile for Installs {
+    const PATH: &'static str = INSTALLS_PATH;
+}
+
+impl Installs {
+    /// Load the record. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the record.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+}
+
+/// Stage the replacements of experiments whose packages have just been installed: each file
+/// that activating them will replace is listed, checking its replacement binary is in place, and
+/// recorded, leaving the system files untouched.
+pub fn stage(system: &dyn Worker, experiments: &[Experiment], now: u64) -> Result<()> {
+    let mut staged = BTreeMap::new();
+    for e in experiments {
+        let mut replacements = Vec::new();
+        for (source, target) in e.replacements()? {
+            if system.file_checksum(&source)?.is_none() {
+                anyhow::bail!(
+                    "'{}' wasn't installed by the package of {}",
+                    source.display(),
+                    e.name()
+                );
+            }
+            replacements.push(StagedReplacement { source, target });
+        }
+        info!(
+            "Staged {} replacement(s) for {}, to be activated with 'oxidizr activate'",
+            replacements.len(),
+            e.name()
+        );
+        staged.insert(
+            e.name(),
+            StagedInstall {
+                installed: now,
+                replacements,
+            },
+        );
+    }
+    update_state(system, |installs: &mut Installs| {
+        installs.experiments.append(&mut staged);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Forget the staged replacements of the named experiments, once they are in place or removed.
+pub fn unstage(system: &dyn Worker, names: &[String]) -> Result<()> {
+    if !Installs::load(system)?
+        .experiments
+        .keys()
+        .any(|n| names.contains(n))
+    {
+        return Ok(());
+    }
+    update_state(system, |installs: &mut Installs| {
+        installs.experiments.retain(|n, _| !names.contains(n));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::SudoRsExperiment;
+    use crate::utils::MockSystem;
+    use std::path::Path;
+
+    #[test]
+    fn test_stage() {
+        let runner = MockSystem::default();
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+        assert!(stage(&runner, &experiments, 1).is_err());
+        assert_eq!(Installs::load(&runner).unwrap(), Installs::default());
+
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/su", "", false),
+            ("/usr/lib/cargo/bin/sudo", "", false),
+            ("/usr/lib/cargo/bin/visudo", "", false),
+        ]);
+        stage(&runner, &experiments, 1).unwrap();
+        let installs = Installs::load(&runner).unwrap();
+        let staged = &installs.experiments["sudo-rs"];
+        assert_eq!(staged.installed, 1);
+        assert_eq!(staged.replacements.len(), 3);
+        assert_eq!(staged.replacements[1].target, Path::new("/usr/bin/sudo"));
+        // Nothing was replaced.
+        assert!(runner.created_symlinks.clone().into_inner().is_empty());
+
+        unstage(&runner, &["coreutils".to_string()]).unwrap();
+        assert!(
+            Installs::load(&runner)
+                .unwrap()
+                .experiments
+                .contains_key("sudo-rs")
+        );
+        unstage(&runner, &["sudo-rs".to_string()]).unwrap();
+        assert_eq!(Installs::load(&runner).unwrap(), Installs::default());
+    }
+}
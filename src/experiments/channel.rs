@@ -0,0 +1,528 @@
+use super::Experiment;
+use crate::utils::{
+    Command, ErrorCode, StateFile, Worker, hex_digest, read_state, update_state, warn_or_fail,
+    write_state,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Read,
+    os::unix::fs::{PermissionsExt, symlink},
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info};
+
+/// The location of the record of the channel each experiment is installed from.
+pub const CHANNELS_PATH: &str = "/var/lib/oxidizr/channels.json";
+
+/// The directory into which the releases of experiments installed from upstream are unpacked,
+/// in a directory of their own for each experiment.
+pub const CHANNELS_DIRECTORY: &str = "/var/lib/oxidizr/channels";
+
+/// The GitHub API endpoint under which the releases of each upstream project are described.
+const GITHUB_API_URL: &str = "https://api.github.com/repos";
+
+/// The maximum size of a release archive that will be downloaded.
+const MAX_DOWNLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Where the replacement binaries of an experiment are installed from.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// The package in the archive of the distribution.
+    #[default]
+    Stable,
+    /// The latest release published by the upstream project.
+    Latest,
+    /// The newest build published by the upstream project, including pre-releases.
+    Nightly,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Latest => write!(f, "latest"),
+            Self::Nightly => write!(f, "nightly"),
+        }
+    }
+}
+
+/// A release of an experiment installed from an upstream channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ChannelRelease {
+    pub channel: Channel,
+    /// The tag of the release, such as `0.2.2`.
+    pub release: String,
+    /// Where the release archive was downloaded from.
+    pub url: String,
+    /// The sha256 digest of the release archive.
+    pub sha256: String,
+}
+
+/// The channel each experiment is installed from, by experiment name, and the release installed
+/// from the upstream channels. Experiments that aren't recorded are installed from the
+/// distribution.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Channels {
+    pub experiments: BTreeMap<String, Channel>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub releases: BTreeMap<String, ChannelRelease>,
+}
+
+impl StateFile for Channels {
+    const PATH: &'static str = CHANNELS_PATH;
+}
+
+impl Channels {
+    /// Load the recorded channels. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded channels.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Report the channel recorded for an experiment, which is the stable channel unless
+    /// recorded otherwise.
+    pub fn of(&self, experiment: &str) -> Channel {
+        self.experiments
+            .get(experiment)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Report the release of an experiment installed from its upstream channel, if it is still
+    /// the channel recorded for it.
+    pub fn release(&self, experiment: &str) -> Option<&ChannelRelease> {
+        self.releases
+            .get(experiment)
+            .filter(|r| r.channel == self.of(experiment))
+    }
+}
+
+/// Record the channel from which a set of experiments are to be installed. Only the experiments
+/// replacing GNU utilities are published upstream as release archives; the others can only be
+/// installed from the distribution.
+pub fn record_channel(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    channel: Channel,
+) -> Result<()> {
+    for e in experiments {
+        if channel != Channel::Stable && !matches!(e, Experiment::Uutils(_)) {
+            warn_or_fail(
+                system.strict(),
+                format!(
+                    "{} is only offered on the stable channel, so is installed from the distribution",
+                    e.name()
+                ),
+            )?;
+        }
+    }
+    update_state(system, |channels: &mut Channels| {
+        for e in experiments {
+            if let Experiment::Uutils(_) = e {
+                match channel {
+                    Channel::Stable => channels.experiments.remove(&e.name()),
+                    channel => channels.experiments.insert(e.name(), channel),
+                };
+            }
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// The directory into which the releases of an experiment are unpacked: the binaries are in
+/// `bin`, except for a unified binary, which is alongside it.
+pub fn channel_directory(experiment: &str) -> PathBuf {
+    Path::new(CHANNELS_DIRECTORY).join(experiment)
+}
+
+/// A subset of the release information returned by the GitHub API.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a GitHub release.
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    /// The digest of the file, such as `sha256:5f2b...`, for releases published since GitHub
+    /// began computing them.
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+/// The GitHub API endpoint describing the release of an upstream project on a channel: its
+/// latest release, or the newest of its releases, pre-releases included.
+fn release_url(upstream: &str, channel: Channel) -> Result<Option<String>> {
+    let repository = upstream
+        .strip_prefix("https://github.com/")
+        .with_context(|| format!("the upstream project {upstream} isn't hosted on GitHub"))?
+        .trim_end_matches('/');
+    Ok(match channel {
+        Channel::Stable => None,
+        Channel::Latest => Some(format!("{GITHUB_API_URL}/{repository}/releases/latest")),
+        Channel::Nightly => Some(format!("{GITHUB_API_URL}/{repository}/releases?per_page=1")),
+    })
+}
+
+/// Find the archive of a release built for Linux on `arch`, such as
+/// `coreutils-0.2.2-x86_64-unknown-linux-gnu.tar.gz`.
+fn archive_asset<'a>(release: &'a Release, arch: &str) -> Option<&'a Asset> {
+    let suffix = format!("-{arch}-unknown-linux-gnu.tar.gz");
+    release.assets.iter().find(|a| a.name.ends_with(&suffix))
+}
+
+/// Fetch a JSON document from the GitHub API.
+fn fetch<T: serde::de::DeserializeOwned>(url: &str) -> Result<T> {
+    debug!("Fetching {url}");
+    Ok(ureq::get(url)
+        .header("User-Agent", concat!("oxidizr/", env!("CARGO_PKG_VERSION")))
+        .call()?
+        .body_mut()
+        .read_json()?)
+}
+
+/// Download the contents of a URL into memory.
+fn download(url: &str) -> Result<Vec<u8>> {
+    debug!("Downloading {url}");
+    Ok(ureq::get(url)
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_SIZE)
+        .read_to_vec()?)
+}
+
+/// Install the release of an experiment from the upstream channel recorded for it, unless it is
+/// installed from the distribution or the release is already installed, into the channel
+/// directory of the experiment.
+pub fn install_channel(
+    system: &dyn Worker,
+    experiment: &str,
+    upstream: &str,
+    unified_binary: Option<&Path>,
+) -> Result<()> {
+    let channels = Channels::load(system)?;
+    let channel = channels.of(experiment);
+    if channel == Channel::Stable {
+        return Ok(());
+    }
+    if system.dry_run() {
+        info!("Would install the {channel} release of {experiment} from {upstream}");
+        return Ok(());
+    }
+
+    let directory = channel_directory(experiment);
+    let installed = channels.release(experiment);
+    let Some(release) = unpack_release(
+        system,
+        experiment,
+        upstream,
+        channel,
+        installed,
+        &directory,
+        unified_binary,
+    )?
+    else {
+        return Ok(());
+    };
+    update_state(system, |channels: &mut Channels| {
+        channels
+            .releases
+            .insert(experiment.to_string(), release.clone());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Download the release of an experiment on an upstream channel, unless it is the `installed`
+/// one, and unpack it into `directory`, returning what was unpacked. The archive is checked
+/// against the digest GitHub publishes for it, and its executables unpacked into `bin`. A unified
+/// binary is linked into `bin` under the name of each utility it lists, as the package of the
+/// distribution does.
+pub fn unpack_release(
+    system: &dyn Worker,
+    experiment: &str,
+    upstream: &str,
+    channel: Channel,
+    installed: Option<&ChannelRelease>,
+    directory: &Path,
+    unified_binary: Option<&Path>,
+) -> Result<Option<ChannelRelease>> {
+    let Some(url) = release_url(upstream, channel)? else {
+        return Ok(None);
+    };
+
+    info!("Checking for the {channel} release of {experiment}");
+    let release: Release = match channel {
+        Channel::Nightly => fetch::<Vec<Release>>(&url)?
+            .into_iter()
+            .next()
+            .with_context(|| format!("{upstream} has published no releases"))?,
+        _ => fetch(&url)?,
+    };
+    let arch = std::env::consts::ARCH;
+    let asset = archive_asset(&release, arch).ok_or_else(|| {
+        ErrorCode::ChannelUnavailable.error(format!(
+            "release {} of {experiment} has no archive for Linux on {arch}",
+            release.tag_name
+        ))
+    })?;
+    if let Some(installed) = installed
+        && installed.url == asset.browser_download_url
+    {
+        info!(
+            "Release {} of {experiment} is already installed",
+            installed.release
+        );
+        return Ok(None);
+    }
+
+    info!("Installing release {} of {experiment}", release.tag_name);
+    let archive = download(&asset.browser_download_url)?;
+    let sha256 = hex_digest(&archive);
+    match asset
+        .digest
+        .as_deref()
+        .and_then(|d| d.strip_prefix("sha256:"))
+    {
+        Some(expected) if expected != sha256 => {
+            return Err(ErrorCode::ChecksumMismatch.error(format!(
+                "checksum mismatch for {}: expected {expected}, got {sha256}",
+                asset.name
+            )));
+        }
+        Some(_) => debug!("Verified sha256 checksum of {}", asset.name),
+        None => warn_or_fail(
+            system.strict(),
+            format!(
+                "no checksum is published for {}, so it can't be verified",
+                asset.name
+            ),
+        )?,
+    }
+
+    let unified = unified_binary.and_then(|u| u.file_name());
+    unpack(&archive, directory, unified)?;
+    if let Some(unified) = unified {
+        let binary = directory.join(unified);
+        let output = system.run(&Command::build(&binary, &["--list"]))?;
+        let utilities = String::from_utf8_lossy(&output.stdout);
+        link_utilities(directory, &binary, utilities.split_whitespace())?;
+    }
+
+    Ok(Some(ChannelRelease {
+        channel,
+        release: release.tag_name.clone(),
+        url: asset.browser_download_url.clone(),
+        sha256,
+    }))
+}
+
+/// Unpack the executables of a gzipped release archive, wherever they are in it, into `bin` in
+/// `directory`, or into `directory` itself for the unified binary. Each is written alongside
+/// and renamed into place, so that a binary already linked to is replaced atomically.
+fn unpack(archive: &[u8], directory: &Path, unified: Option<&std::ffi::OsStr>) -> Result<()> {
+    let bin = directory.join("bin");
+    fs::create_dir_all(&bin).with_context(|| format!("unable to create {}", bin.display()))?;
+
+    let mut tarball = tar::Archive::new(GzDecoder::new(archive));
+    let mut unpacked = 0;
+    for entry in tarball.entries()? {
+        let mut entry = entry?;
+        let executable = entry.header().mode().is_ok_and(|m| m & 0o111 != 0);
+        if !entry.header().entry_type().is_file() || !executable {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let destination = match Some(name) == unified {
+            true => directory.join(name),
+            false => bin.join(name),
+        };
+
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        let staged = destination.with_extension("oxidizr-new");
+        fs::write(&staged, &contents)
+            .with_context(|| format!("unable to write {}", staged.display()))?;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+        fs::rename(&staged, &destination)
+            .with_context(|| format!("unable to install {}", destination.display()))?;
+        unpacked += 1;
+    }
+    anyhow::ensure!(unpacked > 0, "the release archive holds no executables");
+    Ok(())
+}
+
+/// Link each utility of a unified binary into `bin` in `directory`, named after the utility.
+fn link_utilities<'a>(
+    directory: &Path,
+    binary: &Path,
+    utilities: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+    for utility in utilities {
+        replace_symlink(binary, &directory.join("bin").join(utility))?;
+    }
+    Ok(())
+}
+
+/// Point `link` at `binary`, replacing whatever is there atomically, unless it already does.
+pub fn replace_symlink(binary: &Path, link: &Path) -> Result<()> {
+    if fs::read_link(link).is_ok_and(|to| to == binary) {
+        return Ok(());
+    }
+    let staged = link.with_extension("oxidizr-new");
+    let _ = fs::remove_file(&staged);
+    symlink(binary, &staged).with_context(|| format!("unable to link {}", link.display()))?;
+    fs::rename(&staged, link)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_channels() {
+        let runner = MockSystem::default();
+        let experiments = crate::experiments::all_experiments(&runner);
+        let (coreutils, sudo_rs): (Vec<_>, Vec<_>) = experiments
+            .into_iter()
+            .partition(|e| matches!(e, Experiment::Uutils(_)));
+
+        record_channel(&runner, &coreutils[..1], Channel::Nightly).unwrap();
+        let mut channels = Channels::load(&runner).unwrap();
+        assert_eq!(channels.of("coreutils"), Channel::Nightly);
+        assert_eq!(channels.of("findutils"), Channel::Stable);
+
+        // A release is only the installed one while its channel is recorded.
+        channels.releases.insert(
+            "coreutils".to_string(),
+            ChannelRelease {
+                channel: Channel::Latest,
+                release: "0.2.2".to_string(),
+                url: "https://example.com/coreutils.tar.gz".to_string(),
+                sha256: String::new(),
+            },
+        );
+        assert_eq!(channels.release("coreutils"), None);
+        channels
+            .experiments
+            .insert("coreutils".to_string(), Channel::Latest);
+        assert!(channels.release("coreutils").is_some());
+
+        // Going back to the distribution forgets the channel.
+        record_channel(&runner, &coreutils[..1], Channel::Stable).unwrap();
+        assert_eq!(Channels::load(&runner).unwrap(), Channels::default());
+
+        // Only experiments replacing GNU utilities are offered upstream.
+        assert!(record_channel(&runner, &sudo_rs[..1], Channel::Latest).is_ok());
+        assert_eq!(Channels::load(&runner).unwrap(), Channels::default());
+    }
+
+    #[test]
+    fn test_release_url() {
+        let upstream = "https://github.com/uutils/coreutils";
+        assert_eq!(release_url(upstream, Channel::Stable).unwrap(), None);
+        assert_eq!(
+            release_url(upstream, Channel::Latest).unwrap().unwrap(),
+            "https://api.github.com/repos/uutils/coreutils/releases/latest"
+        );
+        assert_eq!(
+            release_url(upstream, Channel::Nightly).unwrap().unwrap(),
+            "https://api.github.com/repos/uutils/coreutils/releases?per_page=1"
+        );
+        assert!(release_url("https://example.com/sudo-rs", Channel::Latest).is_err());
+    }
+
+    #[test]
+    fn test_archive_asset() {
+        let asset = |name: &str| Asset {
+            name: name.to_string(),
+            browser_download_url: format!("https://example.com/{name}"),
+            digest: None,
+        };
+        let release = Release {
+            tag_name: "0.2.2".to_string(),
+            assets: vec![
+                asset("coreutils-0.2.2-aarch64-unknown-linux-gnu.tar.gz"),
+                asset("coreutils-0.2.2-x86_64-unknown-linux-gnu.tar.gz"),
+                asset("coreutils-0.2.2-x86_64-pc-windows-msvc.zip"),
+            ],
+        };
+        assert_eq!(
+            archive_asset(&release, "x86_64").unwrap().name,
+            "coreutils-0.2.2-x86_64-unknown-linux-gnu.tar.gz"
+        );
+        assert!(archive_asset(&release, "riscv64").is_none());
+    }
+
+    #[test]
+    fn test_unpack() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::fast(),
+        ));
+        let mut append = |path: &str, mode: u32| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_mode(mode);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &b"\x7fELF"[..])
+                .unwrap();
+        };
+        append("coreutils-0.2.2-x86_64-unknown-linux-gnu/coreutils", 0o755);
+        append("coreutils-0.2.2-x86_64-unknown-linux-gnu/README.md", 0o644);
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let directory =
+            std::env::temp_dir().join(format!("oxidizr-channel-{}", std::process::id()));
+        unpack(
+            &archive,
+            &directory,
+            Some(std::ffi::OsStr::new("coreutils")),
+        )
+        .unwrap();
+        let binary = directory.join("coreutils");
+        assert_eq!(fs::read(&binary).unwrap(), b"\x7fELF");
+        assert!(!directory.join("bin/README.md").exists());
+
+        link_utilities(&directory, &binary, ["ls", "cp"]).unwrap();
+        link_utilities(&directory, &binary, ["ls"]).unwrap();
+        assert_eq!(fs::read_link(directory.join("bin/ls")).unwrap(), binary);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
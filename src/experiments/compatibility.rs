@@ -0,0 +1,306 @@
+use super::Experiment;
+use crate::utils::Distribution;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// The mount table read to find whether `/usr` can be written to.
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+
+/// Why an experiment can't be enabled on this system.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Incompatibility {
+    /// The distribution release couldn't be determined.
+    UnknownDistribution { error: String },
+    /// The experiment supports no release of the distribution at all.
+    DistributionUnsupported {
+        distribution: String,
+        supported: Vec<String>,
+    },
+    /// The release is older than the oldest release of the distribution supported.
+    ReleaseTooOld { release: String, minimum: String },
+    /// The release isn't among those supported, though it isn't older than them, such as an
+    /// interim release.
+    ReleaseUnsupported {
+        release: String,
+        supported: Vec<String>,
+    },
+    /// The release isn't a long-term support release, which the `lts-only` policy requires.
+    NotLts { release: String },
+    /// The package of the replacements isn't built for the architecture of the system.
+    PackageUnavailable {
+        package: String,
+        architecture: String,
+    },
+    /// The files to replace are on a filesystem mounted read-only, as on image-based systems.
+    ImmutableUsr { mount_point: PathBuf },
+    /// A policy compiled in by an organization refused the experiment.
+    Policy { reason: String },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatibility::UnknownDistribution { error } => {
+                write!(f, "unable to determine the distribution release: {error}")
+            }
+            Incompatibility::DistributionUnsupported { distribution, .. } => {
+                write!(f, "{distribution} isn't supported")
+            }
+            Incompatibility::ReleaseTooOld { release, minimum } => write!(
+                f,
+                "{release} is older than {minimum}, the oldest release supported"
+            ),
+            Incompatibility::ReleaseUnsupported { release, .. } => {
+                write!(f, "{release} isn't a supported release")
+            }
+            Incompatibility::NotLts { release } => write!(f, "{release} is not an LTS release"),
+            Incompatibility::PackageUnavailable {
+                package,
+                architecture,
+            } => write!(f, "{package} isn't available for {architecture}"),
+            Incompatibility::ImmutableUsr { mount_point } => write!(
+                f,
+                "{} is mounted read-only, so /usr can't be changed",
+                mount_point.display()
+            ),
+            Incompatibility::Policy { reason } => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// A rule deciding whether an experiment may be enabled on the running distribution release.
+/// Every policy in effect must allow an experiment for it to be considered compatible.
+///
+/// Organizations can compile in their own gating rules by implementing this trait and adding
+/// them to those returned by [`policies`], or choose among the built-in rules in the
+/// `[compatibility]` section of the configuration.
+pub trait CompatibilityPolicy {
+    /// Check whether the policy allows an experiment on a release, reporting why not if it
+    /// doesn't. Policies of their own report `Incompatibility::Policy`.
+    fn check(
+        &self,
+        experiment: &Experiment,
+        distribution: &Distribution,
+    ) -> Result<(), Incompatibility>;
+}
+
+/// The policy that always applies: an experiment is only allowed on the releases its
+/// replacements are known to work on. Experiments that list no releases, such as binary
+/// overrides, are allowed on any.
+#[derive(Debug, Clone, Copy)]
+pub struct SupportedReleases;
+
+impl CompatibilityPolicy for SupportedReleases {
+    fn check(
+        &self,
+        experiment: &Experiment,
+        distribution: &Distribution,
+    ) -> Result<(), Incompatibility> {
+        let supported = experiment.supported_releases();
+        if supported.is_empty() || supported.contains(&distribution.base()) {
+            return Ok(());
+        }
+        let releases: Vec<&Distribution> = supported
+            .iter()
+            .filter(|d| d.id == distribution.id)
+            .collect();
+        let names = |releases: &[&Distribution]| releases.iter().map(|d| d.to_string()).collect();
+        if releases.is_empty() {
+            return Err(Incompatibility::DistributionUnsupported {
+                distribution: distribution.id.clone(),
+                supported: names(&supported.iter().collect::<Vec<_>>()),
+            });
+        }
+        let oldest = releases
+            .iter()
+            .filter_map(|d| release_number(&d.release).map(|n| (n, *d)))
+            .min_by(|(a, _), (b, _)| a.cmp(b));
+        match (release_number(&distribution.release), oldest) {
+            (Some(release), Some((minimum, oldest))) if release < minimum => {
+                Err(Incompatibility::ReleaseTooOld {
+                    release: distribution.base().to_string(),
+                    minimum: oldest.to_string(),
+                })
+            }
+            _ => Err(Incompatibility::ReleaseUnsupported {
+                release: distribution.base().to_string(),
+                supported: names(&releases),
+            }),
+        }
+    }
+}
+
+/// Parse a release such as `24.04` into its numbers, for comparing releases in order.
+fn release_number(release: &str) -> Option<Vec<u32>> {
+    release.split('.').map(|n| n.parse().ok()).collect()
+}
+
+/// The policy that always applies: experiments replace files under `/usr`, so can't be enabled
+/// where it is mounted read-only, as on image-based systems whose `/usr` comes from an image.
+#[derive(Debug, Clone, Copy)]
+pub struct WritableUsr;
+
+impl CompatibilityPolicy for WritableUsr {
+    fn check(&self, experiment: &Experiment, _: &Distribution) -> Result<(), Incompatibility> {
+        let mounts = experiment
+            .system()
+            .read_file(Path::new(MOUNTS_PATH))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        match read_only_usr(&mounts) {
+            Some(mount_point) => Err(Incompatibility::ImmutableUsr { mount_point }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Find the mount point of the filesystem holding `/usr` in a mount table, if it is mounted
+/// read-only. The last mount at `/usr`, or at `/` if `/usr` isn't a mount of its own, is the one
+/// in effect.
+fn read_only_usr(mounts: &str) -> Option<PathBuf> {
+    let mut usr = None;
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (Some(mount_point), Some(options)) = (fields.get(1), fields.get(3)) else {
+            continue;
+        };
+        let read_only = options.split(',').any(|o| o == "ro");
+        match *mount_point {
+            "/usr" => usr = Some(("/usr", read_only)),
+            "/" if usr.is_none_or(|(m, _)| m == "/") => usr = Some(("/", read_only)),
+            _ => (),
+        }
+    }
+    usr.filter(|(_, read_only)| *read_only)
+        .map(|(mount_point, _)| PathBuf::from(mount_point))
+}
+
+/// A policy only allowing experiments on long-term support releases of Ubuntu, which are those
+/// released in April of even years.
+#[derive(Debug, Clone, Copy)]
+pub struct LtsOnly;
+
+impl CompatibilityPolicy for LtsOnly {
+    fn check(&self, _: &Experiment, distribution: &Distribution) -> Result<(), Incompatibility> {
+        let lts = distribution
+            .release
+            .split_once('.')
+            .is_some_and(|(year, month)| {
+                month == "04" && year.parse::<u32>().is_ok_and(|y| y % 2 == 0)
+            });
+        match lts {
+            true => Ok(()),
+            false => Err(Incompatibility::NotLts {
+                release: distribution.base().to_string(),
+            }),
+        }
+    }
+}
+
+/// The built-in policies that can be selected in the configuration, in addition to the
+/// supported releases of each experiment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyName {
+    /// Only allow experiments on LTS releases.
+    LtsOnly,
+}
+
+/// Build the policies in effect: the supported releases of each experiment and a writable `/usr`,
+/// followed by those selected in the configuration.
+pub fn policies(selected: &[PolicyName]) -> Vec<Box<dyn CompatibilityPolicy>> {
+    let mut policies: Vec<Box<dyn CompatibilityPolicy>> =
+        vec![Box::new(SupportedReleases), Box::new(WritableUsr)];
+    for name in selected {
+        policies.push(match name {
+            PolicyName::LtsOnly => Box::new(LtsOnly),
+        });
+    }
+    policies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{SudoRsExperiment, all_experiments};
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_lts_only() {
+        let runner = MockSystem::default();
+        let coreutils = &all_experiments(&runner)[0];
+
+        assert!(
+            LtsOnly
+                .check(coreutils, &Distribution::new("Ubuntu", "24.04"))
+                .is_ok()
+        );
+        assert_eq!(
+            LtsOnly
+                .check(coreutils, &Distribution::new("Ubuntu", "24.10"))
+                .unwrap_err()
+                .to_string(),
+            "Ubuntu 24.10 is not an LTS release"
+        );
+        assert!(
+            LtsOnly
+                .check(coreutils, &Distribution::new("Ubuntu", "25.04"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_configured_policies() {
+        let runner = MockSystem::default().with_policies(&[PolicyName::LtsOnly]);
+        let coreutils = &all_experiments(&runner)[0];
+        assert!(coreutils.check_compatible());
+
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "24.10"))
+            .with_policies(&[PolicyName::LtsOnly]);
+        let coreutils = &all_experiments(&runner)[0];
+        assert_eq!(
+            coreutils.compatibility(),
+            Err(Incompatibility::NotLts {
+                release: "Ubuntu 24.10".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_supported_releases() {
+        let runner = MockSystem::default();
+        let sudors = Experiment::SudoRs(SudoRsExperiment::new(&runner));
+        let check = |id, release| SupportedReleases.check(&sudors, &Distribution::new(id, release));
+
+        assert!(matches!(
+            check("Ubuntu", "22.04"),
+            Err(Incompatibility::ReleaseTooOld { minimum, .. }) if minimum == "Ubuntu 24.04"
+        ));
+        assert!(matches!(
+            check("Fedora", "41"),
+            Err(Incompatibility::DistributionUnsupported { .. })
+        ));
+        assert!(matches!(
+            check("Ubuntu", "99.10"),
+            Err(Incompatibility::ReleaseUnsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_only_usr() {
+        let writable = "/dev/sda1 / ext4 rw,relatime 0 0\n";
+        assert_eq!(read_only_usr(writable), None);
+        let image = "/dev/sda1 / ext4 rw,relatime 0 0\n/dev/dm-0 /usr erofs ro,relatime 0 0\n";
+        assert_eq!(read_only_usr(image), Some(PathBuf::from("/usr")));
+        assert_eq!(
+            read_only_usr("/dev/sda1 / btrfs ro,noatime 0 0\n"),
+            Some(PathBuf::from("/"))
+        );
+    }
+}
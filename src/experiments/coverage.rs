@@ -0,0 +1,117 @@
+use crate::utils::{Command, Worker};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// How many of the options accepted by a GNU binary are also accepted by its replacement.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct OptionCoverage {
+    pub binary: String,
+    /// The number of options accepted by the GNU binary.
+    pub total: usize,
+    /// The number of those options that the replacement also accepts.
+    pub supported: usize,
+    /// The options accepted by the GNU binary, but not by the replacement.
+    pub missing: Vec<String>,
+}
+
+impl OptionCoverage {
+    /// Compare the options accepted by a GNU binary with those accepted by its replacement.
+    pub fn new(binary: &str, gnu: &BTreeSet<String>, replacement: &BTreeSet<String>) -> Self {
+        let missing: Vec<String> = gnu.difference(replacement).cloned().collect();
+        Self {
+            binary: binary.to_string(),
+            total: gnu.len(),
+            supported: gnu.len() - missing.len(),
+            missing,
+        }
+    }
+}
+
+/// The percentage of options covered across a set of binaries, rounded down.
+pub fn coverage_percent(coverage: &[OptionCoverage]) -> usize {
+    let total: usize = coverage.iter().map(|c| c.total).sum();
+    let supported: usize = coverage.iter().map(|c| c.supported).sum();
+    match total {
+        0 => 100,
+        _ => supported * 100 / total,
+    }
+}
+
+/// Run a command printing usage information, such as `ls --help`, and extract the options it
+/// documents. A command that fails documents no options.
+pub fn help_options(system: &dyn Worker, cmd: &Command) -> BTreeSet<String> {
+    system
+        .run(cmd)
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|help| parse_options(&help))
+        .unwrap_or_default()
+}
+
+/// Extract the options documented in usage information. Both GNU and uutils list each option on
+/// a line of its own, as in `  -a, --all    do not ignore entries starting with .`, with the
+/// option names separated from their description by at least two spaces. uutils lists any
+/// aliases of an option after its description, as in `[aliases: --utc]`.
+fn parse_options(help: &str) -> BTreeSet<String> {
+    help.lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with('-'))
+        .flat_map(|line| {
+            let names = line.split("  ").next().unwrap_or_default();
+            let aliases = line
+                .split_once("[aliases: ")
+                .and_then(|(_, aliases)| aliases.split_once(']'))
+                .map_or("", |(aliases, _)| aliases);
+            [names, aliases]
+                .into_iter()
+                .flat_map(|names| names.split([',', ' ']))
+                .filter_map(|word| word.split(['=', '[', '<']).next())
+                .filter(|word| is_option(word))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Report whether a word is an option name, such as `-a` or `--all`.
+fn is_option(word: &str) -> bool {
+    let name = word
+        .strip_prefix("--")
+        .or_else(|| word.strip_prefix('-'))
+        .unwrap_or_default();
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        let gnu = "Usage: ls [OPTION]... [FILE]...\n\
+        List information about the FILEs (the current directory by default).\n\n\
+        Mandatory arguments to long options are mandatory for short options too.\n  \
+          -a, --all                  do not ignore entries starting with .\n      \
+              --author               with -l, print the author of each file\n      \
+              --block-size=SIZE      with -l, scale sizes by SIZE when printing them;\n                               \
+                                     e.g., '--block-size=M'; see SIZE format below\n      \
+              --color[=WHEN]         color the output WHEN\n";
+        let uutils = "Usage: ls [OPTION]... [FILE]...\n\nOptions:\n  \
+          -a, --all\n          Do not ignore hidden files\n      \
+              --block-size <BLOCK_SIZE>  scale sizes by BLOCK_SIZE\n      \
+              --color [<color>]      Color output based on file type.\n";
+
+        let gnu = parse_options(gnu);
+        assert_eq!(
+            gnu.iter().map(String::as_str).collect::<Vec<_>>(),
+            vec!["--all", "--author", "--block-size", "--color", "-a"]
+        );
+
+        let coverage = OptionCoverage::new("ls", &gnu, &parse_options(uutils));
+        assert_eq!(coverage.supported, 4);
+        assert_eq!(coverage.missing, vec!["--author"]);
+        assert_eq!(coverage_percent(&[coverage]), 80);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::utils::{StateFile, Worker, read_state, write_state};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// The location of the record of the directories each enabled experiment manages.
+pub const DIRECTORIES_PATH: &str = "/var/lib/oxidizr/directories.json";
+
+/// The directories of system binaries on distributions that have merged /bin into /usr/bin, and
+/// /sbin into /usr/sbin, such as Ubuntu and Debian 12 onwards.
+const MERGED_USR_DIRECTORIES: &[&str] = &["/usr/bin", "/usr/sbin"];
+
+/// The directories of system binaries on distributions that keep /bin and /sbin apart from /usr.
+const SPLIT_USR_DIRECTORIES: &[&str] = &["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+
+/// The directories configured for the files each experiment replaces, by experiment name, when
+/// it was enabled. They are recorded, as the pins are, so that reapplying, listing and disabling
+/// it manage the same files, even if the configuration has changed since. Experiments without
+/// any use those of the layout of the distribution.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Directories {
+    pub experiments: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl StateFile for Directories {
+    const PATH: &'static str = DIRECTORIES_PATH;
+}
+
+impl Directories {
+    /// Load the recorded directories. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded directories.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Report the directories configured for an experiment, if any.
+    pub fn of(&self, experiment: &str) -> &[PathBuf] {
+        self.experiments
+            .get(experiment)
+            .map_or(&[], |directories| directories.as_slice())
+    }
+
+    /// Set the directories configured for an experiment, forgetting it if there are none.
+    pub fn set(&mut self, experiment: &str, directories: &[PathBuf]) {
+        match directories.is_empty() {
+            true => self.experiments.remove(experiment),
+            false => self
+                .experiments
+                .insert(experiment.to_string(), directories.to_vec()),
+        };
+    }
+
+    /// Find the system file an experiment replaces with its binary `name`: the first of the
+    /// directories configured for it holding the file, or the first of them if none does.
+    /// Without any configured, the file is found on the PATH, or else in the directories of the
+    /// layout of the distribution.
+    pub fn locate(&self, system: &dyn Worker, experiment: &str, name: &OsStr) -> PathBuf {
+        let configured = self.of(experiment);
+        if configured.is_empty()
+            && let Ok(path) = system.which(name)
+        {
+            return path;
+        }
+        let directories = match configured.is_empty() {
+            true => layout_directories(system),
+            false => configured.to_vec(),
+        };
+        directories
+            .iter()
+            .map(|d| d.join(name))
+            .find(|path| exists(system, path))
+            .unwrap_or_else(|| directories[0].join(name))
+    }
+}
+
+/// The directories of system binaries for the layout of the distribution, most used first.
+pub fn layout_directories(system: &dyn Worker) -> Vec<PathBuf> {
+    let merged = system
+        .read_symlink(Path::new("/bin"))
+        .ok()
+        .flatten()
+        .is_some_and(|target| target.ends_with("usr/bin"));
+    let directories = match merged {
+        true => MERGED_USR_DIRECTORIES,
+        false => SPLIT_USR_DIRECTORIES,
+    };
+    directories.iter().map(PathBuf::from).collect()
+}
+
+/// Report whether a file exists, as a regular file or a symlink.
+fn exists(system: &dyn Worker, path: &Path) -> bool {
+    matches!(system.file_size(path), Ok(Some(_)))
+        || matches!(system.read_symlink(path), Ok(Some(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_locate() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/bin/date", "", true),
+            ("/sbin/chroot", "", false),
+        ]);
+        let mut directories = Directories::default();
+        // Without directories configured, files are found on the PATH, or in those of the
+        // layout, defaulting to the first.
+        let locate = |d: &Directories, name: &str| d.locate(&runner, "coreutils", name.as_ref());
+        assert_eq!(locate(&directories, "date"), Path::new("/usr/bin/date"));
+        assert_eq!(locate(&directories, "chroot"), Path::new("/sbin/chroot"));
+        assert_eq!(locate(&directories, "nproc"), Path::new("/usr/bin/nproc"));
+
+        directories.set("coreutils", &[PathBuf::from("/usr/sbin")]);
+        assert_eq!(locate(&directories, "date"), Path::new("/usr/sbin/date"));
+        directories.save(&runner).unwrap();
+        assert_eq!(Directories::load(&runner).unwrap(), directories);
+    }
+}
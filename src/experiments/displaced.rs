@@ -0,0 +1,174 @@
+use super::{Experiment, PackageCache};
+use crate::utils::{StateFile, Worker, read_state, update_state, warn_or_fail, write_state};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+/// The location of the record of the GNU packages removed when installing replacements.
+pub const DISPLACED_PATH: &str = "/var/lib/oxidizr/displaced.json";
+
+/// A GNU package that was removed by the package manager when its replacement was installed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplacedPackage {
+    pub package: String,
+    /// The version that was installed before it was removed.
+    pub version: String,
+}
+
+/// The GNU packages displaced by installing the replacement of each experiment, by experiment
+/// name, such as where the replacement package conflicts with the package it replaces. Disabling
+/// an experiment reinstalls them, so that the system returns to a supported baseline.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Displaced {
+    pub experiments: BTreeMap<String, DisplacedPackage>,
+}
+
+impl StateFile for Displaced {
+    const PATH: &'static str = DISPLACED_PATH;
+}
+
+impl Displaced {
+    /// Load the record. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the record.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+}
+
+/// Report the installed version of the GNU package replaced by each experiment, by experiment
+/// name, for those that are installed.
+pub fn installed_replaced(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+) -> BTreeMap<String, DisplacedPackage> {
+    experiments
+        .iter()
+        .filter_map(|e| {
+            let package = e.metadata().replaces.to_string();
+            let version = system.installed_version(&package).ok()??.to_string();
+            Some((e.name(), DisplacedPackage { package, version }))
+        })
+        .collect()
+}
+
+/// Record the GNU packages that were installed before the replacements were, as reported by
+/// `installed_replaced`, but no longer are.
+pub fn record_displaced(
+    system: &dyn Worker,
+    before: BTreeMap<String, DisplacedPackage>,
+) -> Result<()> {
+    let mut displaced: BTreeMap<String, DisplacedPackage> = before
+        .into_iter()
+        .filter(|(_, d)| !system.check_installed(&d.package).unwrap_or(true))
+        .collect();
+    if displaced.is_empty() {
+        return Ok(());
+    }
+    for (name, d) in &displaced {
+        warn!(
+            "Installing the replacement for {name} removed {} {}, which will be reinstalled when \
+            it is disabled",
+            d.package, d.version
+        );
+    }
+    update_state(system, |record: &mut Displaced| {
+        record.experiments.append(&mut displaced);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Reinstall the GNU packages displaced by the replacements of the named experiments, at the
+/// versions they were displaced at: from the package file cached when the experiments were
+/// enabled if there is one, or from the package archive if it still has that version. Otherwise,
+/// the current version is installed, with a warning, or the operation fails in strict mode.
+pub fn restore_displaced(system: &dyn Worker, names: &[String]) -> Result<()> {
+    let record = Displaced::load(system)?;
+    let cache = PackageCache::load(system).unwrap_or_default();
+    let mut restored = Vec::new();
+    for name in names {
+        let Some(DisplacedPackage { package, version }) = record.experiments.get(name) else {
+            continue;
+        };
+        if system.check_installed(package)? {
+            info!("{package}, displaced by {}, is installed again", name);
+        } else if let Some(file) = cache.file(system, package, version) {
+            info!("Reinstalling {package} {version}, displaced by {}", name);
+            system.install_package_file(&file)?;
+        } else if system.available_versions(package)?.contains(version) {
+            info!("Reinstalling {package} {version}, displaced by {}", name);
+            system.install_package_version(package, version)?;
+        } else {
+            warn_or_fail(
+                system.strict(),
+                format!(
+                    "Version {version} of {package}, displaced by {}, is neither cached nor \
+                    available, so the current version is installed",
+                    name
+                ),
+            )?;
+            system.install_package(package)?;
+        }
+        restored.push(name.clone());
+    }
+
+    if !restored.is_empty() {
+        update_state(system, |record: &mut Displaced| {
+            record.experiments.retain(|n, _| !restored.contains(n));
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_displaced_packages() {
+        let runner = MockSystem::default();
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["coreutils", "sudo-rs"].contains(&e.name().as_str()))
+            .collect();
+        runner.mock_install_package("coreutils");
+        runner.mock_install_package("sudo");
+        runner.mock_command("dpkg-query -W -f=${Version} coreutils", "9.4-3ubuntu6");
+        runner.mock_command("dpkg-query -W -f=${Version} sudo", "1.9.15p5-3");
+
+        // Installing rust-coreutils removes coreutils.
+        let before = installed_replaced(&runner, &experiments);
+        assert_eq!(before.len(), 2);
+        runner
+            .installed_packages
+            .borrow_mut()
+            .retain(|p| p != "coreutils");
+        record_displaced(&runner, before).unwrap();
+        let record = Displaced::load(&runner).unwrap();
+        assert_eq!(
+            record.experiments.keys().collect::<Vec<_>>(),
+            vec!["coreutils"]
+        );
+
+        // Disabling coreutils reinstalls the version displaced.
+        runner.mock_command(
+            "apt-cache madison coreutils",
+            " coreutils | 9.4-3ubuntu6 | http://archive.ubuntu.com/ubuntu noble/main amd64 Packages\n",
+        );
+        restore_displaced(&runner, &["coreutils".to_string()]).unwrap();
+        assert!(
+            runner.commands.borrow().contains(
+                &"apt-get install -y --allow-downgrades coreutils=9.4-3ubuntu6".to_string()
+            )
+        );
+        assert_eq!(Displaced::load(&runner).unwrap(), Displaced::default());
+    }
+}
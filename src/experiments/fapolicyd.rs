@@ -0,0 +1,62 @@
+use crate::utils::{Command, Worker};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tracing::{debug, info, warn};
+
+/// Report whether fapolicyd is present on the system. Hosts running fapolicyd deny execution of
+/// binaries that aren't in its trust database, which includes the replacement binaries.
+pub(super) fn detected(system: &dyn Worker) -> bool {
+    system.which("fapolicyd-cli".as_ref()).is_ok()
+}
+
+/// Add the replacement binaries to the fapolicyd trust database, so that they can be executed
+/// once the system files are replaced.
+pub(super) fn trust(system: &dyn Worker, files: &[PathBuf]) -> Result<()> {
+    if files.is_empty() || !detected(system) {
+        return Ok(());
+    }
+
+    info!(
+        "Adding {} replacement binaries to fapolicyd trust",
+        files.len()
+    );
+    for file in files {
+        system
+            .run(&Command::build("fapolicyd-cli", &["--file", "add"]).arg(file))
+            .with_context(|| {
+                format!(
+                    "unable to add {} to the fapolicyd trust database",
+                    file.display()
+                )
+            })?;
+    }
+    system.run(&Command::build("fapolicyd-cli", &["--update"]))?;
+    Ok(())
+}
+
+/// Remove the replacement binaries from the fapolicyd trust database. Failures are logged rather
+/// than returned, as the files have already been restored by this point.
+pub(super) fn untrust(system: &dyn Worker, files: &[PathBuf]) {
+    if files.is_empty() || !detected(system) {
+        return;
+    }
+
+    info!(
+        "Removing {} replacement binaries from fapolicyd trust",
+        files.len()
+    );
+    for file in files {
+        if let Err(e) =
+            system.run(&Command::build("fapolicyd-cli", &["--file", "delete"]).arg(file))
+        {
+            debug!(
+                "Unable to remove {} from fapolicyd trust: {}",
+                file.display(),
+                e
+            );
+        }
+    }
+    if let Err(e) = system.run(&Command::build("fapolicyd-cli", &["--update"])) {
+        warn!("Unable to update the fapolicyd trust database: {}", e);
+    }
+}
@@ -0,0 +1,296 @@
+use super::{
+    CHANNELS_PATH, DIRECTORIES_PATH, Experiment, OVERRIDES_PATH, PINS_PATH, STRATEGIES_PATH,
+};
+use crate::utils::{ErrorCode, StateFile, Worker, read_state, update_state, write_state};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info};
+
+/// The location of the record of the generations.
+pub const GENERATIONS_PATH: &str = "/var/lib/oxidizr/generations.json";
+
+/// The most generations kept, the oldest being forgotten first.
+pub const MAX_GENERATIONS: usize = 20;
+
+/// The state files that, along with the experiments enabled, decide which files are replaced and
+/// how. A generation records their contents, so that switching back to it replaces the same
+/// files in the same way.
+const GENERATION_STATE: &[&str] = &[
+    PINS_PATH,
+    DIRECTORIES_PATH,
+    STRATEGIES_PATH,
+    CHANNELS_PATH,
+    OVERRIDES_PATH,
+];
+
+/// The configuration of the system after an operation: the experiments enabled, the files linked
+/// to their replacements, and the state deciding them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Generation {
+    pub number: u32,
+    /// When the generation was recorded, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The operation that produced it, such as `enable`.
+    pub command: String,
+    pub experiments: Vec<String>,
+    /// The replaced files, each with the replacement binary it links to.
+    pub links: BTreeMap<PathBuf, PathBuf>,
+    /// The contents of the state files in `GENERATION_STATE` that existed, by path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub state: BTreeMap<PathBuf, String>,
+}
+
+/// How a generation differs from the one before it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub struct GenerationDiff {
+    /// The experiments enabled that weren't before.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enabled: Vec<String>,
+    /// The experiments no longer enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disabled: Vec<String>,
+    /// The files newly linked, or linked to a different replacement.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub linked: Vec<PathBuf>,
+    /// The files no longer linked, which are GNU again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unlinked: Vec<PathBuf>,
+}
+
+impl GenerationDiff {
+    /// Report whether the generations are the same, as far as the files replaced are concerned.
+    pub fn is_empty(&self) -> bool {
+        self.enabled.is_empty()
+            && self.disabled.is_empty()
+            && self.linked.is_empty()
+            && self.unlinked.is_empty()
+    }
+}
+
+impl Generation {
+    /// Capture the configuration of the system as it is now.
+    pub fn capture(system: &dyn Worker, experiments: &[Experiment]) -> Result<Self> {
+        let mut generation = Self::default();
+        for e in experiments.iter().filter(|e| e.check_installed()) {
+            let linked: Vec<PathBuf> = e
+                .check_replaced()?
+                .into_iter()
+                .filter(|(_, linked)| *linked)
+                .map(|(target, _)| target)
+                .collect();
+            if linked.is_empty() {
+                continue;
+            }
+            generation.experiments.push(e.name());
+            for (source, target) in e.replacements()? {
+                if linked.contains(&target) {
+                    generation.links.insert(target, source);
+                }
+            }
+        }
+        for path in GENERATION_STATE {
+            if let Some(contents) = system.read_file(Path::new(path))? {
+                generation.state.insert(PathBuf::from(path), contents);
+            }
+        }
+        Ok(generation)
+    }
+
+    /// Describe how the generation differs from `previous`, or from a system without any
+    /// experiments enabled if there is none.
+    pub fn diff(&self, previous: Option<&Generation>) -> GenerationDiff {
+        let empty = Generation::default();
+        let previous = previous.unwrap_or(&empty);
+        GenerationDiff {
+            enabled: self
+                .experiments
+                .iter()
+                .filter(|e| !previous.experiments.contains(e))
+                .cloned()
+                .collect(),
+            disabled: previous
+                .experiments
+                .iter()
+                .filter(|e| !self.experiments.contains(e))
+                .cloned()
+                .collect(),
+            linked: self
+                .links
+                .iter()
+                .filter(|(target, source)| previous.links.get(*target) != Some(*source))
+                .map(|(target, _)| target.clone())
+                .collect(),
+            unlinked: previous
+                .links
+                .keys()
+                .filter(|target| !self.links.contains_key(*target))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Report whether the generation configures the system as `other` does.
+    fn same_configuration(&self, other: &Generation) -> bool {
+        self.experiments == other.experiments
+            && self.links == other.links
+            && self.state == other.state
+    }
+
+    /// Write the state files recorded in the generation back in place, removing those it had
+    /// none of.
+    pub fn restore_state(&self, system: &dyn Worker) -> Result<()> {
+        for path in GENERATION_STATE.iter().map(Path::new) {
+            match self.state.get(path) {
+                Some(contents) => system.write_file(path, contents)?,
+                None if system.read_file(path)?.is_some() => system.remove_file(path)?,
+                None => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The configurations the system has been in after each operation, oldest first, so that it can
+/// be switched back to any of them with `oxidizr rollback --generation`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Generations {
+    pub generations: Vec<Generation>,
+}
+
+impl StateFile for Generations {
+    const PATH: &'static str = GENERATIONS_PATH;
+}
+
+impl Generations {
+    /// Load the record. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the record.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Find a generation by its number, failing if it was never recorded or has been forgotten.
+    pub fn get(&self, number: u32) -> Result<&Generation> {
+        self.generations
+            .iter()
+            .find(|g| g.number == number)
+            .ok_or_else(|| {
+                ErrorCode::GenerationNotFound.error(format!(
+                    "Generation {number} isn't recorded: run 'oxidizr generations' to list them"
+                ))
+            })
+    }
+
+    /// The generation before `generation`, if it is still recorded.
+    pub fn previous(&self, generation: &Generation) -> Option<&Generation> {
+        self.generations
+            .iter()
+            .rev()
+            .find(|g| g.number < generation.number)
+    }
+}
+
+/// Record the configuration of the system as a new generation, after `command` completed, unless
+/// it is that of the latest generation. Only the latest `MAX_GENERATIONS` are kept.
+pub fn record_generation(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    command: &str,
+    now: u64,
+) -> Result<()> {
+    let mut generation = Generation::capture(system, experiments)?;
+    update_state(system, |generations: &mut Generations| {
+        let latest = generations.generations.last();
+        if latest.is_some_and(|l| l.same_configuration(&generation)) {
+            debug!("The configuration is unchanged, so no generation is recorded");
+            return Ok(());
+        }
+        generation.number = latest.map_or(1, |l| l.number + 1);
+        generation.created = now;
+        generation.command = command.to_string();
+        info!("Recorded generation {}", generation.number);
+        generations.generations.push(generation);
+        let excess = generations
+            .generations
+            .len()
+            .saturating_sub(MAX_GENERATIONS);
+        generations.generations.drain(..excess);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::SudoRsExperiment;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_record_generation() {
+        let runner = MockSystem::default();
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+        record_generation(&runner, &experiments, "disable", 1).unwrap();
+        // An unchanged configuration doesn't make a new generation.
+        record_generation(&runner, &experiments, "reapply", 2).unwrap();
+        let generations = Generations::load(&runner).unwrap();
+        assert_eq!(generations.generations.len(), 1);
+        assert!(generations.get(2).is_err());
+
+        runner.mock_files(vec![(PINS_PATH, "{\"experiments\":{}}", false)]);
+        record_generation(&runner, &experiments, "enable", 3).unwrap();
+        let generations = Generations::load(&runner).unwrap();
+        let second = generations.get(2).unwrap();
+        assert_eq!((second.created, second.command.as_str()), (3, "enable"));
+        assert_eq!(generations.previous(second).unwrap().number, 1);
+
+        // Restoring the first generation removes the state it had none of.
+        generations.get(1).unwrap().restore_state(&runner).unwrap();
+        assert_eq!(runner.read_file(Path::new(PINS_PATH)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_generation_diff() {
+        let generation = |experiments: &[&str], links: &[(&str, &str)]| Generation {
+            experiments: experiments.iter().map(|e| e.to_string()).collect(),
+            links: links
+                .iter()
+                .map(|(t, s)| (PathBuf::from(t), PathBuf::from(s)))
+                .collect(),
+            ..Generation::default()
+        };
+        let before = generation(
+            &["coreutils", "sudo-rs"],
+            &[
+                ("/usr/bin/ls", "/usr/lib/cargo/bin/coreutils/ls"),
+                ("/usr/bin/sudo", "/usr/lib/cargo/bin/sudo"),
+            ],
+        );
+        let after = generation(
+            &["coreutils", "findutils"],
+            &[
+                ("/usr/bin/ls", "/var/lib/oxidizr/channels/coreutils/bin/ls"),
+                ("/usr/bin/find", "/usr/lib/cargo/bin/findutils/find"),
+            ],
+        );
+
+        let diff = after.diff(Some(&before));
+        assert_eq!(diff.enabled, vec!["findutils"]);
+        assert_eq!(diff.disabled, vec!["sudo-rs"]);
+        assert_eq!(
+            diff.linked,
+            vec![PathBuf::from("/usr/bin/find"), PathBuf::from("/usr/bin/ls")]
+        );
+        assert_eq!(diff.unlinked, vec![PathBuf::from("/usr/bin/sudo")]);
+        assert!(after.diff(Some(&after)).is_empty());
+        assert_eq!(after.diff(None).linked.len(), 2);
+    }
+}
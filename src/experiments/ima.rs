@@ -0,0 +1,63 @@
+use crate::utils::{Command, Worker};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::path::Path;
+
+/// The IMA policy loaded into the kernel.
+const IMA_POLICY_PATH: &str = "/sys/kernel/security/ima/policy";
+
+/// How to handle replacement binaries that lack an IMA signature on systems enforcing IMA
+/// appraisal, where executing them would be denied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum ImaPolicy {
+    /// Replace the files anyway, with a warning.
+    #[default]
+    Warn,
+    /// Leave the files that would be replaced by unsigned binaries in place.
+    Skip,
+    /// Sign the replacement binaries with `evmctl` before replacing any files.
+    Sign,
+}
+
+/// Report whether the kernel is enforcing IMA appraisal: the loaded policy contains appraise
+/// rules, and appraisal hasn't been relaxed to logging or fixing on the kernel command line.
+pub fn appraisal_enforced(system: &dyn Worker) -> bool {
+    let read = |path: &str| {
+        system
+            .read_file(Path::new(path))
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    };
+
+    let appraising = read(IMA_POLICY_PATH)
+        .lines()
+        .any(|rule| rule.trim_start().starts_with("appraise"));
+    let relaxed = read("/proc/cmdline").split_whitespace().any(|arg| {
+        matches!(
+            arg,
+            "ima_appraise=off" | "ima_appraise=log" | "ima_appraise=fix"
+        )
+    });
+
+    appraising && !relaxed
+}
+
+/// Report whether a file carries an IMA signature.
+pub(super) fn signed(system: &dyn Worker, file: &Path) -> bool {
+    let cmd = Command::build("getfattr", &["--absolute-names", "-n", "security.ima"]).arg(file);
+    system
+        .run(&cmd)
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("security.ima="))
+}
+
+/// Sign a file for IMA appraisal with the specified private key.
+pub(super) fn sign(system: &dyn Worker, file: &Path, key: &Path) -> Result<()> {
+    let cmd = Command::build("evmctl", &["ima_sign", "--key"])
+        .arg(key)
+        .arg(file);
+    system
+        .run(&cmd)
+        .with_context(|| format!("unable to sign {} for IMA appraisal", file.display()))?;
+    Ok(())
+}
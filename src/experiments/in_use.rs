@@ -0,0 +1,125 @@
+use crate::utils::{ManagedTarget, Process, Worker};
+use anyhow::Result;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// How often to check whether the processes executing a deferred file have exited.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The systemd-tmpfiles configuration used to replace files at the next boot.
+pub const BOOT_SCHEDULE_PATH: &str = "/etc/tmpfiles.d/oxidizr.conf";
+
+/// How to handle files that are being executed by running processes when they are replaced.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InUsePolicy {
+    /// Replace the file anyway. Running processes keep using the original until restarted.
+    #[default]
+    Replace,
+    /// Replace the file once the processes using it have exited.
+    Wait,
+    /// Back up the file now, and replace it with its symlink at the next boot.
+    AtBoot,
+}
+
+/// The processes currently executing a file.
+pub(super) fn users<'a>(processes: &'a [Process], file: &Path) -> Vec<&'a Process> {
+    processes.iter().filter(|p| p.exe == file).collect()
+}
+
+/// Warn that a file about to be replaced is being executed by running processes.
+pub(super) fn warn_in_use(file: &Path, users: &[&Process], policy: InUsePolicy) {
+    let users = users
+        .iter()
+        .map(|p| format!("{} ({})", p.pid, p.name))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    match policy {
+        InUsePolicy::Wait => info!(
+            "'{}' is in use by {}, deferring its replacement until they exit",
+            file.display(),
+            users
+        ),
+        InUsePolicy::AtBoot => info!(
+            "'{}' is in use by {}, scheduling its replacement at the next boot",
+            file.display(),
+            users
+        ),
+        InUsePolicy::Replace => warn!(
+            "'{}' is in use by {}. Running processes will keep using the original until restarted",
+            file.display(),
+            users
+        ),
+    }
+}
+
+/// Block until no running process is executing a file.
+pub(super) fn wait_for_exit(system: &dyn Worker, file: &Path) -> Result<()> {
+    let mut users = system.processes()?;
+    while users.iter().any(|p| p.exe == file) {
+        thread::sleep(POLL_INTERVAL);
+        users = system.processes()?;
+    }
+    Ok(())
+}
+
+/// Report the services running a replaced file, which should be restarted to pick up the
+/// replacement.
+pub(super) fn report_restarts<'a>(processes: &[Process], replaced: impl Iterator<Item = &'a Path>) {
+    let replaced: BTreeSet<&Path> = replaced.collect();
+    let units: BTreeSet<&str> = processes
+        .iter()
+        .filter(|p| replaced.contains(p.exe.as_path()))
+        .filter_map(|p| p.unit.as_deref())
+        .collect();
+
+    if !units.is_empty() {
+        warn!(
+            "The following services are running replaced binaries and should be restarted: {}",
+            units.into_iter().collect::<Vec<&str>>().join(", ")
+        );
+    }
+}
+
+/// Back up a file now, and schedule its replacement with a symlink to `source` at the next boot
+/// using systemd-tmpfiles, rather than swapping it under the processes executing it.
+pub(super) fn schedule_at_boot(system: &dyn Worker, source: &Path, target: &Path) -> Result<()> {
+    system.backup_file(ManagedTarget::new(target)?)?;
+
+    let entry = format!("L+ {} - - - - {}", target.display(), source.display());
+    let path = Path::new(BOOT_SCHEDULE_PATH);
+    let mut schedule = system.read_file(path)?.unwrap_or_default();
+    if !schedule.lines().any(|line| line == entry) {
+        schedule.push_str(&entry);
+        schedule.push('\n');
+        system.write_file(path, &schedule)?;
+    }
+    Ok(())
+}
+
+/// Cancel any replacements of the specified files scheduled for the next boot.
+pub(super) fn unschedule(system: &dyn Worker, targets: &[PathBuf]) -> Result<()> {
+    let path = Path::new(BOOT_SCHEDULE_PATH);
+    let Some(schedule) = system.read_file(path)? else {
+        return Ok(());
+    };
+
+    let remaining: String = schedule
+        .lines()
+        .filter(|line| {
+            let target = line.split_whitespace().nth(1).map(PathBuf::from);
+            !target.is_some_and(|t| targets.contains(&t))
+        })
+        .map(|line| format!("{line}\n"))
+        .collect();
+
+    if remaining != schedule {
+        system.write_file(path, &remaining)?;
+    }
+    Ok(())
+}
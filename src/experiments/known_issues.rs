@@ -0,0 +1,137 @@
+use crate::utils::{PackageVersion, Worker};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::warn;
+
+/// The database of known issues shipped with oxidizr.
+const EMBEDDED_KNOWN_ISSUES: &str = include_str!("known_issues.toml");
+
+/// The location of a refreshed copy of the database, used in place of the shipped one.
+pub const KNOWN_ISSUES_PATH: &str = "/var/lib/oxidizr/known-issues.toml";
+
+/// The default location from which the database is refreshed.
+pub const KNOWN_ISSUES_URL: &str =
+    "https://raw.githubusercontent.com/jnsgruk/oxidizr/main/src/experiments/known_issues.toml";
+
+/// A known functional regression in a range of versions of a replacement package.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KnownIssue {
+    pub package: String,
+    /// The replacement binary affected, if the issue is specific to one.
+    pub binary: Option<String>,
+    /// The first affected version. Every earlier version is affected if omitted.
+    pub introduced: Option<String>,
+    /// The first version in which the issue is fixed. Every later version is affected if
+    /// omitted.
+    pub fixed: Option<String>,
+    pub summary: String,
+}
+
+impl KnownIssue {
+    /// Report whether a version of a package is affected by the issue. Bounds that can't be
+    /// parsed are treated as absent, so that a malformed entry errs on the side of warning.
+    pub fn affects(&self, package: &str, version: &PackageVersion) -> bool {
+        let bound =
+            |v: &Option<String>| v.as_deref().and_then(|v| v.parse::<PackageVersion>().ok());
+        self.package == package
+            && bound(&self.introduced).is_none_or(|introduced| *version >= introduced)
+            && bound(&self.fixed).is_none_or(|fixed| *version < fixed)
+    }
+}
+
+/// A database of known issues in replacement packages.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KnownIssues {
+    #[serde(default)]
+    pub issue: Vec<KnownIssue>,
+}
+
+impl KnownIssues {
+    /// Parse a database from the contents of a TOML file.
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Load the refreshed copy of the database if there is a valid one, or the shipped one
+    /// otherwise.
+    pub fn load(system: &dyn Worker) -> Self {
+        if let Ok(Some(contents)) = system.read_file(Path::new(KNOWN_ISSUES_PATH)) {
+            match Self::parse(&contents) {
+                Ok(issues) => return issues,
+                Err(e) => warn!("Ignoring invalid {}: {:#}", KNOWN_ISSUES_PATH, e),
+            }
+        }
+        Self::parse(EMBEDDED_KNOWN_ISSUES).expect("the shipped known issues database is invalid")
+    }
+
+    /// Download the latest database from `url`, and save it in place of the shipped one once
+    /// it has been validated.
+    pub fn refresh(system: &dyn Worker, url: &str) -> Result<Self> {
+        let contents = ureq::get(url)
+            .header("User-Agent", concat!("oxidizr/", env!("CARGO_PKG_VERSION")))
+            .call()
+            .with_context(|| format!("unable to download the known issues database from {url}"))?
+            .body_mut()
+            .read_to_string()?;
+        let issues = Self::parse(&contents)
+            .with_context(|| format!("invalid known issues database downloaded from {url}"))?;
+        system.write_file(Path::new(KNOWN_ISSUES_PATH), &contents)?;
+        Ok(issues)
+    }
+
+    /// List the known issues affecting a version of a package.
+    pub fn affecting(&self, package: &str, version: &PackageVersion) -> Vec<KnownIssue> {
+        self.issue
+            .iter()
+            .filter(|issue| issue.affects(package, version))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_known_issues_affecting() {
+        let issues = KnownIssues::parse(
+            r#"
+            [[issue]]
+            package = "rust-coreutils"
+            binary = "split"
+            introduced = "0.0.28"
+            fixed = "0.0.30-1"
+            summary = "'split -n' writes empty chunks"
+            "#,
+        )
+        .unwrap();
+
+        let affected = |package: &str, version: &str| {
+            issues.affecting(package, &version.parse().unwrap()).len()
+        };
+        assert_eq!(affected("rust-coreutils", "0.0.27-1"), 0);
+        assert_eq!(affected("rust-coreutils", "0.0.28"), 1);
+        assert_eq!(affected("rust-coreutils", "0.0.29-2ubuntu1"), 1);
+        assert_eq!(affected("rust-coreutils", "0.0.30-1"), 0);
+        assert_eq!(affected("rust-findutils", "0.0.29"), 0);
+    }
+
+    #[test]
+    fn test_known_issues_load() {
+        let runner = MockSystem::default();
+        assert!(!KnownIssues::load(&runner).issue.is_empty());
+
+        // A valid refreshed database takes precedence over the shipped one.
+        runner.mock_files(vec![(KNOWN_ISSUES_PATH, "", false)]);
+        assert!(KnownIssues::load(&runner).issue.is_empty());
+
+        runner.mock_files(vec![(KNOWN_ISSUES_PATH, "[[issue]]\npackage = 1\n", false)]);
+        assert!(!KnownIssues::load(&runner).issue.is_empty());
+    }
+}
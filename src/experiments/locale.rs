@@ -0,0 +1,198 @@
+use super::Experiment;
+use super::sed::scripts;
+use crate::utils::{Worker, warn_or_fail};
+use anyhow::Result;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// The name of the experiment replacing GNU coreutils, whose sort and ls order by the locale.
+const COREUTILS_EXPERIMENT: &str = "coreutils";
+
+/// The files setting the locale of the system, on Debian and its derivatives and on systemd
+/// distributions, in order of precedence.
+const LOCALE_FILES: &[&str] = &["/etc/default/locale", "/etc/locale.conf"];
+
+/// The utilities whose output is ordered by the collation of the locale.
+const COLLATING_BINARIES: &[&str] = &["sort", "comm", "join", "ls"];
+
+/// A line of a script in /etc relying on an order that depends on the locale, which the
+/// replacement may collate differently from GNU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocaleFinding {
+    pub file: PathBuf,
+    pub line: usize,
+    pub text: String,
+    pub binary: String,
+}
+
+impl fmt::Display for LocaleFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({} orders by the collation of the locale)",
+            self.file.display(),
+            self.line,
+            self.text,
+            self.binary
+        )
+    }
+}
+
+/// Find the locale the system collates in: that of `LC_ALL`, `LC_COLLATE` or `LANG`, in order of
+/// precedence, in the first of `LOCALE_FILES` setting any. Returns `None` if none is set.
+pub(super) fn system_locale(system: &dyn Worker) -> Option<String> {
+    LOCALE_FILES.iter().find_map(|path| {
+        let contents = system.read_file(Path::new(path)).ok()??;
+        let setting = |name: &str| {
+            contents.lines().rev().find_map(|line| {
+                let value = line.trim().strip_prefix(name)?.strip_prefix('=')?;
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                (!value.is_empty()).then(|| value.to_string())
+            })
+        };
+        setting("LC_ALL")
+            .or_else(|| setting("LC_COLLATE"))
+            .or_else(|| setting("LANG"))
+    })
+}
+
+/// Report whether a locale collates other than by byte, as the C and POSIX locales do.
+fn collates(locale: &str) -> bool {
+    let language = locale.split('.').next().unwrap_or_default();
+    !matches!(language, "C" | "POSIX")
+}
+
+/// Report whether a word of shell sets a locale collating by byte, such as `LC_ALL=C`.
+fn sets_byte_collation(word: &str) -> bool {
+    ["LC_ALL=", "LC_COLLATE="].iter().any(|prefix| {
+        word.strip_prefix(prefix)
+            .is_some_and(|value| !collates(value.trim_matches(|c| c == '"' || c == '\'')))
+    })
+}
+
+/// Report the utility, if any, run in a line of shell whose output is ordered by the locale. The
+/// order of ls only matters when its output is read by the script, rather than shown.
+fn collating_binary(line: &str) -> Option<String> {
+    let consumed = line.contains('|') || line.contains("$(") || line.contains('`');
+    line.split(|c: char| c.is_whitespace() || ";|&()`".contains(c))
+        .filter_map(|word| {
+            let name = word.rsplit('/').next()?;
+            COLLATING_BINARIES.iter().find(|b| **b == name)
+        })
+        .find(|b| **b != "ls" || consumed)
+        .map(|b| b.to_string())
+}
+
+/// Check the scripts in /etc for runs of utilities whose order depends on the locale, unless the
+/// script sets one collating by byte, such as with `LC_ALL=C`, as portable scripts do.
+pub(super) fn lint(system: &dyn Worker) -> Vec<LocaleFinding> {
+    let mut findings = Vec::new();
+    for file in scripts(system) {
+        let Some(contents) = system.read_file(&file).ok().flatten() else {
+            continue;
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let text = line.trim();
+            if text.starts_with('#') {
+                continue;
+            }
+            let byte_collation = text.split_whitespace().any(sets_byte_collation);
+            match collating_binary(text) {
+                // A locale set on its own line applies to the rest of the script.
+                None if byte_collation => break,
+                Some(binary) if !byte_collation => findings.push(LocaleFinding {
+                    file: file.clone(),
+                    line: number + 1,
+                    text: text.to_string(),
+                    binary,
+                }),
+                _ => (),
+            }
+        }
+    }
+    findings
+}
+
+/// Warn about the scripts in /etc that rely on ordering by the locale before enabling coreutils,
+/// when the system locale doesn't collate by byte, or fail in strict mode. The collation of the
+/// replacement may differ from that of GNU in such locales, changing the order the scripts see.
+pub fn check_locale(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    if !experiments.iter().any(|e| e.name() == COREUTILS_EXPERIMENT) {
+        return Ok(());
+    }
+    let Some(locale) = system_locale(system).filter(|l| collates(l)) else {
+        return Ok(());
+    };
+
+    let findings = lint(system);
+    if findings.is_empty() {
+        return Ok(());
+    }
+    warn_or_fail(
+        system.strict(),
+        format!(
+            "the system locale is {locale}, in which uutils may order lines and files \
+            differently from GNU, and scripts in /etc rely on the order:\n  {}\n\
+            Set LC_ALL=C in these scripts to order by byte whatever the locale, or compare the \
+            orders with 'oxidizr compare-one sort -- <file>'",
+            findings
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join("\n  ")
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_system_locale() {
+        let runner = MockSystem::default();
+        assert_eq!(system_locale(&runner), None);
+        runner.mock_files(vec![(
+            "/etc/locale.conf",
+            "LANG=\"en_US.UTF-8\"\nLC_COLLATE=C.UTF-8\n",
+            false,
+        )]);
+        assert_eq!(system_locale(&runner).as_deref(), Some("C.UTF-8"));
+        assert!(!collates("C.UTF-8"));
+        runner.mock_files(vec![("/etc/default/locale", "LANG=de_DE.UTF-8\n", false)]);
+        assert_eq!(system_locale(&runner).as_deref(), Some("de_DE.UTF-8"));
+        assert!(collates("de_DE.UTF-8"));
+    }
+
+    #[test]
+    fn test_lint_locale() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            (
+                "/etc/cron.daily/report",
+                "#!/bin/sh\nls /var/log\nfor f in $(ls /srv); do echo $f; done\n\
+                LC_ALL=C sort -u /srv/a > /srv/b\ncut -f1 /srv/b | /usr/bin/sort\n",
+                false,
+            ),
+            (
+                "/etc/cron.daily/portable",
+                "#!/bin/sh\nexport LC_ALL=C\ncomm -12 /srv/a /srv/b\n",
+                false,
+            ),
+        ]);
+
+        let findings: Vec<String> = lint(&runner).iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            findings,
+            vec![
+                "/etc/cron.daily/report:3: for f in $(ls /srv); do echo $f; done (ls orders by \
+                the collation of the locale)",
+                "/etc/cron.daily/report:5: cut -f1 /srv/b | /usr/bin/sort (sort orders by the \
+                collation of the locale)",
+            ]
+        );
+    }
+}
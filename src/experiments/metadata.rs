@@ -0,0 +1,40 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+
+/// How mature the replacement provided by an experiment is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Maturity {
+    /// Suitable for general use, and shipped by default by at least one distribution.
+    Stable,
+    /// Usable, but missing features or known to behave differently from the original.
+    Experimental,
+    /// Likely to break scripts or workflows that depend on the original.
+    Risky,
+}
+
+impl fmt::Display for Maturity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Experimental => write!(f, "experimental"),
+            Self::Risky => write!(f, "risky"),
+        }
+    }
+}
+
+/// Information about the upstream project providing an experiment's replacement.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Metadata {
+    /// The URL of the upstream project.
+    pub upstream: &'static str,
+    /// The SPDX license expression of the replacement.
+    pub license: &'static str,
+    pub maturity: Maturity,
+    pub maintainer: &'static str,
+    /// The package providing the original binaries that the replacement takes the place of.
+    pub replaces: &'static str,
+    /// The SPDX license expression of the original binaries.
+    pub replaced_license: &'static str,
+}
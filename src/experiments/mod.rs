@@ -1,15 +1,105 @@
+mod activation;
+mod channel;
+mod compatibility;
+mod coverage;
+mod directories;
+mod displaced;
+mod fapolicyd;
+mod generations;
+mod ima;
+mod in_use;
+mod known_issues;
+mod locale;
+mod metadata;
+mod overrides;
+mod package_cache;
+mod pam;
+mod pins;
+mod plan;
+mod quarantine;
+mod removal;
+mod repositories;
+mod rescue;
+mod risk;
+mod schedule;
+mod sed;
+mod shim;
+mod space;
+mod staged;
+mod status;
+mod sudoers;
 mod sudors;
+mod tar;
+mod trial;
+mod usage;
 mod uutils;
-use crate::utils::Worker;
-use anyhow::Result;
-use std::path::PathBuf;
+mod versions;
+use crate::utils::{
+    Command, Distribution, ErrorCode, ManagedTarget, PackageVersion, ReplacementSource, SkipReason,
+    SkippedFile, Worker, warn_or_fail,
+};
+pub use activation::{INSTALLS_PATH, Installs, StagedInstall, StagedReplacement, stage, unstage};
+use anyhow::{Context, Result};
+pub use channel::{
+    CHANNELS_DIRECTORY, CHANNELS_PATH, Channel, ChannelRelease, Channels, record_channel,
+    replace_symlink,
+};
+pub use compatibility::{
+    CompatibilityPolicy, Incompatibility, LtsOnly, PolicyName, SupportedReleases, WritableUsr,
+    policies,
+};
+pub use coverage::{OptionCoverage, coverage_percent};
+pub use directories::{DIRECTORIES_PATH, Directories, layout_directories};
+pub use displaced::{DISPLACED_PATH, Displaced, DisplacedPackage, restore_displaced};
+pub use generations::{
+    GENERATIONS_PATH, Generation, GenerationDiff, Generations, MAX_GENERATIONS, record_generation,
+};
+pub use ima::ImaPolicy;
+pub use in_use::{BOOT_SCHEDULE_PATH, InUsePolicy};
+pub use known_issues::{KNOWN_ISSUES_URL, KnownIssue, KnownIssues};
+pub use locale::check_locale;
+pub use metadata::{Maturity, Metadata};
+pub use overrides::{OVERRIDES_PATH, Overrides, OverridesExperiment, record_overrides};
+pub use package_cache::{
+    CachedPackage, PACKAGE_CACHE_DIRECTORY, PACKAGE_CACHE_PATH, PackageCache, warm_package_cache,
+};
+pub use pins::{PINS_PATH, Pins};
+pub use plan::{Plan, PlannedExperiment};
+pub use quarantine::{
+    Implication, QUARANTINE_PATH, QUARANTINE_STRIKES, Quarantine, Suspicion, count_fallbacks,
+    implicate,
+};
+pub use removal::{RemovalImpact, removal_impact};
+pub use repositories::check_repositories;
+pub use rescue::{RESCUE_PACKAGE, requires_rescue_toolset, rescue_toolset_installed};
+pub use risk::{Risk, RiskLevel};
+pub use schedule::{
+    SCHEDULE_PATH, SCHEDULE_SERVICE_PATH, SCHEDULE_TIMER_PATH, Schedule, resolve_time,
+    unscheduled_args,
+};
+pub use sed::{SED_EXPERIMENT, check_sed_scripts};
+pub use shim::{LinkStrategy, SHIMS_DIRECTORY, STRATEGIES_PATH, Strategies, record_strategy};
+pub use space::check_space;
+pub use staged::{disable_staged, enable_staged};
+pub use status::ExperimentStatus;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 pub use sudors::SudoRsExperiment;
-use tracing::warn;
+pub use tar::TAR_EXPERIMENT;
+use tracing::{info, warn};
+pub use trial::{
+    TRIAL_PATH, TRIAL_SERVICE_PATH, TRIAL_TIMER_PATH, Trial, calendar_time, parse_period,
+};
+pub use usage::{BinaryUsage, USAGE_LOG_PATH, USAGE_PATH, Usage};
 pub use uutils::UutilsExperiment;
+pub use versions::{RECORDED_VERSIONS_PATH, RecordedVersions, record_versions};
 
 pub enum Experiment<'a> {
     Uutils(UutilsExperiment<'a>),
     SudoRs(SudoRsExperiment<'a>),
+    Overrides(OverridesExperiment<'a>),
 }
 
 impl Experiment<'_> {
@@ -17,46 +107,139 @@ impl Experiment<'_> {
         match self {
             Experiment::Uutils(uutils) => uutils.name(),
             Experiment::SudoRs(sudors) => sudors.name(),
+            Experiment::Overrides(e) => e.name(),
         }
     }
 
-    pub fn enable(&self, no_compatibility_check: bool) -> Result<()> {
-        if !no_compatibility_check && !self.check_compatible() {
-            warn!(
-                "Skipping '{}'. Minimum supported releases are {}.",
-                self.name(),
-                self.supported_releases().join(", ")
-            );
-            return Ok(());
+    pub fn package(&self) -> String {
+        match self {
+            Experiment::Uutils(e) => e.package(),
+            Experiment::SudoRs(e) => e.package(),
+            Experiment::Overrides(e) => e.package(),
         }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        match self {
+            Experiment::Uutils(e) => e.metadata(),
+            Experiment::SudoRs(e) => e.metadata(),
+            Experiment::Overrides(e) => e.metadata(),
+        }
+    }
+
+    /// Report whether the experiment can be enabled on this system, warning if it will be skipped.
+    /// In strict mode, an experiment that would be skipped is an error instead.
+    pub fn should_enable(&self, no_compatibility_check: bool, strict: bool) -> Result<bool> {
+        if no_compatibility_check {
+            return Ok(true);
+        }
+        if let Err(reason) = self.compatibility() {
+            let supported: Vec<String> = self
+                .supported_releases()
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+            let message = match supported.is_empty() {
+                true => format!("Skipping '{}': {reason}.", self.name()),
+                false => format!(
+                    "Skipping '{}': {reason}. Supported releases are {}.",
+                    self.name(),
+                    supported.join(", ")
+                ),
+            };
+            warn_or_fail(strict, message)?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Report whether the experiment can be disabled, warning if it will be skipped. In strict
+    /// mode, an experiment that would be skipped is an error instead.
+    pub fn should_disable(&self, strict: bool) -> Result<bool> {
+        if !self.check_installed() {
+            warn_or_fail(
+                strict,
+                format!("'{}' not enabled, skipping restore", self.name()),
+            )?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub fn enable(&self) -> Result<()> {
         match self {
             Experiment::Uutils(e) => e.enable(),
             Experiment::SudoRs(e) => e.enable(),
+            Experiment::Overrides(e) => e.enable(),
         }
     }
 
     pub fn disable(&self) -> Result<()> {
-        if !self.check_installed() {
-            warn!("'{}' not enabled, skipping restore", self.name());
-            return Ok(());
-        }
         match self {
             Experiment::Uutils(e) => e.disable(),
             Experiment::SudoRs(e) => e.disable(),
+            Experiment::Overrides(e) => e.disable(),
         }
     }
 
+    /// Report whether every compatibility policy in effect allows the experiment on the running
+    /// distribution release.
     pub fn check_compatible(&self) -> bool {
+        self.compatibility().is_ok()
+    }
+
+    /// Check the experiment against every compatibility policy in effect, reporting why the
+    /// first policy that refuses it does so.
+    pub fn compatibility(&self) -> std::result::Result<(), Incompatibility> {
+        let system = self.system();
+        let distribution =
+            system
+                .distribution()
+                .map_err(|e| Incompatibility::UnknownDistribution {
+                    error: format!("{e:#}"),
+                })?;
+        policies(system.compatibility_policies())
+            .iter()
+            .try_for_each(|policy| policy.check(self, &distribution))
+    }
+
+    /// Check that the package of the replacements can be installed, unless it already is: not
+    /// every replacement is built for `armhf`, for example. The answer is only as current as the
+    /// package lists, and a failure to query the archive is left for the installation to report.
+    pub fn availability(&self) -> std::result::Result<(), Incompatibility> {
+        let system = self.system();
+        let package = self.package();
+        let missing = !package.is_empty()
+            && !self.check_installed()
+            && matches!(system.candidate_version(&package), Ok(None));
+        if !missing {
+            return Ok(());
+        }
+        let architecture = system
+            .distribution()
+            .ok()
+            .and_then(|d| d.architecture)
+            .unwrap_or("this architecture".into());
+        Err(Incompatibility::PackageUnavailable {
+            package,
+            architecture,
+        })
+    }
+
+    /// The system the experiment operates on.
+    fn system(&self) -> &dyn Worker {
         match self {
-            Experiment::Uutils(e) => e.check_compatible(),
-            Experiment::SudoRs(e) => e.check_compatible(),
+            Experiment::Uutils(e) => e.system(),
+            Experiment::SudoRs(e) => e.system(),
+            Experiment::Overrides(e) => e.system(),
         }
     }
 
-    pub fn supported_releases(&self) -> Vec<String> {
+    pub fn supported_releases(&self) -> Vec<Distribution> {
         match self {
             Experiment::Uutils(e) => e.supported_releases(),
             Experiment::SudoRs(e) => e.supported_releases(),
+            Experiment::Overrides(e) => e.supported_releases(),
         }
     }
 
@@ -64,36 +247,819 @@ impl Experiment<'_> {
         match self {
             Experiment::Uutils(e) => e.check_installed(),
             Experiment::SudoRs(e) => e.check_installed(),
+            Experiment::Overrides(e) => e.check_installed(),
+        }
+    }
+
+    pub fn installed_version(&self) -> Option<PackageVersion> {
+        match self {
+            Experiment::Uutils(e) => e.installed_version(),
+            Experiment::SudoRs(e) => e.installed_version(),
+            Experiment::Overrides(_) => None,
+        }
+    }
+
+    pub fn replacements(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        match self {
+            Experiment::Uutils(e) => e.replacements(),
+            Experiment::SudoRs(e) => Ok(e.replacements()),
+            Experiment::Overrides(e) => e.replacements(),
+        }
+    }
+
+    /// Report the PAM services used by the binaries the experiment replaces, such as `sudo`.
+    /// Experiments that don't replace binaries which authenticate users have none.
+    pub fn authentication_services(&self) -> Vec<&'static str> {
+        self.replacements()
+            .map(|replacements| pam::services(&replacements))
+            .unwrap_or_default()
+    }
+
+    /// Report how many of the options of each GNU binary the experiment replaces are supported by
+    /// its replacement.
+    pub fn option_coverage(&self) -> Vec<OptionCoverage> {
+        match self {
+            Experiment::Uutils(e) => e.option_coverage(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Vec::new(),
+        }
+    }
+
+    /// Build the command running the replacement `source` of the binary `name`, to which
+    /// arguments can be appended.
+    pub fn replacement_command(&self, source: &Path, name: &OsStr) -> Command {
+        match self {
+            Experiment::Uutils(e) => e.replacement_command(source, name),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Command::build(source, &[]),
         }
     }
+
+    /// Report the mode of the copy that replaces a system file, or `None` if the file is
+    /// replaced with a symlink to its replacement.
+    pub fn copy_mode(&self, target: &Path) -> Option<u32> {
+        match self {
+            Experiment::Uutils(_) | Experiment::Overrides(_) => None,
+            Experiment::SudoRs(e) => e.copy_mode(target),
+        }
+    }
+
+    /// Report the binaries that were pinned to GNU when the experiment was enabled.
+    pub fn pinned(&self) -> Result<Vec<String>> {
+        match self {
+            Experiment::Uutils(e) => e.pinned(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Report the binaries quarantined after failures implicated them, which are among those
+    /// pinned.
+    pub fn quarantined(&self) -> Result<Vec<String>> {
+        match self {
+            Experiment::Uutils(e) => e.quarantined(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Report the files pinned to GNU since they were replaced, which still link to their
+    /// replacements.
+    pub fn pinned_replaced(&self) -> Result<Vec<PathBuf>> {
+        match self {
+            Experiment::Uutils(e) => e.pinned_replaced(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Report the channel the experiment's replacements are installed from.
+    pub fn channel(&self) -> Channel {
+        match self {
+            Experiment::Uutils(e) => e.channel(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Channel::Stable,
+        }
+    }
+
+    /// Report the strategy with which the experiment's files are replaced.
+    pub fn strategy(&self) -> LinkStrategy {
+        match self {
+            Experiment::Uutils(e) => e.strategy().unwrap_or_default(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => LinkStrategy::Symlink,
+        }
+    }
+
+    /// Restore any GNU binaries that have been pinned since they were replaced.
+    pub fn restore_pinned(&self) -> Result<()> {
+        match self {
+            Experiment::Uutils(e) => e.restore_pinned(),
+            Experiment::SudoRs(_) | Experiment::Overrides(_) => Ok(()),
+        }
+    }
+
+    pub fn check_replaced(&self) -> Result<Vec<(PathBuf, bool)>> {
+        match self {
+            Experiment::Uutils(e) => e.check_replaced(),
+            Experiment::SudoRs(e) => e.check_replaced(),
+            Experiment::Overrides(e) => e.check_replaced(),
+        }
+    }
+
+    pub fn status(&self) -> ExperimentStatus {
+        ExperimentStatus::gather(self)
+    }
 }
 
-pub fn all_experiments<'a>(system: &'a impl Worker) -> Vec<Experiment<'a>> {
+/// Options controlling how experiments are enabled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnableOptions {
+    /// How to handle files that are being executed by running processes.
+    pub in_use: InUsePolicy,
+    /// How to handle unsigned replacement binaries when IMA appraisal is enforced.
+    pub ima: ImaPolicy,
+    /// The private key used to sign replacement binaries with `ImaPolicy::Sign`.
+    pub ima_key: Option<PathBuf>,
+    /// Whether the user can be prompted: to confirm that authentication still works after
+    /// replacing binaries such as `sudo` (rolling back otherwise), and to install a rescue
+    /// toolset when one is required but missing.
+    pub interactive: bool,
+    /// Whether to restart the running services that invoke replaced binaries once enabled.
+    pub restart_services: bool,
+    /// Whether to stop at the first experiment that fails, rather than carrying on with the rest.
+    pub fail_fast: bool,
+    /// Whether to only install the replacements and stage the files they will replace, leaving
+    /// the system files in place until the experiments are activated.
+    pub no_activate: bool,
+    /// Whether the replacements were installed beforehand, by an install without activation, so
+    /// are put in place without installing anything.
+    pub preinstalled: bool,
+}
+
+/// Check that every experiment is stable, unless experiments of lower maturity are allowed.
+pub fn check_maturity(experiments: &[Experiment], allow_experimental: bool) -> Result<()> {
+    let immature: Vec<String> = experiments
+        .iter()
+        .filter(|e| e.metadata().maturity > Maturity::Stable)
+        .map(|e| format!("{} ({})", e.name(), e.metadata().maturity))
+        .collect();
+    if !allow_experimental && !immature.is_empty() {
+        return Err(ErrorCode::ExperimentalNotAllowed.error(format!(
+            "not enabling experiments that aren't yet stable: {}",
+            immature.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Keep the experiments whose packages can be installed, skipping those whose package isn't
+/// available for the system's architecture - not every replacement is built for `armhf`, for
+/// example. The package lists must be up to date. In strict mode, an experiment that would be
+/// skipped is an error instead.
+pub fn available_experiments<'a>(
+    system: &dyn Worker,
+    experiments: Vec<Experiment<'a>>,
+) -> Result<Vec<Experiment<'a>>> {
+    let mut available = Vec::new();
+    for e in experiments {
+        if let Err(reason) = e.availability() {
+            warn_or_fail(
+                system.strict(),
+                format!("Skipping '{}': {reason}", e.name()),
+            )?;
+            continue;
+        }
+        available.push(e);
+    }
+    Ok(available)
+}
+
+/// Check that the PAM configuration used by the authentication binaries the experiments replace
+/// is usable, so that enabling them can't leave the system without a working way to become root.
+pub fn check_authentication(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    let services: Vec<&str> = experiments
+        .iter()
+        .flat_map(|e| e.authentication_services())
+        .collect();
+
+    let problems = pam::check(system, &services);
+    if !problems.is_empty() {
+        return Err(ErrorCode::PamMisconfigured.error(format!(
+            "the PAM configuration is unsuitable for the replaced binaries:\n  {}",
+            problems.join("\n  ")
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the sudoers configuration is supported by sudo-rs before enabling it, listing every
+/// unsupported line otherwise. Without the check, sudo-rs would ignore those lines, changing which
+/// users may run which commands.
+pub fn check_sudoers(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    if !experiments
+        .iter()
+        .any(|e| matches!(e, Experiment::SudoRs(_)))
+    {
+        return Ok(());
+    }
+
+    let findings = sudoers::lint(system);
+    if !findings.is_empty() {
+        return Err(ErrorCode::SudoersUnsupported.error(format!(
+            "the sudoers configuration uses constructs that sudo-rs does not support:\n  {}",
+            findings
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join("\n  ")
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the replacements enabled by the experiments work, for those with a smoke test, such
+/// as the in-place edit of the sed replacement and the extraction of an archive by the tar
+/// replacement. A failure is reported with `ErrorCode::SmokeTestFailed`.
+pub fn smoke_test(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    // The replacements aren't in place in a dry run, so there is nothing to test.
+    if system.dry_run() {
+        return Ok(());
+    }
+    sed::smoke_test(system, experiments)?;
+    tar::smoke_test(system, experiments)
+}
+
+/// Install the replacements of a set of experiments: the packages of those installed from the
+/// distribution in a single package manager transaction, then the releases of those installed
+/// from an upstream channel. Should anything fail to install, every experiment is recorded as
+/// having failed. The GNU packages replaced are cached beforehand, so that they can be reinstalled
+/// offline, and those removed by installing their replacements are recorded, to be reinstalled
+/// when the experiments are disabled.
+pub fn install_all(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    record: &mut impl FnMut(&str, bool),
+) -> Result<()> {
+    if !system.dry_run() {
+        warm_package_cache(system, experiments);
+    }
+    let replaced = displaced::installed_replaced(system, experiments);
+
+    let packages: Vec<String> = experiments
+        .iter()
+        .filter(|e| e.channel() == Channel::Stable)
+        .map(|e| e.package())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if !packages.is_empty() {
+        info!("Installing {}", packages.join(", "));
+        if let Err(e) = system.install_packages(&packages) {
+            experiments.iter().for_each(|x| record(&x.name(), false));
+            return Err(e.context(
+                ErrorCode::PackageInstallFailed
+                    .context(format!("unable to install {}", packages.join(", "))),
+            ));
+        }
+        if let Err(e) = displaced::record_displaced(system, replaced) {
+            warn!("Unable to record the packages removed: {:#}", e);
+        }
+    }
+    for e in experiments {
+        if let Experiment::Uutils(uutils) = e
+            && uutils.channel() != Channel::Stable
+            && let Err(err) = uutils.install_channel()
+        {
+            experiments.iter().for_each(|x| record(&x.name(), false));
+            return Err(err.context(ErrorCode::ChannelUnavailable.context(format!(
+                "unable to install the {} release of {}",
+                uutils.channel(),
+                e.name()
+            ))));
+        }
+    }
+    Ok(())
+}
+
+/// Enable a set of experiments. The packages for all of the experiments are installed in a single
+/// package manager transaction before any files are replaced, unless `options.preinstalled`. The
+/// outcome for each experiment is passed to `record`. Binaries pinned to GNU since they were
+/// replaced are restored first.
+///
+/// Files being executed by running processes are handled according to `options.in_use`: they are
+/// either replaced anyway with a warning, replaced once the other files have been replaced and
+/// the processes have exited, or backed up and scheduled for replacement at the next boot.
+/// Services left running a replaced binary are reported afterwards. When IMA appraisal is
+/// enforced, unsigned replacement binaries are handled according to `options.ima`.
+///
+/// The files left in place, such as those pinned to GNU, are returned with the reason for each.
+pub fn enable_all(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    options: &EnableOptions,
+    mut record: impl FnMut(&str, bool),
+) -> Result<Vec<SkippedFile>> {
+    if !options.preinstalled {
+        install_all(system, experiments, &mut record)?;
+    }
+
+    let processes = system.processes().unwrap_or_else(|e| {
+        warn!("Unable to list running processes: {}", e);
+        Vec::new()
+    });
+
+    let in_use = options.in_use;
+    let ima_enforced = ima::appraisal_enforced(system);
+
+    let mut replaced = Vec::new();
+    let mut deferred = Vec::new();
+    let mut skipped = Vec::new();
+    for e in experiments {
+        info!("Configuring {}", e.name());
+        let quarantined = e.quarantined().unwrap_or_default();
+        let directories = Directories::load(system).unwrap_or_default();
+        for binary in e.pinned().unwrap_or_default() {
+            let file = directories.locate(system, &e.name(), binary.as_ref());
+            skipped.push(SkippedFile {
+                file,
+                reason: match quarantined.contains(&binary) {
+                    true => SkipReason::Quarantined,
+                    false => SkipReason::Pinned,
+                },
+            });
+        }
+        let result = e
+            .restore_pinned()
+            .and_then(|_| e.replacements())
+            .and_then(|replacements| {
+                let sources: Vec<PathBuf> = replacements.iter().map(|(s, _)| s.clone()).collect();
+                fapolicyd::trust(system, &sources)?;
+
+                for (source, target) in replacements {
+                    if ima_enforced && !ima::signed(system, &source) {
+                        match (options.ima, &options.ima_key) {
+                            (ImaPolicy::Warn, _) => warn_or_fail(
+                                system.strict(),
+                                format!(
+                                    "'{}' has no IMA signature and may be denied execution",
+                                    source.display()
+                                ),
+                            )?,
+                            (ImaPolicy::Skip, _) => {
+                                warn!(
+                                    "Skipping '{}': '{}' has no IMA signature",
+                                    target.display(),
+                                    source.display()
+                                );
+                                skipped.push(SkippedFile {
+                                    file: target,
+                                    reason: SkipReason::Unsigned,
+                                });
+                                continue;
+                            }
+                            (ImaPolicy::Sign, Some(key)) => ima::sign(system, &source, key)?,
+                            (ImaPolicy::Sign, None) => {
+                                return Err(ErrorCode::ImaKeyRequired
+                                    .error("signing for IMA appraisal requires a key"));
+                            }
+                        }
+                    }
+
+                    let mode = e.copy_mode(&target);
+                    let users = in_use::users(&processes, &target);
+                    if !users.is_empty() {
+                        in_use::warn_in_use(&target, &users, in_use);
+                        match in_use {
+                            InUsePolicy::Wait => {
+                                deferred.push((e, source, target, mode));
+                                continue;
+                            }
+                            // Copies and shims are renamed into place, which running processes
+                            // are unaffected by, so only symlinks are deferred to the next boot.
+                            InUsePolicy::AtBoot
+                                if mode.is_none() && e.strategy() == LinkStrategy::Symlink =>
+                            {
+                                in_use::schedule_at_boot(system, &source, &target)?;
+                                skipped.push(SkippedFile {
+                                    file: target,
+                                    reason: SkipReason::InUse,
+                                });
+                                continue;
+                            }
+                            InUsePolicy::AtBoot | InUsePolicy::Replace => (),
+                        }
+                    }
+                    replace(system, e, source, target.clone(), mode)?;
+                    replaced.push(target);
+                }
+                Ok(())
+            });
+
+        // Experiments with deferred files are recorded once those files have been replaced.
+        if result.is_err() || !deferred.iter().any(|(d, ..)| d.name() == e.name()) {
+            record(&e.name(), result.is_ok());
+        }
+        result?;
+    }
+
+    let result: Result<()> = deferred.iter().try_for_each(|(e, source, target, mode)| {
+        in_use::wait_for_exit(system, target)?;
+        replace(system, e, source.clone(), target.clone(), *mode)?;
+        replaced.push(target.clone());
+        Ok(())
+    });
+    let mut names: Vec<String> = deferred.iter().map(|(e, ..)| e.name()).collect();
+    names.dedup();
+    names.iter().for_each(|name| record(name, result.is_ok()));
+    result?;
+
+    in_use::report_restarts(&processes, replaced.iter().map(|p| p.as_path()));
+    Ok(skipped)
+}
+
+/// Replace a system file with its replacement binary: using the strategy recorded for experiments
+/// replacing GNU utilities, otherwise with a copy of the specified mode if there is one, or a
+/// symlink.
+fn replace(
+    system: &dyn Worker,
+    experiment: &Experiment,
+    source: PathBuf,
+    target: PathBuf,
+    mode: Option<u32>,
+) -> Result<()> {
+    let (source, target) = (ReplacementSource::new(source)?, ManagedTarget::new(target)?);
+    match (experiment, mode) {
+        (Experiment::Uutils(e), _) => e.link(source, target),
+        (_, Some(mode)) => system.replace_file_with_copy(source, target, mode),
+        (_, None) => {
+            system.replace_file_with_symlink(source, target)?;
+            Ok(())
+        }
+    }
+}
+
+/// Disable a set of experiments. Files are restored for every experiment first, then the packages
+/// for all of the experiments are removed in a single package manager transaction, and the GNU
+/// packages their installation removed are reinstalled. Packages that are Essential, or that other
+/// packages depend on, are left installed. The outcome for each experiment is passed to `record`.
+pub fn disable_all(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    mut record: impl FnMut(&str, bool),
+) -> Result<()> {
+    for e in experiments {
+        let result = e.replacements().and_then(|replacements| {
+            let (sources, targets): (Vec<PathBuf>, Vec<PathBuf>) = replacements.into_iter().unzip();
+            in_use::unschedule(system, &targets)?;
+            e.disable()?;
+            fapolicyd::untrust(system, &sources);
+            Ok(())
+        });
+        if let Err(err) = result {
+            record(&e.name(), false);
+            return Err(err);
+        }
+    }
+
+    let packages: Vec<String> = experiments
+        .iter()
+        .map(|e| e.package())
+        .filter(|p| !p.is_empty())
+        .collect();
+    let packages = match removal::removable_packages(system, &packages) {
+        Ok(packages) => packages,
+        Err(err) => {
+            experiments.iter().for_each(|e| record(&e.name(), false));
+            return Err(err);
+        }
+    };
+    if !packages.is_empty() {
+        info!("Removing {}", packages.join(", "));
+        // The GNU packages are only reinstalled for the replacements that were removed.
+        let removed: Vec<String> = experiments
+            .iter()
+            .filter(|e| packages.contains(&e.package()))
+            .map(|e| e.name())
+            .collect();
+        let result = system.remove_packages(&packages).and_then(|_| {
+            restore_displaced(system, &removed).context("unable to reinstall the GNU packages")
+        });
+        experiments
+            .iter()
+            .for_each(|e| record(&e.name(), result.is_ok()));
+        result.with_context(|| {
+            ErrorCode::PackageRemoveFailed
+                .context(format!("unable to remove {}", packages.join(", ")))
+        })?;
+    } else {
+        experiments.iter().for_each(|e| record(&e.name(), true));
+    }
+    Ok(())
+}
+
+pub fn all_experiments<'a>(system: &'a dyn Worker) -> Vec<Experiment<'a>> {
     vec![
         Experiment::Uutils(UutilsExperiment::<'a>::new(
             "coreutils",
             system,
             "rust-coreutils",
-            &["24.04", "24.10", "25.04"],
+            ubuntu(&["24.04", "24.10", "25.04"]),
             Some(PathBuf::from("/usr/bin/coreutils")),
             PathBuf::from("/usr/lib/cargo/bin/coreutils"),
+            &Metadata {
+                upstream: "https://github.com/uutils/coreutils",
+                license: "MIT",
+                maturity: Maturity::Stable,
+                maintainer: "uutils",
+                replaces: "coreutils",
+                replaced_license: "GPL-3.0-or-later",
+            },
         )),
         Experiment::Uutils(UutilsExperiment::<'a>::new(
             "diffutils",
             system,
             "rust-diffutils",
-            &["24.10", "25.04"],
+            ubuntu(&["24.10", "25.04"]),
             Some(PathBuf::from("/usr/lib/cargo/bin/diffutils/diffutils")),
             PathBuf::from("/usr/lib/cargo/bin/diffutils"),
+            &Metadata {
+                upstream: "https://github.com/uutils/diffutils",
+                license: "MIT OR Apache-2.0",
+                maturity: Maturity::Experimental,
+                maintainer: "uutils",
+                replaces: "diffutils",
+                replaced_license: "GPL-3.0-or-later",
+            },
         )),
         Experiment::Uutils(UutilsExperiment::<'a>::new(
             "findutils",
             system,
             "rust-findutils",
-            &["24.04", "24.10", "25.04"],
+            ubuntu(&["24.04", "24.10", "25.04"]),
             None,
             PathBuf::from("/usr/lib/cargo/bin/findutils"),
+            &Metadata {
+                upstream: "https://github.com/uutils/findutils",
+                license: "MIT",
+                maturity: Maturity::Experimental,
+                maintainer: "uutils",
+                replaces: "findutils",
+                replaced_license: "GPL-3.0-or-later",
+            },
+        )),
+        Experiment::Uutils(UutilsExperiment::<'a>::new(
+            SED_EXPERIMENT,
+            system,
+            "rust-sed",
+            // No release packages it yet.
+            ubuntu(&[]),
+            None,
+            PathBuf::from("/usr/lib/cargo/bin/sed"),
+            &Metadata {
+                upstream: "https://github.com/uutils/sed",
+                license: "MIT",
+                maturity: Maturity::Experimental,
+                maintainer: "uutils",
+                replaces: "sed",
+                replaced_license: "GPL-3.0-or-later",
+            },
+        )),
+        Experiment::Uutils(UutilsExperiment::<'a>::new(
+            TAR_EXPERIMENT,
+            system,
+            "rust-tar",
+            // No release packages it yet.
+            ubuntu(&[]),
+            None,
+            PathBuf::from("/usr/lib/cargo/bin/tar"),
+            &Metadata {
+                upstream: "https://github.com/uutils/tar",
+                license: "MIT",
+                maturity: Maturity::Experimental,
+                maintainer: "uutils",
+                replaces: "tar",
+                replaced_license: "GPL-3.0-or-later",
+            },
         )),
         Experiment::SudoRs(SudoRsExperiment::<'a>::new(system)),
+        Experiment::Overrides(OverridesExperiment::<'a>::new(system)),
     ]
 }
+
+/// Build the list of supported releases for an experiment available on Ubuntu.
+fn ubuntu(releases: &[&str]) -> Vec<Distribution> {
+    releases
+        .iter()
+        .map(|release| Distribution::new("Ubuntu", release))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+    use std::path::Path;
+
+    #[test]
+    fn test_check_maturity() {
+        let runner = MockSystem::default();
+        let experiments = all_experiments(&runner);
+        let err = check_maturity(&experiments, false).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::ExperimentalNotAllowed));
+        assert!(err.to_string().ends_with(
+            ": diffutils (experimental), findutils (experimental), sed (experimental), \
+                tar (experimental), overrides (risky)"
+        ));
+
+        assert!(check_maturity(&experiments, true).is_ok());
+        let stable: Vec<Experiment> = experiments
+            .into_iter()
+            .filter(|e| e.metadata().maturity == Maturity::Stable)
+            .collect();
+        assert!(check_maturity(&stable, false).is_ok());
+    }
+
+    #[test]
+    fn test_enable_all_single_transaction() {
+        let runner = MockSystem::default();
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["coreutils", "sudo-rs"].contains(&e.name().as_str()))
+            .collect();
+
+        let mut outcomes = Vec::new();
+        let result = enable_all(
+            &runner,
+            &experiments,
+            &EnableOptions::default(),
+            |name, ok| outcomes.push((name.to_string(), ok)),
+        );
+        assert!(result.is_ok());
+
+        assert_eq!(
+            runner.commands.clone().into_inner(),
+            vec!["apt-get install -y rust-coreutils sudo-rs".to_string()]
+        );
+        assert_eq!(
+            outcomes,
+            vec![
+                ("coreutils".to_string(), true),
+                ("sudo-rs".to_string(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disable_all_single_transaction() {
+        let runner = MockSystem::default();
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["findutils", "sudo-rs"].contains(&e.name().as_str()))
+            .collect();
+
+        assert!(disable_all(&runner, &experiments, |_, _| ()).is_ok());
+        // Each removal is simulated first, to check that it is safe.
+        let removals: Vec<String> = runner
+            .commands
+            .clone()
+            .into_inner()
+            .into_iter()
+            .filter(|c| c.starts_with("apt-get remove"))
+            .collect();
+        assert_eq!(removals, vec!["apt-get remove -y rust-findutils sudo-rs"]);
+        assert_eq!(runner.restored_files.clone().into_inner().len(), 3);
+    }
+
+    #[test]
+    fn test_enable_all_defers_files_in_use() {
+        let runner = MockSystem::default();
+        runner.mock_process(42, "/usr/bin/visudo", Some("cron.service"));
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        let mut outcomes = Vec::new();
+        let result = enable_all(
+            &runner,
+            &experiments,
+            &EnableOptions {
+                in_use: InUsePolicy::Wait,
+                ..Default::default()
+            },
+            |name, ok| outcomes.push((name.to_string(), ok)),
+        );
+        assert!(result.is_ok());
+
+        // The file in use is replaced last, once the process using it has exited.
+        let copies = runner.copied_files.clone().into_inner();
+        assert_eq!(copies.len(), 2);
+        let symlinks = runner.created_symlinks.clone().into_inner();
+        assert_eq!(symlinks.len(), 1);
+        assert_eq!(symlinks[0].1, "/usr/bin/visudo");
+        assert_eq!(outcomes, vec![("sudo-rs".to_string(), true)]);
+    }
+
+    #[test]
+    fn test_enable_all_schedules_files_in_use_at_boot() {
+        let runner = MockSystem::default();
+        runner.mock_process(42, "/usr/bin/visudo", None);
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        assert!(
+            enable_all(
+                &runner,
+                &experiments,
+                &EnableOptions {
+                    in_use: InUsePolicy::AtBoot,
+                    ..Default::default()
+                },
+                |_, _| ()
+            )
+            .is_ok()
+        );
+        assert_eq!(runner.copied_files.clone().into_inner().len(), 2);
+        assert!(runner.created_symlinks.clone().into_inner().is_empty());
+        assert_eq!(
+            runner.backed_up_files.clone().into_inner(),
+            vec!["/usr/bin/visudo".to_string()]
+        );
+
+        let schedule = runner.read_file(Path::new(BOOT_SCHEDULE_PATH)).unwrap();
+        assert_eq!(
+            schedule.unwrap(),
+            "L+ /usr/bin/visudo - - - - /usr/lib/cargo/bin/visudo\n"
+        );
+
+        // Disabling the experiment cancels the scheduled replacement.
+        runner.mock_install_package("sudo-rs");
+        assert!(disable_all(&runner, &experiments, |_, _| ()).is_ok());
+        let schedule = runner.read_file(Path::new(BOOT_SCHEDULE_PATH)).unwrap();
+        assert_eq!(schedule.unwrap(), "");
+    }
+
+    #[test]
+    fn test_fapolicyd_trust() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![("/usr/sbin/fapolicyd-cli", "", true)]);
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        assert!(enable_all(&runner, &experiments, &EnableOptions::default(), |_, _| ()).is_ok());
+        let commands = runner.commands.clone().into_inner();
+        assert!(commands.contains(&"fapolicyd-cli --file add /usr/lib/cargo/bin/sudo".to_string()));
+        assert_eq!(commands.last().unwrap(), "fapolicyd-cli --update");
+
+        runner.mock_install_package("sudo-rs");
+        assert!(disable_all(&runner, &experiments, |_, _| ()).is_ok());
+        let commands = runner.commands.clone().into_inner();
+        assert!(
+            commands.contains(&"fapolicyd-cli --file delete /usr/lib/cargo/bin/sudo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_enable_all_skips_unsigned_binaries() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![(
+            "/sys/kernel/security/ima/policy",
+            "appraise func=BPRM_CHECK appraise_type=imasig\n",
+            false,
+        )]);
+        runner.mock_command(
+            "getfattr --absolute-names -n security.ima /usr/lib/cargo/bin/su",
+            "# file: /usr/lib/cargo/bin/su\nsecurity.ima=0sAwIE\n",
+        );
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        let options = EnableOptions {
+            ima: ImaPolicy::Skip,
+            ..Default::default()
+        };
+        assert!(enable_all(&runner, &experiments, &options, |_, _| ()).is_ok());
+
+        // Only the signed replacement is put in place.
+        assert!(runner.created_symlinks.clone().into_inner().is_empty());
+        let copies = runner.copied_files.clone().into_inner();
+        assert_eq!(copies.len(), 1);
+        assert_eq!(copies[0].1, "/usr/bin/su");
+    }
+
+    #[test]
+    fn test_available_experiments() {
+        let runner = MockSystem::default();
+        runner.mock_command("dpkg --print-architecture", "armhf");
+        runner.mock_command(
+            "apt-cache policy rust-coreutils",
+            "rust-coreutils:\n  Installed: (none)\n  Candidate: 0.0.23-1\n",
+        );
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["coreutils", "sudo-rs"].contains(&e.name().as_str()))
+            .collect();
+
+        let available = available_experiments(&runner, experiments).unwrap();
+        let names: Vec<String> = available.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["coreutils"]);
+    }
+
+    #[test]
+    fn test_should_enable_strict() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "22.04"));
+        let sudors = Experiment::SudoRs(SudoRsExperiment::new(&runner));
+
+        assert!(!sudors.should_enable(false, false).unwrap());
+        assert!(sudors.should_enable(true, true).unwrap());
+        assert!(sudors.should_enable(false, true).is_err());
+        assert!(sudors.should_disable(true).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use super::{Directories, Maturity, Metadata};
+use crate::utils::{
+    Distribution, ErrorCode, FsOperation, StateFile, Worker, read_state, write_state,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use tracing::info;
+
+/// The location of the record of the binary overrides in effect.
+pub const OVERRIDES_PATH: &str = "/var/lib/oxidizr/overrides.json";
+
+/// The binaries replaced by the `overrides` experiment when it was enabled, each mapped to the
+/// file that replaces it. The overrides are taken from the configuration when the experiment is
+/// enabled, and recorded so that reapplying, listing and disabling it agree with what was actually
+/// replaced - even if the configuration has changed since.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Overrides {
+    pub binaries: BTreeMap<String, PathBuf>,
+}
+
+impl StateFile for Overrides {
+    const PATH: &'static str = OVERRIDES_PATH;
+}
+
+impl Overrides {
+    /// Load the recorded overrides. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded overrides.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Check that each override names a binary, rather than a path, and maps it to an absolute
+    /// path to a file that exists.
+    pub fn validate(&self, system: &dyn Worker) -> Result<()> {
+        for (binary, source) in &self.binaries {
+            let problem = if binary.is_empty() || binary.contains('/') {
+                Some("the name of a binary is expected, not a path")
+            } else if !source.is_absolute() {
+                Some("the replacement must be an absolute path")
+            } else if system.file_checksum(source)?.is_none() {
+                Some("the replacement doesn't exist")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err(ErrorCode::InvalidConfig.error(format!(
+                    "invalid override of '{binary}' with '{}': {problem}",
+                    source.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record the overrides declared in the configuration before the `overrides` experiment is
+/// enabled. Overriding a binary that another enabled experiment replaces is refused, as the two
+/// would fight over the same file and its backup.
+pub fn record_overrides(system: &dyn Worker, binaries: &BTreeMap<String, PathBuf>) -> Result<()> {
+    let overrides = Overrides {
+        binaries: binaries.clone(),
+    };
+    overrides.validate(system)?;
+
+    for e in super::all_experiments(system) {
+        if matches!(e, super::Experiment::Overrides(_)) || !e.check_installed() {
+            continue;
+        }
+        for (target, _) in e
+            .check_replaced()?
+            .into_iter()
+            .filter(|(_, linked)| *linked)
+        {
+            if target
+                .file_name()
+                .is_some_and(|name| binaries.keys().any(|b| name == b.as_str()))
+            {
+                return Err(ErrorCode::InvalidConfig.error(format!(
+                    "'{}' is already replaced by the '{}' experiment, so can't be overridden",
+                    target.display(),
+                    e.name()
+                )));
+            }
+        }
+    }
+
+    overrides.save(system)
+}
+
+/// An experiment replacing arbitrary binaries with files declared in the configuration, such as a
+/// wrapper script, rather than with those from a package.
+pub struct OverridesExperiment<'a> {
+    system: &'a dyn Worker,
+}
+
+impl<'a> OverridesExperiment<'a> {
+    /// Create a new OverridesExperiment.
+    pub fn new(system: &'a dyn Worker) -> Self {
+        Self { system }
+    }
+
+    /// The system the experiment operates on.
+    pub(super) fn system(&self) -> &'a dyn Worker {
+        self.system
+    }
+
+    /// Overrides are declared by the administrator for their own system, so aren't tied to any
+    /// distribution release.
+    pub fn supported_releases(&self) -> Vec<Distribution> {
+        Vec::new()
+    }
+
+    /// Report whether any overrides are recorded, the equivalent of a package being installed.
+    pub fn check_installed(&self) -> bool {
+        Overrides::load(self.system).is_ok_and(|o| !o.binaries.is_empty())
+    }
+
+    /// Report the name of the experiment.
+    pub fn name(&self) -> String {
+        String::from("overrides")
+    }
+
+    /// Overrides aren't provided by a package.
+    pub fn package(&self) -> String {
+        String::new()
+    }
+
+    /// Report information about the experiment. The replacements are chosen by the administrator,
+    /// so nothing is known about their license, and they are considered risky.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            upstream: "",
+            license: "NOASSERTION",
+            maturity: Maturity::Risky,
+            maintainer: "the local administrator",
+            replaces: "",
+            replaced_license: "NOASSERTION",
+        }
+    }
+
+    /// Enable the experiment by replacing each overridden binary with a symlink to its
+    /// replacement.
+    pub fn enable(&self) -> Result<()> {
+        info!("Configuring overrides");
+
+        let symlinks: Vec<FsOperation> = self
+            .replacements()?
+            .into_iter()
+            .map(|(source, target)| FsOperation::symlink(source, target))
+            .collect::<Result<_>>()?;
+        self.system
+            .apply(&symlinks)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    /// Disable the experiment by restoring the original binaries.
+    pub fn disable(&self) -> Result<()> {
+        info!("Restoring files replaced by overrides");
+
+        let restores: Vec<FsOperation> = self
+            .replacements()?
+            .into_iter()
+            .map(|(_, file)| FsOperation::restore(file))
+            .collect::<Result<_>>()?;
+        self.system
+            .apply(&restores)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    /// Report each overridden binary, and whether it currently links to its replacement.
+    pub fn check_replaced(&self) -> Result<Vec<(PathBuf, bool)>> {
+        self.replacements()?
+            .into_iter()
+            .map(|(source, target)| {
+                let linked = self.system.read_symlink(&target)? == Some(source);
+                Ok((target, linked))
+            })
+            .collect()
+    }
+
+    /// List the recorded replacements, paired with the system files they replace.
+    pub fn replacements(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let directories = Directories::load(self.system)?;
+        Ok(Overrides::load(self.system)?
+            .binaries
+            .into_iter()
+            .map(|(binary, source)| {
+                let existing = directories.locate(self.system, &self.name(), binary.as_ref());
+                (source, existing)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_overrides_enable() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/bin/grep", "grep", true),
+            ("/usr/local/bin/rg-wrapper", "#!/bin/sh", false),
+        ]);
+        let overrides = OverridesExperiment::new(&runner);
+        assert!(!overrides.check_installed());
+
+        let recorded = Overrides {
+            binaries: BTreeMap::from([(
+                "grep".to_string(),
+                PathBuf::from("/usr/local/bin/rg-wrapper"),
+            )]),
+        };
+        recorded.validate(&runner).unwrap();
+        recorded.save(&runner).unwrap();
+        assert!(overrides.check_installed());
+
+        overrides.enable().unwrap();
+        assert_eq!(
+            runner.created_symlinks.clone().into_inner(),
+            vec![(
+                "/usr/local/bin/rg-wrapper".to_string(),
+                "/usr/bin/grep".to_string()
+            )]
+        );
+        assert!(overrides.check_replaced().unwrap().iter().all(|(_, r)| *r));
+    }
+
+    #[test]
+    fn test_overrides_validate() {
+        let runner = MockSystem::default();
+        let invalid = |binary: &str, source: &str| {
+            let overrides = Overrides {
+                binaries: BTreeMap::from([(binary.to_string(), PathBuf::from(source))]),
+            };
+            let err = overrides.validate(&runner).unwrap_err();
+            assert_eq!(ErrorCode::of(&err), Some(ErrorCode::InvalidConfig));
+        };
+
+        invalid("/usr/bin/grep", "/usr/local/bin/rg-wrapper");
+        invalid("grep", "rg-wrapper");
+        invalid("grep", "/usr/local/bin/rg-wrapper");
+    }
+}
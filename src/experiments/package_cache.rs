@@ -0,0 +1,203 @@
+use super::Experiment;
+use crate::utils::{Command, StateFile, Worker, read_state, update_state, write_state};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info, warn};
+
+/// The directory holding the package files of the GNU packages replaced by enabled experiments.
+pub const PACKAGE_CACHE_DIRECTORY: &str = "/var/lib/oxidizr/packages";
+
+/// The location of the record of the package files held in `PACKAGE_CACHE_DIRECTORY`.
+pub const PACKAGE_CACHE_PATH: &str = "/var/lib/oxidizr/packages.json";
+
+/// A package file downloaded into the cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedPackage {
+    pub version: String,
+    pub file: PathBuf,
+    /// The sha256 digest of the file when it was downloaded.
+    pub sha256: String,
+}
+
+/// The package files of the GNU packages replaced by enabled experiments, by package name, at the
+/// versions installed when they were enabled. A rollback reinstalls them from here, so that it
+/// needs neither the network nor a package archive still carrying those versions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageCache {
+    pub packages: BTreeMap<String, CachedPackage>,
+}
+
+impl StateFile for PackageCache {
+    const PATH: &'static str = PACKAGE_CACHE_PATH;
+}
+
+impl PackageCache {
+    /// Load the record. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the record.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Find the cached file of a package at `version`, if it is cached and unchanged since it was
+    /// downloaded.
+    pub fn file(&self, system: &dyn Worker, package: &str, version: &str) -> Option<PathBuf> {
+        let cached = self
+            .packages
+            .get(package)
+            .filter(|c| c.version == version)?;
+        match system.file_checksum(&cached.file) {
+            Ok(Some(sha256)) if sha256 == cached.sha256 => Some(cached.file.clone()),
+            _ => {
+                warn!(
+                    "The cached package file {} is missing or has changed, so isn't used",
+                    cached.file.display()
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The name apt gives the file of a package it downloads, such as
+/// `coreutils_9.4-3ubuntu6_amd64.deb`, in which the colon of an epoch is escaped.
+fn package_file_prefix(package: &str, version: &str) -> String {
+    format!("{package}_{}_", version.replace(':', "%3a"))
+}
+
+/// Download the package file of each GNU package replaced by the experiments, at its installed
+/// version, into the cache, replacing those of other versions. Packages already cached are left
+/// as they are. Failures, such as when the network is unreachable, are logged rather than
+/// returned, so that they don't fail an otherwise successful operation.
+pub fn warm_package_cache(system: &dyn Worker, experiments: &[Experiment]) {
+    let mut cache = PackageCache::load(system).unwrap_or_default();
+    let mut changed = false;
+    for e in experiments {
+        let package = e.metadata().replaces;
+        let version = match system.installed_version(package) {
+            Ok(Some(version)) => version.to_string(),
+            _ => continue,
+        };
+        if cache.file(system, package, &version).is_some() {
+            debug!("{package} {version} is already cached");
+            continue;
+        }
+        match download(system, package, &version) {
+            Ok(downloaded) => {
+                let file = downloaded.file.clone();
+                if let Some(old) = cache.packages.insert(package.to_string(), downloaded)
+                    && old.file != file
+                    && let Err(err) = system.remove_file(&old.file)
+                {
+                    warn!("Unable to remove {}: {err:#}", old.file.display());
+                }
+                changed = true;
+            }
+            Err(err) => warn!(
+                "Unable to cache {package} {version}, so rolling back will need the package \
+                archive: {err:#}"
+            ),
+        }
+    }
+
+    if changed
+        && let Err(e) = update_state(system, |c: &mut PackageCache| {
+            c.packages.append(&mut cache.packages);
+            Ok(())
+        })
+    {
+        warn!("Unable to record the cached packages: {:#}", e);
+    }
+}
+
+/// Download the package file of a package at `version` into the cache, without installing it.
+fn download(system: &dyn Worker, package: &str, version: &str) -> Result<CachedPackage> {
+    info!("Caching {package} {version} for rollbacks");
+    let directory = Path::new(PACKAGE_CACHE_DIRECTORY);
+    // apt-get downloads into the working directory.
+    let script = r#"mkdir -p "$1" && cd "$1" && exec apt-get download "$2""#;
+    let cmd = Command::build("sh", &["-c", script, "sh"])
+        .arg(directory)
+        .arg(format!("{package}={version}"));
+    system.run(&cmd)?;
+
+    let prefix = package_file_prefix(package, version);
+    let file = system
+        .list_files(directory.to_path_buf())?
+        .into_iter()
+        .find(|f| {
+            f.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".deb"))
+        })
+        .ok_or_else(|| anyhow::anyhow!("apt-get downloaded no file for {package} {version}"))?;
+    let sha256 = system
+        .file_checksum(&file)?
+        .ok_or_else(|| anyhow::anyhow!("'{}' can't be read", file.display()))?;
+    Ok(CachedPackage {
+        version: version.to_string(),
+        file,
+        sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_package_file_prefix() {
+        assert_eq!(
+            package_file_prefix("coreutils", "9.4-3ubuntu6"),
+            "coreutils_9.4-3ubuntu6_"
+        );
+        assert_eq!(package_file_prefix("tar", "2:1.35"), "tar_2%3a1.35_");
+    }
+
+    #[test]
+    fn test_warm_package_cache() {
+        let runner = MockSystem::default();
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == "coreutils")
+            .collect();
+        runner.mock_install_package("coreutils");
+        runner.mock_command("dpkg-query -W -f=${Version} coreutils", "9.4-3ubuntu6");
+        let deb = "/var/lib/oxidizr/packages/coreutils_9.4-3ubuntu6_amd64.deb";
+        runner.mock_files(vec![(deb, "a package", false)]);
+
+        warm_package_cache(&runner, &experiments);
+        let cache = PackageCache::load(&runner).unwrap();
+        assert_eq!(
+            cache.file(&runner, "coreutils", "9.4-3ubuntu6"),
+            Some(PathBuf::from(deb))
+        );
+        assert_eq!(cache.file(&runner, "coreutils", "9.4-3ubuntu7"), None);
+        let downloads = |runner: &MockSystem| {
+            runner
+                .commands
+                .borrow()
+                .iter()
+                .filter(|c| c.starts_with("sh -c"))
+                .count()
+        };
+        assert_eq!(downloads(&runner), 1);
+
+        // A package already cached isn't downloaded again.
+        warm_package_cache(&runner, &experiments);
+        assert_eq!(downloads(&runner), 1);
+
+        // Nor is a package file that has changed since used.
+        runner.mock_files(vec![(deb, "another package", false)]);
+        assert_eq!(cache.file(&runner, "coreutils", "9.4-3ubuntu6"), None);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::utils::Worker;
+use std::path::{Path, PathBuf};
+
+/// The directory containing the PAM configuration for each service.
+const PAM_DIR: &str = "/etc/pam.d";
+
+/// The binaries that authenticate users, paired with the PAM service each of them uses. Replacing
+/// any of these with a binary that can't authenticate would lock administrators out of root.
+const AUTH_SERVICES: &[(&str, &str)] = &[("sudo", "sudo"), ("su", "su"), ("login", "login")];
+
+/// Report the PAM services used by the binaries a set of replacements would replace. Replacements
+/// of binaries that don't authenticate users have no services.
+pub(super) fn services(replacements: &[(PathBuf, PathBuf)]) -> Vec<&'static str> {
+    AUTH_SERVICES
+        .iter()
+        .filter(|(binary, _)| {
+            replacements
+                .iter()
+                .any(|(_, target)| target.file_name().is_some_and(|name| name == *binary))
+        })
+        .map(|(_, service)| *service)
+        .collect()
+}
+
+/// Check the PAM configuration of each service, returning a description of every problem that
+/// would stop it from authenticating users: a missing service file, a service without an `auth`
+/// stack, or an `@include` of a file that doesn't exist.
+pub(super) fn check(system: &dyn Worker, services: &[&str]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for service in services {
+        let path = Path::new(PAM_DIR).join(service);
+        let Some(config) = system.read_file(&path).ok().flatten() else {
+            problems.push(format!(
+                "PAM service '{service}' is not configured: {} does not exist",
+                path.display()
+            ));
+            continue;
+        };
+
+        let mut authenticates = false;
+        for line in config.lines().map(str::trim) {
+            if let Some(include) = line.strip_prefix("@include") {
+                let include = Path::new(PAM_DIR).join(include.trim());
+                if system.read_file(&include).ok().flatten().is_none() {
+                    problems.push(format!(
+                        "{} includes {}, which does not exist",
+                        path.display(),
+                        include.display()
+                    ));
+                }
+                authenticates |= include.ends_with("common-auth");
+            }
+            authenticates |= line.starts_with("auth");
+        }
+
+        if !authenticates {
+            problems.push(format!("{} has no auth stack", path.display()));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_services() {
+        let replacements = vec![
+            (
+                PathBuf::from("/usr/lib/cargo/bin/sudo"),
+                PathBuf::from("/usr/bin/sudo"),
+            ),
+            (
+                PathBuf::from("/usr/lib/cargo/bin/visudo"),
+                PathBuf::from("/usr/sbin/visudo"),
+            ),
+        ];
+        assert_eq!(services(&replacements), vec!["sudo"]);
+        assert!(services(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_check() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            (
+                "/etc/pam.d/sudo",
+                "#%PAM-1.0\nsession required pam_env.so\n@include common-auth\n@include common-session-noninteractive\n",
+                false,
+            ),
+            ("/etc/pam.d/common-auth", "auth required pam_unix.so\n", false),
+            ("/etc/pam.d/su", "session required pam_env.so\n", false),
+        ]);
+
+        assert_eq!(
+            check(&runner, &["sudo", "su", "login"]),
+            vec![
+                "/etc/pam.d/sudo includes /etc/pam.d/common-session-noninteractive, which does not exist",
+                "/etc/pam.d/su has no auth stack",
+                "PAM service 'login' is not configured: /etc/pam.d/login does not exist",
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::utils::{StateFile, Worker, read_state, write_state};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The location of the record of the binaries that each enabled experiment leaves in place.
+pub const PINS_PATH: &str = "/var/lib/oxidizr/pins.json";
+
+/// The binaries pinned to GNU when each experiment was enabled, by experiment name. The pins are
+/// taken from the configuration at the time an experiment is enabled, and recorded so that
+/// reapplying, listing and disabling it agree with what was actually replaced - even if the
+/// configuration has changed since.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pins {
+    pub experiments: BTreeMap<String, Vec<String>>,
+}
+
+impl StateFile for Pins {
+    const PATH: &'static str = PINS_PATH;
+}
+
+impl Pins {
+    /// Load the recorded pins. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded pins.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Report the binaries pinned to GNU for an experiment.
+    pub fn of(&self, experiment: &str) -> &[String] {
+        self.experiments
+            .get(experiment)
+            .map_or(&[], |pinned| pinned.as_slice())
+    }
+
+    /// Set the binaries pinned to GNU for an experiment, forgetting it if there are none.
+    pub fn set(&mut self, experiment: &str, pinned: &[String]) {
+        match pinned.is_empty() {
+            true => self.experiments.remove(experiment),
+            false => self
+                .experiments
+                .insert(experiment.to_string(), pinned.to_vec()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_pins_round_trip() {
+        let runner = MockSystem::default();
+        assert_eq!(Pins::load(&runner).unwrap(), Pins::default());
+
+        let mut pins = Pins::default();
+        pins.set("coreutils", &["dd".to_string(), "stat".to_string()]);
+        pins.save(&runner).unwrap();
+
+        let mut pins = Pins::load(&runner).unwrap();
+        assert_eq!(pins.of("coreutils"), ["dd", "stat"]);
+        assert!(pins.of("findutils").is_empty());
+
+        pins.set("coreutils", &[]);
+        assert_eq!(pins, Pins::default());
+    }
+}
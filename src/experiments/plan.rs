@@ -0,0 +1,297 @@
+use super::{
+    Experiment, KnownIssue, KnownIssues, Maturity, OptionCoverage, Pins, RemovalImpact, Risk,
+    coverage_percent, ima, removal_impact,
+};
+use crate::utils::Worker;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+
+/// An experiment included in a plan, and the reason it will be skipped, if any.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PlannedExperiment {
+    pub name: String,
+    pub package: String,
+    pub maturity: Maturity,
+    pub skip_reason: Option<String>,
+    /// The options of each replaced GNU binary that its replacement supports. Only known when
+    /// the replacement package is already installed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub option_coverage: Vec<OptionCoverage>,
+    /// Known issues affecting the version of the replacement package that is installed, or that
+    /// would be installed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub known_issues: Vec<KnownIssue>,
+    /// The binaries that will be left as GNU.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<String>,
+    /// The risk of enabling the experiment. Only assessed for experiments that will be enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+    /// What removing the replacement package would do, when it can't be removed safely and will
+    /// be left installed. Only assessed for experiments that will be disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removal: Option<RemovalImpact>,
+}
+
+/// A summary of what an enable or disable operation will do, shown before the user is asked to
+/// confirm it.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Plan {
+    pub command: String,
+    pub experiments: Vec<PlannedExperiment>,
+    pub warnings: Vec<String>,
+}
+
+impl Plan {
+    /// Plan the enabling of a set of experiments, leaving the binaries in `pins` as GNU.
+    pub fn enable(
+        system: &dyn Worker,
+        experiments: &[Experiment],
+        no_compat: bool,
+        pins: &Pins,
+    ) -> Self {
+        let known_issues = KnownIssues::load(system);
+        let experiments = experiments
+            .iter()
+            .map(|e| {
+                let skip_reason = match no_compat {
+                    true => None,
+                    false => e.compatibility().err().map(|i| i.to_string()),
+                };
+                if skip_reason.is_some() {
+                    return PlannedExperiment {
+                        name: e.name(),
+                        package: e.package(),
+                        maturity: e.metadata().maturity,
+                        skip_reason,
+                        option_coverage: Vec::new(),
+                        known_issues: Vec::new(),
+                        pinned: Vec::new(),
+                        risk: None,
+                        removal: None,
+                    };
+                }
+
+                // Pinned binaries stay GNU, so neither lose options nor suffer from known issues.
+                let pinned = pins.of(&e.name()).to_vec();
+                let is_pinned = |binary: &str| pinned.iter().any(|p| p == binary);
+                let mut planned = PlannedExperiment {
+                    name: e.name(),
+                    package: e.package(),
+                    maturity: e.metadata().maturity,
+                    option_coverage: e
+                        .option_coverage()
+                        .into_iter()
+                        .filter(|c| !is_pinned(&c.binary))
+                        .collect(),
+                    known_issues: issues_affecting(system, &known_issues, &e.package())
+                        .into_iter()
+                        .filter(|i| !i.binary.as_deref().is_some_and(is_pinned))
+                        .collect(),
+                    pinned,
+                    skip_reason,
+                    risk: None,
+                    removal: None,
+                };
+                planned.risk = Some(Risk::assess(e, &planned));
+                planned
+            })
+            .collect();
+
+        let mut warnings = Vec::new();
+        if ima::appraisal_enforced(system) {
+            warnings.push(
+                "IMA appraisal is enforced: replacement binaries without an IMA signature will \
+                fail to execute. Use '--ima skip' to leave those files in place, or '--ima sign' \
+                to sign the replacements"
+                    .to_string(),
+            );
+        }
+
+        Self {
+            command: "enable".to_string(),
+            experiments,
+            warnings,
+        }
+    }
+
+    /// Plan the disabling of a set of experiments, noting the replacement packages that are
+    /// Essential or depended on, which will be left installed.
+    pub fn disable(system: &dyn Worker, experiments: &[Experiment]) -> Self {
+        let experiments = experiments
+            .iter()
+            .map(|e| {
+                let skip_reason = (!e.check_installed()).then(|| "not enabled".to_string());
+                let removal = match skip_reason.is_none() && !e.package().is_empty() {
+                    true => removal_impact(system, &e.package())
+                        .ok()
+                        .filter(|impact| !impact.is_safe()),
+                    false => None,
+                };
+                PlannedExperiment {
+                    name: e.name(),
+                    package: e.package(),
+                    maturity: e.metadata().maturity,
+                    skip_reason,
+                    option_coverage: Vec::new(),
+                    known_issues: Vec::new(),
+                    pinned: Vec::new(),
+                    risk: None,
+                    removal,
+                }
+            })
+            .collect();
+
+        Self {
+            command: "disable".to_string(),
+            experiments,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Report whether any experiment in the plan is affected by a known issue.
+    pub fn has_known_issues(&self) -> bool {
+        self.experiments.iter().any(|e| !e.known_issues.is_empty())
+    }
+}
+
+/// List the known issues affecting the installed version of a package, or the version that would
+/// be installed if it isn't installed yet.
+fn issues_affecting(system: &dyn Worker, issues: &KnownIssues, package: &str) -> Vec<KnownIssue> {
+    if package.is_empty() {
+        return Vec::new();
+    }
+    let version = match system.installed_version(package) {
+        Ok(Some(version)) => Some(version),
+        _ => system.candidate_version(package).ok().flatten(),
+    };
+    version.map_or_else(Vec::new, |version| issues.affecting(package, &version))
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "oxidizr will {} the following experiments:",
+            self.command
+        )?;
+        for e in &self.experiments {
+            match &e.skip_reason {
+                Some(reason) => writeln!(f, "  - {} (skipped: {})", e.name, reason)?,
+                None if e.package.is_empty() => writeln!(f, "  - {} ({})", e.name, e.maturity)?,
+                None if e.maturity == Maturity::Stable => {
+                    writeln!(f, "  - {} (package {})", e.name, e.package)?
+                }
+                None => writeln!(f, "  - {} (package {}, {})", e.name, e.package, e.maturity)?,
+            }
+            if let Some(risk) = &e.risk {
+                writeln!(f, "      Risk: {risk}")?;
+            }
+            if !e.option_coverage.is_empty() {
+                writeln!(
+                    f,
+                    "      {}% of the options of the replaced binaries are supported",
+                    coverage_percent(&e.option_coverage)
+                )?;
+            }
+            if !e.pinned.is_empty() {
+                writeln!(f, "      pinned to GNU: {}", e.pinned.join(", "))?;
+            }
+            if let Some(removal) = &e.removal {
+                writeln!(f, "      {}: it will be left installed", removal.reason())?;
+            }
+            for c in e.option_coverage.iter().filter(|c| !c.missing.is_empty()) {
+                writeln!(f, "      {}: missing {}", c.binary, c.missing.join(", "))?;
+            }
+            for issue in &e.known_issues {
+                let binary = issue.binary.as_deref().unwrap_or(&issue.package);
+                match &issue.fixed {
+                    Some(fixed) => writeln!(
+                        f,
+                        "      Known issue in {binary}: {} (fixed in {fixed})",
+                        issue.summary
+                    )?,
+                    None => writeln!(f, "      Known issue in {binary}: {}", issue.summary)?,
+                }
+            }
+        }
+        for warning in &self.warnings {
+            writeln!(f, "Warning: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::SudoRsExperiment;
+    use crate::utils::{Distribution, MockSystem};
+
+    #[test]
+    fn test_enable_plan() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "22.04"));
+        runner.mock_files(vec![(
+            "/sys/kernel/security/ima/policy",
+            "measure func=BPRM_CHECK\nappraise func=BPRM_CHECK appraise_type=imasig\n",
+            false,
+        )]);
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        let plan = Plan::enable(&runner, &experiments, false, &Pins::default());
+        assert_eq!(
+            plan.experiments[0].skip_reason.as_deref(),
+            Some("Ubuntu 22.04 is older than Ubuntu 24.04, the oldest release supported")
+        );
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.to_string().contains("  - sudo-rs (skipped: "));
+
+        // IMA appraisal isn't enforced when relaxed on the kernel command line.
+        runner.mock_files(vec![("/proc/cmdline", "ro quiet ima_appraise=log", false)]);
+        assert!(
+            Plan::enable(&runner, &experiments, true, &Pins::default())
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_enable_plan_known_issues() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "24.04"));
+        runner.mock_command(
+            "apt-cache policy rust-coreutils",
+            "rust-coreutils:\n  Installed: (none)\n  Candidate: 0.0.23-1\n",
+        );
+        let experiments: Vec<Experiment> = crate::experiments::all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == "coreutils")
+            .collect();
+
+        let plan = Plan::enable(&runner, &experiments, false, &Pins::default());
+        assert!(plan.has_known_issues());
+        assert!(plan.to_string().contains("      Known issue in date: "));
+
+        // Pinning the affected binary to GNU avoids the issue.
+        let mut pins = Pins::default();
+        pins.set("coreutils", &["date".to_string()]);
+        let plan = Plan::enable(&runner, &experiments, false, &pins);
+        assert!(!plan.has_known_issues());
+        assert!(plan.to_string().contains("      pinned to GNU: date\n"));
+    }
+
+    #[test]
+    fn test_disable_plan_removal() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "24.04"));
+        runner.mock_install_package("sudo-rs");
+        runner.mock_command("dpkg-query -W -f=${Essential} sudo-rs", "yes");
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+
+        let plan = Plan::disable(&runner, &experiments);
+        assert!(plan.experiments[0].removal.as_ref().unwrap().essential);
+        assert!(
+            plan.to_string()
+                .contains("      sudo-rs is an Essential package: it will be left installed\n")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use super::{Experiment, Usage};
+use crate::utils::{StateFile, Worker, read_state, update_state, write_state};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+
+/// The location of the record of the binaries implicated in failures on this host.
+pub const QUARANTINE_PATH: &str = "/var/lib/oxidizr/quarantine.json";
+
+/// The number of failures implicating a binary after which it is quarantined.
+pub const QUARANTINE_STRIKES: u32 = 3;
+
+/// What implicated a binary in a failure.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Implication {
+    /// The replacement failed the smoke test run once an experiment was enabled.
+    SmokeTest,
+    /// `oxidizr bisect` found that the replacement alone makes a command fail.
+    Bisect,
+    /// The shim replacing the binary fell back to GNU, the replacement having rejected an option.
+    Fallback,
+}
+
+impl Implication {
+    /// The number of strikes counted against a binary each time. A bisection has already run the
+    /// command with and without the replacement, so it is conclusive on its own.
+    fn strikes(self) -> u32 {
+        match self {
+            Self::Bisect => QUARANTINE_STRIKES,
+            Self::SmokeTest | Self::Fallback => 1,
+        }
+    }
+}
+
+impl fmt::Display for Implication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SmokeTest => write!(f, "smoke test"),
+            Self::Bisect => write!(f, "bisect"),
+            Self::Fallback => write!(f, "fallback to GNU"),
+        }
+    }
+}
+
+/// The failures implicating a binary, and whether they have got it quarantined.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Suspicion {
+    /// The experiment replacing the binary.
+    pub experiment: String,
+    pub strikes: u32,
+    pub implicated_by: BTreeSet<Implication>,
+    /// The latest failure implicating the binary.
+    pub reason: String,
+    /// When the binary was quarantined, in seconds since the Unix epoch, or `None` while it is
+    /// only suspected.
+    pub quarantined: Option<u64>,
+}
+
+/// The binaries implicated in failures on this host, by binary name. Once a binary has been
+/// implicated `QUARANTINE_STRIKES` times it is quarantined: it is left as GNU, as if it were
+/// pinned, by every later enable and reapply - whatever the configuration pins - until the
+/// quarantine is cleared.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Quarantine {
+    pub binaries: BTreeMap<String, Suspicion>,
+    /// The fallbacks of each binary already counted as strikes, so that they are only counted
+    /// once, even once a quarantine is cleared.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallbacks_counted: BTreeMap<String, u64>,
+}
+
+impl StateFile for Quarantine {
+    const PATH: &'static str = QUARANTINE_PATH;
+}
+
+impl Quarantine {
+    /// Load the record. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the record.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Report the binaries of an experiment that are quarantined.
+    pub fn of(&self, experiment: &str) -> Vec<String> {
+        self.binaries
+            .iter()
+            .filter(|(_, s)| s.experiment == experiment && s.quarantined.is_some())
+            .map(|(binary, _)| binary.clone())
+            .collect()
+    }
+
+    /// Count a failure against a binary, quarantining it once it has been implicated enough
+    /// times. Returns whether it was quarantined by this failure.
+    pub fn implicate(
+        &mut self,
+        experiment: &str,
+        binary: &str,
+        by: Implication,
+        reason: String,
+        now: u64,
+    ) -> bool {
+        let suspicion = self
+            .binaries
+            .entry(binary.to_string())
+            .or_insert_with(|| Suspicion {
+                experiment: experiment.to_string(),
+                strikes: 0,
+                implicated_by: BTreeSet::new(),
+                reason: String::new(),
+                quarantined: None,
+            });
+        suspicion.strikes += by.strikes();
+        suspicion.implicated_by.insert(by);
+        suspicion.reason = reason;
+        if suspicion.quarantined.is_some() || suspicion.strikes < QUARANTINE_STRIKES {
+            return false;
+        }
+        suspicion.quarantined = Some(now);
+        true
+    }
+
+    /// Count each fallback to GNU recorded by the shims since they were last counted, as a
+    /// failure of the binary. `experiment_of` names the experiment replacing a binary, if it is
+    /// still replaced. Returns the binaries quarantined as a result.
+    pub fn count_fallbacks(
+        &mut self,
+        usage: &Usage,
+        experiment_of: impl Fn(&str) -> Option<String>,
+        now: u64,
+    ) -> Vec<String> {
+        let mut quarantined = Vec::new();
+        for (binary, u) in &usage.binaries {
+            let counted = self.fallbacks_counted.entry(binary.clone()).or_default();
+            // The counts start again from nothing if the usage record is removed.
+            let new = u.fallbacks.saturating_sub(*counted);
+            *counted = u.fallbacks;
+            let Some(experiment) = experiment_of(binary) else {
+                continue;
+            };
+            for _ in 0..new {
+                let reason = format!(
+                    "the shim for {binary} fell back to GNU {} time(s)",
+                    u.fallbacks
+                );
+                if self.implicate(&experiment, binary, Implication::Fallback, reason, now) {
+                    quarantined.push(binary.clone());
+                }
+            }
+        }
+        quarantined
+    }
+
+    /// Clear the quarantine of a binary, forgetting the failures that implicated it. Returns
+    /// whether it was implicated in any.
+    pub fn clear(&mut self, binary: &str) -> bool {
+        self.binaries.remove(binary).is_some()
+    }
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Record a failure implicating the replacement `binary` of an experiment, warning if it is
+/// quarantined as a result. Returns whether it was.
+pub fn implicate(
+    system: &dyn Worker,
+    experiment: &str,
+    binary: &Path,
+    by: Implication,
+    reason: &str,
+) -> Result<bool> {
+    let name = binary
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mut quarantined = false;
+    update_state(system, |q: &mut Quarantine| {
+        quarantined = q.implicate(experiment, &name, by, reason.to_string(), now());
+        Ok(())
+    })?;
+    if quarantined {
+        warn!(
+            "'{name}' is quarantined, as {reason}: it is left as GNU until 'oxidizr quarantine \
+            clear {name}'"
+        );
+    }
+    Ok(quarantined)
+}
+
+/// Record the failure of a smoke test of the replacement `binary`, passing on the error it failed
+/// with. Failing to record it is only logged, so that the error of the smoke test is reported.
+pub(super) fn smoke_test_failed(
+    system: &dyn Worker,
+    experiment: &str,
+    binary: &Path,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    if let Err(e) = implicate(
+        system,
+        experiment,
+        binary,
+        Implication::SmokeTest,
+        &format!("{error:#}"),
+    ) {
+        debug!("Unable to record the failed smoke test: {e:#}");
+    }
+    error
+}
+
+/// Count the fallbacks to GNU recorded by the shims of the experiments since they were last
+/// counted, quarantining the binaries that fall back repeatedly, which are returned.
+pub fn count_fallbacks(system: &dyn Worker, experiments: &[Experiment]) -> Result<Vec<String>> {
+    let usage = Usage::load(system)?;
+    let mut replaced = BTreeMap::new();
+    for e in experiments {
+        for (_, target) in e.replacements().unwrap_or_default() {
+            if let Some(name) = target.file_name() {
+                replaced.insert(name.to_string_lossy().to_string(), e.name());
+            }
+        }
+    }
+
+    let mut quarantined = Vec::new();
+    update_state(system, |q: &mut Quarantine| {
+        quarantined = q.count_fallbacks(&usage, |b| replaced.get(b).cloned(), now());
+        Ok(())
+    })?;
+    Ok(quarantined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::BinaryUsage;
+
+    #[test]
+    fn test_quarantine() {
+        let mut quarantine = Quarantine::default();
+        let reason = || "sort failed".to_string();
+        assert!(!quarantine.implicate("coreutils", "sort", Implication::SmokeTest, reason(), 1));
+        assert!(!quarantine.implicate("coreutils", "sort", Implication::Fallback, reason(), 2));
+        assert!(quarantine.of("coreutils").is_empty());
+        assert!(quarantine.implicate("coreutils", "sort", Implication::Fallback, reason(), 3));
+        assert_eq!(quarantine.of("coreutils"), ["sort"]);
+        assert_eq!(quarantine.binaries["sort"].quarantined, Some(3));
+
+        // A bisection quarantines a binary at once.
+        assert!(quarantine.implicate("findutils", "find", Implication::Bisect, reason(), 4));
+        assert_eq!(quarantine.of("findutils"), ["find"]);
+
+        assert!(quarantine.clear("sort"));
+        assert!(!quarantine.clear("sort"));
+        assert!(quarantine.of("coreutils").is_empty());
+    }
+
+    #[test]
+    fn test_quarantine_counts_fallbacks() {
+        let mut usage = Usage::default();
+        let fallbacks = |fallbacks| BinaryUsage {
+            invocations: 10,
+            fallbacks,
+        };
+        usage.binaries.insert("date".to_string(), fallbacks(2));
+        usage.binaries.insert("gone".to_string(), fallbacks(5));
+        let experiment_of = |binary: &str| (binary == "date").then(|| "coreutils".to_string());
+
+        let mut quarantine = Quarantine::default();
+        assert!(
+            quarantine
+                .count_fallbacks(&usage, experiment_of, 1)
+                .is_empty()
+        );
+        assert_eq!(quarantine.binaries["date"].strikes, 2);
+        assert!(!quarantine.binaries.contains_key("gone"));
+
+        // Fallbacks already counted aren't counted again.
+        assert!(
+            quarantine
+                .count_fallbacks(&usage, experiment_of, 2)
+                .is_empty()
+        );
+        usage.binaries.insert("date".to_string(), fallbacks(3));
+        assert_eq!(
+            quarantine.count_fallbacks(&usage, experiment_of, 3),
+            ["date"]
+        );
+
+        // Nor once the quarantine is cleared.
+        quarantine.clear("date");
+        assert!(
+            quarantine
+                .count_fallbacks(&usage, experiment_of, 4)
+                .is_empty()
+        );
+        assert!(!quarantine.binaries.contains_key("date"));
+    }
+}
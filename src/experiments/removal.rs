@@ -0,0 +1,140 @@
+use crate::utils::{Command, Worker, warn_or_fail};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tracing::debug;
+
+/// What removing a package would do besides removing it, which could leave apt unable to
+/// complete later operations.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub struct RemovalImpact {
+    pub package: String,
+    /// Whether the package is marked Essential, which apt refuses to remove without a typed
+    /// confirmation.
+    pub essential: bool,
+    /// The other installed packages that apt would remove along with it, as they depend on it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<String>,
+}
+
+impl RemovalImpact {
+    /// Report whether the package can be removed on its own.
+    pub fn is_safe(&self) -> bool {
+        !self.essential && self.dependents.is_empty()
+    }
+
+    /// Describe why the package is left installed.
+    pub fn reason(&self) -> String {
+        match self.essential {
+            true => format!("{} is an Essential package", self.package),
+            false => format!(
+                "removing {} would also remove {}",
+                self.package,
+                self.dependents.join(", ")
+            ),
+        }
+    }
+}
+
+/// Find out what removing a package would do, by checking whether it is Essential and simulating
+/// its removal with apt-get.
+pub fn removal_impact(system: &dyn Worker, package: &str) -> Result<RemovalImpact> {
+    let essential = system.run(&Command::build(
+        "dpkg-query",
+        &["-W", "-f=${Essential}", package],
+    ))?;
+    let simulation = system.run(&Command::build("apt-get", &["-s", "remove", package]))?;
+    let dependents = removed_packages(&String::from_utf8_lossy(&simulation.stdout))
+        .into_iter()
+        .filter(|p| p != package)
+        .collect();
+    Ok(RemovalImpact {
+        package: package.to_string(),
+        essential: String::from_utf8_lossy(&essential.stdout).trim() == "yes",
+        dependents,
+    })
+}
+
+/// List the packages removed by a simulated apt-get transaction, from its `Remv` lines, such as
+/// `Remv rust-coreutils [0.0.28-1]`.
+fn removed_packages(simulation: &str) -> Vec<String> {
+    simulation
+        .lines()
+        .filter_map(|line| line.strip_prefix("Remv "))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|p| p.split(':').next().unwrap_or(p).to_string())
+        .collect()
+}
+
+/// Leave out of a removal the packages that are Essential or that other installed packages
+/// depend on, warning about each, or failing in strict mode. A package whose removal can't be
+/// simulated is kept in, as apt-get will report the problem itself.
+pub fn removable_packages(system: &dyn Worker, packages: &[String]) -> Result<Vec<String>> {
+    let mut removable = Vec::new();
+    for package in packages {
+        match removal_impact(system, package) {
+            Ok(impact) if !impact.is_safe() => warn_or_fail(
+                system.strict(),
+                format!("Leaving {package} installed: {}", impact.reason()),
+            )?,
+            Ok(_) => removable.push(package.clone()),
+            Err(e) => {
+                debug!("Unable to simulate the removal of {package}: {e:#}");
+                removable.push(package.clone());
+            }
+        }
+    }
+    Ok(removable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_removed_packages() {
+        let simulation = "Reading package lists...\n\
+            The following packages will be REMOVED:\n  \
+            rust-coreutils ubuntu-minimal\n\
+            Remv ubuntu-minimal [1.539]\n\
+            Remv rust-coreutils:amd64 [0.0.28-1]\n";
+        assert_eq!(
+            removed_packages(simulation),
+            vec!["ubuntu-minimal", "rust-coreutils"]
+        );
+    }
+
+    #[test]
+    fn test_removable_packages() {
+        let runner = MockSystem::default();
+        runner.mock_command(
+            "apt-get -s remove rust-coreutils",
+            "Remv ubuntu-minimal [1.539]\nRemv rust-coreutils [0.0.28-1]\n",
+        );
+        runner.mock_command("dpkg-query -W -f=${Essential} sudo-rs", "yes");
+        runner.mock_command(
+            "apt-get -s remove rust-findutils",
+            "Remv rust-findutils [0.7.0-1]\n",
+        );
+
+        let impact = removal_impact(&runner, "rust-coreutils").unwrap();
+        assert_eq!(impact.dependents, vec!["ubuntu-minimal"]);
+        assert_eq!(
+            impact.reason(),
+            "removing rust-coreutils would also remove ubuntu-minimal"
+        );
+        assert!(removal_impact(&runner, "sudo-rs").unwrap().essential);
+
+        let packages = ["rust-coreutils", "sudo-rs", "rust-findutils"].map(String::from);
+        assert_eq!(
+            removable_packages(&runner, &packages).unwrap(),
+            vec!["rust-findutils"]
+        );
+
+        // In strict mode, a package that can't be removed safely fails the operation.
+        let mut runner = runner;
+        runner.strict = true;
+        assert!(removable_packages(&runner, &packages).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use super::Experiment;
+use crate::utils::Worker;
+
+/// The package installed to provide a rescue toolset when none is present.
+pub const RESCUE_PACKAGE: &str = "busybox-static";
+
+/// Packages providing statically linked shell utilities, which keep working regardless of the
+/// state of the utilities that oxidizr replaces.
+const RESCUE_PACKAGES: &[&str] = &[RESCUE_PACKAGE, "sash", "toybox"];
+
+/// Report whether enabling a set of experiments calls for a rescue toolset: replacing coreutils
+/// with a broken build would break `cp`, `mv`, `ls` and the like, leaving an administrator unable
+/// to repair the system without other working utilities.
+pub fn requires_rescue_toolset(experiments: &[Experiment]) -> bool {
+    experiments.iter().any(|e| e.name() == "coreutils")
+}
+
+/// Report whether a statically linked rescue toolset is installed.
+pub fn rescue_toolset_installed(system: &dyn Worker) -> bool {
+    RESCUE_PACKAGES
+        .iter()
+        .any(|package| system.check_installed(package).unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_rescue_toolset() {
+        let runner = MockSystem::default();
+        let experiments = all_experiments(&runner);
+        assert!(requires_rescue_toolset(&experiments));
+        assert!(!requires_rescue_toolset(&experiments[1..]));
+
+        assert!(!rescue_toolset_installed(&runner));
+        runner.mock_install_package("sash");
+        assert!(rescue_toolset_installed(&runner));
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Experiment, Maturity, PlannedExperiment, coverage_percent};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+
+/// The setuid bit of a file mode.
+const SETUID: u32 = 0o4000;
+
+/// How risky enabling an experiment is judged to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// The risk of enabling an experiment: a score summing the factors that contribute to it, so
+/// that users can weigh experiments against each other before confirming a plan.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Risk {
+    pub score: u32,
+    pub level: RiskLevel,
+    /// The factors contributing to the score, such as `replaces 104 binaries`.
+    pub factors: Vec<String>,
+}
+
+impl Risk {
+    /// Assess the risk of enabling an experiment as planned. The score grows with the number of
+    /// binaries replaced, the replacement of privileged binaries, the options the replacements
+    /// lack, the known issues affecting them and their maturity. Binaries pinned to GNU don't
+    /// count, and the binaries and options of replacements that aren't installed yet can't be
+    /// counted.
+    pub fn assess(experiment: &Experiment, planned: &PlannedExperiment) -> Self {
+        let mut score = 0;
+        let mut factors = Vec::new();
+        let mut factor = |points: u32, description: String| {
+            score += points;
+            factors.push(description);
+        };
+
+        let replaced: Vec<_> = experiment
+            .replacements()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, target)| target)
+            .filter(|target| {
+                let name = target.file_name().map(|n| n.to_string_lossy());
+                !planned
+                    .pinned
+                    .iter()
+                    .any(|p| Some(p.as_str()) == name.as_deref())
+            })
+            .collect();
+        match replaced.len() {
+            0 => (),
+            n @ 1..=5 => factor(1, format!("replaces {n} binaries")),
+            n @ 6..=25 => factor(2, format!("replaces {n} binaries")),
+            n => factor(3, format!("replaces {n} binaries")),
+        }
+
+        let privileged = replaced
+            .iter()
+            .filter(|target| {
+                experiment
+                    .copy_mode(target)
+                    .is_some_and(|m| m & SETUID != 0)
+            })
+            .count();
+        if privileged > 0 {
+            factor(3, format!("replaces {privileged} setuid binaries"));
+        }
+
+        if !planned.option_coverage.is_empty() {
+            match coverage_percent(&planned.option_coverage) {
+                90.. => (),
+                p @ 75..90 => factor(1, format!("supports {p}% of the options")),
+                p => factor(2, format!("supports {p}% of the options")),
+            }
+        }
+
+        match planned.known_issues.len() {
+            0 => (),
+            1 => factor(2, "1 known issue".to_string()),
+            n => factor(2 * n.min(3) as u32, format!("{n} known issues")),
+        }
+
+        match planned.maturity {
+            Maturity::Stable => (),
+            Maturity::Experimental => factor(2, "experimental".to_string()),
+            Maturity::Risky => factor(4, "risky".to_string()),
+        }
+
+        let level = match score {
+            0..=2 => RiskLevel::Low,
+            3..=5 => RiskLevel::Medium,
+            _ => RiskLevel::High,
+        };
+        Self {
+            score,
+            level,
+            factors,
+        }
+    }
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (score {})", self.level, self.score)?;
+        if !self.factors.is_empty() {
+            write!(f, ": {}", self.factors.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{Pins, Plan, SudoRsExperiment, all_experiments};
+    use crate::utils::{Distribution, MockSystem};
+
+    #[test]
+    fn test_assess_risk() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "24.04"));
+        let experiments = vec![Experiment::SudoRs(SudoRsExperiment::new(&runner))];
+        let plan = Plan::enable(&runner, &experiments, false, &Pins::default());
+
+        let risk = plan.experiments[0].risk.as_ref().unwrap();
+        assert_eq!(risk.level, RiskLevel::Medium);
+        assert!(risk.factors.iter().any(|f| f.ends_with(" setuid binaries")));
+        assert!(plan.to_string().contains("\n      Risk: medium (score "));
+    }
+
+    #[test]
+    fn test_assess_risk_findutils() {
+        let runner = MockSystem::new(Distribution::new("Ubuntu", "25.04"));
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/findutils/find", "", true),
+            ("/usr/lib/cargo/bin/findutils/xargs", "", true),
+        ]);
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == "findutils")
+            .collect();
+        let plan = Plan::enable(&runner, &experiments, false, &Pins::default());
+
+        let risk = plan.experiments[0].risk.as_ref().unwrap();
+        assert_eq!(risk.level, RiskLevel::Medium);
+        assert_eq!(
+            risk.to_string(),
+            "medium (score 3): replaces 2 binaries, experimental"
+        );
+    }
+}
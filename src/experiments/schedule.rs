@@ -0,0 +1,254 @@
+use super::{Plan, calendar_time};
+use crate::utils::{Command, StateFile, Worker, read_state, write_state};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use tracing::info;
+
+/// The location of the operation queued for a maintenance window, and the plan frozen for it.
+pub const SCHEDULE_PATH: &str = "/var/lib/oxidizr/schedule.json";
+
+/// The systemd units running the queued operation when its window opens.
+pub const SCHEDULE_TIMER_PATH: &str = "/etc/systemd/system/oxidizr-scheduled.timer";
+pub const SCHEDULE_SERVICE_PATH: &str = "/etc/systemd/system/oxidizr-scheduled.service";
+
+/// An enable or disable operation queued for a maintenance window. Its plan is frozen when it is
+/// queued and approved, and the operation only goes ahead if the plan is unchanged when the
+/// window opens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    /// The arguments oxidizr is run with when the window opens.
+    pub args: Vec<String>,
+    /// When the window opens, in seconds since the Unix epoch.
+    pub at: u64,
+    /// How long the window stays open, if limited. An operation that can't start before the
+    /// window closes, such as because the system was off, is abandoned.
+    pub window: Option<u64>,
+    /// The plan approved when the operation was queued.
+    pub plan: serde_json::Value,
+}
+
+impl StateFile for Schedule {
+    const PATH: &'static str = SCHEDULE_PATH;
+}
+
+impl Schedule {
+    /// Load the queued operation, if any.
+    pub fn load(system: &dyn Worker) -> Result<Option<Self>> {
+        read_state(system)
+    }
+
+    /// Queue an operation for a window, freezing its plan, and schedule a systemd timer to run
+    /// it when the window opens. The timer is persistent, so a window opening while the system is
+    /// off opens at the next boot. Queueing an operation replaces any other.
+    pub fn queue(
+        system: &dyn Worker,
+        args: Vec<String>,
+        at: u64,
+        window: Option<Duration>,
+        plan: &Plan,
+        oxidizr: &Path,
+    ) -> Result<Self> {
+        let schedule = Self {
+            args,
+            at,
+            window: window.map(|w| w.as_secs()),
+            plan: serde_json::to_value(plan)?,
+        };
+        system.write_file(Path::new(SCHEDULE_SERVICE_PATH), &schedule.service(oxidizr))?;
+        system.write_file(Path::new(SCHEDULE_TIMER_PATH), &schedule.timer())?;
+        write_state(system, &schedule)?;
+        system.run(&Command::build("systemctl", &["daemon-reload"]))?;
+        system.run(&Command::build(
+            "systemctl",
+            &["enable", "--now", "oxidizr-scheduled.timer"],
+        ))?;
+        info!(
+            "Queued '{}' for {}: run 'oxidizr scheduled --cancel' to cancel it",
+            schedule.args.join(" "),
+            calendar_time(at)
+        );
+        Ok(schedule)
+    }
+
+    /// Check, when the timer runs the queued operation, that its window is still open and that
+    /// the plan is the one approved when it was queued. The operation is dequeued either way, as
+    /// it only runs once.
+    pub fn verify(system: &dyn Worker, plan: &Plan, now: u64) -> Result<()> {
+        let schedule = Self::load(system)?.context("no operation is scheduled")?;
+        Self::cancel(system)?;
+
+        if let Some(window) = schedule.window
+            && now > schedule.at + window
+        {
+            anyhow::bail!(
+                "the maintenance window closed at {}, so the scheduled operation was abandoned",
+                calendar_time(schedule.at + window)
+            );
+        }
+        if schedule.plan != serde_json::to_value(plan)? {
+            anyhow::bail!(
+                "the plan has drifted since the operation was scheduled, so it was abandoned: \
+                run it again to review and approve the new plan"
+            );
+        }
+        Ok(())
+    }
+
+    /// Dequeue the operation, and stop its timer.
+    pub fn cancel(system: &dyn Worker) -> Result<()> {
+        system.run(&Command::build(
+            "systemctl",
+            &["disable", "--now", "oxidizr-scheduled.timer"],
+        ))?;
+        let _lock = system.lock_file(Path::new(SCHEDULE_PATH), true)?;
+        system.write_file(Path::new(SCHEDULE_PATH), "")
+    }
+
+    /// The service running the queued operation.
+    fn service(&self, oxidizr: &Path) -> String {
+        let args: Vec<String> = self.args.iter().map(|a| quote(a)).collect();
+        format!(
+            "# Written by oxidizr: runs the operation scheduled for a maintenance window\n\
+            [Unit]\n\
+            Description=Run the scheduled oxidizr {command}\n\n\
+            [Service]\n\
+            Type=oneshot\n\
+            ExecStart={oxidizr} {args} --yes --scheduled\n",
+            command = self.args.first().map_or("operation", String::as_str),
+            oxidizr = oxidizr.display(),
+            args = args.join(" "),
+        )
+    }
+
+    /// The timer starting the service when the window opens.
+    fn timer(&self) -> String {
+        format!(
+            "# Written by oxidizr: runs the operation scheduled for a maintenance window\n\
+            [Unit]\n\
+            Description=Run the scheduled oxidizr operation\n\n\
+            [Timer]\n\
+            OnCalendar={at}\n\
+            Persistent=true\n\n\
+            [Install]\n\
+            WantedBy=timers.target\n",
+            at = calendar_time(self.at),
+        )
+    }
+}
+
+/// Quote an argument for a systemd `ExecStart=` line, if needed.
+fn quote(arg: &str) -> String {
+    match arg.contains(|c: char| c.is_whitespace() || "\"'\\$%;".contains(c)) {
+        true => format!(
+            "\"{}\"",
+            arg.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('$', "$$")
+                .replace('%', "%%")
+        ),
+        false => arg.to_string(),
+    }
+}
+
+/// The arguments with which oxidizr was run, without those scheduling the operation, so that
+/// running them again performs it immediately.
+pub fn unscheduled_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut unscheduled = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" | "--window" => {
+                args.next();
+            }
+            "-y" | "--yes" => (),
+            a if a.starts_with("--at=") || a.starts_with("--window=") => (),
+            _ => unscheduled.push(arg),
+        }
+    }
+    unscheduled
+}
+
+/// Resolve when a window opens, such as `2025-06-01 02:00` or `tomorrow 02:00` in local time, to
+/// seconds since the Unix epoch. Resolving it is left to `date`, which understands many forms.
+pub fn resolve_time(system: &dyn Worker, at: &str) -> Result<u64> {
+    let output = system
+        .run(&Command::build("date", &["--date", at, "+%s"]))
+        .with_context(|| format!("unable to understand the time '{at}'"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .with_context(|| format!("unable to understand the time '{at}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{Pins, all_experiments};
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_unscheduled_args() {
+        let args = [
+            "enable",
+            "--at",
+            "tomorrow 02:00",
+            "--window=2h",
+            "-y",
+            "-e",
+            "coreutils",
+        ];
+        assert_eq!(
+            unscheduled_args(args.map(String::from)),
+            vec!["enable", "-e", "coreutils"]
+        );
+    }
+
+    #[test]
+    fn test_schedule() {
+        let runner = MockSystem::default();
+        let experiments = all_experiments(&runner);
+        let plan = Plan::enable(&runner, &experiments[..1], false, &Pins::default());
+        let args = vec!["enable".to_string(), "--at-boot".to_string()];
+        let window = Some(Duration::from_secs(3600));
+        let oxidizr = Path::new("/usr/bin/oxidizr");
+        Schedule::queue(&runner, args.clone(), 86400, window, &plan, oxidizr).unwrap();
+
+        let timer = runner.read_file(Path::new(SCHEDULE_TIMER_PATH)).unwrap();
+        assert!(
+            timer
+                .unwrap()
+                .contains("OnCalendar=1970-01-02 00:00:00 UTC\n")
+        );
+        let service = runner.read_file(Path::new(SCHEDULE_SERVICE_PATH)).unwrap();
+        assert!(
+            service
+                .unwrap()
+                .contains("ExecStart=/usr/bin/oxidizr enable --at-boot --yes --scheduled\n")
+        );
+
+        // The operation goes ahead within the window while the plan is unchanged, and only once.
+        assert!(Schedule::verify(&runner, &plan, 86400 + 60).is_ok());
+        assert!(Schedule::verify(&runner, &plan, 86400 + 60).is_err());
+
+        Schedule::queue(&runner, args.clone(), 86400, window, &plan, oxidizr).unwrap();
+        let err = Schedule::verify(&runner, &plan, 86400 + 7200).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("window closed at 1970-01-02 01:00:00 UTC")
+        );
+
+        Schedule::queue(&runner, args, 86400, window, &plan, oxidizr).unwrap();
+        let drifted = Plan::enable(&runner, &experiments[..2], false, &Pins::default());
+        let err = Schedule::verify(&runner, &drifted, 86400).unwrap_err();
+        assert!(err.to_string().contains("plan has drifted"));
+        assert_eq!(Schedule::load(&runner).unwrap(), None);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("coreutils"), "coreutils");
+        assert_eq!(quote("/etc/my config.toml"), "\"/etc/my config.toml\"");
+        assert_eq!(quote("100%"), "\"100%%\"");
+    }
+}
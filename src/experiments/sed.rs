@@ -0,0 +1,279 @@
+use super::Experiment;
+use super::quarantine::smoke_test_failed;
+use crate::utils::{Command, ErrorCode, Worker, warn_or_fail};
+use anyhow::Result;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// The name of the experiment replacing GNU sed.
+pub const SED_EXPERIMENT: &str = "sed";
+
+/// The files in /etc most likely to run sed and the other replaced utilities, such as from cron
+/// jobs and init or network scripts.
+const SCRIPT_FILES: &[&str] = &["/etc/rc.local", "/etc/profile", "/etc/bash.bashrc"];
+
+/// The directories of scripts in /etc most likely to run sed and the other replaced utilities.
+const SCRIPT_DIRECTORIES: &[&str] = &[
+    "/etc/cron.d",
+    "/etc/cron.hourly",
+    "/etc/cron.daily",
+    "/etc/cron.weekly",
+    "/etc/cron.monthly",
+    "/etc/init.d",
+    "/etc/profile.d",
+    "/etc/network/if-up.d",
+    "/etc/network/if-pre-up.d",
+    "/etc/network/if-down.d",
+    "/etc/update-motd.d",
+    "/etc/kernel/postinst.d",
+    "/etc/NetworkManager/dispatcher.d",
+];
+
+/// Options that are GNU extensions to sed, which scripts written against GNU sed may rely on and
+/// the replacement may not support, or may handle differently.
+const GNU_OPTIONS: &[(&str, &str)] = &[
+    ("-z", "separates lines with NUL characters"),
+    ("--null-data", "separates lines with NUL characters"),
+    ("-s", "treats files as separate"),
+    ("--separate", "treats files as separate"),
+    ("-u", "unbuffered output"),
+    ("--unbuffered", "unbuffered output"),
+    (
+        "--follow-symlinks",
+        "edits the targets of symlinks in place",
+    ),
+    ("--posix", "disables GNU extensions"),
+    (
+        "--sandbox",
+        "rejects commands that run programs or write files",
+    ),
+    ("--debug", "annotates execution"),
+    ("-l", "wraps lines at a length"),
+    ("--line-length", "wraps lines at a length"),
+];
+
+/// A line of a script in /etc running sed in a way that may not work with the replacement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SedFinding {
+    pub file: PathBuf,
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SedFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({})",
+            self.file.display(),
+            self.line,
+            self.text,
+            self.reason
+        )
+    }
+}
+
+/// The scripts in /etc most likely to run the replaced utilities unattended, whether or not they
+/// exist.
+pub(super) fn scripts(system: &dyn Worker) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = SCRIPT_FILES.iter().map(PathBuf::from).collect();
+    for directory in SCRIPT_DIRECTORIES {
+        let mut scripts = system
+            .list_files(PathBuf::from(directory))
+            .unwrap_or_default();
+        scripts.sort();
+        files.extend(scripts);
+    }
+    files
+}
+
+/// Check the scripts in /etc for runs of sed relying on GNU extensions. Scripts such as these
+/// run unattended, so a difference in behaviour may go unnoticed until it has done damage.
+pub(super) fn lint(system: &dyn Worker) -> Vec<SedFinding> {
+    let mut findings = Vec::new();
+    for file in scripts(system) {
+        let Some(contents) = system.read_file(&file).ok().flatten() else {
+            continue;
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let text = line.trim();
+            if text.starts_with('#') {
+                continue;
+            }
+            if let Some(reason) = gnu_extension(text) {
+                findings.push(SedFinding {
+                    file: file.clone(),
+                    line: number + 1,
+                    text: text.to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Report the GNU extension used by a run of sed in a line of shell, if any. Short options may be
+/// combined, such as `-nz`.
+fn gnu_extension(line: &str) -> Option<String> {
+    let words: Vec<&str> = line
+        .split(|c: char| c.is_whitespace() || ";|&()`".contains(c))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut in_sed = false;
+    for word in words {
+        if word == "sed" || word.ends_with("/sed") {
+            in_sed = true;
+            continue;
+        }
+        if !in_sed || !word.starts_with('-') {
+            continue;
+        }
+        let option = word.split_once('=').map_or(word, |(o, _)| o);
+        let short =
+            |o: &str| o.len() == 2 && !option.starts_with("--") && option[1..].contains(&o[1..]);
+        if let Some((option, description)) =
+            GNU_OPTIONS.iter().find(|(o, _)| *o == option || short(o))
+        {
+            return Some(format!("GNU extension {option}: {description}"));
+        }
+    }
+    None
+}
+
+/// Warn about the scripts in /etc that may not work with the sed replacement before enabling it,
+/// or fail in strict mode.
+pub fn check_sed_scripts(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    if !experiments.iter().any(|e| e.name() == SED_EXPERIMENT) {
+        return Ok(());
+    }
+
+    let findings = lint(system);
+    if findings.is_empty() {
+        return Ok(());
+    }
+    warn_or_fail(
+        system.strict(),
+        format!(
+            "scripts in /etc run sed with GNU extensions that the replacement may not support:\n  {}",
+            findings
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join("\n  ")
+        ),
+    )
+}
+
+/// The file edited in place by the smoke test of the sed replacement, which is left in place
+/// for inspection.
+const SMOKE_TEST_PATH: &str = "/var/lib/oxidizr/sed-smoke-test";
+
+/// Check that the sed replacement, once enabled, can edit a file in place: the most common use
+/// of sed in system scripts, and the most damaging to get wrong, as a failure can truncate the
+/// file being edited.
+pub(super) fn smoke_test(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    let Some(sed) = experiments
+        .iter()
+        .filter(|e| e.name() == SED_EXPERIMENT)
+        .flat_map(|e| e.replacements().unwrap_or_default())
+        .map(|(_, target)| target)
+        .find(|target| target.file_name().is_some_and(|n| n == "sed"))
+    else {
+        return Ok(());
+    };
+
+    info!("Checking that {} can edit files in place", sed.display());
+    let path = Path::new(SMOKE_TEST_PATH);
+    system.write_file(path, "first line\nsmoke test: before\nlast line\n")?;
+    let result = system.run(&Command::build(
+        &sed,
+        &["-i", "-e", "s/before/after/", SMOKE_TEST_PATH],
+    ));
+    let edited = system.read_file(path)?;
+
+    let error = match (result, edited.as_deref()) {
+        (Ok(_), Some("first line\nsmoke test: after\nlast line\n")) => return Ok(()),
+        (Err(e), _) => ErrorCode::SmokeTestFailed.error(format!(
+            "{} failed to edit a file in place: {e:#}",
+            sed.display()
+        )),
+        (Ok(_), edited) => ErrorCode::SmokeTestFailed.error(format!(
+            "{} edited a file in place incorrectly, leaving {:?}",
+            sed.display(),
+            edited.unwrap_or_default()
+        )),
+    };
+    Err(smoke_test_failed(system, SED_EXPERIMENT, &sed, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_gnu_extension() {
+        assert_eq!(gnu_extension("sed -i 's/a/b/' /etc/hosts"), None);
+        assert_eq!(gnu_extension("ls -z | sort"), None);
+        assert!(gnu_extension("find . -print0 | xargs -0 sed -nz 'p'").is_some());
+        assert!(
+            gnu_extension("/usr/bin/sed --follow-symlinks -i 's/a/b/' x")
+                .unwrap()
+                .contains("--follow-symlinks")
+        );
+        assert!(gnu_extension("sed --line-length=40 l").is_some());
+    }
+
+    #[test]
+    fn test_lint() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            (
+                "/etc/cron.daily/rotate",
+                "#!/bin/sh\n# sed -z is used below\ncat /var/log/app | sed -u 's/x/y/'\n",
+                false,
+            ),
+            (
+                "/etc/profile.d/path.sh",
+                "PATH=$(echo $PATH | sed 's/a/b/')\n",
+                false,
+            ),
+        ]);
+
+        let findings = lint(&runner);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].to_string(),
+            "/etc/cron.daily/rotate:3: cat /var/log/app | sed -u 's/x/y/' (GNU extension -u: \
+            unbuffered output)"
+        );
+    }
+
+    #[test]
+    fn test_smoke_test_sed() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![("/usr/lib/cargo/bin/sed/sed", "", true)]);
+        let experiments: Vec<Experiment> = crate::experiments::all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == SED_EXPERIMENT)
+            .collect();
+
+        // The mocked sed doesn't edit the file, as a broken replacement wouldn't.
+        let err = smoke_test(&runner, &experiments).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::SmokeTestFailed));
+        assert!(
+            runner
+                .commands
+                .borrow()
+                .last()
+                .unwrap()
+                .ends_with("sed -i -e s/before/after/ /var/lib/oxidizr/sed-smoke-test")
+        );
+    }
+}
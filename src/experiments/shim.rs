@@ -0,0 +1,267 @@
+use super::{Experiment, USAGE_LOG_PATH};
+use crate::utils::{
+    ManagedTarget, ReplacementSource, StateFile, Worker, backup_filename, hex_digest, read_state,
+    update_state, write_state,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ffi::OsStr, path::Path};
+
+/// The location of the record of the strategy used to replace the files of each experiment.
+pub const STRATEGIES_PATH: &str = "/var/lib/oxidizr/strategies.json";
+
+/// The directory in which shims are written before being copied into place.
+pub const SHIMS_DIRECTORY: &str = "/var/lib/oxidizr/shims";
+
+/// The mode of an installed shim: executable by everyone.
+const SHIM_MODE: u32 = 0o755;
+
+/// Messages with which the replacements reject options they don't support.
+const UNSUPPORTED_OPTION_PATTERNS: &[&str] = &[
+    "unexpected argument",
+    "unrecognized option",
+    "unrecognised option",
+    "invalid option",
+];
+
+/// How the files replaced by an experiment link to their replacements.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStrategy {
+    /// Replace each file with a symlink to its replacement.
+    #[default]
+    Symlink,
+    /// Replace each file with a wrapper script that runs the replacement, and runs the original
+    /// GNU binary instead if the replacement rejects an option it doesn't support.
+    Shim,
+}
+
+/// The strategy with which the files of each experiment were replaced when it was enabled, by
+/// experiment name, so that reapplying and listing it agree with how it was enabled.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Strategies {
+    pub experiments: BTreeMap<String, LinkStrategy>,
+}
+
+impl StateFile for Strategies {
+    const PATH: &'static str = STRATEGIES_PATH;
+}
+
+impl Strategies {
+    /// Load the recorded strategies. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded strategies.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Report the strategy recorded for an experiment, which is to symlink unless recorded
+    /// otherwise.
+    pub fn of(&self, experiment: &str) -> LinkStrategy {
+        self.experiments
+            .get(experiment)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Record the strategy with which the files of a set of experiments are to be replaced. Only
+/// experiments replacing GNU utilities can use shims, as only they have a GNU binary to fall back
+/// to.
+pub fn record_strategy(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    strategy: LinkStrategy,
+) -> Result<()> {
+    update_state(system, |strategies: &mut Strategies| {
+        for e in experiments {
+            if let Experiment::Uutils(_) = e {
+                strategies.experiments.insert(e.name(), strategy);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Generate a shim that replaces `target` by running `command` with the arguments it is given.
+/// If the command fails having rejected an option, the event is logged and the GNU binary that
+/// was backed up is run in its place.
+///
+/// Scripts commonly run the utilities being replaced, so the shim uses nothing but shell
+/// builtins - which also means that it can't recurse into another shim. The standard error of the
+/// replacement is held until it exits, so that it can be inspected.
+///
+/// Each invocation, and each fallback, is appended to the usage log. Only root can write to the
+/// log, so invocations by other users aren't counted.
+///
+/// The paths run by the shim are written into it, so they must be valid UTF-8.
+pub fn script(command: &[&OsStr], target: &Path) -> Result<String> {
+    script_logging_to(command, target, Path::new(USAGE_LOG_PATH))
+}
+
+/// Generate a shim as [`script`] does, appending its usage to `log`.
+fn script_logging_to(command: &[&OsStr], target: &Path, log: &Path) -> Result<String> {
+    let name = utf8(target.file_name().unwrap_or_default())?;
+    let command: Vec<String> = command
+        .iter()
+        .map(|c| Ok(quote(utf8(c)?)))
+        .collect::<Result<_>>()?;
+    let patterns: Vec<String> = UNSUPPORTED_OPTION_PATTERNS
+        .iter()
+        .map(|p| format!("*{}*", quote(p)))
+        .collect();
+
+    Ok(format!(
+        "#!/bin/sh\n\
+        # Generated by oxidizr: runs the replacement for {name}, falling back to GNU {name} if it\n\
+        # rejects an option. Changes to this file are overwritten.\n\
+        printf 'run %s\\n' {quoted_name} 2>/dev/null >>{log}\n\
+        exec 3>&1\n\
+        err=$({command} \"$@\" 2>&1 >&3 3>&-)\n\
+        status=$?\n\
+        exec 3>&-\n\
+        if [ \"$status\" -ne 0 ]; then\n\
+        \x20 case \"$err\" in\n\
+        \x20   {patterns})\n\
+        \x20     printf 'fallback %s\\n' {quoted_name} 2>/dev/null >>{log}\n\
+        \x20     command -v logger >/dev/null 2>&1 && logger -t oxidizr \"{name}: option not supported by the replacement, running GNU {name}\"\n\
+        \x20     exec {fallback} \"$@\"\n\
+        \x20     ;;\n\
+        \x20 esac\n\
+        fi\n\
+        [ -n \"$err\" ] && printf '%s\\n' \"$err\" >&2\n\
+        exit \"$status\"\n",
+        command = command.join(" "),
+        patterns = patterns.join("|"),
+        fallback = quote(utf8(backup_filename(target)?.as_os_str())?),
+        quoted_name = quote(name),
+        log = quote(utf8(log.as_os_str())?),
+    ))
+}
+
+/// Convert part of a path written into a shim to a string, failing if it isn't valid UTF-8.
+fn utf8(s: &OsStr) -> Result<&str> {
+    s.to_str().with_context(|| {
+        format!(
+            "'{}' isn't valid UTF-8, so it can't be written into a shim",
+            s.display()
+        )
+    })
+}
+
+/// Report whether `target` is currently the shim `script`.
+pub fn installed(system: &dyn Worker, target: &Path, script: &str) -> Result<bool> {
+    Ok(system.file_checksum(target)? == Some(hex_digest(script.as_bytes())))
+}
+
+/// Replace `target` with the shim `script`. The shim is written to the state directory, then
+/// copied into place so that it is journaled and backed up like any other copy.
+pub fn install(system: &dyn Worker, target: ManagedTarget, script: &str) -> Result<()> {
+    let staged = ReplacementSource::new(Path::new(SHIMS_DIRECTORY).join(target.file_name()))?;
+    system.write_file(staged.as_path(), script)?;
+    system.replace_file_with_copy(staged, target, SHIM_MODE)
+}
+
+/// Quote a string for the shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, os::unix::fs::PermissionsExt, process};
+
+    /// Install a shim for `date` in a scratch directory, with a replacement and a GNU backup
+    /// that are both scripts, and run it with `args`. Returns its output and the usage it logged.
+    fn run_shim(
+        test: &str,
+        replacement: &str,
+        gnu: &str,
+        args: &[&str],
+    ) -> (process::Output, String) {
+        let dir = std::env::temp_dir().join(format!("oxidizr-{test}-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |path: &Path, contents: &str| {
+            fs::write(path, contents).unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(SHIM_MODE)).unwrap();
+        };
+
+        let target = dir.join("date");
+        write(&dir.join("replacement"), replacement);
+        write(&backup_filename(&target).unwrap(), gnu);
+        let replacement = dir.join("replacement");
+        let log = dir.join("usage.log");
+        let script = script_logging_to(&[replacement.as_os_str()], &target, &log).unwrap();
+        write(&target, &script);
+
+        let output = process::Command::new(&target).args(args).output().unwrap();
+        let logged = fs::read_to_string(&log).unwrap_or_default();
+        fs::remove_dir_all(&dir).unwrap();
+        (output, logged)
+    }
+
+    #[test]
+    fn test_shim_runs_replacement() {
+        let (output, logged) = run_shim(
+            "shim-replacement",
+            "#!/bin/sh\necho \"rust $*\"\necho warning >&2\n",
+            "#!/bin/sh\necho gnu\n",
+            &["-u", "it's"],
+        );
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "rust -u it's\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "warning\n");
+        assert_eq!(logged, "run date\n");
+    }
+
+    #[test]
+    fn test_shim_falls_back_to_gnu() {
+        let (output, logged) = run_shim(
+            "shim-fallback",
+            "#!/bin/sh\necho \"error: unexpected argument '$1' found\" >&2\nexit 1\n",
+            "#!/bin/sh\necho \"gnu $*\"\n",
+            &["--rfc-email"],
+        );
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "gnu --rfc-email\n");
+        assert_eq!(logged, "run date\nfallback date\n");
+
+        // Other failures are passed through.
+        let (output, _) = run_shim(
+            "shim-failure",
+            "#!/bin/sh\necho \"date: invalid date\" >&2\nexit 1\n",
+            "#!/bin/sh\necho gnu\n",
+            &["-d", "never"],
+        );
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "date: invalid date\n"
+        );
+    }
+
+    #[test]
+    fn test_shim_requires_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let target = Path::new(OsStr::from_bytes(b"/usr/bin/d\xffte"));
+        let err = script(&[OsStr::new("/usr/bin/coreutils")], target).unwrap_err();
+        assert!(err.to_string().contains("isn't valid UTF-8"));
+        assert!(
+            script(
+                &[OsStr::new("/usr/bin/coreutils")],
+                Path::new("/usr/bin/date")
+            )
+            .is_ok()
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::Experiment;
+use crate::utils::{ErrorCode, Filesystem, Worker, backup_filename, format_size};
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// The space needed on a filesystem to back up the files replaced on it, and the space available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SpaceNeeded {
+    needed: u64,
+    available: u64,
+}
+
+/// Check, before anything is changed, that the files replaced by a set of experiments can be
+/// backed up and restored: that each filesystem has the space for the backups of the files on it
+/// (and for the copies that replace setuid binaries, which are written alongside them), and that
+/// no file is a mount point of its own, such as a bind mount, which a backup can't be renamed
+/// over. The check is skipped with a warning if the filesystems can't be inspected.
+pub fn check_space(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    match preflight(system, experiments) {
+        Ok((space, crossing)) => report(space, crossing),
+        Err(e) => {
+            warn!("Unable to check the space available for backups: {e:#}");
+            Ok(())
+        }
+    }
+}
+
+/// Work out the space needed on each filesystem, by its mount point, and the files that are on a
+/// different filesystem from the directory they are in.
+fn preflight(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+) -> Result<(BTreeMap<PathBuf, SpaceNeeded>, Vec<PathBuf>)> {
+    let mut directories: BTreeMap<PathBuf, Filesystem> = BTreeMap::new();
+    let mut space: BTreeMap<PathBuf, SpaceNeeded> = BTreeMap::new();
+    let mut crossing = Vec::new();
+
+    for e in experiments {
+        for (source, target) in e.replacements().unwrap_or_default() {
+            // Files already replaced are symlinks, or copies that have been backed up before.
+            let Some(size) = system.file_size(&target)? else {
+                continue;
+            };
+            if system.file_checksum(&backup_filename(&target)?)?.is_some() {
+                continue;
+            }
+
+            let directory = target.parent().unwrap_or(Path::new("/")).to_path_buf();
+            let filesystem = match directories.get(&directory) {
+                Some(filesystem) => filesystem.clone(),
+                None => {
+                    let filesystem = system.filesystem(&directory)?;
+                    directories.insert(directory, filesystem.clone());
+                    filesystem
+                }
+            };
+            if system.filesystem(&target)?.mount_point != filesystem.mount_point {
+                crossing.push(target.clone());
+            }
+
+            let copy = match e.copy_mode(&target) {
+                Some(_) => system.file_size(&source)?.unwrap_or_default(),
+                None => 0,
+            };
+            let entry = space.entry(filesystem.mount_point).or_insert(SpaceNeeded {
+                needed: 0,
+                available: filesystem.available,
+            });
+            entry.needed += size + copy;
+        }
+    }
+    Ok((space, crossing))
+}
+
+/// Fail with the files that can't be restored by renaming, or the shortfall on each filesystem
+/// without the space needed.
+fn report(space: BTreeMap<PathBuf, SpaceNeeded>, crossing: Vec<PathBuf>) -> Result<()> {
+    if !crossing.is_empty() {
+        let files: Vec<String> = crossing.iter().map(|f| f.display().to_string()).collect();
+        return Err(ErrorCode::CrossesFilesystems.error(format!(
+            "these files are mount points of their own, so they can't be restored by renaming \
+            their backups over them: {}",
+            files.join(", ")
+        )));
+    }
+
+    let shortfalls: Vec<String> = space
+        .iter()
+        .filter(|(_, s)| s.needed > s.available)
+        .map(|(mount_point, s)| {
+            format!(
+                "{}: {} needed, {} available ({} short)",
+                mount_point.display(),
+                format_size(s.needed),
+                format_size(s.available),
+                format_size(s.needed - s.available)
+            )
+        })
+        .collect();
+    if !shortfalls.is_empty() {
+        return Err(ErrorCode::InsufficientSpace.error(format!(
+            "not enough space to back up the files to be replaced:\n  {}",
+            shortfalls.join("\n  ")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    fn df(runner: &MockSystem, path: &str, available: u64, mount_point: &str) {
+        runner.mock_command(
+            &format!("df -P -k {path}"),
+            &format!(
+                "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                /dev/sda1 1000000 1000 {available} 1% {mount_point}\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn test_check_space() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/sed/sed", "", false),
+            ("/usr/bin/sed", &"x".repeat(4096), true),
+        ]);
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == "sed")
+            .collect();
+
+        // Without the filesystems reported, the check is skipped.
+        check_space(&runner, &experiments).unwrap();
+
+        df(&runner, "/usr/bin", 8, "/");
+        df(&runner, "/usr/bin/sed", 8, "/");
+        check_space(&runner, &experiments).unwrap();
+
+        df(&runner, "/usr/bin", 2, "/");
+        df(&runner, "/usr/bin/sed", 2, "/");
+        let err = check_space(&runner, &experiments).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::InsufficientSpace));
+        assert!(
+            err.to_string()
+                .contains("/: 4.0 KiB needed, 2.0 KiB available (2.0 KiB short)")
+        );
+
+        // A file bind-mounted over the original can't have its backup renamed over it.
+        df(&runner, "/usr/bin/sed", 8, "/usr/bin/sed");
+        let err = check_space(&runner, &experiments).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::CrossesFilesystems));
+    }
+}
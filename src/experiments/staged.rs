@@ -0,0 +1,195 @@
+use super::{EnableOptions, Experiment, Pins, Plan, disable_all, enable_all, smoke_test};
+use crate::utils::{ExperimentFailures, SkippedFile, Worker};
+use anyhow::Result;
+use std::slice;
+use tracing::{info, warn};
+
+/// The experiments in order of how safe they are to enable, from the tools fewest scripts depend
+/// on to the replacements of privileged binaries and arbitrary files.
+const SAFETY_ORDER: &[&str] = &[
+    "diffutils",
+    "findutils",
+    "tar",
+    "sed",
+    "coreutils",
+    "sudo-rs",
+    "overrides",
+];
+
+/// Order experiments from the lowest to the highest risk, by their place in `SAFETY_ORDER`.
+/// Experiments missing from it come last, ordered by their assessed risk.
+pub fn safety_order<'a, 'b>(
+    system: &dyn Worker,
+    experiments: &'b [Experiment<'a>],
+    pins: &Pins,
+) -> Vec<&'b Experiment<'a>> {
+    let plan = Plan::enable(system, experiments, true, pins);
+    let rank = |e: &Experiment| {
+        let score = plan
+            .experiments
+            .iter()
+            .find(|p| p.name == e.name())
+            .and_then(|p| p.risk.as_ref())
+            .map_or(0, |r| r.score);
+        (
+            SAFETY_ORDER
+                .iter()
+                .position(|n| *n == e.name())
+                .unwrap_or(SAFETY_ORDER.len()),
+            score,
+        )
+    };
+
+    let mut ordered: Vec<&Experiment> = experiments.iter().collect();
+    ordered.sort_by_key(|e| rank(e));
+    ordered
+}
+
+/// Enable experiments one at a time, from the lowest to the highest risk, running the smoke tests
+/// of each before moving on to the next. The changes made for an experiment that fails are
+/// undone, and the others are carried on with, unless `options.fail_fast` is set, in which case
+/// the rest are skipped. The outcome for each experiment is reported in an `ExperimentFailures`
+/// error if any failed, and the files left in place by those that succeeded are added to
+/// `skipped`. A single experiment is enabled and tested as usual, so that a failing smoke test
+/// rolls back the whole operation.
+pub fn enable_staged(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    options: &EnableOptions,
+    mut record: impl FnMut(&str, bool),
+    skipped: &mut Vec<SkippedFile>,
+) -> Result<()> {
+    if experiments.len() <= 1 {
+        skipped.extend(enable_all(system, experiments, options, record)?);
+        return smoke_test(system, experiments);
+    }
+
+    let pins = Pins::load(system).unwrap_or_default();
+    let ordered = safety_order(system, experiments, &pins);
+    let names: Vec<String> = ordered.iter().map(|e| e.name()).collect();
+    info!(
+        "Enabling experiments in order of risk: {}",
+        names.join(", ")
+    );
+
+    let mut failures = ExperimentFailures::new("enable");
+    for e in ordered {
+        if options.fail_fast && !failures.failed.is_empty() {
+            failures.skipped.push(e.name());
+            continue;
+        }
+
+        let stage = slice::from_ref(e);
+        let result = enable_all(system, stage, options, &mut record)
+            .and_then(|files| smoke_test(system, stage).map(|_| files));
+        match result {
+            Ok(files) => {
+                failures.succeeded.push(e.name());
+                skipped.extend(files);
+            }
+            Err(err) => {
+                warn!("Failed to enable {}: {err:#}", e.name());
+                warn!("Undoing the changes made for {}", e.name());
+                if let Err(e) = disable_all(system, stage, |_, _| ()) {
+                    warn!("Unable to undo the changes made: {e:#}");
+                }
+                failures.fail(&e.name(), &err);
+            }
+        }
+    }
+    failures.into_result()
+}
+
+/// Disable experiments one at a time, in the reverse of the order they are enabled in, carrying
+/// on past those that fail unless `fail_fast` is set. The outcome for each experiment is reported
+/// in an `ExperimentFailures` error if any failed.
+pub fn disable_staged(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    fail_fast: bool,
+    mut record: impl FnMut(&str, bool),
+) -> Result<()> {
+    if experiments.len() <= 1 {
+        return disable_all(system, experiments, record);
+    }
+
+    let pins = Pins::load(system).unwrap_or_default();
+    let mut failures = ExperimentFailures::new("disable");
+    for e in safety_order(system, experiments, &pins).into_iter().rev() {
+        if fail_fast && !failures.failed.is_empty() {
+            failures.skipped.push(e.name());
+            continue;
+        }
+        match disable_all(system, slice::from_ref(e), &mut record) {
+            Ok(()) => failures.succeeded.push(e.name()),
+            Err(err) => {
+                warn!("Failed to disable {}: {err:#}", e.name());
+                failures.fail(&e.name(), &err);
+            }
+        }
+    }
+    failures.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_safety_order() {
+        let runner = MockSystem::default();
+        let experiments = all_experiments(&runner);
+
+        let ordered: Vec<String> = safety_order(&runner, &experiments, &Pins::default())
+            .iter()
+            .map(|e| e.name())
+            .collect();
+        assert_eq!(ordered.first().unwrap(), "diffutils");
+        assert_eq!(ordered.last().unwrap(), "overrides");
+        let position = |name: &str| ordered.iter().position(|n| n == name).unwrap();
+        assert!(position("findutils") < position("sed"));
+        assert!(position("sed") < position("coreutils"));
+        assert!(position("coreutils") < position("sudo-rs"));
+    }
+
+    #[test]
+    fn test_enable_staged_isolates_failures() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/findutils/find", "", true),
+            ("/usr/lib/cargo/bin/tar/tar", "", true),
+            ("/usr/lib/cargo/bin/sed/sed", "", true),
+        ]);
+        let experiments: Vec<Experiment> = all_experiments(&runner)
+            .into_iter()
+            .filter(|e| ["sed", "tar", "findutils"].contains(&e.name().as_str()))
+            .collect();
+        let failures = |options: &EnableOptions| {
+            let err = enable_staged(&runner, &experiments, options, |_, _| (), &mut Vec::new())
+                .unwrap_err();
+            err.downcast::<ExperimentFailures>().unwrap()
+        };
+
+        // The mocked tar and sed fail their smoke tests, and are undone, after findutils has been
+        // enabled. By default, sed is still attempted after tar fails.
+        let report = failures(&EnableOptions::default());
+        assert_eq!(report.succeeded, vec!["findutils"]);
+        let failed: Vec<&str> = report
+            .failed
+            .iter()
+            .map(|f| f.experiment.as_str())
+            .collect();
+        assert_eq!(failed, vec!["tar", "sed"]);
+        assert_eq!(report.failed[0].code, Some("E020"));
+        assert_eq!(runner.restored_files.borrow().len(), 2);
+
+        let report = failures(&EnableOptions {
+            fail_fast: true,
+            ..EnableOptions::default()
+        });
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.skipped, vec!["sed"]);
+    }
+}
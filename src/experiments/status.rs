@@ -0,0 +1,149 @@
+use super::{Channel, Experiment, Incompatibility, LinkStrategy, Metadata};
+use crate::utils::PackageVersion;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// A snapshot of the state of an experiment on the current system.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ExperimentStatus {
+    pub name: String,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    pub compatible: bool,
+    /// Why the experiment can't be enabled on this system, if it can't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incompatibility: Option<Incompatibility>,
+    pub installed: bool,
+    pub enabled: bool,
+    /// The installed version of the replacement package, such as `0.2.5-1`.
+    #[schemars(with = "Option<String>")]
+    pub version: Option<PackageVersion>,
+    /// Where the replacement binaries are installed from.
+    pub channel: Channel,
+    /// Files that should link to a replacement binary while the experiment is enabled, but don't.
+    pub drift: Vec<PathBuf>,
+    /// How the replaced files link to their replacements.
+    pub strategy: LinkStrategy,
+    /// Binaries left as GNU while the experiment is enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<String>,
+    /// The pinned binaries that are quarantined, failures having implicated them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quarantined: Vec<String>,
+    /// Checks that could not be made, such as those reading files the user has no permission to
+    /// read. The rest of the status is still reported.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<String>,
+}
+
+impl ExperimentStatus {
+    /// Gather the status of an experiment from the system.
+    pub fn gather(experiment: &Experiment) -> Self {
+        let installed = experiment.check_installed();
+
+        // The replacement binaries are only known once the package is installed.
+        let mut unchecked = Vec::new();
+        let replaced = match installed {
+            true => experiment.check_replaced().unwrap_or_else(|e| {
+                unchecked.push(format!("replaced files could not be checked: {e:#}"));
+                Vec::new()
+            }),
+            false => Vec::new(),
+        };
+
+        let enabled = replaced.iter().any(|(_, linked)| *linked);
+        let (pinned, quarantined) = match enabled {
+            true => (
+                experiment.pinned().unwrap_or_default(),
+                experiment.quarantined().unwrap_or_default(),
+            ),
+            false => (Vec::new(), Vec::new()),
+        };
+        let drift = match enabled {
+            true => replaced
+                .into_iter()
+                .filter(|(_, linked)| !linked)
+                .map(|(target, _)| target)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let incompatibility = experiment
+            .compatibility()
+            .and_then(|()| experiment.availability())
+            .err();
+        Self {
+            name: experiment.name(),
+            metadata: experiment.metadata(),
+            compatible: incompatibility.is_none(),
+            incompatibility,
+            installed,
+            enabled,
+            version: experiment.installed_version(),
+            channel: experiment.channel(),
+            drift,
+            strategy: experiment.strategy(),
+            pinned,
+            quarantined,
+            unchecked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::SudoRsExperiment;
+    use crate::utils::{ManagedTarget, MockSystem, ReplacementSource, Worker};
+
+    #[test]
+    fn test_status_not_installed() {
+        let runner = MockSystem::default();
+        let sudors = Experiment::SudoRs(SudoRsExperiment::new(&runner));
+        // The package of the replacements isn't in the archive until it is mocked.
+        assert_eq!(
+            sudors.status().incompatibility,
+            Some(Incompatibility::PackageUnavailable {
+                package: "sudo-rs".to_string(),
+                architecture: "this architecture".to_string(),
+            })
+        );
+        runner.mock_command(
+            "apt-cache policy sudo-rs",
+            "sudo-rs:\n  Candidate: 0.2.5-1\n",
+        );
+
+        let status = sudors.status();
+        assert!(status.compatible);
+        assert!(!status.installed);
+        assert!(!status.enabled);
+        assert_eq!(status.version, None);
+        assert!(status.drift.is_empty());
+        assert!(status.unchecked.is_empty());
+    }
+
+    #[test]
+    fn test_status_enabled_with_drift() {
+        let runner = MockSystem::default();
+        runner.mock_install_package("sudo-rs");
+        runner.mock_command("dpkg-query -W -f=${Version} sudo-rs", "0.2.5-1");
+        runner
+            .create_symlink(
+                ReplacementSource::new("/usr/lib/cargo/bin/visudo").unwrap(),
+                ManagedTarget::new("/usr/bin/visudo").unwrap(),
+            )
+            .unwrap();
+
+        let sudors = Experiment::SudoRs(SudoRsExperiment::new(&runner));
+
+        let status = sudors.status();
+        assert!(status.installed);
+        assert!(status.enabled);
+        assert_eq!(status.version, Some("0.2.5-1".parse().unwrap()));
+        assert_eq!(
+            status.drift,
+            vec![PathBuf::from("/usr/bin/su"), PathBuf::from("/usr/bin/sudo")]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use crate::utils::Worker;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// The main sudoers file, from which any other sudoers files are included.
+const SUDOERS_PATH: &str = "/etc/sudoers";
+
+/// The settings sudo-rs accepts in `Defaults` lines. Any other setting is rejected by sudo-rs,
+/// which ignores the whole line - and so the user specifications that depend on it.
+const SUPPORTED_DEFAULTS: &[&str] = &[
+    "always_query_group_plugin",
+    "env_check",
+    "env_delete",
+    "env_editor",
+    "env_keep",
+    "env_reset",
+    "editor",
+    "lecture",
+    "mail_badpass",
+    "noexec",
+    "passwd_tries",
+    "pwfeedback",
+    "rootpw",
+    "secure_path",
+    "setenv",
+    "targetpw",
+    "timestamp_timeout",
+    "umask",
+    "use_pty",
+    "visiblepw",
+];
+
+/// The command tags sudo-rs accepts in user specifications.
+const SUPPORTED_TAGS: &[&str] = &["NOPASSWD", "PASSWD", "SETENV", "NOSETENV", "NOEXEC", "EXEC"];
+
+/// Options of user specifications that sudo-rs does not implement.
+const UNSUPPORTED_OPTIONS: &[&str] = &[
+    "ROLE",
+    "TYPE",
+    "APPARMOR_PROFILE",
+    "TIMEOUT",
+    "NOTBEFORE",
+    "NOTAFTER",
+    "PRIVS",
+    "LIMITPRIVS",
+];
+
+/// A line of sudoers configuration that sudo-rs does not support.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SudoersFinding {
+    pub file: PathBuf,
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SudoersFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({})",
+            self.file.display(),
+            self.line,
+            self.text,
+            self.reason
+        )
+    }
+}
+
+/// Check /etc/sudoers, and every file it includes, for constructs that sudo-rs does not support.
+pub(super) fn lint(system: &dyn Worker) -> Vec<SudoersFinding> {
+    let mut findings = Vec::new();
+    lint_file(system, Path::new(SUDOERS_PATH), &mut findings, 0);
+    findings
+}
+
+/// Check a single sudoers file, following its include directives.
+fn lint_file(system: &dyn Worker, path: &Path, findings: &mut Vec<SudoersFinding>, depth: usize) {
+    // sudo itself refuses to nest includes more deeply than this.
+    if depth > 128 {
+        return;
+    }
+    let Some(contents) = system.read_file(path).ok().flatten() else {
+        return;
+    };
+
+    for (number, line) in logical_lines(&contents) {
+        if let Some(include) = include_target(&line) {
+            match include {
+                Include::File(file) => lint_file(system, &resolve(path, file), findings, depth + 1),
+                Include::Dir(dir) => {
+                    let mut files = system
+                        .list_files(resolve(path, dir))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|f| included_from_dir(f))
+                        .collect::<Vec<_>>();
+                    files.sort();
+                    for file in files {
+                        lint_file(system, &file, findings, depth + 1);
+                    }
+                }
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut report = |reason: String| {
+            findings.push(SudoersFinding {
+                file: path.to_path_buf(),
+                line: number,
+                text: line.clone(),
+                reason,
+            })
+        };
+
+        if let Some(defaults) = line.strip_prefix("Defaults") {
+            if let Some(scope) = defaults.chars().next().filter(|c| "@!".contains(*c)) {
+                report(format!("'Defaults{scope}' scoping is not supported"));
+            }
+            let settings = defaults.split_once(char::is_whitespace).map_or("", |s| s.1);
+            for setting in split_unquoted(settings, ',') {
+                let name = setting
+                    .trim()
+                    .trim_start_matches('!')
+                    .split(['=', '+', '-'])
+                    .next()
+                    .unwrap_or_default()
+                    .trim();
+                if !name.is_empty() && !SUPPORTED_DEFAULTS.contains(&name) {
+                    report(format!("setting '{name}' is not supported"));
+                }
+            }
+            continue;
+        }
+
+        let is_alias = [
+            "User_Alias",
+            "Runas_Alias",
+            "Host_Alias",
+            "Cmnd_Alias",
+            "Cmd_Alias",
+        ]
+        .iter()
+        .any(|alias| line.starts_with(alias));
+        for word in line.split(|c: char| c.is_whitespace() || "(),=".contains(c)) {
+            if word.starts_with('+') {
+                report(format!("netgroup '{word}' is not supported"));
+            } else if ["sha224:", "sha256:", "sha384:", "sha512:"]
+                .iter()
+                .any(|digest| word.starts_with(digest))
+            {
+                report("command digests are not supported".to_string());
+            }
+        }
+        if is_alias {
+            continue;
+        }
+
+        // Tags precede the command they apply to, separated by colons, as in 'NOPASSWD:ALL'. Runas
+        // specifications such as '(ALL:ALL)' use colons too, but are parenthesised.
+        let tags = line
+            .split_whitespace()
+            .filter(|w| !w.contains(['(', ')']))
+            .flat_map(|w| w.split(':').rev().skip(1));
+        for tag in tags {
+            if is_tag(tag) && !SUPPORTED_TAGS.contains(&tag) {
+                report(format!("tag '{tag}' is not supported"));
+            }
+        }
+        for option in UNSUPPORTED_OPTIONS {
+            if line
+                .split_whitespace()
+                .any(|w| w.starts_with(&format!("{option}=")))
+            {
+                report(format!("option '{option}' is not supported"));
+            }
+        }
+    }
+}
+
+/// Split a string on a separator, except where the separator appears in double quotes.
+fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&s[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Join lines continued with a trailing backslash, returning each logical line with the number of
+/// the line it starts on.
+fn logical_lines(contents: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let (number, mut text) = current.take().unwrap_or((index + 1, String::new()));
+        match line.trim_end().strip_suffix('\\') {
+            Some(continued) => {
+                text.push_str(continued.trim());
+                text.push(' ');
+                current = Some((number, text));
+            }
+            None => {
+                text.push_str(line.trim());
+                lines.push((number, text));
+            }
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+enum Include<'a> {
+    File(&'a str),
+    Dir(&'a str),
+}
+
+/// Parse an include directive, in either its `@include` or legacy `#include` form.
+fn include_target(line: &str) -> Option<Include<'_>> {
+    let directive = line.strip_prefix('@').or_else(|| line.strip_prefix('#'))?;
+    if let Some(dir) = directive.strip_prefix("includedir") {
+        return Some(Include::Dir(dir.trim().trim_matches('"')));
+    }
+    directive
+        .strip_prefix("include")
+        .filter(|file| file.starts_with(char::is_whitespace))
+        .map(|file| Include::File(file.trim().trim_matches('"')))
+}
+
+/// Resolve an included path, which is relative to the directory of the including file.
+fn resolve(including: &Path, included: &str) -> PathBuf {
+    match including.parent() {
+        Some(dir) => dir.join(included),
+        None => PathBuf::from(included),
+    }
+}
+
+/// Report whether a file in an included directory is read by sudo, which skips files whose names
+/// contain a '.' or end with '~'.
+fn included_from_dir(file: &Path) -> bool {
+    file.file_name()
+        .map(|name| name.as_encoded_bytes())
+        .is_some_and(|name| !name.contains(&b'.') && !name.ends_with(b"~"))
+}
+
+/// Report whether a word is a command tag: sudoers tags are upper case, optionally with
+/// underscores.
+fn is_tag(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_lint_default_sudoers() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![(
+            "/etc/sudoers",
+            "Defaults\tenv_reset\nDefaults\tmail_badpass\nDefaults\tsecure_path=\"/usr/sbin:/usr/bin\"\nDefaults\tuse_pty\n\nroot\tALL=(ALL:ALL) ALL\n%admin ALL=(ALL) ALL\n%sudo\tALL=(ALL:ALL) ALL\n\n@includedir /etc/sudoers.d\n",
+            false,
+        )]);
+        assert!(lint(&runner).is_empty());
+    }
+
+    #[test]
+    fn test_lint_unsupported_constructs() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            (
+                "/etc/sudoers",
+                "Defaults@db01 log_output\nDefaults env_keep += \"LANG, LC_ALL\"\n#includedir /etc/sudoers.d\n",
+                false,
+            ),
+            (
+                "/etc/sudoers.d/ops",
+                "%ops ALL=(ALL) NOPASSWD: LOG_INPUT: \\\n    /usr/bin/systemctl\n+admins ALL = ALL\ndeploy ALL=(ALL:ALL) NOPASSWD:NOLOG_OUTPUT:ALL\n",
+                false,
+            ),
+            (
+                "/etc/sudoers.d/README.txt",
+                "Defaults insults\n",
+                false,
+            ),
+        ]);
+
+        let findings: Vec<String> = lint(&runner).iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            findings,
+            vec![
+                "/etc/sudoers:1: Defaults@db01 log_output ('Defaults@' scoping is not supported)",
+                "/etc/sudoers:1: Defaults@db01 log_output (setting 'log_output' is not supported)",
+                "/etc/sudoers.d/ops:1: %ops ALL=(ALL) NOPASSWD: LOG_INPUT: /usr/bin/systemctl (tag 'LOG_INPUT' is not supported)",
+                "/etc/sudoers.d/ops:3: +admins ALL = ALL (netgroup '+admins' is not supported)",
+                "/etc/sudoers.d/ops:4: deploy ALL=(ALL:ALL) NOPASSWD:NOLOG_OUTPUT:ALL (tag 'NOLOG_OUTPUT' is not supported)",
+            ]
+        );
+    }
+}
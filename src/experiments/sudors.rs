@@ -1,10 +1,20 @@
-use crate::utils::Worker;
+use super::{Directories, Maturity, Metadata, ubuntu};
+use crate::utils::{
+    Distribution, FsOperation, ManagedTarget, PackageVersion, ReplacementSource, Worker,
+};
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 use tracing::info;
 
 const PACKAGE: &str = "sudo-rs";
 
+/// The binaries that must run setuid root. A symlink can't carry the setuid bit, so these are
+/// replaced with copies instead.
+const PRIVILEGED: &[&str] = &["su", "sudo"];
+
+/// The mode of the copies of privileged binaries: setuid, and executable by everyone.
+const PRIVILEGED_MODE: u32 = 0o4755;
+
 /// An experiment to install and configure sudo-rs as a replacement for sudo.
 pub struct SudoRsExperiment<'a> {
     system: &'a dyn Worker,
@@ -16,24 +26,14 @@ impl<'a> SudoRsExperiment<'a> {
         Self { system }
     }
 
-    /// Check if the system is compatible with the experiment.
-    pub fn check_compatible(&self) -> bool {
-        self.supported_releases().contains(
-            &self
-                .system
-                .distribution()
-                .expect("unable to determine distribution information")
-                .release,
-        )
+    /// The system the experiment operates on.
+    pub(super) fn system(&self) -> &'a dyn Worker {
+        self.system
     }
 
-    /// Reports the first supported release for the experiment.
-    pub fn supported_releases(&self) -> Vec<String> {
-        vec![
-            "24.04".to_string(),
-            "24.10".to_string(),
-            "25.04".to_string(),
-        ]
+    /// Reports the distribution releases supported by the experiment.
+    pub fn supported_releases(&self) -> Vec<Distribution> {
+        ubuntu(&["24.04", "24.10", "25.04"])
     }
 
     /// Check if the package is installed.
@@ -46,40 +46,115 @@ impl<'a> SudoRsExperiment<'a> {
         String::from("sudo-rs")
     }
 
-    /// Enable the experiment by installing and configuring the package.
+    /// Report the installed version of the package, if any.
+    pub fn installed_version(&self) -> Option<PackageVersion> {
+        self.system.installed_version(PACKAGE).unwrap_or(None)
+    }
+
+    /// Report the package providing the experiment's replacement binaries.
+    pub fn package(&self) -> String {
+        PACKAGE.to_string()
+    }
+
+    /// Report information about the upstream project providing the replacement binaries.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            upstream: "https://github.com/trifectatechfoundation/sudo-rs",
+            license: "Apache-2.0 OR MIT",
+            maturity: Maturity::Stable,
+            maintainer: "Trifecta Tech Foundation",
+            replaces: "sudo",
+            replaced_license: "ISC",
+        }
+    }
+
+    /// Enable the experiment by replacing the system files with symlinks to the binaries from
+    /// the package, which must already be installed.
     pub fn enable(&self) -> Result<()> {
-        info!("Installing and configuring {}", PACKAGE);
-        self.system.install_package(PACKAGE)?;
-
-        for f in Self::sudors_files() {
-            let filename = f.file_name().unwrap().to_str().unwrap();
-            let existing = match self.system.which(filename) {
-                Ok(path) => path,
-                Err(_) => Path::new("/usr/bin").join(filename),
-            };
-            self.system.replace_file_with_symlink(f, existing)?;
+        info!("Configuring {}", PACKAGE);
+
+        for (source, target) in self.replacements() {
+            let mode = self.copy_mode(&target);
+            let (source, target) = (ReplacementSource::new(source)?, ManagedTarget::new(target)?);
+            match mode {
+                Some(mode) => {
+                    // Releases before the copies replaced the setuid binaries with symlinks too.
+                    // Backing up a symlink would copy the replacement over the backup of GNU, so
+                    // the original is restored first and backed up as usual.
+                    if self.system.read_symlink(target.as_path())?.as_deref()
+                        == Some(source.as_path())
+                    {
+                        self.system.restore_file(target.clone())?;
+                    }
+                    self.system.replace_file_with_copy(source, target, mode)?
+                }
+                None => {
+                    self.system.replace_file_with_symlink(source, target)?;
+                }
+            }
         }
 
         Ok(())
     }
 
-    /// Disable the experiment by removing the package and restoring the original files.
+    /// Disable the experiment by restoring the original files. The package can be removed
+    /// afterwards.
     pub fn disable(&self) -> Result<()> {
-        for f in Self::sudors_files() {
-            let filename = f.file_name().unwrap().to_str().unwrap();
-            let existing = match self.system.which(filename) {
-                Ok(path) => path,
-                Err(_) => Path::new("/usr/bin").join(filename),
-            };
-            self.system.restore_file(existing.clone())?;
-        }
-
-        info!("Removing {}", PACKAGE);
-        self.system.remove_package(PACKAGE)?;
+        info!("Restoring files replaced by {}", PACKAGE);
 
+        let restores: Vec<FsOperation> = self
+            .replacements()
+            .into_iter()
+            .map(|(_, file)| FsOperation::restore(file))
+            .collect::<Result<_>>()?;
+        self.system
+            .apply(&restores)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
         Ok(())
     }
 
+    /// Report the mode of the copy that replaces a system file, or `None` if the file is
+    /// replaced with a symlink.
+    pub fn copy_mode(&self, target: &Path) -> Option<u32> {
+        target
+            .file_name()
+            .is_some_and(|name| PRIVILEGED.iter().any(|p| name == *p))
+            .then_some(PRIVILEGED_MODE)
+    }
+
+    /// Report each file replaced by the experiment, and whether it currently links to its
+    /// replacement. A copy only counts as linked while it matches its source, so that a copy left
+    /// stale by an upgrade of the package is reported as drifted.
+    pub fn check_replaced(&self) -> Result<Vec<(PathBuf, bool)>> {
+        self.replacements()
+            .into_iter()
+            .map(|(source, target)| {
+                let linked = match self.copy_mode(&target) {
+                    Some(_) => {
+                        let checksum = self.system.file_checksum(&target)?;
+                        checksum.is_some() && checksum == self.system.file_checksum(&source)?
+                    }
+                    None => self.system.read_symlink(&target)? == Some(source),
+                };
+                Ok((target, linked))
+            })
+            .collect()
+    }
+
+    /// List the sudo-rs binaries, paired with the system files they replace.
+    pub fn replacements(&self) -> Vec<(PathBuf, PathBuf)> {
+        let directories = Directories::load(self.system).unwrap_or_default();
+        Self::sudors_files()
+            .into_iter()
+            .filter_map(|f| {
+                let filename = f.file_name()?;
+                let existing = directories.locate(self.system, &self.name(), filename);
+                Some((f, existing))
+            })
+            .collect()
+    }
+
     /// List of files from the package to replace system equivalents with.
     fn sudors_files() -> Vec<PathBuf> {
         vec![
@@ -93,13 +168,14 @@ impl<'a> SudoRsExperiment<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::{Distribution, MockSystem, vecs_eq};
+    use crate::experiments::Experiment;
+    use crate::utils::{MockSystem, vecs_eq};
 
     #[test]
     fn test_sudors_incompatible_distribution() {
         let runner = incompatible_runner();
-        let coreutils = sudors_fixture(&runner);
-        assert!(!coreutils.check_compatible());
+        let sudors = Experiment::SudoRs(sudors_fixture(&runner));
+        assert!(!sudors.check_compatible());
     }
 
     #[test]
@@ -109,8 +185,7 @@ mod tests {
 
         assert!(sudors.enable().is_ok());
 
-        let commands = runner.commands.clone().into_inner();
-        assert_eq!(commands, &["apt-get install -y sudo-rs"]);
+        assert!(runner.commands.clone().into_inner().is_empty());
 
         let backed_up_files = runner.backed_up_files.clone().into_inner();
         let expected = vec![
@@ -120,24 +195,85 @@ mod tests {
         ];
         assert!(vecs_eq(backed_up_files, expected));
 
-        let created_symlinks = runner.created_symlinks.clone().into_inner();
+        // The setuid binaries are copied into place, as a symlink can't carry the setuid bit.
+        let copied_files = runner.copied_files.clone().into_inner();
         let expected = vec![
             (
                 "/usr/lib/cargo/bin/su".to_string(),
                 "/usr/bin/su".to_string(),
+                0o4755,
             ),
             (
                 "/usr/lib/cargo/bin/sudo".to_string(),
                 "/usr/bin/sudo".to_string(),
-            ),
-            (
-                "/usr/lib/cargo/bin/visudo".to_string(),
-                "/usr/sbin/visudo".to_string(),
+                0o4755,
             ),
         ];
+        assert!(vecs_eq(copied_files, expected));
 
-        assert!(vecs_eq(created_symlinks, expected));
+        let created_symlinks = runner.created_symlinks.clone().into_inner();
+        let expected = vec![(
+            "/usr/lib/cargo/bin/visudo".to_string(),
+            "/usr/sbin/visudo".to_string(),
+        )];
+        assert_eq!(created_symlinks, expected);
         assert_eq!(runner.restored_files.clone().into_inner().len(), 0);
+
+        assert!(sudors.check_replaced().unwrap().iter().all(|(_, r)| *r));
+    }
+
+    #[test]
+    fn test_sudors_refresh_stale_copy() {
+        let runner = sudors_compatible_runner();
+        let sudors = sudors_fixture(&runner);
+        assert!(sudors.enable().is_ok());
+
+        // An upgrade of the package leaves the copies stale, which is reported as drift and
+        // corrected by enabling again - without backing up the stale copies.
+        runner.mock_files(vec![("/usr/lib/cargo/bin/sudo", "sudo-rs 0.2.6", false)]);
+        let drifted: Vec<PathBuf> = sudors
+            .check_replaced()
+            .unwrap()
+            .into_iter()
+            .filter(|(_, replaced)| !replaced)
+            .map(|(target, _)| target)
+            .collect();
+        assert_eq!(drifted, vec![PathBuf::from("/usr/bin/sudo")]);
+
+        assert!(sudors.enable().is_ok());
+        assert!(sudors.check_replaced().unwrap().iter().all(|(_, r)| *r));
+        let backups = runner.backed_up_files.clone().into_inner();
+        assert_eq!(backups.iter().filter(|f| *f == "/usr/bin/sudo").count(), 1);
+    }
+
+    #[test]
+    fn test_sudors_upgrade_from_symlinks() {
+        let runner = sudors_compatible_runner();
+        let sudors = sudors_fixture(&runner);
+        for name in ["sudo", "su"] {
+            runner.created_symlinks.borrow_mut().push((
+                format!("/usr/lib/cargo/bin/{name}"),
+                format!("/usr/bin/{name}"),
+            ));
+        }
+
+        // The symlinks left by an earlier release are undone before the copies replace GNU.
+        assert!(sudors.enable().is_ok());
+        let restored_files = runner.restored_files.clone().into_inner();
+        assert!(vecs_eq(
+            restored_files,
+            vec!["/usr/bin/sudo".to_string(), "/usr/bin/su".to_string()]
+        ));
+        let copied: Vec<String> = runner
+            .copied_files
+            .borrow()
+            .iter()
+            .map(|(_, target, _)| target.clone())
+            .collect();
+        assert!(vecs_eq(
+            copied,
+            vec!["/usr/bin/sudo".to_string(), "/usr/bin/su".to_string()]
+        ));
     }
 
     #[test]
@@ -151,9 +287,7 @@ mod tests {
         assert_eq!(runner.created_symlinks.clone().into_inner().len(), 0);
         assert_eq!(runner.backed_up_files.clone().into_inner().len(), 0);
 
-        let commands = runner.commands.clone().into_inner();
-        assert_eq!(commands.len(), 1);
-        assert!(commands.contains(&"apt-get remove -y sudo-rs".to_string()));
+        assert!(runner.commands.clone().into_inner().is_empty());
 
         let restored_files = runner.restored_files.clone().into_inner();
         let expected = vec![
@@ -164,27 +298,24 @@ mod tests {
         assert!(vecs_eq(restored_files, expected));
     }
 
-    fn sudors_fixture(system: &MockSystem) -> SudoRsExperiment {
+    fn sudors_fixture(system: &MockSystem) -> SudoRsExperiment<'_> {
         SudoRsExperiment::new(system)
     }
 
     fn sudors_compatible_runner() -> MockSystem {
         let runner = MockSystem::default();
         runner.mock_files(vec![
-            ("/usr/lib/cargo/bin/sudo", "", false),
-            ("/usr/lib/cargo/bin/su", "", false),
-            ("/usr/lib/cargo/bin/visudo", "", false),
-            ("/usr/bin/sudo", "", true),
-            ("/usr/bin/su", "", true),
-            ("/usr/sbin/visudo", "", true),
+            ("/usr/lib/cargo/bin/sudo", "sudo-rs", false),
+            ("/usr/lib/cargo/bin/su", "sudo-rs su", false),
+            ("/usr/lib/cargo/bin/visudo", "sudo-rs visudo", false),
+            ("/usr/bin/sudo", "sudo", true),
+            ("/usr/bin/su", "su", true),
+            ("/usr/sbin/visudo", "visudo", true),
         ]);
         runner
     }
 
     fn incompatible_runner() -> MockSystem {
-        MockSystem::new(Distribution {
-            id: "Ubuntu".to_string(),
-            release: "20.04".to_string(),
-        })
+        MockSystem::new(Distribution::new("Ubuntu", "20.04"))
     }
 }
@@ -0,0 +1,121 @@
+use super::Experiment;
+use super::quarantine::smoke_test_failed;
+use crate::utils::{Command, ErrorCode, Worker};
+use anyhow::Result;
+use std::path::Path;
+use tracing::info;
+
+/// The name of the experiment replacing GNU tar.
+pub const TAR_EXPERIMENT: &str = "tar";
+
+/// An archive bundled with oxidizr, extracted by the smoke test of the tar replacement. It holds
+/// a directory, a nested directory and two files, in the ustar format, so that it is plain text.
+const SMOKE_TEST_ARCHIVE: &str = include_str!("tar_smoke_test.tar");
+
+/// Where the archive is written before being extracted, and the directory it is extracted into.
+const SMOKE_TEST_ARCHIVE_PATH: &str = "/var/lib/oxidizr/tar-smoke-test.tar";
+const SMOKE_TEST_DIRECTORY: &str = "/var/lib/oxidizr";
+
+/// The files in the archive, and their contents.
+const SMOKE_TEST_FILES: &[(&str, &str)] = &[
+    (
+        "/var/lib/oxidizr/tar-smoke-test/README",
+        "Extracted by oxidizr to check the tar replacement.\n",
+    ),
+    (
+        "/var/lib/oxidizr/tar-smoke-test/nested/data.txt",
+        "smoke test: extracted\n",
+    ),
+];
+
+/// Check that the tar replacement, once enabled, can extract an archive: a broken tar silently
+/// breaks backups, package builds and installers. The files extracted by a previous run are
+/// emptied first, so that they can't pass for the output of this one.
+pub(super) fn smoke_test(system: &dyn Worker, experiments: &[Experiment]) -> Result<()> {
+    let Some(tar) = experiments
+        .iter()
+        .filter(|e| e.name() == TAR_EXPERIMENT)
+        .flat_map(|e| e.replacements().unwrap_or_default())
+        .map(|(_, target)| target)
+        .find(|target| target.file_name().is_some_and(|n| n == "tar"))
+    else {
+        return Ok(());
+    };
+
+    info!("Checking that {} can extract archives", tar.display());
+    for (path, _) in SMOKE_TEST_FILES {
+        if system.read_file(Path::new(path))?.is_some() {
+            system.write_file(Path::new(path), "")?;
+        }
+    }
+    system.write_file(Path::new(SMOKE_TEST_ARCHIVE_PATH), SMOKE_TEST_ARCHIVE)?;
+    if let Err(e) = system.run(&Command::build(
+        &tar,
+        &[
+            "-x",
+            "-f",
+            SMOKE_TEST_ARCHIVE_PATH,
+            "-C",
+            SMOKE_TEST_DIRECTORY,
+        ],
+    )) {
+        let error = ErrorCode::SmokeTestFailed.error(format!(
+            "{} failed to extract an archive: {e:#}",
+            tar.display()
+        ));
+        return Err(smoke_test_failed(system, TAR_EXPERIMENT, &tar, error));
+    }
+
+    for (path, expected) in SMOKE_TEST_FILES {
+        let extracted = system.read_file(Path::new(path))?;
+        if extracted.as_deref() != Some(expected) {
+            let error = ErrorCode::SmokeTestFailed.error(format!(
+                "{} extracted an archive incorrectly, leaving {path} with {:?}",
+                tar.display(),
+                extracted.unwrap_or_default()
+            ));
+            return Err(smoke_test_failed(system, TAR_EXPERIMENT, &tar, error));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_smoke_test_archive() {
+        // The bundled archive is a ustar archive of the files the smoke test checks.
+        assert_eq!(SMOKE_TEST_ARCHIVE.len() % 512, 0);
+        assert_eq!(&SMOKE_TEST_ARCHIVE[257..262], "ustar");
+        for (path, contents) in SMOKE_TEST_FILES {
+            let name = path.strip_prefix("/var/lib/oxidizr/").unwrap();
+            assert!(SMOKE_TEST_ARCHIVE.contains(name));
+            assert!(SMOKE_TEST_ARCHIVE.contains(contents));
+        }
+    }
+
+    #[test]
+    fn test_smoke_test_tar() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![("/usr/lib/cargo/bin/tar/tar", "", true)]);
+        let experiments: Vec<Experiment> = crate::experiments::all_experiments(&runner)
+            .into_iter()
+            .filter(|e| e.name() == TAR_EXPERIMENT)
+            .collect();
+
+        // The mocked tar doesn't extract anything, as a broken replacement wouldn't.
+        let err = smoke_test(&runner, &experiments).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::SmokeTestFailed));
+        assert!(
+            runner
+                .commands
+                .borrow()
+                .last()
+                .unwrap()
+                .ends_with("tar -x -f /var/lib/oxidizr/tar-smoke-test.tar -C /var/lib/oxidizr")
+        );
+    }
+}
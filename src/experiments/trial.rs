@@ -0,0 +1,262 @@
+use crate::utils::{Command, StateFile, Worker, read_state, update_state, write_state};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use tracing::info;
+
+/// The location of the record of the experiments being trialled, and when the trial ends.
+pub const TRIAL_PATH: &str = "/var/lib/oxidizr/trial.json";
+
+/// The systemd units disabling the trialled experiments once the trial ends.
+pub const TRIAL_TIMER_PATH: &str = "/etc/systemd/system/oxidizr-trial.timer";
+pub const TRIAL_SERVICE_PATH: &str = "/etc/systemd/system/oxidizr-trial.service";
+
+/// Experiments enabled for a limited period, after which they are disabled and the original
+/// utilities restored unless `oxidizr keep` is run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Trial {
+    pub experiments: Vec<String>,
+    /// When the trial ends, in seconds since the Unix epoch.
+    pub expires: u64,
+}
+
+impl StateFile for Trial {
+    const PATH: &'static str = TRIAL_PATH;
+}
+
+impl Trial {
+    /// Load the trial in progress, if any.
+    pub fn load(system: &dyn Worker) -> Result<Option<Self>> {
+        Ok(read_state::<Self>(system)?.filter(|t| !t.experiments.is_empty()))
+    }
+
+    /// Save the trial.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Start a trial of experiments that have just been enabled: record when it ends, and
+    /// schedule a systemd timer to disable them then. The timer is persistent, so a trial ending
+    /// while the system is off ends at the next boot. Starting a trial replaces any other.
+    pub fn start(
+        system: &dyn Worker,
+        experiments: Vec<String>,
+        period: Duration,
+        now: u64,
+        oxidizr: &Path,
+    ) -> Result<Self> {
+        let trial = Self {
+            experiments,
+            expires: now + period.as_secs(),
+        };
+        system.write_file(Path::new(TRIAL_SERVICE_PATH), &trial.service(oxidizr))?;
+        system.write_file(Path::new(TRIAL_TIMER_PATH), &trial.timer())?;
+        trial.save(system)?;
+        system.run(&Command::build("systemctl", &["daemon-reload"]))?;
+        system.run(&Command::build(
+            "systemctl",
+            &["enable", "--now", "oxidizr-trial.timer"],
+        ))?;
+        info!(
+            "Trialling {} until {}: run 'oxidizr keep' to keep them enabled",
+            trial.experiments.join(", "),
+            calendar_time(trial.expires)
+        );
+        Ok(trial)
+    }
+
+    /// End the trial of experiments that have been disabled, or are being kept. Once no
+    /// experiments remain on trial, the timer is stopped.
+    pub fn end(system: &dyn Worker, experiments: &[String]) -> Result<()> {
+        if Self::load(system)?.is_none() {
+            return Ok(());
+        }
+        update_state(system, |trial: &mut Self| {
+            // Another process may have ended the trial in the meantime.
+            if trial.experiments.is_empty() {
+                return Ok(());
+            }
+            trial.experiments.retain(|e| !experiments.contains(e));
+            if trial.experiments.is_empty() {
+                system.run(&Command::build(
+                    "systemctl",
+                    &["disable", "--now", "oxidizr-trial.timer"],
+                ))?;
+            } else {
+                system.write_file(
+                    Path::new(TRIAL_SERVICE_PATH),
+                    &trial.service(&current_oxidizr(system)?),
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// The service run when the trial ends, disabling the experiments on trial.
+    fn service(&self, oxidizr: &Path) -> String {
+        format!(
+            "# Written by oxidizr: ends the trial of {experiments}\n\
+            [Unit]\n\
+            Description=End the oxidizr trial of {experiments}\n\n\
+            [Service]\n\
+            Type=oneshot\n\
+            ExecStart={oxidizr} disable --yes --experiments {experiments}\n",
+            experiments = self.experiments.join(" "),
+            oxidizr = oxidizr.display(),
+        )
+    }
+
+    /// The timer starting the service when the trial ends.
+    fn timer(&self) -> String {
+        format!(
+            "# Written by oxidizr: ends the trial of {experiments}\n\
+            [Unit]\n\
+            Description=End the oxidizr trial of {experiments}\n\n\
+            [Timer]\n\
+            OnCalendar={expires}\n\
+            Persistent=true\n\n\
+            [Install]\n\
+            WantedBy=timers.target\n",
+            experiments = self.experiments.join(" "),
+            expires = calendar_time(self.expires),
+        )
+    }
+}
+
+/// The oxidizr binary the trial service runs, as recorded in the current service.
+fn current_oxidizr(system: &dyn Worker) -> Result<std::path::PathBuf> {
+    let service = system
+        .read_file(Path::new(TRIAL_SERVICE_PATH))?
+        .unwrap_or_default();
+    let oxidizr = service
+        .lines()
+        .find_map(|line| line.strip_prefix("ExecStart="))
+        .and_then(|exec| exec.split_whitespace().next())
+        .unwrap_or("/usr/bin/oxidizr");
+    Ok(oxidizr.into())
+}
+
+/// Parse a period, such as the `7d` of a trial: a number of minutes (`m`), hours (`h`), days (`d`)
+/// or weeks (`w`).
+pub fn parse_period(period: &str) -> std::result::Result<Duration, String> {
+    let unit = period.chars().last().ok_or("the period is empty")?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "'{period}' doesn't end with a unit of m, h, d or w"
+            ));
+        }
+    };
+    let count: u64 = period[..period.len() - 1]
+        .parse()
+        .map_err(|_| format!("'{period}' doesn't start with a whole number"))?;
+    match count {
+        0 => Err("the period must be longer than zero".to_string()),
+        count => Ok(Duration::from_secs(count * seconds)),
+    }
+}
+
+/// Format a time in seconds since the Unix epoch as a UTC calendar time, such as
+/// `2025-06-01 12:00:00 UTC`, which systemd understands.
+pub fn calendar_time(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // Convert days since the epoch to a date in the proleptic Gregorian calendar, counting years
+    // from March so that leap days fall at the end.
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!(parse_period("7d"), Ok(Duration::from_secs(604800)));
+        assert_eq!(parse_period("12h"), Ok(Duration::from_secs(43200)));
+        assert_eq!(parse_period("2w"), Ok(Duration::from_secs(1209600)));
+        assert!(parse_period("7").is_err());
+        assert!(parse_period("d").is_err());
+        assert!(parse_period("0d").is_err());
+        assert!(parse_period("").is_err());
+    }
+
+    #[test]
+    fn test_calendar_time() {
+        assert_eq!(calendar_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(calendar_time(951825600), "2000-02-29 12:00:00 UTC");
+        assert_eq!(calendar_time(1767225599), "2025-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_trial() {
+        let runner = MockSystem::default();
+        let experiments = vec!["coreutils".to_string(), "sudo-rs".to_string()];
+        let oxidizr = Path::new("/usr/bin/oxidizr");
+        Trial::start(
+            &runner,
+            experiments,
+            Duration::from_secs(604800),
+            0,
+            oxidizr,
+        )
+        .unwrap();
+
+        let timer = runner.read_file(Path::new(TRIAL_TIMER_PATH)).unwrap();
+        assert!(
+            timer
+                .unwrap()
+                .contains("OnCalendar=1970-01-08 00:00:00 UTC\n")
+        );
+        let service = runner.read_file(Path::new(TRIAL_SERVICE_PATH)).unwrap();
+        assert!(service.unwrap().contains(
+            "ExecStart=/usr/bin/oxidizr disable --yes --experiments coreutils sudo-rs\n"
+        ));
+        assert!(
+            runner
+                .commands
+                .borrow()
+                .contains(&"systemctl enable --now oxidizr-trial.timer".to_string())
+        );
+
+        // Disabling one of the experiments leaves the other on trial.
+        Trial::end(&runner, &["sudo-rs".to_string()]).unwrap();
+        assert_eq!(
+            Trial::load(&runner).unwrap().map(|t| t.experiments),
+            Some(vec!["coreutils".to_string()])
+        );
+        let service = runner.read_file(Path::new(TRIAL_SERVICE_PATH)).unwrap();
+        assert!(service.unwrap().contains("--experiments coreutils\n"));
+
+        // Keeping the last one stops the timer.
+        Trial::end(&runner, &["coreutils".to_string()]).unwrap();
+        assert_eq!(Trial::load(&runner).unwrap(), None);
+        assert!(
+            runner
+                .commands
+                .borrow()
+                .contains(&"systemctl disable --now oxidizr-trial.timer".to_string())
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::utils::{StateFile, Worker, read_state, write_state};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// The location of the invocation counts of each binary replaced with a shim.
+pub const USAGE_PATH: &str = "/var/lib/oxidizr/usage.json";
+
+/// The location of the log to which shims append a line for each event, which is folded into the
+/// counts whenever they are read. Shims run nothing but shell builtins, so they can't update the
+/// counts themselves.
+pub const USAGE_LOG_PATH: &str = "/var/lib/oxidizr/usage.log";
+
+/// How often a binary replaced with a shim has been run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BinaryUsage {
+    pub invocations: u64,
+    /// How many of the invocations were handed to the GNU binary, having used an option that the
+    /// replacement doesn't support.
+    pub fallbacks: u64,
+}
+
+/// The invocation counts of each binary replaced with a shim, by binary name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Usage {
+    pub binaries: BTreeMap<String, BinaryUsage>,
+}
+
+impl StateFile for Usage {
+    const PATH: &'static str = USAGE_PATH;
+}
+
+impl Usage {
+    /// Load the counts, including the events logged since they were last saved.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        let mut usage: Self = read_state(system)?.unwrap_or_default();
+        if let Some(log) = system.read_file(Path::new(USAGE_LOG_PATH))? {
+            usage.count(&log);
+        }
+        Ok(usage)
+    }
+
+    /// Save the counts, and empty the log of the events they now include. Events logged in
+    /// between loading and saving the counts are lost, which is tolerable for counts such as
+    /// these.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)?;
+        system.write_file(Path::new(USAGE_LOG_PATH), "")
+    }
+
+    /// Count the events in a log written by shims: each line is either `run <binary>` or
+    /// `fallback <binary>`. Lines that are neither are ignored.
+    fn count(&mut self, log: &str) {
+        for line in log.lines() {
+            match line.split_once(' ') {
+                Some(("run", binary)) => {
+                    self.binaries
+                        .entry(binary.to_string())
+                        .or_default()
+                        .invocations += 1;
+                }
+                Some(("fallback", binary)) => {
+                    self.binaries
+                        .entry(binary.to_string())
+                        .or_default()
+                        .fallbacks += 1;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_usage_counts_logged_events() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![(
+            USAGE_LOG_PATH,
+            "run date\nrun date\nfallback date\nrun sort\ngarbage\n",
+            false,
+        )]);
+
+        let usage = Usage::load(&runner).unwrap();
+        let date = &usage.binaries["date"];
+        assert_eq!((date.invocations, date.fallbacks), (2, 1));
+        assert_eq!(usage.binaries["sort"].invocations, 1);
+
+        // Once saved, the logged events are only counted once.
+        usage.save(&runner).unwrap();
+        assert_eq!(Usage::load(&runner).unwrap(), usage);
+    }
+}
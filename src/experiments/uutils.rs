@@ -1,6 +1,16 @@
-use crate::utils::Worker;
-use anyhow::Result;
-use std::path::{Path, PathBuf};
+use super::channel::{self, Channel, ChannelRelease, Channels};
+use super::coverage::{OptionCoverage, help_options};
+use super::shim::{self, LinkStrategy, Strategies};
+use super::{Directories, Metadata, Pins, Quarantine};
+use crate::utils::{
+    Command, Distribution, FsOperation, ManagedTarget, PackageVersion, ReplacementSource, Worker,
+    backup_filename,
+};
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 use tracing::info;
 
 /// An experiment to install and configure a Rust-based replacement for a system utility.
@@ -8,9 +18,10 @@ pub struct UutilsExperiment<'a> {
     name: String,
     system: &'a dyn Worker,
     package: String,
-    supported_releases: Vec<String>,
+    supported_releases: Vec<Distribution>,
     unified_binary: Option<PathBuf>,
     bin_directory: PathBuf,
+    metadata: &'static Metadata,
 }
 
 impl<'a> UutilsExperiment<'a> {
@@ -19,42 +30,39 @@ impl<'a> UutilsExperiment<'a> {
         name: &str,
         system: &'a dyn Worker,
         package: &str,
-        supported_releases: &[&str],
+        supported_releases: Vec<Distribution>,
         unified_binary: Option<PathBuf>,
         bin_directory: PathBuf,
+        metadata: &'static Metadata,
     ) -> Self {
         Self {
             name: name.to_string(),
             system,
             package: package.to_string(),
-            supported_releases: supported_releases
-                .iter()
-                .map(|&release| release.to_string())
-                .collect(),
+            supported_releases,
             unified_binary,
             bin_directory,
+            metadata,
         }
     }
 
-    /// Check if the system is compatible with the experiment.
-    pub fn check_compatible(&self) -> bool {
-        self.supported_releases().contains(
-            &self
-                .system
-                .distribution()
-                .expect("unable to determine distribution information")
-                .release,
-        )
+    /// The system the experiment operates on.
+    pub(super) fn system(&self) -> &'a dyn Worker {
+        self.system
     }
 
-    /// Reports the first supported release for the experiment.
-    pub fn supported_releases(&self) -> Vec<String> {
+    /// Reports the distribution releases supported by the experiment.
+    pub fn supported_releases(&self) -> Vec<Distribution> {
         self.supported_releases.clone()
     }
 
-    /// Check if the package is installed.
+    /// Check if the package is installed, or the release from the upstream channel recorded for
+    /// the experiment.
     pub fn check_installed(&self) -> bool {
-        self.system.check_installed(&self.package).unwrap_or(false)
+        match self.channel() {
+            Channel::Stable => self.system.check_installed(&self.package).unwrap_or(false),
+            _ => Channels::load(self.system).is_ok_and(|c| c.release(&self.name).is_some()),
+        }
     }
 
     /// Report the name of the experiment.
@@ -62,63 +70,332 @@ impl<'a> UutilsExperiment<'a> {
         self.name.clone()
     }
 
-    /// Enable the experiment by installing and configuring the package.
+    /// Report the installed version of the package, if any, or of the release from the upstream
+    /// channel recorded for the experiment, if its tag is a version.
+    pub fn installed_version(&self) -> Option<PackageVersion> {
+        match self.channel() {
+            Channel::Stable => self.system.installed_version(&self.package).unwrap_or(None),
+            _ => Channels::load(self.system)
+                .ok()?
+                .release(&self.name)
+                .and_then(|r| r.release.trim_start_matches('v').parse().ok()),
+        }
+    }
+
+    /// Report the channel the replacement binaries are installed from.
+    pub fn channel(&self) -> Channel {
+        Channels::load(self.system)
+            .map(|c| c.of(&self.name))
+            .unwrap_or_default()
+    }
+
+    /// Install the release from the upstream channel recorded for the experiment, if it isn't
+    /// installed from the distribution.
+    pub fn install_channel(&self) -> Result<()> {
+        channel::install_channel(
+            self.system,
+            &self.name,
+            self.metadata.upstream,
+            self.unified_binary.as_deref(),
+        )
+    }
+
+    /// Unpack the release of the experiment on an upstream channel into `directory`, without
+    /// recording it as installed, such as for a deployment under a prefix.
+    pub fn unpack_release(
+        &self,
+        channel: Channel,
+        directory: &Path,
+    ) -> Result<Option<ChannelRelease>> {
+        channel::unpack_release(
+            self.system,
+            &self.name,
+            self.metadata.upstream,
+            channel,
+            None,
+            directory,
+            self.unified_binary.as_deref(),
+        )
+    }
+
+    /// The directory holding the replacement binaries: that of the package, or that into which
+    /// the release from an upstream channel is unpacked.
+    fn bin_directory(&self) -> PathBuf {
+        match self.channel() {
+            Channel::Stable => self.bin_directory.clone(),
+            _ => channel::channel_directory(&self.name).join("bin"),
+        }
+    }
+
+    /// The unified binary providing every replacement, if the experiment has one, from the
+    /// package or the release from an upstream channel.
+    fn unified_binary(&self) -> Option<PathBuf> {
+        let unified = self.unified_binary.as_ref()?;
+        Some(match self.channel() {
+            Channel::Stable => unified.clone(),
+            _ => channel::channel_directory(&self.name).join(unified.file_name()?),
+        })
+    }
+
+    /// Report the package providing the experiment's replacement binaries.
+    pub fn package(&self) -> String {
+        self.package.clone()
+    }
+
+    /// Report information about the upstream project providing the replacement binaries.
+    pub fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    /// Enable the experiment by replacing the system files with symlinks to the binaries from
+    /// the package, which must already be installed, or with shims if it was enabled with them.
     pub fn enable(&self) -> Result<()> {
-        info!("Installing and configuring {}", self.package);
-        self.system.install_package(&self.package)?;
-
-        let files = self.system.list_files(self.bin_directory.clone())?;
-
-        for f in files {
-            let filename = f.file_name().unwrap().to_str().unwrap();
-            let existing = match self.system.which(filename) {
-                Ok(path) => path,
-                Err(_) => Path::new("/usr/bin").join(filename),
-            };
-
-            if let Some(unified_binary) = &self.unified_binary {
-                self.system
-                    .replace_file_with_symlink(unified_binary.to_path_buf(), existing.clone())?;
-            } else {
-                self.system.replace_file_with_symlink(f, existing)?;
-            }
+        info!("Configuring {}", self.name);
+
+        for (source, target) in self.replacements()? {
+            self.link(ReplacementSource::new(source)?, ManagedTarget::new(target)?)?;
         }
 
         Ok(())
     }
 
-    /// Disable the experiment by removing the package and restoring the original files.
+    /// Report the strategy with which the experiment's files are replaced.
+    pub fn strategy(&self) -> Result<LinkStrategy> {
+        Ok(Strategies::load(self.system)?.of(&self.name))
+    }
+
+    /// Replace a system file with its replacement binary, using the strategy recorded for the
+    /// experiment. A file replaced using the other strategy is restored first, so that the
+    /// replacement isn't backed up in place of the original.
+    pub fn link(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()> {
+        match self.strategy()? {
+            LinkStrategy::Symlink => {
+                if self.is_shim(source.as_path(), target.as_path())? {
+                    self.system.restore_file(target.clone())?;
+                }
+                self.system.replace_file_with_symlink(source, target)?;
+                Ok(())
+            }
+            LinkStrategy::Shim => {
+                let script = self.shim_script(source.as_path(), target.as_path())?;
+                if self.system.read_symlink(target.as_path())?.as_deref() == Some(source.as_path())
+                {
+                    self.system.restore_file(target.clone())?;
+                }
+                shim::install(self.system, target, &script)
+            }
+        }
+    }
+
+    /// Generate the shim that runs the replacement for a system file.
+    fn shim_script(&self, source: &Path, target: &Path) -> Result<String> {
+        let mut command = vec![source.as_os_str()];
+        if self.unified_binary.is_some()
+            && let Some(name) = target.file_name()
+        {
+            command.push(name);
+        }
+        shim::script(&command, target)
+    }
+
+    /// Check whether a system file is the shim running its replacement. A file whose path can't
+    /// be written into a shim can't be one.
+    fn is_shim(&self, source: &Path, target: &Path) -> Result<bool> {
+        match self.shim_script(source, target) {
+            Ok(script) => shim::installed(self.system, target, &script),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Check whether a system file is replaced by its replacement binary, with either strategy.
+    fn is_linked(&self, source: &Path, target: &Path) -> Result<bool> {
+        Ok(self.system.read_symlink(target)?.as_deref() == Some(source)
+            || self.is_shim(source, target)?)
+    }
+
+    /// Disable the experiment by restoring the original files. The package can be removed
+    /// afterwards.
     pub fn disable(&self) -> Result<()> {
-        let files = self.system.list_files(self.bin_directory.clone())?;
-
-        for f in files {
-            let filename = f.file_name().unwrap().to_str().unwrap();
-            let existing = match self.system.which(filename) {
-                Ok(path) => path,
-                Err(_) => Path::new("/usr/bin").join(filename),
-            };
-            self.system.restore_file(existing)?;
+        info!("Restoring files replaced by {}", self.name);
+
+        let restores: Vec<FsOperation> = self
+            .replacements()?
+            .into_iter()
+            .map(|(_, file)| FsOperation::restore(file))
+            .collect::<Result<_>>()?;
+        self.system
+            .apply(&restores)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    /// Report each file replaced by the experiment, and whether it currently links to its
+    /// replacement.
+    pub fn check_replaced(&self) -> Result<Vec<(PathBuf, bool)>> {
+        self.replacements()?
+            .into_iter()
+            .map(|(source, target)| {
+                let linked = self.is_linked(&source, &target)?;
+                Ok((target, linked))
+            })
+            .collect()
+    }
+
+    /// Report the binaries that were pinned to GNU when the experiment was enabled, and those
+    /// quarantined since.
+    pub fn pinned(&self) -> Result<Vec<String>> {
+        let mut pinned = Pins::load(self.system)?.of(&self.name).to_vec();
+        for binary in self.quarantined()? {
+            if !pinned.contains(&binary) {
+                pinned.push(binary);
+            }
         }
+        Ok(pinned)
+    }
+
+    /// Report the binaries quarantined after failures implicated them.
+    pub fn quarantined(&self) -> Result<Vec<String>> {
+        Ok(Quarantine::load(self.system)?.of(&self.name))
+    }
 
-        info!("Removing {}", self.package);
-        self.system.remove_package(&self.package)?;
+    /// Report the files pinned to GNU since they were replaced, which still link to their
+    /// replacements.
+    pub fn pinned_replaced(&self) -> Result<Vec<PathBuf>> {
+        let pinned = self.pinned()?;
+        let mut replaced = Vec::new();
+        for (source, target) in self.all_replacements()? {
+            if is_pinned(&pinned, &target) && self.is_linked(&source, &target)? {
+                replaced.push(target);
+            }
+        }
+        Ok(replaced)
+    }
 
+    /// Restore the GNU binaries that have been pinned since they were replaced, such as when the
+    /// experiment is enabled again after a binary is added to its pins, or is quarantined.
+    pub fn restore_pinned(&self) -> Result<()> {
+        for target in self.pinned_replaced()? {
+            info!("Restoring GNU '{}', which is pinned", target.display());
+            self.system.restore_file(ManagedTarget::new(target)?)?;
+        }
         Ok(())
     }
+
+    /// List the replacement binaries shipped by the package, paired with the system files they
+    /// replace. Binaries pinned to GNU are left out.
+    pub fn replacements(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let pinned = self.pinned()?;
+        Ok(self
+            .all_replacements()?
+            .into_iter()
+            .filter(|(_, target)| !is_pinned(&pinned, target))
+            .collect())
+    }
+
+    /// List every replacement binary shipped by the package, including those pinned to GNU.
+    fn all_replacements(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let files = self.system.list_files(self.bin_directory())?;
+        let unified_binary = self.unified_binary();
+        let directories = Directories::load(self.system)?;
+
+        files
+            .into_iter()
+            .map(|f| {
+                let filename = f
+                    .file_name()
+                    .with_context(|| format!("'{}' doesn't name a file", f.display()))?;
+                let existing = directories.locate(self.system, &self.name, filename);
+
+                Ok(match &unified_binary {
+                    Some(unified_binary) => (unified_binary.clone(), existing),
+                    None => (f, existing),
+                })
+            })
+            .collect()
+    }
+
+    /// Build the command running the replacement `source` of the binary `name`, which is given as
+    /// the first argument of a unified binary such as `coreutils`.
+    pub fn replacement_command(&self, source: &Path, name: &OsStr) -> Command {
+        match &self.unified_binary {
+            Some(_) => Command::build(source, &[]).arg(name),
+            None => Command::build(source, &[]),
+        }
+    }
+
+    /// Compare the options documented by each GNU binary the experiment replaces with those
+    /// documented by its replacement, so that users can see which options they would lose. Only
+    /// possible once the package is installed; binaries whose options can't be determined are
+    /// omitted.
+    pub fn option_coverage(&self) -> Vec<OptionCoverage> {
+        if !self.check_installed() {
+            return Vec::new();
+        }
+
+        self.replacements()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(source, target)| {
+                let name = target.file_name()?.to_os_string();
+                // Once replaced, the GNU binary is only available as the backup.
+                let original = match self.is_linked(&source, &target).ok()? {
+                    true => backup_filename(&target).ok()?,
+                    false => target,
+                };
+                let gnu = help_options(self.system, &Command::build(&original, &["--help"]));
+                if gnu.is_empty() {
+                    return None;
+                }
+
+                let replacement = self.replacement_command(&source, &name).arg("--help");
+                let replacement = help_options(self.system, &replacement);
+                Some(OptionCoverage::new(
+                    &name.to_string_lossy(),
+                    &gnu,
+                    &replacement,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Check whether a system file is one of the binaries pinned to GNU.
+fn is_pinned(pinned: &[String], target: &Path) -> bool {
+    target
+        .file_name()
+        .is_some_and(|name| pinned.iter().any(|p| name == p.as_str()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::{Distribution, MockSystem, vecs_eq};
+    use crate::experiments::{Experiment, ubuntu};
+    use crate::utils::{MockSystem, vecs_eq};
 
     #[test]
     fn test_uutils_incompatible_distribution() {
         let runner = incompatible_runner();
-        let coreutils = coreutils_fixture(&runner);
+        let coreutils = Experiment::Uutils(coreutils_fixture(&runner));
         assert!(!coreutils.check_compatible());
     }
 
+    #[test]
+    fn test_uutils_compatible_derivative() {
+        let runner = MockSystem::new(Distribution::new("Linuxmint", "22"));
+        runner.mock_files(vec![(
+            "/etc/os-release",
+            "NAME=\"Linux Mint\"\nVERSION_ID=\"22\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\nUBUNTU_CODENAME=noble\n",
+            false,
+        )]);
+        let coreutils = Experiment::Uutils(coreutils_fixture(&runner));
+        assert!(coreutils.check_compatible());
+        assert_eq!(
+            runner.distribution().unwrap().to_string(),
+            "Ubuntu 24.04 (derivative: Linux Mint 22)"
+        );
+    }
+
     #[test]
     fn test_uutils_install_success_unified_binary() {
         let runner = coreutils_compatible_runner();
@@ -126,8 +403,8 @@ mod tests {
 
         assert!(coreutils.enable().is_ok());
 
-        let commands = runner.commands.clone().into_inner();
-        assert_eq!(commands, &["apt-get install -y rust-coreutils"]);
+        // Packages are installed by the caller, in a single transaction for all experiments.
+        assert!(runner.commands.clone().into_inner().is_empty());
 
         let backed_up_files = runner.backed_up_files.clone().into_inner();
         let expected = vec!["/usr/bin/date".to_string(), "/usr/bin/sort".to_string()];
@@ -156,8 +433,7 @@ mod tests {
 
         assert!(findutils.enable().is_ok());
 
-        let commands = runner.commands.clone().into_inner();
-        assert_eq!(commands, &["apt-get install -y rust-findutils"]);
+        assert!(runner.commands.clone().into_inner().is_empty());
 
         let backed_up_files = runner.backed_up_files.clone().into_inner();
         let expected = vec!["/usr/bin/find".to_string(), "/usr/bin/xargs".to_string()];
@@ -190,23 +466,119 @@ mod tests {
         assert_eq!(runner.created_symlinks.clone().into_inner().len(), 0);
         assert_eq!(runner.backed_up_files.clone().into_inner().len(), 0);
 
-        let commands = runner.commands.clone().into_inner();
-        assert_eq!(commands.len(), 1);
-        assert!(commands.contains(&"apt-get remove -y rust-coreutils".to_string()));
+        // Packages are removed by the caller, once all files have been restored.
+        assert!(runner.commands.clone().into_inner().is_empty());
 
         let restored_files = runner.restored_files.clone().into_inner();
         let expected = vec!["/usr/bin/date".to_string(), "/usr/bin/sort".to_string()];
         assert!(vecs_eq(restored_files, expected));
     }
 
-    fn coreutils_fixture(system: &MockSystem) -> UutilsExperiment {
+    #[test]
+    fn test_uutils_option_coverage() {
+        let runner = coreutils_compatible_runner();
+        let coreutils = coreutils_fixture(&runner);
+        let gnu_date = "Usage: date [OPTION]... [+FORMAT]\n  \
+          -R, --rfc-email            output date and time in RFC 5322 format.\n  \
+          -u, --utc, --universal     print or set Coordinated Universal Time (UTC)\n";
+        runner.mock_command("/usr/bin/date --help", gnu_date);
+        runner.mock_command("/usr/bin/.date.oxidizr.bak --help", gnu_date);
+        runner.mock_command(
+            "/usr/bin/coreutils date --help",
+            "Options:\n  -u, --universal  print or set Coordinated Universal Time (UTC) [aliases: --utc]\n",
+        );
+
+        // The options of the replacements are only known once they are installed.
+        assert!(coreutils.option_coverage().is_empty());
+
+        // sort documents no options, so is omitted.
+        runner.mock_install_package("rust-coreutils");
+        let coverage = coreutils.option_coverage();
+        assert_eq!(coverage.len(), 1);
+        assert_eq!(coverage[0].binary, "date");
+        assert_eq!(coverage[0].total, 5);
+        assert_eq!(coverage[0].missing, vec!["--rfc-email", "-R"]);
+
+        // Once replaced, the options of the GNU binary are read from its backup.
+        coreutils.enable().unwrap();
+        assert_eq!(coreutils.option_coverage(), coverage);
+        assert!(
+            runner
+                .commands
+                .clone()
+                .into_inner()
+                .contains(&"/usr/bin/.date.oxidizr.bak --help".to_string())
+        );
+    }
+
+    #[test]
+    fn test_uutils_pinned() {
+        let runner = coreutils_compatible_runner();
+        let coreutils = coreutils_fixture(&runner);
+        coreutils.enable().unwrap();
+
+        let mut pins = Pins::default();
+        pins.set("coreutils", &["date".to_string()]);
+        pins.save(&runner).unwrap();
+
+        // A pinned binary is no longer one of the replacements, so isn't reported as drifted.
+        let replaced = coreutils.check_replaced().unwrap();
+        assert_eq!(replaced, vec![(PathBuf::from("/usr/bin/sort"), true)]);
+
+        // Its replacement is removed without touching the other binaries.
+        coreutils.restore_pinned().unwrap();
+        assert_eq!(
+            runner.restored_files.clone().into_inner(),
+            vec!["/usr/bin/date".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_uutils_shim_strategy() {
+        let runner = coreutils_compatible_runner();
+        let coreutils = coreutils_fixture(&runner);
+        let experiments = vec![crate::experiments::Experiment::Uutils(coreutils_fixture(
+            &runner,
+        ))];
+        shim::record_strategy(&runner, &experiments, LinkStrategy::Shim).unwrap();
+
+        coreutils.enable().unwrap();
+        assert!(runner.created_symlinks.clone().into_inner().is_empty());
+        let date = runner
+            .read_file(Path::new("/usr/bin/date"))
+            .unwrap()
+            .unwrap();
+        assert!(date.contains("err=$('/usr/bin/coreutils' 'date' \"$@\" 2>&1 >&3 3>&-)\n"));
+        assert!(date.contains("exec '/usr/bin/.date.oxidizr.bak' \"$@\"\n"));
+        assert!(coreutils.check_replaced().unwrap().iter().all(|(_, r)| *r));
+
+        // Switching back to symlinks restores the originals before linking them.
+        shim::record_strategy(&runner, &experiments, LinkStrategy::Symlink).unwrap();
+        coreutils.enable().unwrap();
+        assert_eq!(runner.restored_files.clone().into_inner().len(), 2);
+        assert_eq!(runner.created_symlinks.clone().into_inner().len(), 2);
+    }
+
+    fn fixture_metadata() -> &'static Metadata {
+        &Metadata {
+            upstream: "https://github.com/uutils/coreutils",
+            license: "MIT",
+            maturity: crate::experiments::Maturity::Stable,
+            maintainer: "uutils",
+            replaces: "coreutils",
+            replaced_license: "GPL-3.0-or-later",
+        }
+    }
+
+    fn coreutils_fixture(system: &MockSystem) -> UutilsExperiment<'_> {
         UutilsExperiment::new(
             "coreutils",
             system,
             "rust-coreutils",
-            &["24.04", "24.10", "25.04"],
+            ubuntu(&["24.04", "24.10", "25.04"]),
             Some(PathBuf::from("/usr/bin/coreutils")),
             PathBuf::from("/usr/lib/cargo/bin/coreutils"),
+            fixture_metadata(),
         )
     }
 
@@ -221,14 +593,15 @@ mod tests {
         runner
     }
 
-    fn findutils_fixture(system: &MockSystem) -> UutilsExperiment {
+    fn findutils_fixture(system: &MockSystem) -> UutilsExperiment<'_> {
         UutilsExperiment::new(
             "findutils",
             system,
             "rust-findutils",
-            &["24.04", "24.10", "25.04"],
+            ubuntu(&["24.04", "24.10", "25.04"]),
             None,
             PathBuf::from("/usr/lib/cargo/bin/findutils"),
+            fixture_metadata(),
         )
     }
 
@@ -244,9 +617,6 @@ mod tests {
     }
 
     fn incompatible_runner() -> MockSystem {
-        MockSystem::new(Distribution {
-            id: "Ubuntu".to_string(),
-            release: "20.04".to_string(),
-        })
+        MockSystem::new(Distribution::new("Ubuntu", "20.04"))
     }
 }
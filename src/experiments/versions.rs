@@ -0,0 +1,170 @@
+use super::{Experiment, PackageCache};
+use crate::utils::{StateFile, Worker, read_state, update_state, warn_or_fail, write_state};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+/// The location of the record of the package versions in place when experiments were enabled.
+pub const RECORDED_VERSIONS_PATH: &str = "/var/lib/oxidizr/versions.json";
+
+/// A package, and the version of it that was installed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedPackage {
+    pub package: String,
+    pub version: Option<String>,
+}
+
+/// The packages involved in an experiment when it was enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedExperiment {
+    pub recorded: u64,
+    /// The package providing the original binaries.
+    pub replaced: RecordedPackage,
+    /// The package providing the replacement binaries.
+    pub replacement: RecordedPackage,
+}
+
+/// The exact package versions in place when each experiment was last enabled, so that a rollback
+/// can return the system to them rather than to whatever versions are current.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordedVersions {
+    pub experiments: BTreeMap<String, RecordedExperiment>,
+}
+
+impl StateFile for RecordedVersions {
+    const PATH: &'static str = RECORDED_VERSIONS_PATH;
+}
+
+impl RecordedVersions {
+    /// Load the recorded versions. A missing record is equivalent to an empty one.
+    pub fn load(system: &dyn Worker) -> Result<Self> {
+        Ok(read_state(system)?.unwrap_or_default())
+    }
+
+    /// Save the recorded versions.
+    pub fn save(&self, system: &dyn Worker) -> Result<()> {
+        write_state(system, self)
+    }
+
+    /// Record the versions of the packages involved in an experiment, replacing any recorded
+    /// when it was previously enabled.
+    pub fn record(&mut self, experiment: &Experiment, system: &dyn Worker) -> Result<()> {
+        // Experiments such as overrides aren't provided by a package, so have no version.
+        let package = |package: String| -> Result<RecordedPackage> {
+            let version = match package.is_empty() {
+                true => None,
+                false => system.installed_version(&package)?.map(|v| v.to_string()),
+            };
+            Ok(RecordedPackage { package, version })
+        };
+
+        self.experiments.insert(
+            experiment.name(),
+            RecordedExperiment {
+                recorded: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                replaced: package(experiment.metadata().replaces.to_string())?,
+                replacement: package(experiment.package())?,
+            },
+        );
+        Ok(())
+    }
+
+    /// Reinstall the recorded version of each package providing original binaries, where it
+    /// differs from the installed version: from the package file cached when it was recorded if
+    /// there is one, or else from the package archive if it still has it. A version that is no
+    /// longer available is warned about, or fails the operation in strict mode.
+    pub fn reinstall_replaced(&self, system: &dyn Worker) -> Result<()> {
+        let cache = PackageCache::load(system).unwrap_or_default();
+        for (name, recorded) in &self.experiments {
+            let RecordedPackage { package, version } = &recorded.replaced;
+            let Some(version) = version else {
+                continue;
+            };
+            let installed = system.installed_version(package)?.map(|v| v.to_string());
+            if installed.as_ref() == Some(version) {
+                info!("{package} is already at recorded version {version}");
+                continue;
+            }
+            if let Some(file) = cache.file(system, package, version) {
+                info!("Reinstalling {package} {version} from {}", file.display());
+                system.install_package_file(&file)?;
+                continue;
+            }
+            if !system.available_versions(package)?.contains(version) {
+                warn_or_fail(
+                    system.strict(),
+                    format!(
+                        "Version {version} of {package}, recorded when enabling '{name}', is no \
+                        longer available"
+                    ),
+                )?;
+                continue;
+            }
+            info!("Reinstalling {package} {version}");
+            system.install_package_version(package, version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Record the package versions of each enabled experiment. Failures are logged rather than
+/// returned, so that they don't fail an otherwise successful operation.
+pub fn record_versions(system: &dyn Worker, experiments: &[Experiment]) {
+    let result = update_state(system, |recorded: &mut RecordedVersions| {
+        for e in experiments.iter().filter(|e| e.check_installed()) {
+            recorded.record(e, system)?;
+        }
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        warn!("Unable to record package versions: {:#}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_record_and_reinstall_versions() {
+        let runner = MockSystem::default();
+        runner.mock_install_package("coreutils");
+        runner.mock_install_package("rust-coreutils");
+        runner.mock_install_package("sudo");
+        runner.mock_command("dpkg-query -W -f=${Version} coreutils", "9.4-3ubuntu6");
+        runner.mock_command("dpkg-query -W -f=${Version} rust-coreutils", "0.0.28-1");
+        runner.mock_command("dpkg-query -W -f=${Version} sudo", "1.9.15p5-3");
+
+        // Only experiments that are enabled are recorded.
+        record_versions(&runner, &all_experiments(&runner));
+        let recorded = RecordedVersions::load(&runner).unwrap();
+        assert_eq!(
+            recorded.experiments.keys().collect::<Vec<_>>(),
+            vec!["coreutils"]
+        );
+        let coreutils = &recorded.experiments["coreutils"];
+        assert_eq!(coreutils.replaced.version.as_deref(), Some("9.4-3ubuntu6"));
+        assert_eq!(coreutils.replacement.version.as_deref(), Some("0.0.28-1"));
+
+        // Once coreutils has been upgraded, the recorded version is reinstalled.
+        runner.mock_command("dpkg-query -W -f=${Version} coreutils", "9.4-3ubuntu7");
+        runner.mock_command(
+            "apt-cache madison coreutils",
+            " coreutils | 9.4-3ubuntu7 | http://archive.ubuntu.com/ubuntu noble-updates/main amd64 Packages\n \
+            coreutils | 9.4-3ubuntu6 | http://archive.ubuntu.com/ubuntu noble/main amd64 Packages\n",
+        );
+        recorded.reinstall_replaced(&runner).unwrap();
+        assert!(
+            runner.commands.borrow().contains(
+                &"apt-get install -y --allow-downgrades coreutils=9.4-3ubuntu6".to_string()
+            )
+        );
+    }
+}
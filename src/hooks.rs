@@ -0,0 +1,117 @@
+//! Commands run before and after experiments are enabled or disabled, so that sites can fit
+//! steps of their own into the flow, such as updating a CMDB or restarting services.
+
+use crate::config::ExperimentHooks;
+use crate::experiments::Experiment;
+use crate::utils::{Command, Worker, warn_or_fail};
+use anyhow::{Context, Result};
+use std::{collections::BTreeMap, fmt};
+use tracing::info;
+
+/// The point in an operation at which a hook runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    PreEnable,
+    PostEnable,
+    PreDisable,
+    PostDisable,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::PreEnable => write!(f, "pre-enable"),
+            Stage::PostEnable => write!(f, "post-enable"),
+            Stage::PreDisable => write!(f, "pre-disable"),
+            Stage::PostDisable => write!(f, "post-disable"),
+        }
+    }
+}
+
+impl Stage {
+    /// Select the hook for this stage.
+    fn hook(self, hooks: &ExperimentHooks) -> Option<&String> {
+        match self {
+            Stage::PreEnable => hooks.pre_enable.as_ref(),
+            Stage::PostEnable => hooks.post_enable.as_ref(),
+            Stage::PreDisable => hooks.pre_disable.as_ref(),
+            Stage::PostDisable => hooks.post_disable.as_ref(),
+        }
+    }
+}
+
+/// Run the hooks configured for a stage of each experiment, with the shell. The change is
+/// described to them in the environment:
+///
+/// - `OXIDIZR_HOOK`: the stage, such as `pre-enable`
+/// - `OXIDIZR_EXPERIMENT`: the experiment, such as `coreutils`
+/// - `OXIDIZR_PACKAGE`: the package providing the replacements, if any
+/// - `OXIDIZR_REPLACES`: the package providing the original binaries
+///
+/// A failing hook before a change aborts the operation, so that a hook can veto it. Once the
+/// change has been made, a failing hook can't undo it, so is only warned about, or fails the
+/// operation in strict mode.
+pub fn run_hooks(
+    system: &dyn Worker,
+    hooks: &BTreeMap<String, ExperimentHooks>,
+    stage: Stage,
+    experiments: &[Experiment],
+) -> Result<()> {
+    for e in experiments {
+        let Some(hook) = hooks.get(&e.name()).and_then(|h| stage.hook(h)) else {
+            continue;
+        };
+
+        info!("Running {stage} hook of '{}'", e.name());
+        let env = [
+            format!("OXIDIZR_HOOK={stage}"),
+            format!("OXIDIZR_EXPERIMENT={}", e.name()),
+            format!("OXIDIZR_PACKAGE={}", e.package()),
+            format!("OXIDIZR_REPLACES={}", e.metadata().replaces),
+        ];
+        let mut args: Vec<&str> = env.iter().map(String::as_str).collect();
+        args.extend(["sh", "-c", hook]);
+        let result = system
+            .run(&Command::build("env", &args))
+            .with_context(|| format!("the {stage} hook of '{}' failed", e.name()));
+
+        match (result, stage) {
+            (Ok(_), _) => (),
+            (Err(err), Stage::PreEnable | Stage::PreDisable) => return Err(err),
+            (Err(err), _) => warn_or_fail(system.strict(), format!("{err:#}"))?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_run_hooks() {
+        let config = Config::parse(
+            r#"
+            [hooks.sudo-rs]
+            pre_enable = "cmdb-update --pending"
+            post_enable = "systemctl restart ssh"
+            "#,
+        )
+        .unwrap();
+        let runner = MockSystem::default();
+        let experiments = all_experiments(&runner);
+
+        run_hooks(&runner, &config.hooks, Stage::PreEnable, &experiments).unwrap();
+        run_hooks(&runner, &config.hooks, Stage::PreDisable, &experiments).unwrap();
+
+        // Only the hook configured for the stage runs, described in its environment.
+        let commands = runner.commands.borrow();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].starts_with("env OXIDIZR_HOOK=pre-enable OXIDIZR_EXPERIMENT=sudo-rs "));
+        assert!(commands[0].contains(" OXIDIZR_REPLACES=sudo "));
+        assert!(commands[0].ends_with(" sh -c cmdb-update --pending"));
+    }
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    fmt,
+    sync::{Arc, atomic::AtomicBool},
+};
+
+/// The exit code used when an operation is interrupted by SIGINT or SIGTERM.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// An error indicating that an operation was stopped because oxidizr received a termination
+/// signal.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation interrupted by signal")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Catch SIGINT and SIGTERM, setting `flag` rather than terminating immediately, so that the
+/// in-flight step can complete and the operation can be rolled back cleanly. Receiving a second
+/// signal terminates oxidizr straight away.
+pub fn register_signal_handlers(flag: &Arc<AtomicBool>) -> Result<()> {
+    for signal in [SIGINT, SIGTERM] {
+        // Registered first, so the shutdown only happens if the flag was already set.
+        signal_hook::flag::register_conditional_shutdown(signal, EXIT_INTERRUPTED, flag.clone())?;
+        signal_hook::flag::register(signal, flag.clone())?;
+    }
+    Ok(())
+}
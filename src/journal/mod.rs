@@ -0,0 +1,354 @@
+mod interrupt;
+mod worker;
+
+use crate::utils::{
+    ErrorCode, ManagedTarget, STATE_FILE_MODE, System, Worker, create_dirs, write_atomic,
+};
+use anyhow::{Context, Result};
+pub use interrupt::{EXIT_INTERRUPTED, Interrupted};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+pub use worker::JournaledWorker;
+
+/// The default location of the journal of the in-flight operation.
+pub const JOURNAL_PATH: &str = "/var/lib/oxidizr/journal.json";
+
+/// A single mutating step performed on the system during an operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Step {
+    InstallPackage {
+        package: String,
+    },
+    RemovePackage {
+        package: String,
+    },
+    ReplaceWithSymlink {
+        source: PathBuf,
+        target: PathBuf,
+    },
+    ReplaceWithCopy {
+        source: PathBuf,
+        target: PathBuf,
+        mode: u32,
+    },
+    RestoreFile {
+        file: PathBuf,
+    },
+}
+
+/// The state of the operation described by a journal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStatus {
+    /// The operation is running, or oxidizr died before it could complete.
+    #[default]
+    InProgress,
+    /// The operation was stopped by a termination signal.
+    Interrupted,
+}
+
+/// A journal of the steps performed by an in-flight enable or disable operation. The journal is
+/// written to disk before each step is carried out, so that if oxidizr dies part way through an
+/// operation, the steps can be inspected and rolled back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub command: String,
+    pub experiments: Vec<String>,
+    pub started: u64,
+    #[serde(default)]
+    pub status: JournalStatus,
+    pub steps: Vec<Step>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Begin a new journal for an operation. Fails if the journal of a previous operation that
+    /// did not complete is still present.
+    pub fn begin(path: &Path, command: &str, experiments: Vec<String>) -> Result<Self> {
+        if fs::exists(path)? {
+            return Err(ErrorCode::InterruptedOperationFound.error(format!(
+                "Found the journal of an interrupted operation at {}",
+                path.display()
+            )));
+        }
+
+        let journal = Self {
+            command: command.to_string(),
+            experiments,
+            started: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            status: JournalStatus::InProgress,
+            steps: Vec::new(),
+            path: path.to_path_buf(),
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Load the journal of an operation from disk, if one exists.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !fs::exists(path)? {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(path)?;
+        let mut journal: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("invalid journal {}", path.display()))?;
+        journal.path = path.to_path_buf();
+        Ok(Some(journal))
+    }
+
+    /// Record a step in the journal, persisting it to disk before returning.
+    pub fn record(&mut self, step: Step) -> Result<()> {
+        self.steps.push(step);
+        self.save()
+    }
+
+    /// Remove the most recent step from the journal, if it is `step`, persisting it to disk
+    /// before returning. Used for steps that turned out to change nothing, which rolling back
+    /// must not undo.
+    pub fn retract(&mut self, step: &Step) -> Result<()> {
+        if self.steps.last() != Some(step) {
+            return Ok(());
+        }
+        self.steps.pop();
+        self.save()
+    }
+
+    /// Take over the journal of an interrupted operation, so that it can be resumed.
+    pub fn resume(path: &Path) -> Result<Self> {
+        let mut journal = Self::load(path)?
+            .context(ErrorCode::NothingToResume.context("No interrupted operation to resume"))?;
+        journal.mark(JournalStatus::InProgress)?;
+        Ok(journal)
+    }
+
+    /// Update the status of the operation, persisting it to disk before returning.
+    pub fn mark(&mut self, status: JournalStatus) -> Result<()> {
+        self.status = status;
+        self.save()
+    }
+
+    /// Mark the operation as complete by removing the journal from disk.
+    pub fn finish(self) -> Result<()> {
+        if fs::exists(&self.path)? {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Undo the steps of an interrupted operation, most recent first. Replaced files are restored
+    /// from their backups, newly installed packages are removed and removed packages are
+    /// reinstalled. A restored file can't be replaced again, as the journal doesn't record what
+    /// it was replaced with, so the rollback of an operation that restored any fails, keeping the
+    /// journal for the operation to be completed instead.
+    pub fn rollback(&self, system: &dyn Worker) -> Result<()> {
+        let mut failures = 0;
+        let mut restored = 0;
+
+        for step in self.steps.iter().rev() {
+            let result = match step {
+                Step::ReplaceWithSymlink { target, .. } | Step::ReplaceWithCopy { target, .. } => {
+                    info!("Rolling back replacement of {}", target.display());
+                    ManagedTarget::new(target).and_then(|t| system.restore_file(t).map(|_| ()))
+                }
+                Step::InstallPackage { package } => {
+                    info!("Rolling back installation of {}", package);
+                    system.remove_package(package)
+                }
+                Step::RemovePackage { package } => {
+                    info!("Rolling back removal of {}", package);
+                    system.install_package(package)
+                }
+                Step::RestoreFile { file } => {
+                    warn!(
+                        "Unable to roll back restoring {}: what it was replaced with is unknown",
+                        file.display()
+                    );
+                    restored += 1;
+                    continue;
+                }
+            };
+
+            if let Err(e) = result {
+                warn!("Failed to roll back step {:?}: {}", step, e);
+                failures += 1;
+            }
+        }
+
+        if failures > 0 {
+            return Err(ErrorCode::RollbackFailed
+                .error(format!("{failures} step(s) could not be rolled back")));
+        }
+        if restored > 0 {
+            return Err(ErrorCode::RollbackFailed.error(format!(
+                "{restored} restored file(s) can't be replaced again; run 'oxidizr resume' to \
+                complete the '{}' operation instead",
+                self.command
+            )));
+        }
+        Ok(())
+    }
+
+    /// Write the journal to disk, replacing it whole and flushing it, so that the steps recorded
+    /// survive a power loss.
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            create_dirs(parent)?;
+        }
+        write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(self)?.as_bytes(),
+            STATE_FILE_MODE,
+        )
+    }
+}
+
+/// Install a panic hook for mutating commands. If oxidizr panics part way through an operation,
+/// the hook rolls back the steps recorded in the journal and prints recovery instructions, rather
+/// than leaving the system in a half-replaced state.
+pub fn install_panic_hook(journal_path: PathBuf) {
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+
+        let journal = match Journal::load(&journal_path) {
+            Ok(Some(journal)) => journal,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("oxidizr crashed, and its journal could not be read: {e}");
+                eprintln!("{}", recovery_instructions(&journal_path));
+                return;
+            }
+        };
+
+        eprintln!(
+            "oxidizr crashed during '{}'. Attempting to roll back {} step(s)...",
+            journal.command,
+            journal.steps.len()
+        );
+
+        let rolled_back = System::new().and_then(|system| journal.rollback(&system));
+        match rolled_back.and_then(|_| journal.finish()) {
+            Ok(_) => {
+                eprintln!("Rollback complete: the system has been returned to its prior state.")
+            }
+            Err(e) => {
+                eprintln!("Rollback failed: {e}");
+                eprintln!("{}", recovery_instructions(&journal_path));
+            }
+        }
+    }));
+}
+
+/// Instructions for recovering the system by hand after a failed rollback.
+fn recovery_instructions(journal_path: &Path) -> String {
+    format!(
+        "To recover, run 'oxidizr resume' to complete the operation or 'oxidizr resume \
+        --rollback' to undo it. To recover manually: every file replaced by oxidizr was backed \
+        up alongside the original as '.<name>.oxidizr.bak' (e.g. /usr/bin/.sort.oxidizr.bak). \
+        The steps performed are listed in {}. Move each backup back over the replaced file, then \
+        remove the journal.",
+        journal_path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    pub fn journal_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("oxidizr-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.json"));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_journal_persisted() {
+        let path = journal_path("persisted");
+        let mut journal = Journal::begin(&path, "enable", vec!["coreutils".to_string()]).unwrap();
+        journal
+            .record(Step::InstallPackage {
+                package: "rust-coreutils".to_string(),
+            })
+            .unwrap();
+
+        let loaded = Journal::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, journal);
+
+        // A second operation can't begin while the journal is present.
+        assert!(Journal::begin(&path, "enable", Vec::new()).is_err());
+
+        journal.finish().unwrap();
+        assert!(Journal::load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_journal_rollback() {
+        let path = journal_path("rollback");
+        let mut journal = Journal::begin(&path, "enable", vec!["coreutils".to_string()]).unwrap();
+        journal
+            .record(Step::InstallPackage {
+                package: "rust-coreutils".to_string(),
+            })
+            .unwrap();
+        journal
+            .record(Step::ReplaceWithSymlink {
+                source: PathBuf::from("/usr/bin/coreutils"),
+                target: PathBuf::from("/usr/bin/date"),
+            })
+            .unwrap();
+
+        let runner = MockSystem::default();
+        assert!(journal.rollback(&runner).is_ok());
+
+        assert_eq!(
+            runner.restored_files.clone().into_inner(),
+            vec!["/usr/bin/date".to_string()]
+        );
+        assert_eq!(
+            runner.commands.clone().into_inner(),
+            vec!["apt-get remove -y rust-coreutils".to_string()]
+        );
+        journal.finish().unwrap();
+    }
+
+    #[test]
+    fn test_journal_rollback_restores() {
+        let path = journal_path("rollback-restores");
+        let mut journal = Journal::begin(&path, "disable", vec!["coreutils".to_string()]).unwrap();
+        journal
+            .record(Step::RestoreFile {
+                file: PathBuf::from("/usr/bin/date"),
+            })
+            .unwrap();
+        journal
+            .record(Step::RemovePackage {
+                package: "rust-coreutils".to_string(),
+            })
+            .unwrap();
+
+        // The package is reinstalled, but the restored file can't be replaced again, so the
+        // rollback fails rather than report the system as it was.
+        let runner = MockSystem::default();
+        let error = journal.rollback(&runner).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), Some(ErrorCode::RollbackFailed));
+        assert_eq!(
+            runner.commands.clone().into_inner(),
+            vec!["apt-get install -y rust-coreutils".to_string()]
+        );
+        assert!(runner.restored_files.borrow().is_empty());
+        journal.finish().unwrap();
+    }
+}
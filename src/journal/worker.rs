@@ -0,0 +1,530 @@
+use super::{Interrupted, Journal, JournalStatus, Step, interrupt::register_signal_handlers};
+use crate::experiments::PolicyName;
+use crate::progress::{Progress, ProgressEvent};
+use crate::utils::{
+    Command, Distribution, ErrorCode, FileLock, Filesystem, ManagedTarget, PackageVersion, Process,
+    ReplacementSource, SkipReason, SkippedFile, Worker,
+};
+use anyhow::Result;
+use std::{
+    cell::RefCell,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+use tracing::debug;
+
+/// A Worker that records every mutating operation in a journal before delegating it to another
+/// Worker, so that an interrupted operation can be rolled back or resumed.
+pub struct JournaledWorker<'a> {
+    inner: &'a dyn Worker,
+    journal: RefCell<Journal>,
+    interrupted: Arc<AtomicBool>,
+    /// Steps already completed by a previous, interrupted run of the operation.
+    completed: Vec<Step>,
+    /// Files the operation left as they are, with the reason for each.
+    skipped: RefCell<Vec<SkippedFile>>,
+    /// The time by which the operation must complete, after which no further steps are started.
+    deadline: Option<Instant>,
+    /// Where each step is reported as it is started.
+    progress: Progress,
+}
+
+impl<'a> JournaledWorker<'a> {
+    /// Create a new `JournaledWorker`, wrapping the specified Worker. If the journal already
+    /// contains steps from an interrupted run, those steps are skipped when requested again -
+    /// except for the most recent, which may not have finished and is performed again.
+    pub fn new(inner: &'a dyn Worker, journal: Journal) -> Self {
+        let completed = match journal.steps.split_last() {
+            Some((_, completed)) => completed.to_vec(),
+            None => Vec::new(),
+        };
+
+        Self {
+            inner,
+            journal: RefCell::new(journal),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            completed,
+            skipped: RefCell::new(Vec::new()),
+            deadline: None,
+            progress: Progress::default(),
+        }
+    }
+
+    /// Refuse to start any further steps once `deadline` has passed, failing them with
+    /// `ErrorCode::TimedOut`.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Report each step to `progress` as it is started.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Report whether the deadline of the operation has passed.
+    pub fn past_deadline(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Catch SIGINT and SIGTERM for the remainder of the operation. Once a signal is received,
+    /// the step in progress is allowed to complete, but any further steps fail with
+    /// [`Interrupted`].
+    pub fn handle_signals(&self) -> Result<()> {
+        register_signal_handlers(&self.interrupted)
+    }
+
+    /// Report whether a termination signal has been received.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// The steps recorded in the journal so far.
+    pub fn steps(&self) -> Vec<Step> {
+        self.journal.borrow().steps.clone()
+    }
+
+    /// The files the operation left as they are so far, such as existing symlinks that weren't
+    /// replaced or files that had no backup to be restored from.
+    pub fn skipped(&self) -> Vec<SkippedFile> {
+        self.skipped.borrow().clone()
+    }
+
+    /// Record the reason a file was left as it is, if it was.
+    fn skip(
+        &self,
+        file: PathBuf,
+        result: Result<Option<SkipReason>>,
+    ) -> Result<Option<SkipReason>> {
+        if let Ok(Some(reason)) = &result {
+            self.skipped.borrow_mut().push(SkippedFile {
+                file,
+                reason: reason.clone(),
+            });
+        }
+        result
+    }
+
+    /// Mark the operation as complete, removing the journal.
+    pub fn finish(self) -> Result<()> {
+        self.journal.into_inner().finish()
+    }
+
+    /// Mark the operation as interrupted and roll back the steps completed so far using
+    /// `system`, which may be other than the Worker the steps were carried out with, such as one
+    /// without the deadline of the operation. The journal is only removed if the rollback
+    /// succeeds.
+    pub fn roll_back(self, system: &dyn Worker) -> Result<()> {
+        let mut journal = self.journal.into_inner();
+        journal.mark(JournalStatus::Interrupted)?;
+        journal.rollback(system)?;
+        journal.finish()
+    }
+
+    /// Remove a step just recorded from the journal, as the file it would have replaced was
+    /// left as it was, so that rolling the operation back leaves the file alone too.
+    fn retract(&self, step: &Step, result: &Result<Option<SkipReason>>) -> Result<()> {
+        match result {
+            Ok(Some(_)) => self.journal.borrow_mut().retract(step),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a step in the journal, returning whether the step should be performed: steps
+    /// completed by a previous run are skipped. Fails without recording if a termination signal
+    /// has been received or the deadline has passed, so that no new steps are started.
+    fn record(&self, step: Step) -> Result<bool> {
+        if self.interrupted() {
+            return Err(Interrupted.into());
+        }
+        if self.past_deadline() {
+            return Err(ErrorCode::TimedOut.error("the operation ran past its deadline"));
+        }
+        if self.completed.contains(&step) {
+            debug!("Skipping previously completed step {:?}", step);
+            return Ok(false);
+        }
+
+        self.progress.emit(&ProgressEvent::Step { step: &step });
+        let mut journal = self.journal.borrow_mut();
+        if journal.steps.last() != Some(&step) {
+            journal.record(step)?;
+        }
+        Ok(true)
+    }
+}
+
+impl Worker for JournaledWorker<'_> {
+    fn strict(&self) -> bool {
+        self.inner.strict()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.inner.dry_run()
+    }
+
+    fn compatibility_policies(&self) -> &[PolicyName] {
+        self.inner.compatibility_policies()
+    }
+
+    fn scratch_root(&self) -> PathBuf {
+        self.inner.scratch_root()
+    }
+
+    fn distribution(&self) -> Result<Distribution> {
+        self.inner.distribution()
+    }
+
+    fn run(&self, cmd: &Command) -> Result<Output> {
+        self.inner.run(cmd)
+    }
+
+    fn list_files(&self, directory: PathBuf) -> Result<Vec<PathBuf>> {
+        self.inner.list_files(directory)
+    }
+
+    fn which(&self, binary_name: &OsStr) -> Result<PathBuf> {
+        self.inner.which(binary_name)
+    }
+
+    fn install_packages(&self, packages: &[String]) -> Result<()> {
+        let mut pending = Vec::new();
+        for package in packages {
+            let step = Step::InstallPackage {
+                package: package.to_string(),
+            };
+            // A package installed before the operation isn't journaled, so that rolling the
+            // operation back leaves it installed. It is still passed on, to be upgraded.
+            if !self.completed.contains(&step) && self.inner.check_installed(package)? {
+                pending.push(package.to_string());
+                continue;
+            }
+            if self.record(step)? {
+                pending.push(package.to_string());
+            }
+        }
+
+        match pending.is_empty() {
+            true => Ok(()),
+            false => self.inner.install_packages(&pending),
+        }
+    }
+
+    fn remove_packages(&self, packages: &[String]) -> Result<()> {
+        let mut pending = Vec::new();
+        for package in packages {
+            let step = Step::RemovePackage {
+                package: package.to_string(),
+            };
+            if self.record(step)? {
+                pending.push(package.to_string());
+            }
+        }
+
+        match pending.is_empty() {
+            true => Ok(()),
+            false => self.inner.remove_packages(&pending),
+        }
+    }
+
+    fn update_package_lists(&self) -> Result<()> {
+        self.inner.update_package_lists()
+    }
+
+    fn check_installed(&self, package: &str) -> Result<bool> {
+        self.inner.check_installed(package)
+    }
+
+    fn installed_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        self.inner.installed_version(package)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Option<String>> {
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&self, path: &Path, contents: &str) -> Result<()> {
+        self.inner.write_file(path, contents)
+    }
+
+    fn lock_file(&self, path: &Path, exclusive: bool) -> Result<Option<FileLock>> {
+        self.inner.lock_file(path, exclusive)
+    }
+
+    /// Fails once a termination signal has been received, so that waiting for processes to exit
+    /// can be interrupted.
+    fn processes(&self) -> Result<Vec<Process>> {
+        if self.interrupted() {
+            return Err(Interrupted.into());
+        }
+        self.inner.processes()
+    }
+
+    fn read_symlink(&self, path: &Path) -> Result<Option<PathBuf>> {
+        self.inner.read_symlink(path)
+    }
+
+    fn replace_file_with_symlink(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+    ) -> Result<Option<SkipReason>> {
+        let step = Step::ReplaceWithSymlink {
+            source: source.as_path().to_path_buf(),
+            target: target.as_path().to_path_buf(),
+        };
+        // A file replaced before the operation isn't journaled, so that rolling the operation
+        // back keeps it replaced.
+        let replaced =
+            self.inner.read_symlink(target.as_path())? == Some(source.as_path().to_path_buf());
+        if replaced && !self.completed.contains(&step) {
+            return self.skip(
+                target.as_path().to_path_buf(),
+                self.inner.replace_file_with_symlink(source, target),
+            );
+        }
+        if !self.record(step.clone())? {
+            return Ok(None);
+        }
+        let result = self.inner.replace_file_with_symlink(source, target.clone());
+        self.retract(&step, &result)?;
+        self.skip(target.as_path().to_path_buf(), result)
+    }
+
+    fn replace_file_with_copy(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+        mode: u32,
+    ) -> Result<()> {
+        let step = Step::ReplaceWithCopy {
+            source: source.as_path().to_path_buf(),
+            target: target.as_path().to_path_buf(),
+            mode,
+        };
+        // A copy made before the operation, and still matching its source, isn't journaled, so
+        // that rolling the operation back keeps it in place.
+        let checksum = self.inner.file_checksum(target.as_path())?;
+        let replaced =
+            checksum.is_some() && checksum == self.inner.file_checksum(source.as_path())?;
+        if replaced && !self.completed.contains(&step) {
+            return self.inner.replace_file_with_copy(source, target, mode);
+        }
+        match self.record(step)? {
+            true => self.inner.replace_file_with_copy(source, target, mode),
+            false => Ok(()),
+        }
+    }
+
+    fn file_size(&self, path: &Path) -> Result<Option<u64>> {
+        self.inner.file_size(path)
+    }
+
+    fn can_connect(&self, host: &str, port: u16) -> bool {
+        self.inner.can_connect(host, port)
+    }
+
+    fn filesystem(&self, path: &Path) -> Result<Filesystem> {
+        self.inner.filesystem(path)
+    }
+
+    fn file_checksum(&self, path: &Path) -> Result<Option<String>> {
+        self.inner.file_checksum(path)
+    }
+
+    fn backup_file(&self, file: ManagedTarget) -> Result<()> {
+        self.inner.backup_file(file)
+    }
+
+    fn restore_file(&self, file: ManagedTarget) -> Result<Option<SkipReason>> {
+        let path = file.as_path().to_path_buf();
+        match self.record(Step::RestoreFile { file: path.clone() })? {
+            true => self.skip(path, self.inner.restore_file(file)),
+            false => Ok(None),
+        }
+    }
+
+    fn create_symlink(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()> {
+        self.inner.create_symlink(source, target)
+    }
+
+    fn remove_file(&self, file: &Path) -> Result<()> {
+        self.inner.remove_file(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{EnableOptions, Experiment, SudoRsExperiment, enable_all};
+    use crate::journal::tests::journal_path;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_journaled_worker_records_steps() {
+        let path = journal_path("worker");
+        let runner = MockSystem::default();
+        let journal = Journal::begin(&path, "enable", vec!["sudo-rs".to_string()]).unwrap();
+        let worker = JournaledWorker::new(&runner, journal);
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        assert!(enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).is_ok());
+
+        let journal = Journal::load(&path).unwrap().unwrap();
+        assert_eq!(journal.steps.len(), 4);
+        assert_eq!(
+            journal.steps[0],
+            Step::InstallPackage {
+                package: "sudo-rs".to_string()
+            }
+        );
+        assert!(journal.steps[1..].iter().all(|s| matches!(
+            s,
+            Step::ReplaceWithSymlink { .. } | Step::ReplaceWithCopy { .. }
+        )));
+
+        worker.finish().unwrap();
+        assert!(Journal::load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_journaled_worker_resume() {
+        let path = journal_path("resume");
+        let runner = sudors_runner();
+        let mut journal = Journal::begin(&path, "enable", vec!["sudo-rs".to_string()]).unwrap();
+        journal
+            .record(Step::InstallPackage {
+                package: "sudo-rs".to_string(),
+            })
+            .unwrap();
+        journal
+            .record(Step::ReplaceWithCopy {
+                source: PathBuf::from("/usr/lib/cargo/bin/su"),
+                target: PathBuf::from("/usr/bin/su"),
+                mode: 0o4755,
+            })
+            .unwrap();
+
+        let worker = JournaledWorker::new(&runner, journal);
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        assert!(enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).is_ok());
+
+        // The package install was completed by the previous run, so is skipped. The last
+        // recorded step may not have completed, so it is repeated.
+        assert!(runner.commands.clone().into_inner().is_empty());
+        assert_eq!(runner.copied_files.clone().into_inner().len(), 2);
+        assert_eq!(runner.created_symlinks.clone().into_inner().len(), 1);
+
+        let journal = Journal::load(&path).unwrap().unwrap();
+        assert_eq!(journal.steps.len(), 4);
+        worker.finish().unwrap();
+    }
+
+    #[test]
+    fn test_journaled_worker_preinstalled_package() {
+        let path = journal_path("preinstalled");
+        let runner = sudors_runner();
+        runner.mock_install_package("sudo-rs");
+        let journal = Journal::begin(&path, "enable", vec!["sudo-rs".to_string()]).unwrap();
+        let worker = JournaledWorker::new(&runner, journal);
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        assert!(enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).is_ok());
+
+        // The package was there before the operation, so rolling it back keeps it.
+        let journal = Journal::load(&path).unwrap().unwrap();
+        assert!(
+            !journal
+                .steps
+                .iter()
+                .any(|s| matches!(s, Step::InstallPackage { .. }))
+        );
+        assert!(worker.roll_back(&runner).is_ok());
+        assert!(
+            !runner
+                .commands
+                .borrow()
+                .iter()
+                .any(|c| c.starts_with("apt-get remove"))
+        );
+    }
+
+    #[test]
+    fn test_journaled_worker_already_enabled() {
+        let path = journal_path("already-enabled");
+        let runner = sudors_runner();
+        runner.mock_install_package("sudo-rs");
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/sudo", "sudo-rs", false),
+            ("/usr/lib/cargo/bin/su", "su-rs", false),
+            ("/usr/bin/sudo", "sudo-rs", true),
+            ("/usr/bin/su", "su-rs", true),
+        ]);
+        runner.created_symlinks.borrow_mut().push((
+            "/usr/lib/cargo/bin/visudo".to_string(),
+            "/usr/bin/visudo".to_string(),
+        ));
+        let journal = Journal::begin(&path, "reapply", vec!["sudo-rs".to_string()]).unwrap();
+        let worker = JournaledWorker::new(&runner, journal);
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        assert!(enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).is_ok());
+
+        // The files were replaced before the operation, so rolling it back keeps them replaced.
+        assert!(worker.steps().is_empty());
+        assert!(worker.roll_back(&runner).is_ok());
+        assert!(runner.restored_files.borrow().is_empty());
+    }
+
+    fn sudors_runner() -> MockSystem {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/bin/sudo", "", true),
+            ("/usr/bin/su", "", true),
+            ("/usr/bin/visudo", "", true),
+        ]);
+        runner
+    }
+
+    #[test]
+    fn test_journaled_worker_interrupted() {
+        let path = journal_path("interrupted");
+        let runner = MockSystem::default();
+        let journal = Journal::begin(&path, "enable", vec!["sudo-rs".to_string()]).unwrap();
+        let worker = JournaledWorker::new(&runner, journal);
+
+        worker.interrupted.store(true, Ordering::SeqCst);
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        let err = enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).unwrap_err();
+        assert!(err.is::<Interrupted>());
+        assert!(runner.commands.clone().into_inner().is_empty());
+
+        assert!(worker.roll_back(&runner).is_ok());
+        assert!(Journal::load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_journaled_worker_past_deadline() {
+        let path = journal_path("deadline");
+        let runner = MockSystem::default();
+        let journal = Journal::begin(&path, "enable", vec!["sudo-rs".to_string()]).unwrap();
+        let worker = JournaledWorker::new(&runner, journal).with_deadline(Some(Instant::now()));
+        assert!(worker.past_deadline());
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        let err = enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).unwrap_err();
+        assert!(format!("{err:#}").contains("the operation ran past its deadline"));
+        assert!(runner.commands.clone().into_inner().is_empty());
+
+        assert!(worker.roll_back(&runner).is_ok());
+        assert!(Journal::load(&path).unwrap().is_none());
+    }
+}
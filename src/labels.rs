@@ -0,0 +1,145 @@
+//! Host labels, which let a single configuration shared by a fleet enable different experiments
+//! on different hosts, such as sudo-rs only on hosts in a canary ring.
+
+use crate::config::LabelsConfig;
+use crate::experiments::Experiment;
+use crate::utils::{Command, Worker};
+use anyhow::{Context, Result};
+use std::{collections::BTreeMap, path::Path};
+use tracing::info;
+
+/// The default location of the labels file, used when the configuration names none.
+pub const DEFAULT_LABELS_PATH: &str = "/etc/oxidizr/labels";
+
+/// The labels describing a host, such as `ring=canary`.
+pub type Labels = BTreeMap<String, String>;
+
+/// Parse `key=value` lines of labels, such as those in a labels file or printed by a labels
+/// command. Blank lines, comments and lines without an `=` are ignored.
+pub fn parse_labels(contents: &str) -> Labels {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// Gather the labels of this host: those in the labels file, then those printed by the labels
+/// command, then those set in the configuration, each overriding the labels before it. A missing
+/// labels file contributes no labels, while a failing labels command is an error, as selecting
+/// experiments without its labels could enable them on the wrong hosts.
+pub fn host_labels(system: &dyn Worker, config: &LabelsConfig) -> Result<Labels> {
+    let mut labels = Labels::new();
+    let file = config
+        .file
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_LABELS_PATH));
+    if let Some(contents) = system.read_file(file)? {
+        labels.extend(parse_labels(&contents));
+    }
+    if let Some(command) = &config.command {
+        let output = system
+            .run(&Command::build("sh", &["-c", command]))
+            .with_context(|| format!("unable to read host labels from '{command}'"))?;
+        labels.extend(parse_labels(&String::from_utf8_lossy(&output.stdout)));
+    }
+    labels.extend(config.values.clone());
+    Ok(labels)
+}
+
+/// Report the labels an experiment requires that a host doesn't have, as `key=value`. Experiments
+/// without a selector are enabled on every host.
+pub fn unmet_selector(config: &LabelsConfig, labels: &Labels, experiment: &str) -> Vec<String> {
+    config
+        .selectors
+        .get(experiment)
+        .into_iter()
+        .flatten()
+        .filter(|(key, value)| labels.get(*key) != Some(*value))
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect()
+}
+
+/// Filter the experiments selected for enabling down to those whose selector matches the labels
+/// of this host. The labels are only gathered when some selector is configured.
+pub fn labeled_experiments<'a>(
+    system: &dyn Worker,
+    config: &LabelsConfig,
+    experiments: Vec<Experiment<'a>>,
+) -> Result<Vec<Experiment<'a>>> {
+    if config.selectors.is_empty() {
+        return Ok(experiments);
+    }
+
+    let labels = host_labels(system, config)?;
+    let mut labeled = Vec::new();
+    for e in experiments {
+        let unmet = unmet_selector(config, &labels, &e.name());
+        match unmet.is_empty() {
+            true => labeled.push(e),
+            false => info!(
+                "Skipping '{}': this host isn't labeled {}",
+                e.name(),
+                unmet.join(", ")
+            ),
+        }
+    }
+    Ok(labeled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::experiments::all_experiments;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_host_labels() {
+        let config = Config::parse(
+            r#"
+            [labels]
+            command = "/usr/local/bin/host-labels"
+            values = { site = "ams" }
+            "#,
+        )
+        .unwrap()
+        .labels;
+
+        let runner = MockSystem::default();
+        runner.mock_files(vec![(
+            DEFAULT_LABELS_PATH,
+            "# Written by provisioning\nring=stable\nsite = lon\nmalformed\n",
+            false,
+        )]);
+        runner.mock_command("sh -c /usr/local/bin/host-labels", "ring=canary\n");
+
+        let labels = host_labels(&runner, &config).unwrap();
+        assert_eq!(labels.get("ring").map(String::as_str), Some("canary"));
+        assert_eq!(labels.get("site").map(String::as_str), Some("ams"));
+        assert_eq!(labels.len(), 2);
+    }
+
+    #[test]
+    fn test_labeled_experiments() {
+        let config = Config::parse("[labels.selectors]\nsudo-rs = { ring = \"canary\" }\n")
+            .unwrap()
+            .labels;
+        let runner = MockSystem::default();
+        let experiments = |runner: &MockSystem| -> Vec<String> {
+            let experiments = labeled_experiments(runner, &config, all_experiments(runner));
+            experiments.unwrap().iter().map(|e| e.name()).collect()
+        };
+
+        runner.mock_files(vec![(DEFAULT_LABELS_PATH, "ring=stable\n", false)]);
+        let names = experiments(&runner);
+        assert!(names.contains(&"coreutils".to_string()));
+        assert!(!names.contains(&"sudo-rs".to_string()));
+
+        runner.mock_files(vec![(DEFAULT_LABELS_PATH, "ring=canary\n", false)]);
+        assert!(experiments(&runner).contains(&"sudo-rs".to_string()));
+    }
+}
@@ -7,15 +7,34 @@
 //! enable and disable these experiments safely.
 //!
 //! # Usage
-//! The program must be run as root and supports two main commands:
+//! The program must be run as root to make changes and supports these main commands:
 //! - `enable`: Activates selected experiments
 //! - `disable`: Deactivates selected experiments
+//! - `keep`: Keeps the experiments enabled on trial with `enable --for`
+//! - `scheduled`: Shows, or cancels, an operation queued for a maintenance window with `--at`
+//! - `reapply`: Re-links files that a package upgrade has overwritten for enabled experiments
+//! - `resume`: Completes (or rolls back) an operation that was interrupted part way through
+//! - `rollback`: Disables experiments, optionally returning replaced packages to recorded versions,
+//!   or switches back to a previous generation of the replaced files
+//! - `generations`: Lists the generations of the replaced files recorded after each operation
+//! - `gc`: Removes backups, generations and downloads that oxidizr no longer needs
+//! - `rescue`: Restores every replaced file from its backup using only system calls
+//! - `mkosi-hook`: Enables experiments in an OS image from a build postinstall script
+//! - `list`: Reports the status of all known experiments (does not require root)
+//! - `matrix`: Reports the distribution releases supported by each experiment
+//! - `known-issues`: Lists known issues in the replacement packages, optionally refreshing them
+//! - `licenses`: Reports the change of license implied by each enabled experiment
+//! - `usage`: Reports how often each binary replaced with a shim has run (does not require root)
+//! - `deploy`: Deploys the replacements under a prefix for the current user (does not require root)
+//! - `schema`: Prints the JSON Schema of a structured output (does not require root)
+//! - `self-update`: Replaces the oxidizr binary with the latest verified release
 //!
 //! # Safety
 //! The utility includes built-in safety measures:
 //! - Distribution compatibility check
 //! - Confirmation prompts (unless explicitly skipped)
 //! - Package list updates before modifications
+//! - A journal of every change, rolled back automatically if oxidizr crashes mid-operation
 //!
 //! # Example
 //! ```bash
@@ -26,19 +45,63 @@
 //! This utility can make significant system changes that might affect system stability
 //! and functionality. Users should proceed with caution and understand the implications
 //! of replacing system utilities.
+pub mod commands;
+pub mod config;
+pub mod etckeeper;
 pub mod experiments;
+pub mod hooks;
+pub mod journal;
+pub mod labels;
+pub mod maintenance;
+pub mod notify;
+pub mod progress;
+pub mod services;
+pub mod summary;
+pub mod telemetry;
+pub mod triggers;
 pub mod utils;
 
-use std::process::exit;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::exit,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
-use experiments::{Experiment, all_experiments};
+use commands::{CheckState, GenerateTarget, InventoryAction, QuarantineAction, SchemaTarget};
+use config::{Config, DEFAULT_CONFIG_PATH, ExperimentHooks};
+use experiments::{
+    Channel, DIRECTORIES_PATH, Directories, EnableOptions, Experiment, Generation, Generations,
+    ImaPolicy, InUsePolicy, Installs, KNOWN_ISSUES_URL, LinkStrategy, Overrides, Pins, Plan,
+    RESCUE_PACKAGE, RecordedVersions, Schedule, Trial, all_experiments, available_experiments,
+    calendar_time, check_authentication, check_locale, check_maturity, check_repositories,
+    check_sed_scripts, check_space, check_sudoers, count_fallbacks, disable_staged, enable_staged,
+    install_all, parse_period, record_channel, record_generation, record_overrides,
+    record_strategy, record_versions, requires_rescue_toolset, rescue_toolset_installed,
+    resolve_time, stage, unscheduled_args, unstage,
+};
+use hooks::{Stage, run_hooks};
 use inquire::Confirm;
+use journal::{
+    EXIT_INTERRUPTED, Interrupted, JOURNAL_PATH, Journal, JournaledWorker, install_panic_hook,
+};
+use labels::labeled_experiments;
+use maintenance::Maintenance;
+use notify::{Notification, Urgency, notify};
+use progress::{Progress, ProgressEvent, ProgressFormat};
+use services::restart_services;
+use summary::{RunSummary, Summary};
+use telemetry::Telemetry;
 use tracing::{info, warn};
-use tracing_subscriber::{fmt, prelude::*};
-use utils::{System, Worker, vecs_eq};
+use tracing_subscriber::{fmt, fmt::writer::BoxMakeWriter, prelude::*};
+use utils::{
+    DryRunWorker, EXIT_PARTIAL_FAILURE, ErrorCode, ErrorReport, ExperimentFailures, OutputFormat,
+    System, Worker, Wsl, clean_stale_scratch, detect_ephemeral_root, detect_wsl, structured,
+    update_state, vecs_eq, warn_or_fail,
+};
 
 /// A command-line utility to install modern Rust-based replacements of essential
 /// packages such as coreutils, findutils, diffutils and sudo and make them the
@@ -85,92 +148,1601 @@ struct Args {
     )]
     experiments: Vec<String>,
 
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Human,
+        help = "Output format for informational commands"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Stream the progress of an operation to stdout, logging to stderr instead"
+    )]
+    progress: Option<ProgressFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        default_value = DEFAULT_CONFIG_PATH,
+        help = "Path to the oxidizr configuration file"
+    )]
+    config: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        help = "Fail on conditions that are otherwise only warned about"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        help = "Allow enabling experiments that aren't yet stable"
+    )]
+    allow_experimental: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        help = "Stop at the first experiment that fails, rather than carrying on with the rest"
+    )]
+    fail_fast: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        help = "Show the changes an enable or disable operation would make, without making them"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PERIOD",
+        value_parser = parse_period,
+        help = "Roll back an enable or disable operation that hasn't completed within a period, such as 30m"
+    )]
+    timeout: Option<Duration>,
+
+    #[arg(long, default_value_t = false, global = true, hide = true)]
+    scheduled: bool,
+
     #[command(subcommand)]
     cmd: Commands,
 }
 
+/// Arguments queueing an enable or disable operation for a maintenance window.
+#[derive(clap::Args, Debug)]
+struct ScheduleArgs {
+    #[arg(
+        long,
+        value_name = "TIME",
+        help = "Queue the operation for a maintenance window opening at a time, such as 'tomorrow 02:00'"
+    )]
+    at: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PERIOD",
+        requires = "at",
+        value_parser = parse_period,
+        help = "Abandon the queued operation if it can't start within a period of the window opening, such as 2h"
+    )]
+    window: Option<Duration>,
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Enable experiments with oxidizr.
-    Enable,
+    #[command(visible_alias = "install")]
+    Enable {
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with = "at_boot",
+            help = "Defer replacing files that running processes are executing until they exit"
+        )]
+        wait: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Replace files that running processes are executing at the next boot"
+        )]
+        at_boot: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ImaPolicy::Warn,
+            help = "How to handle unsigned replacement binaries when IMA appraisal is enforced"
+        )]
+        ima: ImaPolicy,
+
+        #[arg(
+            long,
+            required_if_eq("ima", "sign"),
+            help = "Private key used to sign replacement binaries with '--ima sign'"
+        )]
+        ima_key: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = LinkStrategy::Symlink,
+            help = "How to replace GNU utilities: with symlinks, or with shims that fall back to GNU on unsupported options"
+        )]
+        strategy: LinkStrategy,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Channel::Stable,
+            help = "Where to install the replacements from: the distribution's package, or the latest or nightly upstream release"
+        )]
+        channel: Channel,
+
+        #[arg(
+            long = "for",
+            value_name = "PERIOD",
+            value_parser = parse_period,
+            help = "Disable the experiments again after a trial period, such as 7d, unless 'oxidizr keep' is run"
+        )]
+        trial: Option<Duration>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Restart the running services whose unit files invoke replaced binaries"
+        )]
+        restart_services: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with = "trial",
+            help = "Only install the replacements, leaving the system files in place until 'oxidizr activate'"
+        )]
+        no_activate: bool,
+
+        #[command(flatten)]
+        schedule: ScheduleArgs,
+    },
+    /// Put in place the experiments installed with --no-activate.
+    Activate {
+        /// The experiments to activate, of those installed [default: all of them]
+        experiments: Vec<String>,
+    },
     /// Disable any previous experiments enabled with oxidizr.
-    Disable,
+    Disable {
+        #[command(flatten)]
+        schedule: ScheduleArgs,
+    },
+    /// Keep the experiments enabled with --for, cancelling the end of their trial.
+    Keep,
+    /// Show the operation queued for a maintenance window with --at, if any.
+    Scheduled {
+        #[arg(long, default_value_t = false, help = "Cancel the queued operation")]
+        cancel: bool,
+    },
+    /// List all known experiments and their status on this system.
+    List,
+    /// Show which experiments are supported on which distribution releases.
+    Matrix,
+    /// List known issues in the replacement packages, which are shown in the plan before enabling.
+    KnownIssues {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Download the latest known issues database first (requires root)"
+        )]
+        refresh: bool,
+    },
+    /// Report the change of license implied by each enabled experiment.
+    Licenses {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Render the report as a Markdown table"
+        )]
+        markdown: bool,
+    },
+    /// Report how often each binary replaced with a shim has been run, and fallen back to GNU.
+    Usage,
+    /// List the binaries implicated in failures, and those quarantined as GNU until cleared.
+    Quarantine {
+        #[command(subcommand)]
+        action: Option<QuarantineAction>,
+    },
+    /// Print the inventory of this host as signed JSON, for collection across a fleet.
+    Inventory {
+        #[command(subcommand)]
+        action: Option<InventoryAction>,
+    },
+    /// Check the health of the enabled experiments, exiting with 1 if anything needs attention.
+    Check {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Print one line and exit with the code of a Nagios or Icinga plugin"
+        )]
+        nagios: bool,
+    },
+    /// Run a binary as both GNU and its replacement, and show how their output and exit codes
+    /// differ, such as `compare-one sort -- -k2 -n file`.
+    CompareOne {
+        /// The binary to run, such as `sort`.
+        binary: String,
+        /// The arguments to run it with, after `--`.
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Find which replaced binary makes a command fail, restoring halves of the replaced binaries
+    /// from their backups in turn, then pin it to GNU and re-apply the rest.
+    Bisect {
+        /// The command that fails, and its arguments, after `--`, such as `-- ./deploy.sh`.
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Re-link files that a package has written over for experiments that are enabled.
+    Reapply,
+    /// Deploy the replacements under a prefix, such as ~/.local/oxidizr, without root and without
+    /// changing the system, printing the environment that puts them first on the PATH.
+    Deploy {
+        #[arg(long, help = "Directory under which to deploy the replacements")]
+        prefix: PathBuf,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Channel::Stable,
+            help = "Where to deploy the replacements from: the installed package, or the latest or nightly upstream release"
+        )]
+        channel: Channel,
+    },
+    /// Resume an enable or disable operation that was interrupted part way through.
+    Resume {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Roll back the interrupted operation instead of completing it"
+        )]
+        rollback: bool,
+    },
+    /// Disable the experiments enabled with oxidizr, optionally returning the packages they replaced
+    /// to the exact versions recorded when they were enabled.
+    Rollback {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Reinstall the versions of the replaced packages recorded when enabling"
+        )]
+        to_recorded: bool,
+
+        #[arg(
+            long,
+            value_name = "N",
+            conflicts_with = "to_recorded",
+            help = "Switch back to the configuration of a generation listed by 'oxidizr generations'"
+        )]
+        generation: Option<u32>,
+    },
+    /// List the generations of the replaced files recorded after each operation, with the changes
+    /// each made, for switching back to with `rollback --generation`.
+    Generations,
+    /// Remove what oxidizr no longer needs: backups of files that aren't replaced, generations
+    /// beyond those kept, cached package files, staged shims and upstream releases no experiment
+    /// can use.
+    Gc,
+    /// Restore every file replaced by oxidizr from its backup, without running any other
+    /// programs. For recovering a system on which the replaced utilities no longer work.
+    Rescue,
+    /// Enable experiments from an image build postinstall script, such as mkosi's. Never prompts,
+    /// and modifies the image root given by --root or $BUILDROOT rather than the host.
+    MkosiHook {
+        #[arg(
+            long,
+            help = "Root directory of the image to modify [default: $BUILDROOT]"
+        )]
+        root: Option<PathBuf>,
+    },
+    /// Update oxidizr to the latest released version.
+    SelfUpdate {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Only check whether a newer version is available"
+        )]
+        check: bool,
+    },
+    /// Print the version of oxidizr. Use --verbose to include build and environment details.
+    Version,
+    /// Print the JSON Schema followed by a structured output of oxidizr.
+    Schema {
+        #[arg(value_enum)]
+        output: SchemaTarget,
+    },
+    /// Generate supporting files, such as man pages, from the command line definition.
+    Generate {
+        #[command(subcommand)]
+        target: GenerateTarget,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    // The application must run as root - exit immediately if it's not.
-    anyhow::ensure!(
-        uzers::get_current_uid() == 0,
-        "This program must be run as root"
-    );
-
     // Initialise the tracing system to enable nice logging. Take into account the verbosity
-    // specified at the command line.
+    // specified at the command line. Build pipelines capture the output as plain text, so it
+    // isn't coloured there. When progress is streamed, stdout is left to the events.
+    let pipeline = matches!(args.cmd, Commands::MkosiHook { .. });
+    let writer = match args.progress {
+        Some(_) => BoxMakeWriter::new(std::io::stderr),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
     tracing_subscriber::registry()
         .with(args.verbose.tracing_level_filter())
-        .with(fmt::layer().compact().with_target(false))
+        .with(
+            fmt::layer()
+                .compact()
+                .with_target(false)
+                .with_ansi(!pipeline)
+                .with_writer(writer),
+        )
         .init();
 
     // Initialise the system, gather system information.
-    let system = System::new()?;
+    let system = System::new()?
+        .with_strict(args.strict)
+        .with_progress(Progress::new(args.progress));
 
-    // Exit if the application is run on a non-Ubuntu machine (unless compatibility check is skipped).
-    if !args.no_compatibility_check {
-        anyhow::ensure!(
-            system.distribution()?.id == "Ubuntu",
-            "This program only supports Ubuntu"
-        );
-    } else if system.distribution()?.id != "Ubuntu" {
+    // When structured output is requested, failures are reported in the same format, including
+    // build and environment information to make bug reports actionable.
+    let result = run(&args, &system);
+    if let (Err(e), Commands::Check { nagios: true }) = (&result, &args.cmd) {
+        // A monitoring plugin that fails to check reports its state as unknown.
+        println!("OXIDIZR {} - {e:#}", CheckState::Unknown);
+        exit(CheckState::Unknown.exit_code());
+    }
+    if let Err(e) = &result {
+        // Operations stopped by a termination signal, and those that succeeded for only some
+        // experiments, exit with distinct codes.
+        let partial = e
+            .downcast_ref::<ExperimentFailures>()
+            .is_some_and(|f| !f.succeeded.is_empty());
+        let code = match (e.is::<Interrupted>(), partial) {
+            (true, _) => EXIT_INTERRUPTED,
+            (false, true) => EXIT_PARTIAL_FAILURE,
+            (false, false) => 1,
+        };
+
+        let progress = system.progress();
+        if progress.enabled() {
+            let report = ErrorReport::new(e, &system);
+            progress.emit(&ProgressEvent::Failed { report: &report });
+        }
+        match args.format {
+            OutputFormat::Human => match ErrorCode::of(e) {
+                Some(code) => eprintln!("Error [{code}]: {e:?}\n\nHint: {}", code.hint()),
+                None => eprintln!("Error: {e:?}"),
+            },
+            _ if progress.enabled() => (),
+            format => print!("{}", structured(format, &ErrorReport::new(e, &system))?),
+        }
+        exit(code);
+    }
+    Ok(())
+}
+
+/// Run the subcommand specified on the command line.
+fn run(args: &Args, system: &System) -> Result<()> {
+    let config = Config::load(&args.config)?;
+    let system = &system
+        .clone()
+        .with_policies(&config.compatibility.policies)
+        .with_scratch_root(config.scratch.directory.clone())
+        .with_managed_directories(&config.all_directories());
+    let journal_path = Path::new(JOURNAL_PATH);
+
+    // Handle subcommands
+    match &args.cmd {
+        Commands::Enable { schedule, .. } | Commands::Disable { schedule } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+
+            if let (Commands::Enable { .. }, Some(fstype)) =
+                (&args.cmd, detect_ephemeral_root(system))
+            {
+                warn!(
+                    "The root filesystem is {fstype}, as on a live or ephemeral image: changes \
+                    won't persist past a reboot. To enable experiments persistently, build them \
+                    into the image with 'oxidizr mkosi-hook', or enable them when instances boot \
+                    with 'oxidizr generate cloud-init'"
+                );
+            }
+
+            // Trials are ended by a systemd timer, which WSL 1 lacks.
+            if let Commands::Enable { trial: Some(_), .. } = &args.cmd
+                && detect_wsl(system) == Some(Wsl::Wsl1)
+            {
+                return Err(anyhow::anyhow!(
+                    "WSL 1 doesn't run systemd, so trials with --for can't be ended"
+                ));
+            }
+
+            let selected =
+                selected_experiments(args.all, args.experiments.clone(), system, &config);
+            let selected = match &args.cmd {
+                Commands::Enable { .. } => labeled_experiments(system, &config.labels, selected)?,
+                _ => selected,
+            };
+            let (command, plan, options) = match &args.cmd {
+                Commands::Enable {
+                    wait,
+                    at_boot,
+                    ima,
+                    ima_key,
+                    restart_services,
+                    no_activate,
+                    ..
+                } => {
+                    check_maturity(&selected, args.allow_experimental)?;
+                    let in_use = match (wait, at_boot) {
+                        (true, _) => InUsePolicy::Wait,
+                        // Replacements at boot are made by systemd-tmpfiles, which WSL 1 lacks.
+                        (_, true) if detect_wsl(system) == Some(Wsl::Wsl1) => {
+                            warn!(
+                                "WSL 1 doesn't run systemd, so files in use can't be replaced at \
+                                the next boot: waiting for the processes using them to exit instead"
+                            );
+                            InUsePolicy::Wait
+                        }
+                        (_, true) => InUsePolicy::AtBoot,
+                        _ => InUsePolicy::Replace,
+                    };
+                    let options = EnableOptions {
+                        in_use,
+                        ima: *ima,
+                        ima_key: ima_key.clone(),
+                        interactive: !args.yes,
+                        restart_services: *restart_services,
+                        fail_fast: args.fail_fast,
+                        ..EnableOptions::default()
+                    };
+                    let pins = configured_pins(&config, &selected);
+                    let plan = Plan::enable(system, &selected, args.no_compatibility_check, &pins);
+                    let command = match no_activate {
+                        true => "install",
+                        false => "enable",
+                    };
+                    (command, plan, options)
+                }
+                _ => (
+                    "disable",
+                    Plan::disable(system, &selected),
+                    EnableOptions {
+                        fail_fast: args.fail_fast,
+                        ..EnableOptions::default()
+                    },
+                ),
+            };
+
+            print_plan(&plan, args.format, system.progress())?;
+            if args.dry_run {
+                let worker = DryRunWorker::new(system);
+                if let Commands::Enable {
+                    strategy, channel, ..
+                } = &args.cmd
+                {
+                    record_strategy(&worker, &selected, *strategy)?;
+                    record_channel(&worker, &selected, *channel)?;
+                }
+                let names: Vec<String> = selected.iter().map(|e| e.name()).collect();
+                return dry_run(
+                    &config,
+                    &worker,
+                    command,
+                    &names,
+                    args.no_compatibility_check,
+                    &options,
+                    args.format == OutputFormat::Human && !system.progress().enabled(),
+                );
+            }
+            confirm_or_exit(args.yes);
+            confirm_known_issues(&plan, args.yes, system.strict())?;
+
+            // Queued operations go ahead later, with the plan approved now.
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            if let Some(at) = &schedule.at {
+                let at = resolve_time(system, at)?;
+                let args = unscheduled_args(std::env::args().skip(1));
+                let oxidizr = std::env::current_exe()?;
+                Schedule::queue(system, args, at, schedule.window, &plan, &oxidizr)?;
+                return Ok(());
+            }
+            if args.scheduled {
+                Schedule::verify(system, &plan, now)?;
+            }
+
+            if let Commands::Enable {
+                strategy, channel, ..
+            } = &args.cmd
+            {
+                record_strategy(system, &selected, *strategy)?;
+                record_channel(system, &selected, *channel)?;
+            }
+
+            let names: Vec<String> = selected.iter().map(|e| e.name()).collect();
+            let journal = Journal::begin(journal_path, command, names.clone())?;
+            let result = run_journaled(
+                &config,
+                system,
+                journal,
+                args.no_compatibility_check,
+                &options,
+                args.format,
+                args.timeout,
+            )
+            .map(|_| ());
+            let Some(names) = succeeded(&result, names) else {
+                return result;
+            };
+
+            match &args.cmd {
+                Commands::Enable {
+                    no_activate: true, ..
+                } => (),
+                Commands::Enable {
+                    trial: Some(period),
+                    ..
+                } if !names.is_empty() => {
+                    Trial::start(system, names, *period, now, &std::env::current_exe()?)?;
+                }
+                _ => Trial::end(system, &names)?,
+            }
+            result
+        }
+        Commands::Activate { experiments } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+
+            let installs = Installs::load(system)?;
+            if let Some(name) = experiments
+                .iter()
+                .find(|n| !installs.experiments.contains_key(*n))
+            {
+                anyhow::bail!(
+                    "'{name}' hasn't been installed with 'oxidizr install --no-activate'"
+                );
+            }
+            let names: Vec<String> = installs
+                .experiments
+                .keys()
+                .filter(|n| experiments.is_empty() || experiments.contains(n))
+                .cloned()
+                .collect();
+            if names.is_empty() {
+                println!("No experiments are installed without being activated.");
+                return Ok(());
+            }
+
+            let options = EnableOptions {
+                interactive: !args.yes,
+                fail_fast: args.fail_fast,
+                ..EnableOptions::default()
+            };
+            if args.dry_run {
+                let worker = DryRunWorker::new(system);
+                return dry_run(
+                    &config,
+                    &worker,
+                    "activate",
+                    &names,
+                    args.no_compatibility_check,
+                    &options,
+                    args.format == OutputFormat::Human && !system.progress().enabled(),
+                );
+            }
+            confirm_or_exit(args.yes);
+            let journal = Journal::begin(journal_path, "activate", names)?;
+            run_journaled(
+                &config,
+                system,
+                journal,
+                args.no_compatibility_check,
+                &options,
+                args.format,
+                args.timeout,
+            )
+            .map(|_| ())
+        }
+        Commands::Keep => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+
+            let Some(trial) = Trial::load(system)? else {
+                println!("No experiments are on trial.");
+                return Ok(());
+            };
+            Trial::end(system, &trial.experiments)?;
+            println!("Keeping {} enabled.", trial.experiments.join(", "));
+            Ok(())
+        }
+        Commands::Scheduled { cancel } => {
+            let Some(schedule) = Schedule::load(system)? else {
+                println!("No operation is scheduled.");
+                return Ok(());
+            };
+            if *cancel {
+                ensure_can_mutate(system, args.no_compatibility_check)?;
+                Schedule::cancel(system)?;
+                println!("Cancelled 'oxidizr {}'.", schedule.args.join(" "));
+                return Ok(());
+            }
+
+            println!(
+                "'oxidizr {}' is scheduled for {}",
+                schedule.args.join(" "),
+                calendar_time(schedule.at)
+            );
+            if let Some(window) = schedule.window {
+                println!(
+                    "It will be abandoned if it can't start by {}",
+                    calendar_time(schedule.at + window)
+                );
+            }
+            Ok(())
+        }
+        Commands::Reapply => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+
+            let experiments = all_experiments(system);
+            if let Err(e) = count_fallbacks(system, &experiments) {
+                warn!("Unable to count the fallbacks to GNU: {e:#}");
+            }
+            let mut names = Vec::new();
+            let mut drifted = 0;
+            for e in &experiments {
+                let status = e.status();
+                // Binaries quarantined since they were replaced are restored by reapplying.
+                let quarantined = e.pinned_replaced().unwrap_or_default().len();
+                if status.enabled && (!status.drift.is_empty() || quarantined > 0) {
+                    drifted += status.drift.len() + quarantined;
+                    names.push(status.name);
+                }
+            }
+
+            if names.is_empty() {
+                info!("All enabled experiments are intact, nothing to reapply");
+                return Ok(());
+            }
+
+            confirm_or_exit(args.yes);
+            let journal = Journal::begin(journal_path, "reapply", names.clone())?;
+            let result = run_journaled(
+                &config,
+                system,
+                journal,
+                args.no_compatibility_check,
+                &EnableOptions::default(),
+                args.format,
+                args.timeout,
+            );
+            notify(
+                system,
+                &config.notifications,
+                &reapply_notification(system, &names, drifted, &result),
+            );
+            result.map(|_| ())
+        }
+        Commands::Resume { rollback } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+            confirm_or_exit(args.yes);
+
+            let journal = Journal::resume(journal_path)?;
+            if *rollback {
+                info!(
+                    "Rolling back interrupted '{}' of {}",
+                    journal.command,
+                    journal.experiments.join(", ")
+                );
+                journal.rollback(system)?;
+                return journal.finish();
+            }
+
+            info!(
+                "Resuming interrupted '{}' of {} ({} step(s) already recorded)",
+                journal.command,
+                journal.experiments.join(", "),
+                journal.steps.len()
+            );
+            run_journaled(
+                &config,
+                system,
+                journal,
+                args.no_compatibility_check,
+                &EnableOptions::default(),
+                args.format,
+                args.timeout,
+            )
+            .map(|_| ())
+        }
+        Commands::Rollback {
+            generation: Some(number),
+            ..
+        } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+            switch_generation(&config, system, args, *number)
+        }
+        Commands::Rollback { to_recorded, .. } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+
+            let recorded = RecordedVersions::load(system)?;
+            if recorded.experiments.is_empty() {
+                println!("No experiments enabled by oxidizr have been recorded.");
+                return Ok(());
+            }
+
+            let names: Vec<String> = recorded.experiments.keys().cloned().collect();
+            let selected: Vec<Experiment> = all_experiments(system)
+                .into_iter()
+                .filter(|e| names.contains(&e.name()))
+                .collect();
+            print_plan(
+                &Plan::disable(system, &selected),
+                args.format,
+                system.progress(),
+            )?;
+            if *to_recorded {
+                for r in recorded.experiments.values() {
+                    if let Some(version) = &r.replaced.version {
+                        println!(
+                            "{} will be returned to version {}",
+                            r.replaced.package, version
+                        );
+                    }
+                }
+            }
+            confirm_or_exit(args.yes);
+
+            let journal = Journal::begin(journal_path, "disable", names.clone())?;
+            run_journaled(
+                &config,
+                system,
+                journal,
+                args.no_compatibility_check,
+                &EnableOptions::default(),
+                args.format,
+                args.timeout,
+            )?;
+            Trial::end(system, &names)?;
+            if *to_recorded {
+                recorded.reinstall_replaced(system)?;
+            }
+            RecordedVersions::default().save(system)
+        }
+        Commands::Gc => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+            // Rolling back an interrupted operation may need what would be collected.
+            if journal_path.exists() {
+                return Err(ErrorCode::InterruptedOperationFound.error(format!(
+                    "Found the journal of an interrupted operation at {}: run 'oxidizr resume' \
+                    before collecting garbage",
+                    journal_path.display()
+                )));
+            }
+
+            let garbage = commands::find_garbage(system, &config.retention)?;
+            commands::print_garbage(&garbage, args.dry_run, args.format)?;
+            if args.dry_run || garbage.is_empty() {
+                return Ok(());
+            }
+            confirm_or_exit(args.yes);
+            commands::collect_garbage(system, &garbage).map(|_| ())
+        }
+        Commands::Bisect { command } => {
+            ensure_can_mutate(system, args.no_compatibility_check)?;
+            println!(
+                "'{}' will be run repeatedly as root, with some of the replaced binaries \
+                restored to GNU each time.",
+                command.join(" ")
+            );
+            confirm_or_exit(args.yes);
+            commands::bisect(system, command, args.format)
+        }
+        Commands::Rescue => {
+            // The usual checks run `lsb_release`, which may be broken, so only root is required.
+            if uzers::get_current_uid() != 0 {
+                return Err(ErrorCode::NotRoot.error("This program must be run as root"));
+            }
+            confirm_or_exit(args.yes);
+
+            let directories = commands::rescue_directories(Path::new(DIRECTORIES_PATH));
+            commands::rescue(&directories)
+        }
+        Commands::MkosiHook { root } => {
+            commands::enter_root(&commands::image_root(root.clone())?)?;
+
+            // Start afresh inside the image, so that nothing cached from the host is used.
+            let system = System::new()?
+                .with_strict(args.strict)
+                .with_progress(Progress::new(args.progress))
+                .with_policies(&config.compatibility.policies)
+                .with_managed_directories(&config.all_directories());
+            ensure_can_mutate(&system, args.no_compatibility_check)?;
+
+            let selected =
+                selected_experiments(args.all, args.experiments.clone(), &system, &config);
+            let selected = labeled_experiments(&system, &config.labels, selected)?;
+            check_maturity(&selected, args.allow_experimental)?;
+            let pins = configured_pins(&config, &selected);
+            let plan = Plan::enable(&system, &selected, args.no_compatibility_check, &pins);
+            print_plan(&plan, args.format, system.progress())?;
+            confirm_known_issues(&plan, true, system.strict())?;
+            record_strategy(&system, &selected, LinkStrategy::Symlink)?;
+
+            let names = selected.iter().map(|e| e.name()).collect();
+            let journal = Journal::begin(journal_path, "enable", names)?;
+            run_journaled(
+                &config,
+                &system,
+                journal,
+                args.no_compatibility_check,
+                &EnableOptions::default(),
+                args.format,
+                args.timeout,
+            )
+            .map(|_| ())
+        }
+        Commands::SelfUpdate { check } => commands::self_update(*check),
+        // Informational commands make no changes, so they can run unprivileged on any distribution.
+        Commands::List => commands::list(system, args.format),
+        Commands::Matrix => commands::matrix(system, args.format),
+        Commands::KnownIssues { refresh } => {
+            if *refresh && uzers::get_current_uid() != 0 {
+                return Err(ErrorCode::NotRoot
+                    .error("Refreshing the known issues database must be run as root"));
+            }
+            let url = config
+                .known_issues
+                .url
+                .as_deref()
+                .unwrap_or(KNOWN_ISSUES_URL);
+            commands::known_issues(system, args.format, refresh.then_some(url))
+        }
+        Commands::Licenses { markdown } => commands::licenses(system, args.format, *markdown),
+        Commands::Generations => commands::generations(system, args.format),
+        // Only root can write the counts, so others see them without folding in the latest log.
+        Commands::Usage => commands::usage(system, args.format, uzers::get_current_uid() == 0),
+        Commands::Quarantine { action } => {
+            if action.is_some() && uzers::get_current_uid() != 0 {
+                return Err(ErrorCode::NotRoot.error("This program must be run as root"));
+            }
+            commands::quarantine(system, action.as_ref(), args.format)
+        }
+        Commands::Inventory { action } => {
+            commands::inventory(system, &config.inventory, action.as_ref(), args.format)
+        }
+        Commands::Check { nagios } => {
+            let code = commands::check(
+                system,
+                &config.notifications,
+                journal_path,
+                *nagios,
+                args.format,
+            )?;
+            if code != 0 {
+                exit(code);
+            }
+            Ok(())
+        }
+        Commands::CompareOne {
+            binary,
+            args: arguments,
+        } => {
+            if !commands::compare_one(system, binary, arguments, args.format)? {
+                exit(1);
+            }
+            Ok(())
+        }
+        Commands::Deploy { prefix, channel } => {
+            let selected =
+                selected_experiments(args.all, args.experiments.clone(), system, &config);
+            commands::deploy(system, &selected, prefix, *channel, args.format)
+        }
+        Commands::Version => commands::version(system, args.format, args.verbose.is_present()),
+        Commands::Schema { output } => commands::schema(*output),
+        Commands::Generate { target } => commands::generate(
+            target,
+            Args::command(),
+            pinned_enable_args(args, system, &config),
+        ),
+    }
+}
+
+/// Describe the outcome of re-applying experiments whose files had drifted, such as after a
+/// package upgrade restored the GNU utilities, for the people using the host. The summary of the
+/// operation, or the report of its error, is attached.
+fn reapply_notification(
+    system: &System,
+    names: &[String],
+    drifted: usize,
+    result: &Result<RunSummary>,
+) -> Notification {
+    let experiments = names.join(", ");
+    match result {
+        Ok(summary) => Notification::new(
+            Urgency::Normal,
+            format!("Re-applied {experiments}"),
+            format!(
+                "A package upgrade restored {drifted} file(s) replaced by oxidizr, which have been \
+                replaced again."
+            ),
+        )
+        .with_report("reapply.json", summary),
+        Err(e) => Notification::new(
+            Urgency::Critical,
+            format!("Unable to re-apply {experiments}"),
+            format!(
+                "A package upgrade restored {drifted} file(s) replaced by oxidizr, which could \
+                not be replaced again: {e:#}"
+            ),
+        )
+        .with_report("reapply.json", &ErrorReport::new(e, system)),
+    }
+}
+
+/// Run the enable or disable operation described by a journal on the experiments it names. Every
+/// step is recorded in the journal, so that it can be rolled back if oxidizr crashes or is
+/// interrupted part way through, and steps completed by a previous run are skipped. The summary
+/// of a successful operation is returned once printed.
+fn run_journaled(
+    config: &Config,
+    system: &System,
+    journal: Journal,
+    no_compatibility_check: bool,
+    options: &EnableOptions,
+    format: OutputFormat,
+    timeout: Option<Duration>,
+) -> Result<RunSummary> {
+    install_panic_hook(PathBuf::from(JOURNAL_PATH));
+
+    let command = journal.command.clone();
+    let names = journal.experiments.clone();
+    let _maintenance = Maintenance::begin(&config.maintenance, &command, &names);
+    let progress = system.progress();
+    progress.emit(&ProgressEvent::Started {
+        command: &command,
+        experiments: &names,
+    });
+    etckeeper::commit_before(system, &command);
+    // Commands still running when the operation times out are stopped, and no further steps are
+    // started. The rollback that follows is given as long as it needs.
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let bounded = system.clone().with_deadline(deadline);
+    let worker = JournaledWorker::new(&bounded, journal)
+        .with_deadline(deadline)
+        .with_progress(progress);
+    worker.handle_signals()?;
+
+    let mut telemetry = Telemetry::new(&config.telemetry, &command);
+    let mut summary = Summary::new(&command).with_progress(progress);
+    let selected: Vec<Experiment> = all_experiments(&worker)
+        .into_iter()
+        .filter(|e| names.contains(&e.name()))
+        .collect();
+
+    let options = &command_options(&command, options);
+    let result = match command.as_str() {
+        "enable" | "install" | "activate" => record_configuration(&worker, config, &names)
+            .and_then(|_| {
+                enable(
+                    &worker,
+                    selected,
+                    no_compatibility_check,
+                    options,
+                    config,
+                    &mut telemetry,
+                    &mut summary,
+                )
+            }),
+        "disable" => disable(
+            &worker,
+            selected,
+            &config.hooks,
+            options.fail_fast,
+            &mut telemetry,
+            &mut summary,
+        ),
+        "reapply" => reapply(selected, &mut telemetry, &mut summary),
+        "switch" => switch(
+            &worker,
+            selected,
+            no_compatibility_check,
+            options,
+            config,
+            &mut telemetry,
+            &mut summary,
+        ),
+        other => Err(anyhow::anyhow!("Unknown operation '{other}' in journal")),
+    };
+    telemetry.submit(system);
+    let steps = worker.steps();
+    summary.skip(worker.skipped());
+
+    // If a termination signal arrived, undo the steps completed so far rather than leaving the
+    // system part way through the operation.
+    if worker.interrupted() {
+        warn!("Interrupted, rolling back the changes made so far");
+        progress.emit(&ProgressEvent::RollingBack);
+        worker.roll_back(system)?;
+        return Err(Interrupted.into());
+    }
+
+    // Likewise if the operation failed because it ran past its deadline.
+    if result.is_err() && worker.past_deadline() {
+        warn!("Timed out, rolling back the changes made so far");
+        progress.emit(&ProgressEvent::RollingBack);
+        worker.roll_back(system)?;
+        return Err(ErrorCode::TimedOut.error(format!(
+            "'{command}' didn't complete within {} minute(s)",
+            timeout.unwrap_or_default().as_secs() / 60
+        )));
+    }
+
+    // Likewise if the user couldn't confirm that they can still authenticate as root, or a
+    // replacement failed its smoke test, or a switch to a generation failed at all, as it is
+    // made whole or not at all.
+    let result = match result {
+        Err(e)
+            if command == "switch"
+                || matches!(
+                    ErrorCode::of(&e),
+                    Some(ErrorCode::AuthenticationUnverified | ErrorCode::SmokeTestFailed)
+                ) =>
+        {
+            warn!("Rolling back the changes made so far");
+            progress.emit(&ProgressEvent::RollingBack);
+            worker.roll_back(system)?;
+            return Err(e);
+        }
+        result => result,
+    };
+
+    worker.finish()?;
+    // The changes made for the experiments that succeeded are kept when others failed, so are
+    // followed up on as usual.
+    let Some(names) = succeeded(&result, names) else {
+        return Err(result.expect_err("only a failed operation has no experiments succeeding"));
+    };
+
+    // Keep the dpkg triggers that reapply experiments after package upgrades in step with the
+    // experiments that are now enabled.
+    if command != "reapply" {
+        triggers::sync(system);
+    }
+    // Keep the rescue script in step with the backups now in place.
+    commands::update_rescue_script(system);
+    // Experiments installed without being activated are forgotten once they are put in place, or
+    // removed.
+    if command != "install"
+        && let Err(e) = unstage(system, &names)
+    {
+        warn!("Unable to record the experiments installed: {:#}", e);
+    }
+    // Record the package versions in place, so that they can be returned to by a rollback.
+    if ["enable", "activate", "switch"].contains(&command.as_str()) {
+        let enabled: Vec<Experiment> = all_experiments(system)
+            .into_iter()
+            .filter(|e| names.contains(&e.name()))
+            .collect();
+        record_versions(system, &enabled);
+    }
+    // Forget the pins of experiments that are no longer enabled: the default configuration pins
+    // nothing.
+    if command == "disable"
+        && let Err(e) = record_pins(system, &Config::default(), &names)
+    {
+        warn!("Unable to record pinned binaries: {:#}", e);
+    }
+    if command == "disable"
+        && let Err(e) = record_directories(system, &Config::default(), &names)
+    {
         warn!(
-            "Running on a non-Ubuntu distribution. This is unsupported and may cause system instability."
+            "Unable to record the directories of replaced files: {:#}",
+            e
         );
     }
+    if command == "disable"
+        && names.iter().any(|n| n == "overrides")
+        && let Err(e) = Overrides::default().save(system)
+    {
+        warn!("Unable to record overrides: {:#}", e);
+    }
+    // Record the configuration the operation left the system in, so that it can be switched
+    // back to.
+    if command != "install"
+        && let Err(e) = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(anyhow::Error::from)
+            .and_then(|now| {
+                record_generation(system, &all_experiments(system), &command, now.as_secs())
+            })
+    {
+        warn!("Unable to record the generation: {:#}", e);
+    }
+    // Record the switch in the history of /etc, when it is kept.
+    etckeeper::commit_after(system, &command, &names, &steps);
 
-    // Get selected experiments from the command line arguments
-    let selected = selected_experiments(args.all, args.experiments.clone(), &system);
+    // Only a successful operation prints its summary in a structured output format, as a failed
+    // one prints its error report instead. Streamed progress carries the summary either way.
+    let summary = summary.report(system, &steps);
+    progress.emit(&ProgressEvent::Finished {
+        succeeded: result.is_ok(),
+        summary: &summary,
+    });
+    match format {
+        _ if progress.enabled() => (),
+        OutputFormat::Human => print!("{summary}"),
+        format if result.is_ok() => print!("{}", structured(format, &summary)?),
+        _ => (),
+    }
+    result.map(|()| summary)
+}
 
-    // Handle subcommands
-    match args.cmd {
-        Commands::Enable => enable(&system, selected, args.yes, args.no_compatibility_check),
-        Commands::Disable => disable(selected, args.yes),
+/// Carry out an enable or disable operation on a `DryRunWorker`, printing the changes it would
+/// make to the system rather than making them, in human output without streamed progress, so
+/// that the events streamed stay the only output. Nothing is prompted for, and the smoke tests of
+/// the replacements are skipped, as they aren't in place.
+fn dry_run(
+    config: &Config,
+    worker: &DryRunWorker,
+    command: &str,
+    names: &[String],
+    no_compatibility_check: bool,
+    options: &EnableOptions,
+    print_changes: bool,
+) -> Result<()> {
+    let selected: Vec<Experiment> = all_experiments(worker)
+        .into_iter()
+        .filter(|e| names.contains(&e.name()))
+        .collect();
+    // Neither is reported, as nothing was done.
+    let mut telemetry = Telemetry::new(&config.telemetry, command);
+    let mut summary = Summary::new(command);
+
+    let options = EnableOptions {
+        interactive: false,
+        ..command_options(command, options)
+    };
+    let result = match command {
+        "enable" | "install" | "activate" => {
+            record_configuration(worker, config, names).and_then(|_| {
+                enable(
+                    worker,
+                    selected,
+                    no_compatibility_check,
+                    &options,
+                    config,
+                    &mut telemetry,
+                    &mut summary,
+                )
+            })
+        }
+        _ => disable(
+            worker,
+            selected,
+            &config.hooks,
+            options.fail_fast,
+            &mut telemetry,
+            &mut summary,
+        ),
+    };
+
+    if print_changes {
+        println!("\nDry run: '{command}' would make these changes, and has made none:");
+        for change in worker.changes() {
+            println!("  {change}");
+        }
     }
+    result
 }
 
-/// Enables selected experiments
+/// The options of an operation, which for an install without activation, and the activation that
+/// follows it, depend on the operation rather than the command line, so that they are the same
+/// when an interrupted operation is resumed.
+fn command_options(command: &str, options: &EnableOptions) -> EnableOptions {
+    EnableOptions {
+        no_activate: command == "install",
+        preinstalled: command == "activate",
+        ..options.clone()
+    }
+}
+
+/// The experiments of an operation that succeeded: all of them if the operation did, or those
+/// that did if it failed for only some of them. Otherwise, `None`.
+fn succeeded<T>(result: &Result<T>, names: Vec<String>) -> Option<Vec<String>> {
+    match result {
+        Ok(_) => Some(names),
+        Err(e) => {
+            let failures = e.downcast_ref::<ExperimentFailures>()?;
+            let unsuccessful = failures.unsuccessful();
+            Some(
+                names
+                    .into_iter()
+                    .filter(|n| !unsuccessful.contains(n))
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// The binaries that the configuration pins to GNU for each of a set of experiments.
+fn configured_pins(config: &Config, experiments: &[Experiment]) -> Pins {
+    let mut pins = Pins::default();
+    for e in experiments {
+        pins.set(&e.name(), config.pin_gnu(&e.name()));
+    }
+    pins
+}
+
+/// Record the configuration applied when enabling the named experiments: the binaries pinned to
+/// GNU, the directories of the files they replace, and the overrides if they are among them.
+fn record_configuration(system: &dyn Worker, config: &Config, names: &[String]) -> Result<()> {
+    record_pins(system, config, names)?;
+    record_directories(system, config, names)?;
+    match names.iter().any(|n| n == "overrides") {
+        true => record_overrides(system, &config.overrides),
+        false => Ok(()),
+    }
+}
+
+/// Record the binaries that the configuration pins to GNU for each of the named experiments
+/// before they are enabled, so that every later operation leaves the same binaries in place.
+fn record_pins(system: &dyn Worker, config: &Config, names: &[String]) -> Result<()> {
+    update_state(system, |pins: &mut Pins| {
+        for name in names {
+            pins.set(name, config.pin_gnu(name));
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Record the directories that the configuration sets for the files each of the named experiments
+/// replaces before they are enabled, so that every later operation manages the same files.
+fn record_directories(system: &dyn Worker, config: &Config, names: &[String]) -> Result<()> {
+    update_state(system, |directories: &mut Directories| {
+        for name in names {
+            directories.set(name, config.directories(name));
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Ensure that the program is able to make changes to the system: it must be running as root,
+/// and on a supported distribution (unless the compatibility check is skipped).
+fn ensure_can_mutate(system: &dyn Worker, no_compatibility_check: bool) -> Result<()> {
+    // The application must run as root - exit immediately if it's not.
+    if uzers::get_current_uid() != 0 {
+        return Err(ErrorCode::NotRoot.error("This program must be run as root"));
+    }
+    clean_stale_scratch(&system.scratch_root());
+
+    // Exit if the application is run on a non-Ubuntu machine (unless compatibility check is skipped).
+    let distribution = system.distribution()?;
+    if let Some(derivative) = &distribution.derivative {
+        info!(
+            "Running on {}, a derivative of {}",
+            derivative,
+            distribution.base()
+        );
+    }
+    if !no_compatibility_check {
+        if distribution.id != "Ubuntu" {
+            let supported = "This program only supports Ubuntu and its derivatives";
+            return Err(
+                ErrorCode::UnsupportedDistribution.error(match &distribution.derivative {
+                    Some(d) => format!("{} is based on {}. {supported}", d.name, distribution.id),
+                    None => supported.to_string(),
+                }),
+            );
+        }
+    } else if distribution.id != "Ubuntu" {
+        warn_or_fail(
+            system.strict(),
+            "Running on a non-Ubuntu distribution. This is unsupported and may cause system instability.",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Enables selected experiments, installing their packages in a single transaction.
 fn enable(
-    system: &impl Worker,
+    system: &dyn Worker,
     experiments: Vec<Experiment>,
-    yes: bool,
     no_compatibility_check: bool,
+    options: &EnableOptions,
+    config: &Config,
+    telemetry: &mut Telemetry,
+    summary: &mut Summary,
 ) -> Result<()> {
-    confirm_or_exit(yes);
+    summary.phase("checks");
+    let mut selected = Vec::new();
+    for e in experiments {
+        if e.should_enable(no_compatibility_check, system.strict())? {
+            selected.push(e);
+        }
+    }
+
+    check_authentication(system, &selected)?;
+    check_sudoers(system, &selected)?;
+    check_sed_scripts(system, &selected)?;
+    check_locale(system, &selected)?;
+    check_space(system, &selected)?;
+    if !options.preinstalled {
+        check_repositories(system, &selected)?;
+    }
+
+    // Make sure a broken replacement can't leave the system without working shell utilities.
+    let install_rescue = requires_rescue_toolset(&selected) && !rescue_toolset_installed(system);
+    if install_rescue && !(options.interactive && confirm_install_rescue()) {
+        return Err(ErrorCode::RescueToolsetMissing.error(format!(
+            "enabling coreutils requires a statically linked rescue toolset, such as {RESCUE_PACKAGE}"
+        )));
+    }
 
-    info!("Updating apt package cache");
-    system.update_package_lists()?;
+    // Experiments installed beforehand are activated without going to the network.
+    let selected = match options.preinstalled {
+        true => selected,
+        false => {
+            summary.phase("package lists");
+            info!("Updating apt package cache");
+            system.update_package_lists()?;
+            available_experiments(system, selected)?
+        }
+    };
 
+    if install_rescue {
+        info!("Installing {}", RESCUE_PACKAGE);
+        system.install_package(RESCUE_PACKAGE)?;
+    }
+
+    if options.no_activate {
+        summary.phase("install");
+        let mut record = |name: &str, ok| {
+            telemetry.record(name, ok);
+            summary.record(name, ok);
+        };
+        install_all(system, &selected, &mut record)?;
+        stage(
+            system,
+            &selected,
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        )?;
+        selected.iter().for_each(|e| record(&e.name(), true));
+        return Ok(());
+    }
+
+    summary.phase("pre-enable hooks");
+    run_hooks(system, &config.hooks, Stage::PreEnable, &selected)?;
+    summary.phase("enable");
+    let mut skipped = Vec::new();
+    let staged = enable_staged(
+        system,
+        &selected,
+        options,
+        |name, ok| {
+            telemetry.record(name, ok);
+            summary.record(name, ok);
+        },
+        &mut skipped,
+    );
+    summary.skip(skipped);
+    let names: Vec<String> = selected.iter().map(|e| e.name()).collect();
+    let Some(names) = succeeded(&staged, names) else {
+        return staged;
+    };
+    let selected: Vec<Experiment> = selected
+        .into_iter()
+        .filter(|e| names.contains(&e.name()))
+        .collect();
+    summary.phase("post-enable hooks");
+    run_hooks(system, &config.hooks, Stage::PostEnable, &selected)?;
+    if options.restart_services {
+        summary.phase("service restarts");
+        restart_services(system, &selected, &config.restart)?;
+    }
+
+    let services: Vec<&str> = selected
+        .iter()
+        .flat_map(|e| e.authentication_services())
+        .collect();
+    if options.interactive && !services.is_empty() && !confirm_authentication(&services) {
+        return Err(ErrorCode::AuthenticationUnverified.error(format!(
+            "authentication with {} was not confirmed to work",
+            services.join(", ")
+        )));
+    }
+    staged
+}
+
+// Disable selected experiments, removing their packages in a single transaction.
+fn disable(
+    system: &dyn Worker,
+    experiments: Vec<Experiment<'_>>,
+    hooks: &BTreeMap<String, ExperimentHooks>,
+    fail_fast: bool,
+    telemetry: &mut Telemetry,
+    summary: &mut Summary,
+) -> Result<()> {
+    summary.phase("checks");
+    let mut selected = Vec::new();
+    for e in experiments {
+        if e.should_disable(system.strict())? {
+            selected.push(e);
+        }
+    }
+
+    summary.phase("pre-disable hooks");
+    run_hooks(system, hooks, Stage::PreDisable, &selected)?;
+    summary.phase("disable");
+    let staged = disable_staged(system, &selected, fail_fast, |name, ok| {
+        telemetry.record(name, ok);
+        summary.record(name, ok);
+    });
+    let names: Vec<String> = selected.iter().map(|e| e.name()).collect();
+    let Some(names) = succeeded(&staged, names) else {
+        return staged;
+    };
+    let selected: Vec<Experiment> = selected
+        .into_iter()
+        .filter(|e| names.contains(&e.name()))
+        .collect();
+    summary.phase("post-disable hooks");
+    run_hooks(system, hooks, Stage::PostDisable, &selected)?;
+    staged
+}
+
+// Reapply enabled experiments whose files have been overwritten, such as by a package upgrade.
+// Binaries quarantined since the experiments were enabled are restored.
+fn reapply(
+    experiments: Vec<Experiment<'_>>,
+    telemetry: &mut Telemetry,
+    summary: &mut Summary,
+) -> Result<()> {
+    summary.phase("reapply");
     for e in experiments.iter() {
-        e.enable(no_compatibility_check)?;
+        info!("Reapplying '{}'", e.name());
+        let result = e.restore_pinned().and_then(|_| e.enable());
+        telemetry.record(&e.name(), result.is_ok());
+        summary.record(&e.name(), result.is_ok());
+        result?;
     }
     Ok(())
 }
 
-// Disable selected experiments
-fn disable(experiments: Vec<Experiment<'_>>, yes: bool) -> Result<()> {
-    confirm_or_exit(yes);
-    for e in experiments.iter() {
-        e.disable()?;
+/// Switch to the experiments of a generation, whose state has been restored: those enabled that
+/// it doesn't include are disabled, those it includes that are enabled are re-applied, so that
+/// they follow its pins and strategies, and the rest are enabled. The first failure stops it.
+fn switch(
+    system: &dyn Worker,
+    experiments: Vec<Experiment>,
+    no_compatibility_check: bool,
+    options: &EnableOptions,
+    config: &Config,
+    telemetry: &mut Telemetry,
+    summary: &mut Summary,
+) -> Result<()> {
+    let names: Vec<String> = experiments.iter().map(|e| e.name()).collect();
+    let stale: Vec<Experiment> = all_experiments(system)
+        .into_iter()
+        .filter(|e| !names.contains(&e.name()) && e.status().enabled)
+        .collect();
+    if !stale.is_empty() {
+        disable(system, stale, &config.hooks, true, telemetry, summary)?;
+    }
+
+    let (enabled, disabled): (Vec<Experiment>, Vec<Experiment>) =
+        experiments.into_iter().partition(|e| e.status().enabled);
+    reapply(enabled, telemetry, summary)?;
+    if !disabled.is_empty() {
+        let options = EnableOptions {
+            fail_fast: true,
+            ..options.clone()
+        };
+        enable(
+            system,
+            disabled,
+            no_compatibility_check,
+            &options,
+            config,
+            telemetry,
+            summary,
+        )?;
     }
     Ok(())
 }
 
+/// Switch the system back to the configuration of a generation, as a single journaled operation
+/// that is rolled back whole should any of it fail, restoring the state from before the switch.
+fn switch_generation(config: &Config, system: &System, args: &Args, number: u32) -> Result<()> {
+    let generations = Generations::load(system)?;
+    let target = generations.get(number)?;
+    let current = Generation::capture(system, &all_experiments(system))?;
+    let changes = target.diff(Some(&current));
+    if changes.is_empty() && target.state == current.state {
+        println!("The system is already in the configuration of generation {number}.");
+        return Ok(());
+    }
+
+    match args.format {
+        OutputFormat::Human => {
+            println!(
+                "oxidizr will switch to generation {number}, recorded at {} after '{}':",
+                calendar_time(target.created),
+                target.command
+            );
+            for e in &changes.enabled {
+                println!("  + {e}");
+            }
+            for e in &changes.disabled {
+                println!("  - {e}");
+            }
+            println!(
+                "  {} file(s) linked, {} file(s) restored to GNU",
+                changes.linked.len(),
+                changes.unlinked.len()
+            );
+        }
+        format => print!("{}", structured(format, &changes)?),
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    confirm_or_exit(args.yes);
+
+    target.restore_state(system)?;
+    let journal = Journal::begin(
+        Path::new(JOURNAL_PATH),
+        "switch",
+        target.experiments.clone(),
+    )?;
+    let result = run_journaled(
+        config,
+        system,
+        journal,
+        args.no_compatibility_check,
+        &EnableOptions::default(),
+        args.format,
+        args.timeout,
+    );
+    if let Err(e) = result {
+        current.restore_state(system)?;
+        return Err(e);
+    }
+    info!("Switched to the configuration of generation {number}");
+    Ok(())
+}
+
 /// Get selected experiments from the command line arguments.
-fn selected_experiments(
+/// With `--all`, overrides are only included once some are configured or enabled.
+fn selected_experiments<'a>(
     all: bool,
     selected: Vec<String>,
-    system: &impl Worker,
-) -> Vec<Experiment<'_>> {
+    system: &'a dyn Worker,
+    config: &Config,
+) -> Vec<Experiment<'a>> {
     let all_experiments = all_experiments(system);
     let default_experiments = default_experiments();
 
@@ -181,6 +1753,12 @@ fn selected_experiments(
             }
 
             all_experiments
+                .into_iter()
+                .filter(|e| match e {
+                    Experiment::Overrides(o) => !config.overrides.is_empty() || o.check_installed(),
+                    _ => true,
+                })
+                .collect()
         }
         false => {
             // If no experiments are selected, default to coreutils and sudo-rs
@@ -198,6 +1776,38 @@ fn selected_experiments(
     }
 }
 
+/// The arguments with which provisioning snippets run oxidizr to enable the experiments selected
+/// by this invocation, with the flags that affect how they are enabled pinned.
+fn pinned_enable_args(args: &Args, system: &dyn Worker, config: &Config) -> Vec<String> {
+    let mut enable_args = vec!["enable".to_string(), "--yes".to_string()];
+    if args.no_compatibility_check {
+        enable_args.push("--no-compatibility-check".to_string());
+    }
+    if args.strict {
+        enable_args.push("--strict".to_string());
+    }
+    if args.allow_experimental {
+        enable_args.push("--allow-experimental".to_string());
+    }
+    enable_args.push("--experiments".to_string());
+    enable_args.extend(
+        selected_experiments(args.all, args.experiments.clone(), system, config)
+            .iter()
+            .map(|e| e.name()),
+    );
+    enable_args
+}
+
+/// Print the plan for an operation before the user is asked to confirm it.
+fn print_plan(plan: &Plan, format: OutputFormat, progress: Progress) -> Result<()> {
+    match format {
+        _ if progress.enabled() => progress.emit(&ProgressEvent::Plan { plan }),
+        OutputFormat::Human => print!("{plan}"),
+        format => print!("{}", structured(format, plan)?),
+    }
+    Ok(())
+}
+
 /// Display a confirmation prompt to the user asking whether they'd like to continue.
 /// If they select no, or there is an error - exit the program.
 /// If `--yes` was supplied on the command line, skip the check and return.
@@ -220,6 +1830,61 @@ fn confirm_or_exit(yes: bool) {
     }
 }
 
+/// Ask the user to confirm that they want to go ahead despite known issues affecting the planned
+/// experiments, exiting if they don't. With `--yes`, the issues are only warned about - or fail
+/// the operation in strict mode.
+fn confirm_known_issues(plan: &Plan, yes: bool, strict: bool) -> Result<()> {
+    if !plan.has_known_issues() {
+        return Ok(());
+    }
+    if yes {
+        return warn_or_fail(
+            strict,
+            "Continuing despite known issues in the replacement packages",
+        );
+    }
+
+    let proceed =
+        Confirm::new("Known issues affect the replacements listed above. Continue anyway?")
+            .with_default(false)
+            .prompt()
+            .unwrap_or(false);
+    if !proceed {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Ask the user to confirm, from a separate session, that the replaced authentication binaries
+/// still work. The current root shell stays open in the meantime, so that the changes can still be
+/// rolled back if they don't.
+fn confirm_authentication(services: &[&str]) -> bool {
+    println!(
+        "Keep this root shell open. In another terminal, check that you can still authenticate \
+        with each of: {}.",
+        services.join(", ")
+    );
+
+    Confirm::new("Does authentication still work?")
+        .with_default(false)
+        .with_help_message("Answering no rolls back the changes made by oxidizr.")
+        .prompt()
+        .unwrap_or(false)
+}
+
+/// Ask the user whether to install a rescue toolset before enabling the experiments.
+fn confirm_install_rescue() -> bool {
+    Confirm::new(&format!(
+        "No statically linked rescue toolset was found. Install {RESCUE_PACKAGE} first?"
+    ))
+    .with_default(true)
+    .with_help_message(
+        "Statically linked utilities keep working even if the replacement coreutils don't.",
+    )
+    .prompt()
+    .unwrap_or(false)
+}
+
 // Default experiments to enable if none are specified
 fn default_experiments() -> Vec<String> {
     let mut defaults = vec!["coreutils".to_string(), "sudo-rs".to_string()];
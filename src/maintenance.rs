@@ -0,0 +1,110 @@
+use crate::config::MaintenanceConfig;
+use crate::utils::{FILE_MODE, create_dirs, write_atomic};
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+/// The message of the day fragment shown to users logging in while an operation is underway.
+pub const MOTD_NOTICE_PATH: &str = "/run/motd.d/oxidizr";
+
+/// The fragment of the pre-login message on consoles shown while an operation is underway.
+pub const ISSUE_NOTICE_PATH: &str = "/run/issue.d/oxidizr.issue";
+
+/// Notices that an operation replacing core utilities is underway on the host, for the humans
+/// logging in and the other automation running on it, which are removed once the operation ends.
+/// They are written under `/run`, so that notices left behind by an operation that was killed
+/// don't survive a reboot.
+///
+/// Failures to write or remove the notices are logged rather than returned: they are a courtesy,
+/// and must not fail an otherwise successful operation.
+pub struct Maintenance {
+    written: Vec<PathBuf>,
+}
+
+impl Maintenance {
+    /// Put up the notices enabled in the configuration. They are taken down when the returned
+    /// value is dropped, including when the operation fails.
+    pub fn begin(config: &MaintenanceConfig, command: &str, experiments: &[String]) -> Self {
+        let experiments = experiments.join(", ");
+        let mut notices = Vec::new();
+        if config.notice {
+            let notice = format!(
+                "oxidizr is running '{command}' for {experiments}: core utilities such as \
+                coreutils and sudo are being replaced, and may briefly behave unexpectedly.\n"
+            );
+            notices.push((PathBuf::from(MOTD_NOTICE_PATH), notice.clone()));
+            notices.push((PathBuf::from(ISSUE_NOTICE_PATH), notice));
+        }
+        if let Some(flag) = &config.flag {
+            let started = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let contents = format!(
+                "command={command}\nexperiments={experiments}\npid={}\nstarted={started}\n",
+                std::process::id()
+            );
+            notices.push((flag.clone(), contents));
+        }
+
+        let mut written = Vec::new();
+        for (path, contents) in notices {
+            match write(&path, &contents) {
+                Ok(()) => written.push(path),
+                Err(e) => warn!(
+                    "Unable to write maintenance notice {}: {e:#}",
+                    path.display()
+                ),
+            }
+        }
+        Self { written }
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        for path in &self.written {
+            if let Err(e) = fs::remove_file(path) {
+                warn!(
+                    "Unable to remove maintenance notice {}: {e}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+/// Write a notice, readable by everyone whatever the umask, creating its directory if needed.
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dirs(parent)?;
+    }
+    write_atomic(path, contents.as_bytes(), FILE_MODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_flag() {
+        let flag = std::env::temp_dir()
+            .join(format!("oxidizr-maintenance-{}", std::process::id()))
+            .join("flag");
+        let config = MaintenanceConfig {
+            notice: false,
+            flag: Some(flag.clone()),
+        };
+
+        let maintenance = Maintenance::begin(&config, "enable", &["coreutils".to_string()]);
+        let contents = fs::read_to_string(&flag).unwrap();
+        assert!(contents.starts_with("command=enable\nexperiments=coreutils\n"));
+
+        drop(maintenance);
+        assert!(!flag.exists());
+        fs::remove_dir(flag.parent().unwrap()).unwrap();
+    }
+}
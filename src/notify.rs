@@ -0,0 +1,359 @@
+//! Notifications of what oxidizr did while nobody was watching, such as re-applying experiments
+//! after a package upgrade reverted them, so that the people using the host notice.
+
+use crate::config::{EmailConfig, NotificationsConfig};
+use crate::utils::{Command, Worker};
+use serde::Serialize;
+use std::{fmt, path::Path};
+use tracing::{debug, warn};
+
+/// The session types of a graphical desktop, as reported by logind.
+const DESKTOP_SESSION_TYPES: [&str; 3] = ["x11", "wayland", "mir"];
+
+/// The program emails are handed to unless another is configured.
+const DEFAULT_SENDMAIL: &str = "/usr/sbin/sendmail";
+
+/// The file holding the hostname of the host, which the subjects of emails name.
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+
+/// The boundary between the parts of an email. Neither the text of a notification nor a JSON
+/// report has lines starting with `--=`.
+const BOUNDARY: &str = "=_oxidizr_report";
+
+/// How urgently a notification needs attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal,
+    Critical,
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Urgency::Normal => write!(f, "normal"),
+            Urgency::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// A notification of something that happened on the host, with a one-line summary, a body
+/// giving the detail, and the structured report of the operation, for the channels that can
+/// carry it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub urgency: Urgency,
+    pub summary: String,
+    pub body: String,
+    pub report: Option<Attachment>,
+}
+
+/// A structured report attached to a notification as JSON, such as the summary of an operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// The name of the file it is attached as, such as `reapply.json`.
+    pub name: String,
+    pub json: String,
+}
+
+impl Notification {
+    pub fn new(urgency: Urgency, summary: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            urgency,
+            summary: summary.into(),
+            body: body.into(),
+            report: None,
+        }
+    }
+
+    /// Attach a structured report, as it is printed with `--format json`.
+    pub fn with_report(mut self, name: &str, report: &impl Serialize) -> Self {
+        match serde_json::to_string_pretty(report) {
+            Ok(json) => {
+                self.report = Some(Attachment {
+                    name: name.to_string(),
+                    json,
+                })
+            }
+            Err(e) => debug!("Unable to attach {name} to a notification: {e}"),
+        }
+        self
+    }
+}
+
+/// A user logged in to a graphical desktop session.
+#[derive(Debug, Clone, PartialEq)]
+struct DesktopUser {
+    uid: u32,
+    name: String,
+}
+
+/// Send a notification through each of the channels enabled in the configuration. Failing to
+/// notify is logged rather than returned, so it never fails the operation being reported on.
+pub fn notify(system: &dyn Worker, config: &NotificationsConfig, notification: &Notification) {
+    if config.desktop {
+        notify_desktop(system, notification);
+    }
+    if !config.email.to.is_empty() {
+        notify_email(system, &config.email, notification);
+    }
+}
+
+/// Email a notification to the configured recipients, through sendmail or a program compatible
+/// with it, such as that of msmtp relaying to an SMTP server. Any report is attached as JSON.
+fn notify_email(system: &dyn Worker, config: &EmailConfig, notification: &Notification) {
+    let hostname = system
+        .read_file(Path::new(HOSTNAME_PATH))
+        .ok()
+        .flatten()
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|| "localhost".to_string());
+    let message = email_message(config, notification, &hostname);
+
+    let sendmail = config
+        .sendmail
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_SENDMAIL));
+    // Recipients are read from the message, and a line holding a single dot doesn't end it.
+    let command = Command::build(sendmail, &["-oi", "-t"]).input(message);
+    match system.run(&command) {
+        Ok(_) => debug!(
+            "Emailed '{}' to {}",
+            notification.summary,
+            config.to.join(", ")
+        ),
+        Err(e) => warn!("Unable to email '{}': {e:#}", notification.summary),
+    }
+}
+
+/// Compose the email for a notification, as a MIME message with the report attached.
+fn email_message(config: &EmailConfig, notification: &Notification, hostname: &str) -> String {
+    let mut message = String::new();
+    if let Some(from) = &config.from {
+        message.push_str(&format!("From: {from}\r\n"));
+    }
+    message.push_str(&format!("To: {}\r\n", config.to.join(", ")));
+    message.push_str(&format!(
+        "Subject: [oxidizr] {}: {}\r\n",
+        hostname, notification.summary
+    ));
+    if notification.urgency == Urgency::Critical {
+        message.push_str("Importance: high\r\n");
+    }
+    message.push_str("MIME-Version: 1.0\r\n");
+
+    let text = format!(
+        "Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+        notification.body.replace('\n', "\r\n")
+    );
+    let Some(report) = &notification.report else {
+        message.push_str(&text);
+        return message;
+    };
+    message.push_str(&format!(
+        "Content-Type: multipart/mixed; boundary=\"{BOUNDARY}\"\r\n\r\n"
+    ));
+    message.push_str(&format!("--{BOUNDARY}\r\n{text}"));
+    message.push_str(&format!(
+        "--{BOUNDARY}\r\nContent-Type: application/json; charset=utf-8\r\n\
+        Content-Transfer-Encoding: 8bit\r\n\
+        Content-Disposition: attachment; filename=\"{}\"\r\n\r\n{}\r\n",
+        report.name,
+        report.json.replace('\n', "\r\n")
+    ));
+    message.push_str(&format!("--{BOUNDARY}--\r\n"));
+    message
+}
+
+/// Show a notification on the desktop of every user logged in to a graphical session, through
+/// `notify-send`. Run by root, such as from a dpkg trigger or a timer, it is sent to each of
+/// their session buses as them; run by a user, such as from a timer of their own, it is sent to
+/// their own session.
+fn notify_desktop(system: &dyn Worker, notification: &Notification) {
+    let urgency = format!("--urgency={}", notification.urgency);
+    let notify_send = [
+        "notify-send",
+        "--app-name=oxidizr",
+        &urgency,
+        &notification.summary,
+        &notification.body,
+    ];
+
+    if uzers::get_current_uid() != 0 {
+        if let Err(e) = system.run(&Command::build(notify_send[0], &notify_send[1..])) {
+            debug!("Unable to show a desktop notification: {e:#}");
+        }
+        return;
+    }
+
+    for user in desktop_users(system) {
+        let bus = format!(
+            "DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/{}/bus",
+            user.uid
+        );
+        let mut args = vec!["-u", &user.name, "--", "env", &bus];
+        args.extend(notify_send);
+        if let Err(e) = system.run(&Command::build("runuser", &args)) {
+            debug!(
+                "Unable to show a desktop notification to {}: {e:#}",
+                user.name
+            );
+        }
+    }
+}
+
+/// The users logged in to a graphical session, rather than a terminal, according to logind, each
+/// listed once however many sessions they have.
+fn desktop_users(system: &dyn Worker) -> Vec<DesktopUser> {
+    let sessions = match system.run(&Command::build(
+        "loginctl",
+        &["list-sessions", "--no-legend"],
+    )) {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => {
+            debug!("Unable to list login sessions: {e:#}");
+            return Vec::new();
+        }
+    };
+
+    let mut users: Vec<DesktopUser> = Vec::new();
+    // Each line starts with the session id, the uid and the name of the user.
+    for line in sessions.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(session), Some(uid), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(uid) = uid.parse() else {
+            continue;
+        };
+        if users.iter().any(|u| u.uid == uid) {
+            continue;
+        }
+
+        // Greeters, such as that of gdm, run in graphical sessions of their own, but aren't users.
+        let Ok(properties) = system.run(&Command::build(
+            "loginctl",
+            &[
+                "show-session",
+                session,
+                "--property=Type",
+                "--property=Class",
+            ],
+        )) else {
+            continue;
+        };
+        let properties = String::from_utf8_lossy(&properties.stdout);
+        let property = |key: &str| {
+            properties
+                .lines()
+                .find_map(|l| l.strip_prefix(key)?.strip_prefix('='))
+                .unwrap_or_default()
+        };
+        if DESKTOP_SESSION_TYPES.contains(&property("Type")) && property("Class") == "user" {
+            users.push(DesktopUser {
+                uid,
+                name: name.to_string(),
+            });
+        }
+    }
+    users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_notify_desktop() {
+        let runner = MockSystem::default();
+        runner.mock_command(
+            "loginctl list-sessions --no-legend",
+            "  2 1000 alice seat0 tty2\n  5 1000 alice -     pts/1\nc1  120 gdm   seat0 tty1\n 7 1001 bob   -     pts/2\n",
+        );
+        let show = |session: &str| {
+            format!("loginctl show-session {session} --property=Type --property=Class")
+        };
+        runner.mock_command(&show("2"), "Type=wayland\nClass=user\n");
+        runner.mock_command(&show("c1"), "Type=x11\nClass=greeter\n");
+        runner.mock_command(&show("7"), "Type=tty\nClass=user\n");
+
+        let users = desktop_users(&runner);
+        assert_eq!(
+            users,
+            vec![DesktopUser {
+                uid: 1000,
+                name: "alice".to_string()
+            }]
+        );
+
+        // Nothing is sent unless desktop notifications are enabled.
+        let notification = Notification::new(Urgency::Normal, "Re-applied coreutils", "2 file(s)");
+        runner.commands.borrow_mut().clear();
+        notify(&runner, &NotificationsConfig::default(), &notification);
+        assert!(runner.commands.borrow().is_empty());
+
+        notify(
+            &runner,
+            &NotificationsConfig {
+                desktop: true,
+                ..Default::default()
+            },
+            &notification,
+        );
+        let commands = runner.commands.borrow();
+        assert!(
+            commands.contains(
+                &"runuser -u alice -- env DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/1000/bus \
+            notify-send --app-name=oxidizr --urgency=normal Re-applied coreutils 2 file(s)"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_notify_email() {
+        let runner = MockSystem::default();
+        let config = NotificationsConfig {
+            email: EmailConfig {
+                to: vec!["ops@example.com".to_string(), "root".to_string()],
+                from: Some("oxidizr@web-1.example.com".to_string()),
+                sendmail: None,
+            },
+            ..Default::default()
+        };
+        let notification = Notification::new(
+            Urgency::Critical,
+            "Unable to re-apply coreutils",
+            "A package upgrade restored 2 file(s).\nSee the report.",
+        )
+        .with_report("reapply.json", &serde_json::json!({ "command": "reapply" }));
+
+        let message = email_message(&config.email, &notification, "web-1");
+        assert!(message.starts_with(
+            "From: oxidizr@web-1.example.com\r\nTo: ops@example.com, root\r\n\
+            Subject: [oxidizr] web-1: Unable to re-apply coreutils\r\nImportance: high\r\n"
+        ));
+        assert!(
+            message
+                .contains("\r\n\r\nA package upgrade restored 2 file(s).\r\nSee the report.\r\n")
+        );
+        assert!(message.contains(
+            "Content-Disposition: attachment; filename=\"reapply.json\"\r\n\r\n\
+            {\r\n  \"command\": \"reapply\"\r\n}\r\n"
+        ));
+        assert!(message.ends_with(&format!("--{BOUNDARY}--\r\n")));
+
+        // Without a report, the message is the text alone.
+        let plain = Notification::new(Urgency::Normal, "Re-applied sed", "1 file(s)");
+        let message = email_message(&config.email, &plain, "web-1");
+        assert!(!message.contains("multipart"));
+        assert!(message.ends_with("\r\n\r\n1 file(s)\r\n"));
+
+        notify(&runner, &config, &notification);
+        assert_eq!(
+            runner.commands.borrow().as_slice(),
+            ["/usr/sbin/sendmail -oi -t"]
+        );
+    }
+}
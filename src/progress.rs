@@ -0,0 +1,118 @@
+//! Progress events streamed while an operation runs, so that wrappers such as GUIs and
+//! provisioning tools can follow it step by step without parsing the log.
+
+use crate::experiments::Plan;
+use crate::journal::Step;
+use crate::summary::RunSummary;
+use crate::utils::ErrorReport;
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::io::Write;
+
+/// The format in which progress events are streamed to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ProgressFormat {
+    /// One JSON object per line, each naming its kind in `event`.
+    JsonLines,
+}
+
+/// An event in the progress of an operation.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    /// The operation started, on the experiments given.
+    Started {
+        command: &'a str,
+        experiments: &'a [String],
+    },
+    /// The plan of the operation, printed before it is confirmed.
+    Plan { plan: &'a Plan },
+    /// A phase of the operation started, such as `install` or `post-enable hooks`.
+    Phase { phase: &'a str },
+    /// A change to the system is about to be made, once recorded in the journal.
+    Step { step: &'a Step },
+    /// The operation on an experiment ended.
+    Experiment {
+        experiment: &'a str,
+        succeeded: bool,
+    },
+    /// The operation failed, and the changes it made so far are being rolled back.
+    RollingBack,
+    /// The operation ended, for all experiments or some of them, with its summary.
+    Finished {
+        succeeded: bool,
+        summary: &'a RunSummary,
+    },
+    /// The operation failed, with the report of its error. Always the last event of a failure.
+    Failed { report: &'a ErrorReport },
+}
+
+/// Streams progress events to stdout, or does nothing if no progress format was requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Progress {
+    format: Option<ProgressFormat>,
+}
+
+impl Progress {
+    pub fn new(format: Option<ProgressFormat>) -> Self {
+        Self { format }
+    }
+
+    /// Report whether events are streamed, in which case stdout carries nothing else.
+    pub fn enabled(&self) -> bool {
+        self.format.is_some()
+    }
+
+    /// Stream an event, flushing it so that it is seen as soon as it happens. A wrapper that
+    /// stopped reading mustn't abort the operation part way through, so failures to write are
+    /// ignored.
+    pub fn emit(&self, event: &ProgressEvent) {
+        if let Some(format) = self.format {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout
+                .write_all(render(format, event).as_bytes())
+                .and_then(|_| stdout.flush());
+        }
+    }
+}
+
+/// Render an event in a progress format.
+fn render(format: ProgressFormat, event: &ProgressEvent) -> String {
+    match format {
+        ProgressFormat::JsonLines => match serde_json::to_string(event) {
+            Ok(line) => format!("{line}\n"),
+            Err(_) => String::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_json_lines() {
+        let step = Step::ReplaceWithSymlink {
+            source: PathBuf::from("/usr/lib/cargo/bin/coreutils/date"),
+            target: PathBuf::from("/usr/bin/date"),
+        };
+        assert_eq!(
+            render(
+                ProgressFormat::JsonLines,
+                &ProgressEvent::Step { step: &step }
+            ),
+            "{\"event\":\"step\",\"step\":{\"op\":\"replace_with_symlink\",\
+            \"source\":\"/usr/lib/cargo/bin/coreutils/date\",\"target\":\"/usr/bin/date\"}}\n"
+        );
+        assert_eq!(
+            render(
+                ProgressFormat::JsonLines,
+                &ProgressEvent::Phase { phase: "install" }
+            ),
+            "{\"event\":\"phase\",\"phase\":\"install\"}\n"
+        );
+        assert!(!Progress::default().enabled());
+    }
+}
@@ -0,0 +1,218 @@
+//! Restarting the systemd services that run replaced binaries once experiments are enabled, so
+//! that long-running daemons pick up the replacements straight away rather than whenever they
+//! next happen to restart.
+
+use crate::config::RestartConfig;
+use crate::experiments::Experiment;
+use crate::utils::{Command, Worker, Wsl, detect_wsl, warn_or_fail};
+use anyhow::Result;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// The properties of a service listing the commands it runs.
+const EXEC_PROPERTIES: &[&str] = &[
+    "ExecStartPre",
+    "ExecStart",
+    "ExecStartPost",
+    "ExecReload",
+    "ExecStop",
+    "ExecStopPost",
+];
+
+/// Restart the running services whose unit files invoke a binary replaced by the experiments,
+/// either by its path or, such as in a `sh -c` command line, by its name. Only the services on
+/// the include list are restarted if there is one, and never those on the exclude list. Failures
+/// to restart a service are warned about, or fail the operation in strict mode.
+pub fn restart_services(
+    system: &dyn Worker,
+    experiments: &[Experiment],
+    config: &RestartConfig,
+) -> Result<()> {
+    let replaced: BTreeSet<PathBuf> = experiments
+        .iter()
+        .flat_map(|e| e.replacements().unwrap_or_default())
+        .map(|(_, target)| merged_usr(&target))
+        .collect();
+    if replaced.is_empty() {
+        return Ok(());
+    }
+    if detect_wsl(system) == Some(Wsl::Wsl1) {
+        warn!("WSL 1 doesn't run systemd, so services can't be restarted");
+        return Ok(());
+    }
+
+    let units = system.run(&Command::build(
+        "systemctl",
+        &[
+            "list-units",
+            "--type=service",
+            "--state=running",
+            "--no-legend",
+            "--plain",
+        ],
+    ))?;
+    let units = parse_units(&String::from_utf8_lossy(&units.stdout));
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    let mut args = vec!["show".to_string()];
+    args.extend(
+        ["Id"]
+            .iter()
+            .chain(EXEC_PROPERTIES)
+            .map(|p| format!("--property={p}")),
+    );
+    args.extend(units);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let shown = system.run(&Command::build("systemctl", &args))?;
+
+    let restarts: Vec<String> = invoking_units(&String::from_utf8_lossy(&shown.stdout), &replaced)
+        .into_iter()
+        .filter(|unit| config.allows(unit))
+        .collect();
+    for unit in restarts {
+        info!("Restarting {unit}, which runs replaced binaries");
+        if let Err(e) = system.run(&Command::build("systemctl", &["try-restart", &unit])) {
+            warn_or_fail(system.strict(), format!("Unable to restart {unit}: {e:#}"))?;
+        }
+    }
+    Ok(())
+}
+
+impl RestartConfig {
+    /// Check whether a service may be restarted. Services can be listed with or without their
+    /// `.service` suffix.
+    pub fn allows(&self, unit: &str) -> bool {
+        let listed = |list: &[String]| {
+            list.iter()
+                .any(|l| l == unit || format!("{l}.service") == unit)
+        };
+        (self.include.is_empty() || listed(&self.include)) && !listed(&self.exclude)
+    }
+}
+
+/// Parse the unit names listed by `systemctl list-units --plain --no-legend`.
+fn parse_units(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|unit| unit.ends_with(".service"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Find the services whose commands, as shown by `systemctl show`, invoke a replaced binary.
+/// Each service is shown as a block of `Property=value` lines, separated by blank lines.
+fn invoking_units(output: &str, replaced: &BTreeSet<PathBuf>) -> Vec<String> {
+    let names: BTreeSet<&str> = replaced
+        .iter()
+        .filter_map(|r| r.file_name()?.to_str())
+        .collect();
+    let invokes = |word: &str| match word.contains('/') {
+        true => replaced.contains(&merged_usr(Path::new(word))),
+        false => names.contains(word),
+    };
+
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut id = None;
+            let mut invoked = false;
+            for line in block.lines() {
+                match line.split_once('=') {
+                    Some(("Id", value)) => id = Some(value.to_string()),
+                    Some((property, value)) if EXEC_PROPERTIES.contains(&property) => {
+                        invoked |= command_words(value).into_iter().any(invokes);
+                    }
+                    _ => (),
+                }
+            }
+            id.filter(|_| invoked)
+        })
+        .collect()
+}
+
+/// The path of a binary on a merged-usr system, on which `/bin` and `/sbin` are symlinks to
+/// their counterparts in `/usr`, so that the same binary is recognised by either path.
+fn merged_usr(path: &Path) -> PathBuf {
+    match path
+        .strip_prefix("/bin")
+        .or_else(|_| path.strip_prefix("/sbin"))
+    {
+        Ok(_) => Path::new("/usr").join(path.strip_prefix("/").unwrap_or(path)),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Split the commands of a service into the words of their command lines, including those of
+/// shell command lines such as `sh -c "sleep 60; dd if=/dev/zero"`. Each command is shown as
+/// `{ path=/bin/sh ; argv[]=/bin/sh -c ... ; ignore_errors=no ; ... }`.
+fn command_words(value: &str) -> Vec<&str> {
+    value
+        .split(" ; ")
+        .map(|field| field.trim_start_matches('{').trim())
+        .filter_map(|field| {
+            field
+                .strip_prefix("path=")
+                .or_else(|| field.strip_prefix("argv[]="))
+        })
+        .flat_map(|command| command.split(|c: char| c.is_whitespace() || ";|&()`\"'".contains(c)))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        let output = "cron.service loaded active running Regular background program processing daemon\n\
+            ssh.service loaded active running OpenBSD Secure Shell server\n\
+            user@1000.service loaded active running User Manager for UID 1000\n";
+        assert_eq!(
+            parse_units(output),
+            vec!["cron.service", "ssh.service", "user@1000.service"]
+        );
+    }
+
+    #[test]
+    fn test_invoking_units() {
+        let output = "Id=backup.service\n\
+            ExecStart={ path=/bin/sh ; argv[]=/bin/sh -c \"sleep 60; dd if=/dev/zero\" ; ignore_errors=no ; start_time=[n/a] ; stop_time=[n/a] ; pid=0 ; code=(null) ; status=0/0 }\n\
+            \n\
+            Id=ssh.service\n\
+            ExecStartPre={ path=/usr/sbin/sshd ; argv[]=/usr/sbin/sshd -t ; ignore_errors=no }\n\
+            ExecStart={ path=/usr/sbin/sshd ; argv[]=/usr/sbin/sshd -D ; ignore_errors=no }\n\
+            \n\
+            Id=reporter.service\n\
+            ExecStart={ path=/bin/date ; argv[]=/bin/date ; ignore_errors=no }\n";
+        let replaced =
+            BTreeSet::from([PathBuf::from("/usr/bin/dd"), PathBuf::from("/usr/bin/date")]);
+        assert_eq!(
+            invoking_units(output, &replaced),
+            vec!["backup.service", "reporter.service"]
+        );
+    }
+
+    #[test]
+    fn test_restart_config_allows() {
+        let config = RestartConfig {
+            include: Vec::new(),
+            exclude: vec!["ssh".to_string()],
+        };
+        assert!(config.allows("cron.service"));
+        assert!(!config.allows("ssh.service"));
+
+        let config = RestartConfig {
+            include: vec!["backup.service".to_string()],
+            exclude: Vec::new(),
+        };
+        assert!(config.allows("backup.service"));
+        assert!(!config.allows("cron.service"));
+    }
+}
@@ -0,0 +1,279 @@
+//! The summary of an operation printed once it ends, so that its outcome can be read at a glance
+//! rather than pieced together from the log.
+
+use crate::journal::Step;
+use crate::progress::{Progress, ProgressEvent};
+use crate::utils::{SkippedFile, Table, Worker, backup_filename};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{fmt, path::PathBuf, time::Instant};
+
+/// Collects the outcome of each experiment, the files left in place and the time taken by each
+/// phase of an operation while it runs.
+pub struct Summary {
+    command: String,
+    started: Instant,
+    phase: Option<(String, Instant)>,
+    phases: Vec<PhaseDuration>,
+    experiments: Vec<ExperimentOutcome>,
+    skipped: Vec<SkippedFile>,
+    progress: Progress,
+}
+
+/// The summary of an operation, printed as tables or in a structured output format.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct RunSummary {
+    pub command: String,
+    pub experiments: Vec<ExperimentOutcome>,
+    /// The files replaced by the operation and not restored by the end of it.
+    pub files_replaced: Vec<PathBuf>,
+    pub files_restored: Vec<PathBuf>,
+    pub files_skipped: Vec<SkippedFile>,
+    /// The number of replaced files backed up, which can be restored.
+    pub backups: usize,
+    pub packages_installed: Vec<String>,
+    pub packages_removed: Vec<String>,
+    pub phases: Vec<PhaseDuration>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ExperimentOutcome {
+    pub experiment: String,
+    pub succeeded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PhaseDuration {
+    pub phase: String,
+    pub duration_ms: u64,
+}
+
+impl Summary {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            started: Instant::now(),
+            phase: None,
+            phases: Vec::new(),
+            experiments: Vec::new(),
+            skipped: Vec::new(),
+            progress: Progress::default(),
+        }
+    }
+
+    /// Report each phase, and the outcome of each experiment, to `progress` as they happen.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Start timing a phase of the operation, ending the phase before it.
+    pub fn phase(&mut self, name: &str) {
+        self.end_phase();
+        self.progress.emit(&ProgressEvent::Phase { phase: name });
+        self.phase = Some((name.to_string(), Instant::now()));
+    }
+
+    fn end_phase(&mut self) {
+        if let Some((phase, started)) = self.phase.take() {
+            self.phases.push(PhaseDuration {
+                phase,
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+    }
+
+    /// Record the outcome of the operation on an experiment.
+    pub fn record(&mut self, experiment: &str, succeeded: bool) {
+        self.progress.emit(&ProgressEvent::Experiment {
+            experiment,
+            succeeded,
+        });
+        self.experiments.push(ExperimentOutcome {
+            experiment: experiment.to_string(),
+            succeeded,
+        });
+    }
+
+    /// Record the files that the operation left as they are.
+    pub fn skip(&mut self, files: Vec<SkippedFile>) {
+        self.skipped.extend(files);
+    }
+
+    /// Summarise the operation, from what was collected while it ran and the steps it recorded in
+    /// its journal. A file replaced and then restored, such as by an experiment whose changes
+    /// were undone, counts as neither.
+    pub fn report(mut self, system: &dyn Worker, steps: &[Step]) -> RunSummary {
+        self.end_phase();
+
+        let mut replaced = Vec::new();
+        let mut restored = Vec::new();
+        let mut installed = Vec::new();
+        let mut removed = Vec::new();
+        for step in steps {
+            match step {
+                Step::ReplaceWithSymlink { target, .. } | Step::ReplaceWithCopy { target, .. } => {
+                    if !replaced.contains(target) {
+                        replaced.push(target.clone());
+                    }
+                }
+                Step::RestoreFile { file } => match replaced.iter().position(|r| r == file) {
+                    Some(i) => {
+                        replaced.remove(i);
+                    }
+                    None => restored.push(file.clone()),
+                },
+                Step::InstallPackage { package } => installed.push(package.clone()),
+                Step::RemovePackage { package } => {
+                    match installed.iter().position(|i| i == package) {
+                        Some(i) => {
+                            installed.remove(i);
+                        }
+                        None => removed.push(package.clone()),
+                    }
+                }
+            }
+        }
+        // Files left as they were, such as existing symlinks, are recorded as steps all the same.
+        let untouched = |file: &PathBuf| !self.skipped.iter().any(|s| &s.file == file);
+        replaced.retain(untouched);
+        restored.retain(untouched);
+
+        let backups = replaced
+            .iter()
+            .filter(|file| {
+                backup_filename(file)
+                    .and_then(|backup| system.file_checksum(&backup))
+                    .is_ok_and(|c| c.is_some())
+            })
+            .count();
+
+        RunSummary {
+            command: self.command,
+            experiments: self.experiments,
+            files_replaced: replaced,
+            files_restored: restored,
+            files_skipped: self.skipped,
+            backups,
+            packages_installed: installed,
+            packages_removed: removed,
+            phases: self.phases,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Render a duration in milliseconds as seconds, such as `1.2s`.
+fn seconds(millis: u64) -> String {
+    format!("{:.1}s", millis as f64 / 1000.0)
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\nSummary of '{}':\n", self.command)?;
+
+        let mut experiments = Table::new(&["EXPERIMENT", "RESULT"]);
+        for outcome in &self.experiments {
+            let result = match outcome.succeeded {
+                true => "succeeded",
+                false => "failed",
+            };
+            experiments.add_row(vec![outcome.experiment.clone(), result.to_string()]);
+        }
+        writeln!(f, "{experiments}")?;
+
+        writeln!(
+            f,
+            "Files replaced: {}, restored: {}, skipped: {}; backups: {}",
+            self.files_replaced.len(),
+            self.files_restored.len(),
+            self.files_skipped.len(),
+            self.backups
+        )?;
+        let packages = |list: &[String]| match list.is_empty() {
+            true => "none".to_string(),
+            false => list.join(", "),
+        };
+        writeln!(
+            f,
+            "Packages installed: {}; removed: {}\n",
+            packages(&self.packages_installed),
+            packages(&self.packages_removed)
+        )?;
+
+        if !self.files_skipped.is_empty() {
+            let mut skipped = Table::new(&["SKIPPED FILE", "REASON"]);
+            for file in &self.files_skipped {
+                skipped.add_row(vec![
+                    file.file.display().to_string(),
+                    file.reason.to_string(),
+                ]);
+            }
+            writeln!(f, "{skipped}")?;
+        }
+
+        let mut phases = Table::new(&["PHASE", "DURATION"]);
+        for phase in &self.phases {
+            phases.add_row(vec![phase.phase.clone(), seconds(phase.duration_ms)]);
+        }
+        phases.add_row(vec!["total".to_string(), seconds(self.duration_ms)]);
+        write!(f, "{phases}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{MockSystem, SkipReason};
+    use std::path::Path;
+
+    #[test]
+    fn test_summary_report() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![("/usr/bin/.date.oxidizr.bak", "", false)]);
+        let mut summary = Summary::new("enable");
+        summary.phase("enable");
+        summary.record("coreutils", true);
+        summary.record("sed", false);
+        summary.skip(vec![SkippedFile {
+            file: PathBuf::from("/usr/bin/ls"),
+            reason: SkipReason::Pinned,
+        }]);
+
+        let symlink = |target: &str| Step::ReplaceWithSymlink {
+            source: PathBuf::from("/usr/bin/coreutils"),
+            target: PathBuf::from(target),
+        };
+        let steps = vec![
+            Step::InstallPackage {
+                package: "rust-coreutils".to_string(),
+            },
+            symlink("/usr/bin/date"),
+            Step::InstallPackage {
+                package: "rust-sed".to_string(),
+            },
+            symlink("/usr/bin/sed"),
+            // The changes made for sed were undone.
+            Step::RestoreFile {
+                file: PathBuf::from("/usr/bin/sed"),
+            },
+            Step::RemovePackage {
+                package: "rust-sed".to_string(),
+            },
+        ];
+
+        let report = summary.report(&runner, &steps);
+        assert_eq!(report.files_replaced, vec![Path::new("/usr/bin/date")]);
+        assert!(report.files_restored.is_empty());
+        assert_eq!(report.backups, 1);
+        assert_eq!(report.packages_installed, vec!["rust-coreutils"]);
+        assert!(report.packages_removed.is_empty());
+        assert_eq!(report.phases[0].phase, "enable");
+
+        let rendered = report.to_string();
+        assert!(rendered.contains("\nsed         failed\n"));
+        assert!(rendered.contains("Files replaced: 1, restored: 0, skipped: 1; backups: 1\n"));
+        assert!(rendered.contains("\n/usr/bin/ls   pinned to GNU\n"));
+    }
+}
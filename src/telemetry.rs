@@ -0,0 +1,129 @@
+use crate::config::TelemetryConfig;
+use crate::utils::{Distribution, Worker};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// The maximum time to wait for the telemetry endpoint to accept a report.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An anonymous usage report. It deliberately contains no host identifiers: only the
+/// distribution, the command that was run, the experiments involved, and their outcomes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TelemetryReport {
+    pub version: String,
+    pub distribution: Option<Distribution>,
+    pub command: String,
+    pub experiments: Vec<String>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Collects the outcome of each experiment during a run, and submits an anonymous report at the
+/// end if telemetry has been explicitly enabled in the configuration.
+pub struct Telemetry {
+    endpoint: Option<String>,
+    command: String,
+    experiments: Vec<String>,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl Telemetry {
+    /// Create a new `Telemetry` collector for a command. Reports are only submitted when
+    /// telemetry is enabled and an endpoint is configured.
+    pub fn new(config: &TelemetryConfig, command: &str) -> Self {
+        let endpoint = match config.enabled {
+            true => config.endpoint.clone(),
+            false => None,
+        };
+
+        Self {
+            endpoint,
+            command: command.to_string(),
+            experiments: Vec::new(),
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+
+    /// Record the outcome of an operation on an experiment.
+    pub fn record(&mut self, experiment: &str, success: bool) {
+        self.experiments.push(experiment.to_string());
+        match success {
+            true => self.succeeded += 1,
+            false => self.failed += 1,
+        }
+    }
+
+    /// Build the anonymous report for the run so far.
+    pub fn report(&self, system: &dyn Worker) -> TelemetryReport {
+        TelemetryReport {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            distribution: system.distribution().ok(),
+            command: self.command.clone(),
+            experiments: self.experiments.clone(),
+            succeeded: self.succeeded,
+            failed: self.failed,
+        }
+    }
+
+    /// Submit the report to the configured endpoint, if telemetry is enabled. Failing to submit
+    /// telemetry never causes oxidizr to fail.
+    pub fn submit(&self, system: &dyn Worker) {
+        let Some(endpoint) = &self.endpoint else {
+            return;
+        };
+
+        let report = self.report(system);
+        info!("Submitting anonymous usage telemetry to {}", endpoint);
+
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .build()
+            .new_agent();
+
+        if let Err(e) = agent.post(endpoint).send_json(&report) {
+            debug!("Failed to submit telemetry: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_telemetry_disabled_by_default() {
+        let config = TelemetryConfig {
+            enabled: false,
+            endpoint: Some("https://telemetry.example.com".to_string()),
+        };
+        let telemetry = Telemetry::new(&config, "enable");
+        assert!(telemetry.endpoint.is_none());
+    }
+
+    #[test]
+    fn test_telemetry_report() {
+        let runner = MockSystem::default();
+        let config = TelemetryConfig {
+            enabled: true,
+            endpoint: Some("https://telemetry.example.com".to_string()),
+        };
+
+        let mut telemetry = Telemetry::new(&config, "enable");
+        telemetry.record("coreutils", true);
+        telemetry.record("sudo-rs", false);
+
+        let report = telemetry.report(&runner);
+        assert_eq!(report.command, "enable");
+        assert_eq!(report.experiments, vec!["coreutils", "sudo-rs"]);
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(report.failed, 1);
+        assert_eq!(
+            report.distribution,
+            Some(Distribution::new("Ubuntu", "24.04"))
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::experiments::{Experiment, all_experiments};
+use crate::utils::{Command, ScratchDir, Worker, create_dirs};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeSet,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info, warn};
+
+/// The name of the local package that carries oxidizr's dpkg triggers.
+pub const TRIGGER_PACKAGE: &str = "oxidizr-triggers";
+
+/// Keep oxidizr's dpkg triggers in step with the enabled experiments. While any experiment is
+/// enabled, a small local package is installed that declares a file trigger on every directory
+/// containing files managed by oxidizr. When any package writes into those directories - such as
+/// an upgrade of GNU coreutils restoring `/usr/bin/date` - dpkg runs `oxidizr reapply` once the
+/// transaction completes. The package is removed once no experiments are enabled.
+///
+/// Failures are logged rather than returned: the triggers are a convenience and must not fail
+/// an otherwise successful operation.
+pub fn sync(system: &dyn Worker) {
+    let experiments = all_experiments(system);
+    let enabled: Vec<&Experiment> = experiments.iter().filter(|e| e.status().enabled).collect();
+
+    let result = match enabled.is_empty() {
+        true => remove(system),
+        false => install(system, &managed_directories(&enabled)),
+    };
+
+    if let Err(e) = result {
+        warn!("Unable to update dpkg triggers: {:#}", e);
+    }
+}
+
+/// The directories containing the files replaced by a set of experiments, and the sources of any
+/// files replaced with copies, which must be refreshed when the source is upgraded.
+fn managed_directories(experiments: &[&Experiment]) -> BTreeSet<PathBuf> {
+    let targets = experiments
+        .iter()
+        .flat_map(|e| e.check_replaced().unwrap_or_default())
+        .map(|(target, _)| target);
+    let copied_sources = experiments.iter().flat_map(|e| {
+        e.replacements()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, target)| e.copy_mode(target).is_some())
+            .map(|(source, _)| source)
+    });
+
+    targets
+        .chain(copied_sources)
+        .filter_map(|path| path.parent().map(Path::to_path_buf))
+        .collect()
+}
+
+/// Build and install the trigger package, declaring interest in the specified directories.
+fn install(system: &dyn Worker, directories: &BTreeSet<PathBuf>) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let scratch = ScratchDir::new(&system.scratch_root(), "triggers")?;
+    let package_dir = scratch.path().join(TRIGGER_PACKAGE);
+    let control_dir = package_dir.join("DEBIAN");
+    create_dirs(&control_dir)?;
+
+    for (name, contents, mode) in control_files(directories, &exe) {
+        let path = control_dir.join(name);
+        fs::write(&path, contents)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    }
+
+    let deb = scratch.path().join(format!("{TRIGGER_PACKAGE}.deb"));
+    let result = system
+        .run(
+            &Command::build("dpkg-deb", &["--build"])
+                .arg(&package_dir)
+                .arg(&deb),
+        )
+        .and_then(|_| system.run(&Command::build("dpkg", &["-i"]).arg(&deb)))
+        .context("unable to install the trigger package");
+    drop(scratch);
+    result?;
+
+    info!(
+        "Registered dpkg triggers on {}",
+        directories
+            .iter()
+            .map(|d| d.display().to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/// Remove the trigger package, if it is installed.
+fn remove(system: &dyn Worker) -> Result<()> {
+    if !system.check_installed(TRIGGER_PACKAGE)? {
+        debug!("{} is not installed, nothing to remove", TRIGGER_PACKAGE);
+        return Ok(());
+    }
+
+    system.run(&Command::build("dpkg", &["--purge", TRIGGER_PACKAGE]))?;
+    info!("Removed dpkg triggers");
+    Ok(())
+}
+
+/// The control files of the trigger package: each file's name, contents and mode.
+fn control_files(directories: &BTreeSet<PathBuf>, exe: &Path) -> Vec<(&'static str, String, u32)> {
+    let control = format!(
+        "Package: {TRIGGER_PACKAGE}\n\
+        Version: {}\n\
+        Architecture: all\n\
+        Maintainer: oxidizr <root@localhost>\n\
+        Priority: optional\n\
+        Section: admin\n\
+        Description: dpkg triggers for files managed by oxidizr\n \
+        Re-applies enabled oxidizr experiments when a package writes into a managed directory.\n",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let triggers: String = directories
+        .iter()
+        .map(|d| format!("interest-noawait {}\n", d.display()))
+        .collect();
+
+    let postinst = format!(
+        "#!/bin/sh\n\
+        set -e\n\
+        if [ \"$1\" = \"triggered\" ]; then\n    \
+            {} reapply --yes || echo \"oxidizr: failed to reapply experiments\" >&2\n\
+        fi\n",
+        exe.display()
+    );
+
+    vec![
+        ("control", control, 0o644),
+        ("triggers", triggers, 0o644),
+        ("postinst", postinst, 0o755),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_control_files() {
+        let directories = BTreeSet::from([PathBuf::from("/usr/bin"), PathBuf::from("/usr/sbin")]);
+        let files = control_files(&directories, Path::new("/usr/bin/oxidizr"));
+
+        let (_, triggers, _) = &files[1];
+        assert_eq!(
+            triggers,
+            "interest-noawait /usr/bin\ninterest-noawait /usr/sbin\n"
+        );
+
+        let (_, postinst, mode) = &files[2];
+        assert!(postinst.contains("/usr/bin/oxidizr reapply --yes"));
+        assert_eq!(*mode, 0o755);
+    }
+
+    #[test]
+    fn test_remove_when_not_installed() {
+        let runner = MockSystem::default();
+        sync(&runner);
+        assert!(
+            !runner
+                .commands
+                .clone()
+                .into_inner()
+                .iter()
+                .any(|c| c.starts_with("dpkg -i") || c.starts_with("dpkg --purge"))
+        );
+    }
+
+    #[test]
+    fn test_remove_when_installed() {
+        let runner = MockSystem::default();
+        runner.mock_install_package(TRIGGER_PACKAGE);
+        sync(&runner);
+        assert_eq!(
+            runner.commands.clone().into_inner().last().unwrap(),
+            "dpkg --purge oxidizr-triggers"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Package operations performed through libapt-pkg, rather than by spawning `apt-get`. Only built
+//! with the `native-apt` feature; the `Worker` falls back to `apt-get` when the apt cache cannot
+//! be opened through the library.
+
+use anyhow::{Context, Result};
+use rust_apt::{
+    Cache,
+    error::AptErrors,
+    new_cache,
+    progress::{AcquireProgress, DynInstallProgress, InstallProgress},
+};
+use tracing::{debug, info, warn};
+
+/// A package management transaction performed through libapt-pkg.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transaction {
+    Install,
+    Remove,
+}
+
+/// Open the apt cache through libapt-pkg. Returns `None` if the library cannot be used, in which
+/// case the caller should fall back to the `apt-get` command line.
+pub fn open_cache() -> Option<Cache> {
+    match new_cache!() {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            debug!(
+                "Unable to open the apt cache with libapt-pkg: {}",
+                errors(&e)
+            );
+            None
+        }
+    }
+}
+
+/// Update the package lists.
+pub fn update(cache: Cache) -> Result<()> {
+    cache
+        .update(&mut AcquireProgress::quiet())
+        .map_err(|e| anyhow::anyhow!(errors(&e)))
+        .context("libapt-pkg failed to update the package lists")
+}
+
+/// Install or remove a set of packages in a single transaction.
+pub fn commit(cache: Cache, transaction: Transaction, packages: &[String]) -> Result<()> {
+    for name in packages {
+        let package = cache
+            .get(name)
+            .with_context(|| format!("package '{name}' not found in the apt cache"))?;
+
+        let marked = match transaction {
+            Transaction::Install => package.mark_install(true, true),
+            Transaction::Remove => package.mark_delete(false),
+        };
+        anyhow::ensure!(
+            marked,
+            "libapt-pkg refused to mark '{name}' for {transaction:?}"
+        );
+        package.protect();
+    }
+
+    cache
+        .resolve(false)
+        .map_err(|e| anyhow::anyhow!(errors(&e)))
+        .with_context(|| format!("unable to resolve dependencies of {}", packages.join(", ")))?;
+
+    cache
+        .commit(
+            &mut AcquireProgress::quiet(),
+            &mut InstallProgress::new(TracingProgress),
+        )
+        .map_err(|e| anyhow::anyhow!(errors(&e)))
+        .with_context(|| format!("libapt-pkg transaction failed for {}", packages.join(", ")))
+}
+
+/// Render the messages reported by libapt-pkg, one per line.
+fn errors(e: &AptErrors) -> String {
+    e.to_string().trim_end().to_string()
+}
+
+/// Reports the progress of a libapt-pkg transaction through the tracing system.
+struct TracingProgress;
+
+impl DynInstallProgress for TracingProgress {
+    fn status_changed(&mut self, package: String, done: u64, total: u64, action: String) {
+        info!("[{}/{}] {}: {}", done, total, package, action);
+    }
+
+    fn error(&mut self, package: String, done: u64, total: u64, error: String) {
+        warn!("[{}/{}] {}: {}", done, total, package, error);
+    }
+}
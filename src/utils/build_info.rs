@@ -0,0 +1,107 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use super::{Distribution, Worker, Wsl, detect_wsl};
+
+/// Information about the oxidizr build, and the environment it is running in.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    pub build_date: String,
+    pub target: String,
+    pub features: Vec<String>,
+    pub distribution: Option<Distribution>,
+    pub kernel: Option<String>,
+    pub container: Option<String>,
+    /// The version of the Windows Subsystem for Linux oxidizr is running under, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wsl: Option<Wsl>,
+    /// The type of the root filesystem, when it is one whose changes don't persist past a
+    /// reboot, such as the overlay of a live image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ephemeral_root: Option<String>,
+}
+
+impl BuildInfo {
+    /// Gather build information, and detect details of the environment from the system.
+    pub fn gather(system: &dyn Worker) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("OXIDIZR_GIT_COMMIT").to_string(),
+            build_date: env!("OXIDIZR_BUILD_DATE").to_string(),
+            target: env!("OXIDIZR_TARGET").to_string(),
+            features: env!("OXIDIZR_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(|f| f.to_string())
+                .collect(),
+            distribution: system.distribution().ok(),
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|k| k.trim().to_string()),
+            container: detect_container(),
+            wsl: detect_wsl(system),
+            ephemeral_root: detect_ephemeral_root(system),
+        }
+    }
+}
+
+/// Detect whether oxidizr is running inside a container, and report the container type.
+fn detect_container() -> Option<String> {
+    if let Ok(container) = fs::read_to_string("/run/systemd/container") {
+        return Some(container.trim().to_string());
+    }
+    if fs::exists("/.dockerenv").unwrap_or(false) {
+        return Some("docker".to_string());
+    }
+    None
+}
+
+/// Filesystems that hold their contents in memory when mounted as the root filesystem, as live
+/// images and some ephemeral cloud images do.
+const EPHEMERAL_FILESYSTEMS: &[&str] = &["overlay", "tmpfs", "ramfs", "aufs"];
+
+/// Detect a root filesystem whose changes won't persist past a reboot, and report its type.
+/// Containers are excluded: their root is commonly an overlay, but one that may well be committed
+/// to an image.
+pub fn detect_ephemeral_root(system: &dyn Worker) -> Option<String> {
+    if detect_container().is_some() {
+        return None;
+    }
+    root_filesystem(&system.read_file(Path::new("/proc/self/mounts")).ok()??)
+        .filter(|fstype| EPHEMERAL_FILESYSTEMS.contains(&fstype.as_str()))
+}
+
+/// Find the type of the filesystem mounted at `/` in a mount table. The last mount at `/` is the
+/// one in effect, such as a live image's overlay on top of its initial ramdisk.
+fn root_filesystem(mounts: &str) -> Option<String> {
+    mounts.lines().rev().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let mount_point = fields.nth(1)?;
+        let fstype = fields.next()?;
+        (mount_point == "/").then(|| fstype.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_filesystem() {
+        let live = "\
+rootfs / rootfs rw 0 0
+/dev/sr0 /cdrom iso9660 ro,noatime 0 0
+/cow / overlay rw,relatime,lowerdir=/filesystem.squashfs,upperdir=/cow/upper 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+";
+        assert_eq!(root_filesystem(live), Some("overlay".to_string()));
+        assert_eq!(
+            root_filesystem("/dev/sda1 / ext4 rw,relatime 0 0\n"),
+            Some("ext4".to_string())
+        );
+        assert_eq!(root_filesystem(""), None);
+    }
+}
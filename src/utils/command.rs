@@ -1,21 +1,40 @@
-/// Command struct to build a command with arguments.
+use std::ffi::{OsStr, OsString};
+
+/// Command struct to build a command with arguments. The command and its arguments are kept as
+/// `OsString`s, so that paths that aren't valid UTF-8 are passed to it as they are.
 pub struct Command {
-    pub command: String,
-    pub args: Vec<String>,
+    pub command: OsString,
+    pub args: Vec<OsString>,
+    /// What is written to the standard input of the command, which is otherwise empty.
+    pub input: Option<Vec<u8>>,
 }
 
 impl Command {
     /// Create a new `Command` instance from the command name and list of arguments.
-    pub fn build(command: &str, args: &[&str]) -> Self {
-        let args = args.iter().map(|s| s.to_string()).collect();
+    pub fn build(command: impl AsRef<OsStr>, args: &[&str]) -> Self {
+        let args = args.iter().map(OsString::from).collect();
         Self {
-            command: command.to_string(),
+            command: command.as_ref().to_os_string(),
             args,
+            input: None,
         }
     }
 
-    /// Get the full command string.
+    /// Append an argument, such as a path, which may not be valid UTF-8.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    /// Write `input` to the standard input of the command, such as a message for `sendmail`.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = Some(input.into());
+        self
+    }
+
+    /// Get the full command string. Anything that isn't valid UTF-8 is replaced with U+FFFD.
     pub fn command(&self) -> String {
-        format!("{} {}", self.command, self.args.join(" "))
+        let args: Vec<_> = self.args.iter().map(|a| a.to_string_lossy()).collect();
+        format!("{} {}", self.command.to_string_lossy(), args.join(" "))
     }
 }
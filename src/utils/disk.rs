@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+/// A mounted filesystem, and the space available on it, as reported by `df`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filesystem {
+    pub mount_point: PathBuf,
+    /// The space available to unprivileged users, in bytes.
+    pub available: u64,
+}
+
+/// Parse the output of `df -P -k` for a single file: a header line, then the device, its size,
+/// the space used and available in 1024-byte blocks, the capacity used and the mount point, which
+/// may contain spaces.
+pub fn parse_df(output: &str) -> Option<Filesystem> {
+    let line = output.lines().nth(1)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    Some(Filesystem {
+        mount_point: PathBuf::from(fields[5..].join(" ")),
+        available: fields[3].parse::<u64>().ok()? * 1024,
+    })
+}
+
+/// Render a number of bytes for display, such as `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+            /dev/nvme0n1p2   490617784 93820532 371801508      21% /\n";
+        assert_eq!(
+            parse_df(output),
+            Some(Filesystem {
+                mount_point: PathBuf::from("/"),
+                available: 371801508 * 1024,
+            })
+        );
+
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+            /dev/sdb1 1000 10 990 1% /mnt/usb drive\n";
+        assert_eq!(
+            parse_df(output).unwrap().mount_point,
+            PathBuf::from("/mnt/usb drive")
+        );
+        assert_eq!(parse_df(""), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
@@ -0,0 +1,267 @@
+use super::{
+    Command, Distribution, FileLock, Filesystem, ManagedTarget, PackageVersion, Process,
+    ReplacementSource, SkipReason, Worker,
+};
+use crate::experiments::PolicyName;
+use anyhow::Result;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+};
+
+/// A Worker that records the changes an operation would make to the system, and makes none of
+/// them. Reads are passed through to the Worker it wraps, so that an operation on a
+/// `DryRunWorker` inspects any system the same way it would otherwise, whichever Worker that is.
+///
+/// Every command run is considered a change, as commands such as `systemctl restart` or hooks
+/// may change the system, and succeeds with no output. The queries that the Worker makes by
+/// running commands, such as the installed version of a package, are passed through instead.
+/// Files written are seen by later reads, so that state recorded by the operation, such as
+/// pinned binaries, is taken into account.
+pub struct DryRunWorker<'a> {
+    inner: &'a dyn Worker,
+    changes: RefCell<Vec<String>>,
+    written: RefCell<HashMap<PathBuf, String>>,
+}
+
+impl<'a> DryRunWorker<'a> {
+    /// Create a new `DryRunWorker`, wrapping the specified Worker.
+    pub fn new(inner: &'a dyn Worker) -> Self {
+        Self {
+            inner,
+            changes: RefCell::new(Vec::new()),
+            written: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The changes that would have been made so far, in order, such as
+    /// `install rust-coreutils`.
+    pub fn changes(&self) -> Vec<String> {
+        self.changes.borrow().clone()
+    }
+
+    fn record(&self, change: String) {
+        self.changes.borrow_mut().push(change);
+    }
+}
+
+impl Worker for DryRunWorker<'_> {
+    fn strict(&self) -> bool {
+        self.inner.strict()
+    }
+
+    fn dry_run(&self) -> bool {
+        true
+    }
+
+    fn compatibility_policies(&self) -> &[PolicyName] {
+        self.inner.compatibility_policies()
+    }
+
+    fn scratch_root(&self) -> PathBuf {
+        self.inner.scratch_root()
+    }
+
+    fn distribution(&self) -> Result<Distribution> {
+        self.inner.distribution()
+    }
+
+    fn run(&self, cmd: &Command) -> Result<Output> {
+        self.record(format!("run '{}'", cmd.command()));
+        Ok(Output {
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+
+    /// Lists nothing for a directory that doesn't exist, such as the replacement binaries of a
+    /// package that would have been installed.
+    fn list_files(&self, directory: PathBuf) -> Result<Vec<PathBuf>> {
+        match self.inner.list_files(directory.clone()) {
+            Ok(files) => Ok(files),
+            Err(_) => {
+                self.record(format!(
+                    "list the files in {}, which doesn't exist yet",
+                    directory.display()
+                ));
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn which(&self, binary_name: &OsStr) -> Result<PathBuf> {
+        self.inner.which(binary_name)
+    }
+
+    fn install_packages(&self, packages: &[String]) -> Result<()> {
+        self.record(format!("install {}", packages.join(", ")));
+        Ok(())
+    }
+
+    fn remove_packages(&self, packages: &[String]) -> Result<()> {
+        self.record(format!("remove {}", packages.join(", ")));
+        Ok(())
+    }
+
+    fn install_package_version(&self, package: &str, version: &str) -> Result<()> {
+        self.record(format!("install version {version} of {package}"));
+        Ok(())
+    }
+
+    fn install_package_file(&self, file: &Path) -> Result<()> {
+        self.record(format!("install {}", file.display()));
+        Ok(())
+    }
+
+    fn available_versions(&self, package: &str) -> Result<Vec<String>> {
+        self.inner.available_versions(package)
+    }
+
+    fn candidate_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        self.inner.candidate_version(package)
+    }
+
+    fn architecture(&self) -> Result<String> {
+        self.inner.architecture()
+    }
+
+    fn update_package_lists(&self) -> Result<()> {
+        self.record("update the package lists".to_string());
+        Ok(())
+    }
+
+    fn check_installed(&self, package: &str) -> Result<bool> {
+        self.inner.check_installed(package)
+    }
+
+    fn installed_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        self.inner.installed_version(package)
+    }
+
+    fn read_symlink(&self, path: &Path) -> Result<Option<PathBuf>> {
+        self.inner.read_symlink(path)
+    }
+
+    fn processes(&self) -> Result<Vec<Process>> {
+        self.inner.processes()
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Option<String>> {
+        if let Some(contents) = self.written.borrow().get(path) {
+            return Ok(Some(contents.clone()));
+        }
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&self, path: &Path, contents: &str) -> Result<()> {
+        self.record(format!("write {}", path.display()));
+        self.written
+            .borrow_mut()
+            .insert(path.to_path_buf(), contents.to_string());
+        Ok(())
+    }
+
+    /// Only ever takes a shared lock, as nothing is written, so that no lock file is created.
+    fn lock_file(&self, path: &Path, _exclusive: bool) -> Result<Option<FileLock>> {
+        self.inner.lock_file(path, false)
+    }
+
+    fn replace_file_with_symlink(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+    ) -> Result<Option<SkipReason>> {
+        self.record(format!("replace {target} with a symlink to {source}"));
+        Ok(None)
+    }
+
+    fn replace_file_with_copy(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+        mode: u32,
+    ) -> Result<()> {
+        self.record(format!(
+            "replace {target} with a copy of {source} (mode {mode:o})"
+        ));
+        Ok(())
+    }
+
+    fn file_size(&self, path: &Path) -> Result<Option<u64>> {
+        self.inner.file_size(path)
+    }
+
+    fn can_connect(&self, host: &str, port: u16) -> bool {
+        self.inner.can_connect(host, port)
+    }
+
+    fn filesystem(&self, path: &Path) -> Result<Filesystem> {
+        self.inner.filesystem(path)
+    }
+
+    fn file_checksum(&self, path: &Path) -> Result<Option<String>> {
+        self.inner.file_checksum(path)
+    }
+
+    fn backup_file(&self, file: ManagedTarget) -> Result<()> {
+        self.record(format!("back up {file}"));
+        Ok(())
+    }
+
+    fn restore_file(&self, file: ManagedTarget) -> Result<Option<SkipReason>> {
+        self.record(format!("restore {file} from its backup"));
+        Ok(None)
+    }
+
+    fn create_symlink(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()> {
+        self.record(format!("symlink {target} to {source}"));
+        Ok(())
+    }
+
+    fn remove_file(&self, file: &Path) -> Result<()> {
+        self.record(format!("remove {}", file.display()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{EnableOptions, Experiment, SudoRsExperiment, enable_all};
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_dry_run_worker() {
+        let runner = MockSystem::default();
+        runner.mock_files(vec![
+            ("/usr/lib/cargo/bin/su", "sudo-rs su", false),
+            ("/usr/bin/su", "su", true),
+        ]);
+        let worker = DryRunWorker::new(&runner);
+
+        let sudors = vec![Experiment::SudoRs(SudoRsExperiment::new(&worker))];
+        enable_all(&worker, &sudors, &EnableOptions::default(), |_, _| ()).unwrap();
+        let changes = worker.changes();
+        assert_eq!(changes[0], "install sudo-rs");
+        assert!(
+            changes
+                .iter()
+                .any(|c| c.starts_with("replace /usr/bin/su "))
+        );
+
+        // Nothing was done to the wrapped worker.
+        assert!(runner.commands.borrow().is_empty());
+        assert!(runner.created_symlinks.borrow().is_empty());
+        assert!(runner.copied_files.borrow().is_empty());
+
+        // Files written are seen by later reads, and only by those of the dry run.
+        let path = Path::new("/var/lib/oxidizr/pins.json");
+        worker.write_file(path, "{}").unwrap();
+        assert_eq!(worker.read_file(path).unwrap().as_deref(), Some("{}"));
+        assert_eq!(runner.read_file(path).unwrap(), None);
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// The directory in which oxidizr keeps its state, such as the journal and pinned binaries.
+pub const STATE_DIRECTORY: &str = "/var/lib/oxidizr";
+
+/// The mode of the files in the state directory: readable by root alone.
+pub const STATE_FILE_MODE: u32 = 0o600;
+
+/// The mode of the other files oxidizr writes, such as systemd units and checksums: readable by
+/// everyone.
+pub const FILE_MODE: u32 = 0o644;
+
+/// The mode of the directories oxidizr creates.
+pub const DIRECTORY_MODE: u32 = 0o755;
+
+/// The mode that a file written by oxidizr is given, according to where it is.
+pub fn file_mode(path: &Path) -> u32 {
+    match path.starts_with(STATE_DIRECTORY) {
+        true => STATE_FILE_MODE,
+        false => FILE_MODE,
+    }
+}
+
+/// Write a file so that it survives a power loss intact: the contents are written to a temporary
+/// file alongside it and flushed to disk, then renamed over it, and the rename is flushed too. The
+/// file is never seen truncated, and is given `mode` whatever the umask, including when it already
+/// exists with another.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let temporary = temporary_path(path);
+    let mut file = fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    file.sync_all()?;
+    drop(file);
+
+    if let Err(e) = fs::rename(&temporary, path) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e.into());
+    }
+    sync_parent(path)
+}
+
+/// Flush the contents of a file to disk.
+pub fn sync_file(path: &Path) -> Result<()> {
+    fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// Flush the directory containing a file to disk, so that the file being created, renamed or
+/// removed survives a power loss.
+pub fn sync_parent(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+/// Create a directory and any of its missing parents, giving each one created `DIRECTORY_MODE`
+/// whatever the umask. Directories that already exist are left as they are.
+pub fn create_dirs(path: &Path) -> Result<()> {
+    let missing: Vec<&Path> = path.ancestors().take_while(|d| !d.exists()).collect();
+    for directory in missing.into_iter().rev() {
+        match fs::create_dir(directory) {
+            Ok(()) => fs::set_permissions(directory, fs::Permissions::from_mode(DIRECTORY_MODE))?,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// The temporary file that `write_atomic` writes before renaming it over `path`, such as
+/// `/var/lib/oxidizr/.pins.json.oxidizr.tmp`.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".oxidizr.tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic() {
+        let root = std::env::temp_dir().join(format!("oxidizr-atomic-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("state.json");
+
+        write_atomic(&path, b"{}", STATE_FILE_MODE).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        // An existing file is replaced whole, and its mode normalized.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        write_atomic(&path, b"{\"pins\": []}", STATE_FILE_MODE).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"pins\": []}");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!temporary_path(&path).exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_create_dirs() {
+        let root = std::env::temp_dir().join(format!("oxidizr-dirs-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let nested = root.join("a/b");
+        create_dirs(&nested).unwrap();
+        for directory in [root.join("a"), nested.clone()] {
+            let mode = fs::metadata(directory).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, DIRECTORY_MODE);
+        }
+        // Existing directories are left alone.
+        let mode = fs::metadata(&root).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        create_dirs(&nested).unwrap();
+
+        assert_eq!(file_mode(Path::new("/var/lib/oxidizr/pins.json")), 0o600);
+        assert_eq!(file_mode(Path::new("/etc/tmpfiles.d/oxidizr.conf")), 0o644);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+use crate::journal::Interrupted;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+
+/// The exit code of an operation that succeeded for some experiments but failed for others.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+
+/// A class of user-facing failure, identified by a stable machine-readable code and paired with
+/// a hint on how to remedy it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NotRoot,
+    UnsupportedDistribution,
+    InterruptedOperationFound,
+    NothingToResume,
+    PackageInstallFailed,
+    PackageRemoveFailed,
+    RollbackFailed,
+    Interrupted,
+    OutsideManagedDirectories,
+    StrictMode,
+    InvalidConfig,
+    ChecksumMismatch,
+    ImaKeyRequired,
+    CommandFailed,
+    PamMisconfigured,
+    AuthenticationUnverified,
+    SudoersUnsupported,
+    RescueToolsetMissing,
+    ExperimentalNotAllowed,
+    SmokeTestFailed,
+    ExperimentsFailed,
+    TimedOut,
+    BackupCollision,
+    InsufficientSpace,
+    CrossesFilesystems,
+    StateVersionUnsupported,
+    NotReplaced,
+    ChannelUnavailable,
+    GenerationNotFound,
+    RepositoriesUnreachable,
+    SignatureInvalid,
+}
+
+impl ErrorCode {
+    /// The stable code identifying the failure, e.g. `E005`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::NotRoot => "E001",
+            Self::UnsupportedDistribution => "E002",
+            Self::InterruptedOperationFound => "E003",
+            Self::NothingToResume => "E004",
+            Self::PackageInstallFailed => "E005",
+            Self::PackageRemoveFailed => "E006",
+            Self::RollbackFailed => "E007",
+            Self::Interrupted => "E008",
+            Self::OutsideManagedDirectories => "E009",
+            Self::StrictMode => "E010",
+            Self::InvalidConfig => "E011",
+            Self::ChecksumMismatch => "E012",
+            Self::ImaKeyRequired => "E013",
+            Self::CommandFailed => "E014",
+            Self::PamMisconfigured => "E015",
+            Self::AuthenticationUnverified => "E016",
+            Self::SudoersUnsupported => "E017",
+            Self::RescueToolsetMissing => "E018",
+            Self::ExperimentalNotAllowed => "E019",
+            Self::SmokeTestFailed => "E020",
+            Self::ExperimentsFailed => "E021",
+            Self::TimedOut => "E022",
+            Self::BackupCollision => "E023",
+            Self::InsufficientSpace => "E024",
+            Self::CrossesFilesystems => "E025",
+            Self::StateVersionUnsupported => "E026",
+            Self::NotReplaced => "E027",
+            Self::ChannelUnavailable => "E028",
+            Self::GenerationNotFound => "E029",
+            Self::RepositoriesUnreachable => "E030",
+            Self::SignatureInvalid => "E031",
+        }
+    }
+
+    /// A hint on how to remedy the failure.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::NotRoot => "run oxidizr with sudo, or as the root user",
+            Self::UnsupportedDistribution => {
+                "oxidizr only supports Ubuntu; pass --no-compatibility-check to run it anyway, at \
+                your own risk"
+            }
+            Self::InterruptedOperationFound => {
+                "run 'oxidizr resume' to complete the interrupted operation, or 'oxidizr resume \
+                --rollback' to undo it"
+            }
+            Self::NothingToResume => "no operation was interrupted, so there is nothing to recover",
+            Self::PackageInstallFailed => {
+                "check that the packages are available: enable the universe repository and run \
+                'apt-get update'"
+            }
+            Self::PackageRemoveFailed => {
+                "check the apt output for the cause, and run 'apt-get --fix-broken install' to \
+                repair any broken packages"
+            }
+            Self::RollbackFailed => {
+                "restore the remaining files by hand from their '.<name>.oxidizr.bak' backups"
+            }
+            Self::Interrupted => {
+                "the changes made so far were rolled back; run the command again to retry"
+            }
+            Self::OutsideManagedDirectories => {
+                "this is a bug in oxidizr; please report it with the output of 'oxidizr version \
+                --verbose'"
+            }
+            Self::StrictMode => {
+                "resolve the condition reported, or run without --strict to only warn about it"
+            }
+            Self::InvalidConfig => {
+                "fix the configuration file, or pass --config to use a different one"
+            }
+            Self::ChecksumMismatch => {
+                "the download may be corrupt or tampered with; try again later, or install the \
+                release by hand"
+            }
+            Self::ImaKeyRequired => {
+                "pass the private key used to sign the replacement binaries with --ima-key"
+            }
+            Self::CommandFailed => "re-run with -vv to log the commands run by oxidizr",
+            Self::PamMisconfigured => {
+                "fix the PAM configuration in /etc/pam.d, or leave the experiment disabled"
+            }
+            Self::AuthenticationUnverified => {
+                "the changes were rolled back; check the PAM configuration and the system log \
+                before trying again"
+            }
+            Self::SudoersUnsupported => {
+                "rewrite the listed lines using constructs supported by sudo-rs, or leave the \
+                sudo-rs experiment disabled"
+            }
+            Self::RescueToolsetMissing => {
+                "install one with 'apt-get install busybox-static', or run without --yes to have \
+                oxidizr install it"
+            }
+            Self::ExperimentalNotAllowed => {
+                "pass --allow-experimental to enable them anyway, or select only stable experiments"
+            }
+            Self::SmokeTestFailed => {
+                "the changes were rolled back; report the failure to the upstream project of the \
+                replacement"
+            }
+            Self::ExperimentsFailed => {
+                "the changes made for the other experiments were kept; resolve the failures listed \
+                and run the command again to retry the rest"
+            }
+            Self::TimedOut => {
+                "the changes made so far were rolled back; check for a stuck package manager or \
+                network, or pass a longer --timeout"
+            }
+            Self::BackupCollision => {
+                "the file wasn't changed; move the existing file aside, or restore the original \
+                from it by hand if it is a backup made by an older version of oxidizr, and run \
+                the command again"
+            }
+            Self::InsufficientSpace => {
+                "nothing was changed; free up space on the filesystems listed, or pin some of the \
+                binaries on them to GNU, and run the command again"
+            }
+            Self::CrossesFilesystems => {
+                "nothing was changed; unmount the files listed, or pin them to GNU, and run the \
+                command again"
+            }
+            Self::StateVersionUnsupported => {
+                "a newer version of oxidizr has been run on this system; upgrade oxidizr, for \
+                example with 'oxidizr self-update', and run the command again"
+            }
+            Self::NotReplaced => {
+                "run 'oxidizr list' to see the experiments; the package of the replacement must \
+                be installed, such as by enabling its experiment"
+            }
+            Self::ChannelUnavailable => {
+                "nothing was changed; enable the experiment with '--channel stable' to install \
+                the package of the distribution instead"
+            }
+            Self::GenerationNotFound => {
+                "nothing was changed; run 'oxidizr generations' to list the generations recorded"
+            }
+            Self::RepositoriesUnreachable => {
+                "nothing was changed; check the network connection and proxy settings, or install \
+                the replacement packages from local package files with 'apt-get install ./<file>.deb' \
+                before enabling, or with 'oxidizr install --no-activate' while online, to activate \
+                later with 'oxidizr activate'"
+            }
+            Self::SignatureInvalid => {
+                "nothing was changed; the release may have been tampered with, so don't install it \
+                by hand, and report it to the maintainers of oxidizr"
+            }
+        }
+    }
+
+    /// Create an error with this code and the specified message.
+    pub fn error(self, message: impl fmt::Display) -> anyhow::Error {
+        self.context(message).into()
+    }
+
+    /// Create a `CodedError` with this code and the specified message, for use as the context of
+    /// an underlying error.
+    pub fn context(self, message: impl fmt::Display) -> CodedError {
+        CodedError {
+            code: self,
+            message: message.to_string(),
+        }
+    }
+
+    /// Find the code of an error. The outermost code in the chain of contexts is used, so that a
+    /// failed command reports the operation it was part of. Errors without a code return `None`.
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        if let Some(coded) = error.downcast_ref::<CodedError>() {
+            return Some(coded.code);
+        }
+        if error.is::<ExperimentFailures>() {
+            return Some(Self::ExperimentsFailed);
+        }
+        error.is::<Interrupted>().then_some(Self::Interrupted)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+/// An error message annotated with an `ErrorCode`.
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// The outcome of an operation on several experiments that failed for some of them. Each
+/// experiment is isolated from the failures of the others, which are either continued with or,
+/// when the operation stops at the first failure, skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ExperimentFailures {
+    /// The operation, such as `enable`.
+    pub command: String,
+    pub succeeded: Vec<String>,
+    pub failed: Vec<ExperimentFailure>,
+    /// The experiments not attempted, as the operation stopped at the first failure.
+    pub skipped: Vec<String>,
+}
+
+/// The failure of an operation for a single experiment.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ExperimentFailure {
+    pub experiment: String,
+    pub code: Option<&'static str>,
+    /// The error, including its causes.
+    pub error: String,
+}
+
+impl ExperimentFailures {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Record the failure of an experiment.
+    pub fn fail(&mut self, experiment: &str, error: &anyhow::Error) {
+        self.failed.push(ExperimentFailure {
+            experiment: experiment.to_string(),
+            code: ErrorCode::of(error).map(ErrorCode::id),
+            error: format!("{error:#}"),
+        });
+    }
+
+    /// The experiments that didn't succeed, whether they failed or were skipped.
+    pub fn unsuccessful(&self) -> Vec<String> {
+        self.failed
+            .iter()
+            .map(|f| f.experiment.clone())
+            .chain(self.skipped.iter().cloned())
+            .collect()
+    }
+
+    /// Succeed if no experiment failed, or fail with the failures recorded.
+    pub fn into_result(self) -> anyhow::Result<()> {
+        match self.failed.is_empty() {
+            true => Ok(()),
+            false => Err(self.into()),
+        }
+    }
+}
+
+impl fmt::Display for ExperimentFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |names: &[String]| match names.is_empty() {
+            true => "none".to_string(),
+            false => names.join(", "),
+        };
+        let failed: Vec<String> = self.failed.iter().map(|f| f.experiment.clone()).collect();
+        write!(
+            f,
+            "failed to {} {}; {}d: {}",
+            self.command,
+            failed.join(", "),
+            self.command,
+            list(&self.succeeded)
+        )?;
+        if !self.skipped.is_empty() {
+            write!(f, "; not attempted: {}", self.skipped.join(", "))?;
+        }
+        for failure in &self.failed {
+            match failure.code {
+                Some(code) => write!(f, "\n  {}: [{code}] {}", failure.experiment, failure.error)?,
+                None => write!(f, "\n  {}: {}", failure.experiment, failure.error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ExperimentFailures {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_code_of() {
+        let err = ErrorCode::NotRoot.error("This program must be run as root");
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::NotRoot));
+        assert_eq!(err.to_string(), "This program must be run as root");
+
+        // The outermost code wins over the code of the underlying failure.
+        let err = Err::<(), _>(ErrorCode::CommandFailed.error("Failed to run command"))
+            .context(ErrorCode::PackageInstallFailed.context("unable to install rust-coreutils"))
+            .unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::PackageInstallFailed));
+
+        let err = anyhow::Error::from(Interrupted).context("while enabling");
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::Interrupted));
+        assert_eq!(ErrorCode::of(&anyhow::anyhow!("uncoded")), None);
+    }
+
+    #[test]
+    fn test_experiment_failures() {
+        let mut failures = ExperimentFailures::new("enable");
+        failures.succeeded.push("hostname".to_string());
+        failures.fail("sed", &ErrorCode::SmokeTestFailed.error("sed failed"));
+        failures.skipped.push("coreutils".to_string());
+
+        let err = failures.into_result().unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::ExperimentsFailed));
+        assert_eq!(
+            err.to_string(),
+            "failed to enable sed; enabled: hostname; not attempted: coreutils\n  \
+            sed: [E020] sed failed"
+        );
+        assert!(ExperimentFailures::new("disable").into_result().is_ok());
+    }
+}
@@ -1,11 +1,49 @@
+#[cfg(feature = "native-apt")]
+mod apt;
+mod build_info;
 mod command;
+mod disk;
+mod dry_run;
+mod durable;
+mod error;
+mod os_release;
+mod output;
+mod paths;
+mod process;
+mod sandbox;
+mod scratch;
+mod state;
+mod table;
+mod version;
 mod worker;
+mod wsl;
 
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
+use std::fmt;
 use std::hash::Hash;
+use tracing::warn;
 
+pub use build_info::*;
 pub use command::*;
+pub use disk::*;
+pub use dry_run::*;
+pub use durable::*;
+pub use error::*;
+pub use os_release::*;
+pub use output::*;
+pub use paths::*;
+pub use process::*;
+pub use sandbox::*;
+pub use scratch::*;
+pub use state::*;
+pub use table::*;
+pub use version::*;
 pub use worker::*;
+pub use wsl::*;
 
 #[cfg(test)]
 mod worker_mock;
@@ -13,10 +51,128 @@ mod worker_mock;
 pub use worker_mock::tests::*;
 
 /// A representation for Linux distribution information for the system.
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, JsonSchema)]
 pub struct Distribution {
     pub id: String,
     pub release: String,
+    /// The codename of the release, such as `noble`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codename: Option<String>,
+    /// The version of the operating system as given by its os-release file, which for a
+    /// derivative is its own version rather than the release it is based on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    /// The distributions the operating system is closely related to, from its os-release file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub id_like: Vec<String>,
+    /// The edition of the operating system, such as `server`, `desktop` or `silverblue`, if it
+    /// reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// The architecture that packages are installed for, such as `amd64` or `armhf`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+    /// The derivative actually running, when the system is a derivative treated as the release
+    /// it is based on, such as Linux Mint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivative: Option<Derivative>,
+}
+
+/// A distribution derived from a supported one, as identified by its os-release file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, JsonSchema)]
+pub struct Derivative {
+    /// The derivative's own id, such as `linuxmint` or `pop`.
+    pub id: String,
+    /// The derivative's human-readable name, such as `Linux Mint`.
+    pub name: String,
+    /// The derivative's own version, such as `22`, if it reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl fmt::Display for Derivative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl Distribution {
+    /// Create a new `Distribution` from its id and release.
+    pub fn new(id: &str, release: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            release: release.to_string(),
+            codename: None,
+            version_id: None,
+            id_like: Vec::new(),
+            variant: None,
+            architecture: None,
+            derivative: None,
+        }
+    }
+
+    /// Mark the release as running on a derivative of it.
+    pub fn derived_by(mut self, derivative: Derivative) -> Self {
+        self.derivative = Some(derivative);
+        self
+    }
+
+    /// Report the distribution release itself, regardless of any derivative running it or the
+    /// details of the installation, for comparing against the releases supported by an
+    /// experiment.
+    pub fn base(&self) -> Self {
+        Self::new(&self.id, &self.release)
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.id, self.release)?;
+        if let Some(derivative) = &self.derivative {
+            write!(f, " (derivative: {derivative})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Distribution {
+    /// Describe the release in full, including its codename, variant and architecture when
+    /// known, such as `Ubuntu 24.04 (noble, server, amd64)`.
+    pub fn describe(&self) -> String {
+        let details: Vec<&str> = [&self.codename, &self.variant, &self.architecture]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        let mut description = format!("{} {}", self.id, self.release);
+        if !details.is_empty() {
+            description.push_str(&format!(" ({})", details.join(", ")));
+        }
+        if let Some(derivative) = &self.derivative {
+            description.push_str(&format!(", a derivative running {derivative}"));
+        }
+        description
+    }
+}
+
+/// Report a condition that is only a warning by default, but fails the operation in strict mode.
+pub fn warn_or_fail(strict: bool, message: impl fmt::Display) -> Result<()> {
+    if strict {
+        return Err(ErrorCode::StrictMode.error(format!("{message} (failing in strict mode)")));
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+/// Compute the hex-encoded sha256 digest of some data.
+pub fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
 }
 
 /// Return true if the two (potentially unordered) vecs contain identical elements.
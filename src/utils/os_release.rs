@@ -0,0 +1,276 @@
+//! Detection of distributions derived from a supported distribution, from `/etc/os-release`.
+
+use super::{Derivative, Distribution};
+use std::collections::HashMap;
+
+/// The location of the operating system identification data.
+pub const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+/// A file present only on Raspberry Pi OS, recording the image it was installed from. The 64-bit
+/// edition otherwise identifies itself as plain Debian.
+pub const RPI_ISSUE_PATH: &str = "/etc/rpi-issue";
+
+/// The releases of Ubuntu, by codename. Derivatives report the Ubuntu release they are based on
+/// by its codename, rather than its version.
+const UBUNTU_RELEASES: &[(&str, &str)] = &[
+    ("focal", "20.04"),
+    ("jammy", "22.04"),
+    ("noble", "24.04"),
+    ("oracular", "24.10"),
+    ("plucky", "25.04"),
+    ("questing", "25.10"),
+];
+
+/// The releases of Debian, by codename. Derivatives such as LMDE report the Debian release they
+/// are based on by its codename, in `DEBIAN_CODENAME`.
+const DEBIAN_RELEASES: &[(&str, &str)] =
+    &[("bullseye", "11"), ("bookworm", "12"), ("trixie", "13")];
+
+/// The ids of the rebuilds of RHEL, and of CentOS Stream, which RHEL is built from.
+const RHEL_REBUILDS: &[&str] = &["centos", "rocky", "almalinux", "ol"];
+
+/// Parse the `KEY=value` assignments of an os-release file, unquoting their values. Comments and
+/// malformed lines are ignored.
+pub fn parse_os_release(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Map a derivative onto the release of the supported distribution it is based on, as listed in
+/// its `ID_LIKE`. Only Ubuntu is supported, so only its derivatives can be mapped - and only
+/// those that report the Ubuntu release they are based on. Derivatives of other families are
+/// identified by `family_release` instead.
+///
+/// Most derivatives, such as Linux Mint, name the Ubuntu release in `UBUNTU_CODENAME`, as their
+/// own version and codename differ from it. Others, such as Pop!_OS, share Ubuntu's codenames, so
+/// `VERSION_CODENAME` is used when it is the codename of an Ubuntu release.
+pub fn derivative_release(os_release: &HashMap<String, String>) -> Option<Distribution> {
+    let id = os_release.get("ID")?;
+    let is_like_ubuntu = os_release
+        .get("ID_LIKE")
+        .is_some_and(|like| like.split_whitespace().any(|l| l == "ubuntu"));
+    if id == "ubuntu" || !is_like_ubuntu {
+        return None;
+    }
+
+    let release = ["UBUNTU_CODENAME", "VERSION_CODENAME"]
+        .iter()
+        .filter_map(|key| os_release.get(*key))
+        .find_map(|codename| UBUNTU_RELEASES.iter().find(|(c, _)| c == codename))
+        .map(|(_, release)| *release)?;
+    let derivative = Derivative {
+        id: id.clone(),
+        name: os_release.get("NAME").unwrap_or(id).clone(),
+        version: os_release.get("VERSION_ID").cloned(),
+    };
+    Some(Distribution::new("Ubuntu", release).derived_by(derivative))
+}
+
+/// Map a derivative of an unsupported family onto the distribution closest to it, with the
+/// derivative marked, so that what is running is reported: CentOS Stream, Rocky Linux,
+/// AlmaLinux and other RHEL rebuilds onto the major release of RHEL, and derivatives of Debian
+/// that aren't based on Ubuntu, such as LMDE, onto the Debian release named by their
+/// `DEBIAN_CODENAME`. Neither family is supported, so compatibility checks still fail on them.
+pub fn family_release(os_release: &HashMap<String, String>) -> Option<Distribution> {
+    let id = os_release.get("ID")?;
+    let like = |family: &str| {
+        os_release
+            .get("ID_LIKE")
+            .is_some_and(|like| like.split_whitespace().any(|l| l == family))
+    };
+    let derivative = Derivative {
+        id: id.clone(),
+        name: os_release.get("NAME").unwrap_or(id).clone(),
+        version: os_release.get("VERSION_ID").cloned(),
+    };
+
+    if id != "rhel" && (RHEL_REBUILDS.contains(&id.as_str()) || like("rhel")) {
+        let version = os_release.get("VERSION_ID").map_or("", |v| v.as_str());
+        let major = version.split('.').next().unwrap_or_default();
+        return Some(Distribution::new("RHEL", major).derived_by(derivative));
+    }
+    if id != "debian" && like("debian") && !like("ubuntu") {
+        let release = os_release
+            .get("DEBIAN_CODENAME")
+            .and_then(|codename| DEBIAN_RELEASES.iter().find(|(c, _)| c == codename))
+            .map_or("", |(_, release)| *release);
+        return Some(Distribution::new("Debian", release).derived_by(derivative));
+    }
+    None
+}
+
+/// Identify Raspberry Pi OS, which is a derivative of Debian: the 32-bit edition by its own id,
+/// and the 64-bit edition, which reports itself as Debian, by the presence of `/etc/rpi-issue`.
+/// Debian isn't supported, so this only serves to report what is running.
+pub fn raspberry_pi_os_release(
+    os_release: &HashMap<String, String>,
+    rpi_issue: bool,
+) -> Option<Distribution> {
+    let id = os_release.get("ID")?;
+    if id != "raspbian" && !(id == "debian" && rpi_issue) {
+        return None;
+    }
+
+    let version = os_release.get("VERSION_ID");
+    let derivative = Derivative {
+        id: id.clone(),
+        name: "Raspberry Pi OS".to_string(),
+        version: version.cloned(),
+    };
+    Some(Distribution::new("Debian", version.map_or("", |v| v.as_str())).derived_by(derivative))
+}
+
+/// Fill in the details of a distribution release from its os-release file. The codename is that
+/// of the release itself, so a derivative's own codename is only used if it doesn't name the
+/// Ubuntu release it is based on.
+pub fn describe_release(distribution: &mut Distribution, os_release: &HashMap<String, String>) {
+    distribution.codename = os_release
+        .get("UBUNTU_CODENAME")
+        .or_else(|| os_release.get("VERSION_CODENAME"))
+        .filter(|c| !c.is_empty())
+        .cloned();
+    distribution.version_id = os_release.get("VERSION_ID").cloned();
+    distribution.id_like = os_release
+        .get("ID_LIKE")
+        .map(|like| like.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    distribution.variant = os_release.get("VARIANT_ID").cloned();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derivative_release() {
+        let mint = parse_os_release(
+            "NAME=\"Linux Mint\"\nVERSION=\"22 (Wilma)\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n\
+            # A comment\nVERSION_ID=\"22\"\nUBUNTU_CODENAME=noble\n",
+        );
+        let mint = derivative_release(&mint).unwrap();
+        assert_eq!(mint.base(), Distribution::new("Ubuntu", "24.04"));
+        assert_eq!(
+            mint.derivative.map(|d| (d.id.clone(), d.to_string())),
+            Some(("linuxmint".to_string(), "Linux Mint 22".to_string()))
+        );
+
+        let pop = parse_os_release(
+            "NAME=\"Pop!_OS\"\nVERSION_ID=\"22.04\"\nID=pop\nID_LIKE=\"ubuntu debian\"\n\
+            VERSION_CODENAME=jammy\n",
+        );
+        assert_eq!(
+            derivative_release(&pop).map(|d| d.to_string()),
+            Some("Ubuntu 22.04 (derivative: Pop!_OS 22.04)".to_string())
+        );
+
+        // Linux Mint Debian Edition is based on Debian, which isn't supported.
+        let lmde = parse_os_release(
+            "NAME=\"LMDE\"\nID=linuxmint\nID_LIKE=debian\nVERSION_CODENAME=faye\n",
+        );
+        assert_eq!(derivative_release(&lmde), None);
+
+        let rocky = parse_os_release(
+            "NAME=\"Rocky Linux\"\nID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n",
+        );
+        assert_eq!(derivative_release(&rocky), None);
+
+        let raspbian = parse_os_release(
+            "PRETTY_NAME=\"Raspbian GNU/Linux 12 (bookworm)\"\nID=raspbian\nID_LIKE=debian\n\
+            VERSION_ID=\"12\"\n",
+        );
+        assert_eq!(derivative_release(&raspbian), None);
+        assert_eq!(
+            raspberry_pi_os_release(&raspbian, false).map(|d| d.to_string()),
+            Some("Debian 12 (derivative: Raspberry Pi OS 12)".to_string())
+        );
+
+        // The 64-bit edition is only told apart from Debian by /etc/rpi-issue.
+        let debian = parse_os_release("NAME=\"Debian GNU/Linux\"\nID=debian\nVERSION_ID=\"12\"\n");
+        assert_eq!(raspberry_pi_os_release(&debian, false), None);
+        assert!(raspberry_pi_os_release(&debian, true).is_some());
+
+        let ubuntu = parse_os_release("NAME=\"Ubuntu\"\nID=ubuntu\nUBUNTU_CODENAME=noble\n");
+        assert_eq!(derivative_release(&ubuntu), None);
+    }
+
+    #[test]
+    fn test_family_release() {
+        let rocky = parse_os_release(
+            "NAME=\"Rocky Linux\"\nVERSION=\"9.4 (Blue Onyx)\"\nID=\"rocky\"\n\
+            ID_LIKE=\"rhel centos fedora\"\nVERSION_ID=\"9.4\"\nPLATFORM_ID=\"platform:el9\"\n\
+            PRETTY_NAME=\"Rocky Linux 9.4 (Blue Onyx)\"\n",
+        );
+        assert_eq!(
+            family_release(&rocky).map(|d| d.to_string()),
+            Some("RHEL 9 (derivative: Rocky Linux 9.4)".to_string())
+        );
+
+        let alma = parse_os_release(
+            "NAME=\"AlmaLinux\"\nVERSION=\"8.10 (Cerulean Leopard)\"\nID=\"almalinux\"\n\
+            ID_LIKE=\"rhel centos fedora\"\nVERSION_ID=\"8.10\"\n",
+        );
+        let alma = family_release(&alma).unwrap();
+        assert_eq!(alma.base(), Distribution::new("RHEL", "8"));
+        assert_eq!(alma.derivative.unwrap().id, "almalinux");
+
+        let stream = parse_os_release(
+            "NAME=\"CentOS Stream\"\nVERSION=\"9\"\nID=\"centos\"\nID_LIKE=\"rhel fedora\"\n\
+            VERSION_ID=\"9\"\n",
+        );
+        assert_eq!(
+            family_release(&stream).map(|d| d.to_string()),
+            Some("RHEL 9 (derivative: CentOS Stream 9)".to_string())
+        );
+
+        let lmde = parse_os_release(
+            "NAME=\"LMDE\"\nVERSION_ID=\"6\"\nID=linuxmint\nID_LIKE=debian\n\
+            DEBIAN_CODENAME=bookworm\n",
+        );
+        assert_eq!(
+            family_release(&lmde).map(|d| d.to_string()),
+            Some("Debian 12 (derivative: LMDE 6)".to_string())
+        );
+
+        // RHEL itself, and the distributions of families already handled, aren't derivatives.
+        for contents in [
+            "NAME=\"Red Hat Enterprise Linux\"\nID=\"rhel\"\nVERSION_ID=\"9.4\"\n",
+            "NAME=\"Debian GNU/Linux\"\nID=debian\nVERSION_ID=\"12\"\n",
+            "NAME=\"Linux Mint\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n",
+        ] {
+            assert_eq!(family_release(&parse_os_release(contents)), None);
+        }
+    }
+
+    #[test]
+    fn test_describe_release() {
+        let os_release = parse_os_release(
+            "NAME=\"Linux Mint\"\nVERSION_ID=\"22\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n\
+            VERSION_CODENAME=wilma\nUBUNTU_CODENAME=noble\n",
+        );
+        let mut mint = derivative_release(&os_release).unwrap();
+        describe_release(&mut mint, &os_release);
+        mint.architecture = Some("amd64".to_string());
+
+        assert_eq!(mint.codename.as_deref(), Some("noble"));
+        assert_eq!(mint.version_id.as_deref(), Some("22"));
+        assert_eq!(mint.id_like, vec!["ubuntu", "debian"]);
+        assert_eq!(mint.base(), Distribution::new("Ubuntu", "24.04"));
+        assert_eq!(
+            mint.describe(),
+            "Ubuntu 24.04 (noble, amd64), a derivative running Linux Mint 22"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::{BuildInfo, ErrorCode, ExperimentFailures, Worker};
+
+/// The format used to render the output of informational commands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable tables and text.
+    #[default]
+    Human,
+    /// Machine-readable JSON.
+    Json,
+    /// Machine-readable YAML.
+    Yaml,
+}
+
+/// Render a value in one of the machine-readable output formats. The output of each
+/// informational command follows the same JSON Schema in either format (see `oxidizr schema`).
+pub fn structured<T: Serialize>(format: OutputFormat, value: &T) -> Result<String> {
+    match format {
+        OutputFormat::Yaml => Ok(serde_norway::to_string(value)?),
+        OutputFormat::Json | OutputFormat::Human => {
+            Ok(format!("{}\n", serde_json::to_string_pretty(value)?))
+        }
+    }
+}
+
+/// Render a boolean as "yes" or "no" for human-readable output.
+pub fn yes_no(value: bool) -> String {
+    match value {
+        true => "yes".to_string(),
+        false => "no".to_string(),
+    }
+}
+
+/// A machine-readable report of a failure, emitted in place of the usual error message when JSON
+/// output is requested. Build and environment details are included to make bug reports actionable.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ErrorReport {
+    pub code: Option<&'static str>,
+    pub hint: Option<&'static str>,
+    pub error: String,
+    pub causes: Vec<String>,
+    /// The outcome for each experiment, when the operation failed for only some of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experiments: Option<ExperimentFailures>,
+    pub build: BuildInfo,
+}
+
+impl ErrorReport {
+    /// Create a new `ErrorReport` from an error, gathering build information from the system.
+    pub fn new(error: &anyhow::Error, system: &dyn Worker) -> Self {
+        let code = ErrorCode::of(error);
+        Self {
+            code: code.map(ErrorCode::id),
+            hint: code.map(ErrorCode::hint),
+            error: error.to_string(),
+            causes: error.chain().skip(1).map(|c| c.to_string()).collect(),
+            experiments: error.downcast_ref::<ExperimentFailures>().cloned(),
+            build: BuildInfo::gather(system),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Result, bail};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    ffi::OsStr,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+/// A file managed by oxidizr: one that is replaced, backed up and restored. Distinct from a
+/// `ReplacementSource`, so that the two can't be swapped when replacing a file.
+///
+/// A `ManagedTarget` is always an absolute path naming a file, without `.` or `..` components.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, JsonSchema)]
+pub struct ManagedTarget(PathBuf);
+
+/// A replacement binary that a managed file is replaced with, either as a symlink to it or as a
+/// copy of it. It is validated and normalized in the same way as a `ManagedTarget`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, JsonSchema)]
+pub struct ReplacementSource(PathBuf);
+
+impl ManagedTarget {
+    /// Validate and normalize the path of a managed file.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        normalize("managed file", path.as_ref()).map(Self)
+    }
+
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The name of the file, which a `ManagedTarget` always has.
+    pub fn file_name(&self) -> &OsStr {
+        self.0.file_name().unwrap_or_default()
+    }
+
+    /// The file alongside this one, named after it with the specified suffix, such as
+    /// `/usr/bin/.date.oxidizr.bak` for the suffix `oxidizr.bak`.
+    pub fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut name = OsStr::new(".").to_os_string();
+        name.push(self.file_name());
+        name.push(".");
+        name.push(suffix);
+        self.0.with_file_name(name)
+    }
+
+    /// The backup of the file, as made by `Worker::backup_file`.
+    pub fn backup(&self) -> PathBuf {
+        self.sibling("oxidizr.bak")
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl ReplacementSource {
+    /// Validate and normalize the path of a replacement binary.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        normalize("replacement", path.as_ref()).map(Self)
+    }
+
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The name of the file, which a `ReplacementSource` always has.
+    pub fn file_name(&self) -> &OsStr {
+        self.0.file_name().unwrap_or_default()
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl AsRef<Path> for ManagedTarget {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ReplacementSource {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl fmt::Display for ManagedTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+impl fmt::Display for ReplacementSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+/// Check that a path is absolute, names a file and has no `..` components, which may not mean
+/// what they appear to once symlinks are followed. `.` components and repeated or trailing
+/// separators are dropped.
+fn normalize(kind: &str, path: &Path) -> Result<PathBuf> {
+    if !path.is_absolute() {
+        bail!("the {kind} '{}' isn't an absolute path", path.display());
+    }
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                bail!("the {kind} '{}' has a '..' component", path.display())
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    if normalized.file_name().is_none() {
+        bail!("the {kind} '{}' doesn't name a file", path.display());
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_managed_target() {
+        let target = ManagedTarget::new("/usr//bin/./date/").unwrap();
+        assert_eq!(target.as_path(), Path::new("/usr/bin/date"));
+        assert_eq!(target.file_name(), "date");
+        assert_eq!(target.backup(), Path::new("/usr/bin/.date.oxidizr.bak"));
+        assert_eq!(target.to_string(), "/usr/bin/date");
+
+        assert!(ManagedTarget::new("bin/date").is_err());
+        assert!(ManagedTarget::new("/usr/bin/../sbin/date").is_err());
+        assert!(ManagedTarget::new("/").is_err());
+        assert!(ReplacementSource::new("").is_err());
+        assert_eq!(
+            ReplacementSource::new("/usr/lib/cargo/bin/coreutils")
+                .unwrap()
+                .file_name(),
+            "coreutils"
+        );
+    }
+}
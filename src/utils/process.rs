@@ -0,0 +1,101 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A process running on the system, and the executable it was started from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    pub exe: PathBuf,
+    /// The systemd service the process belongs to, if any.
+    pub unit: Option<String>,
+}
+
+/// List the processes running on the system by inspecting `/proc/<pid>/exe`. Processes whose
+/// executable can't be read, such as kernel threads, are omitted.
+pub fn list_processes(proc: &Path) -> Result<Vec<Process>> {
+    let mut processes = Vec::new();
+
+    for entry in fs::read_dir(proc)?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|p| p.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        let dir = entry.path();
+        let Ok(exe) = fs::read_link(dir.join("exe")) else {
+            continue;
+        };
+
+        processes.push(Process {
+            pid,
+            name: fs::read_to_string(dir.join("comm"))
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            exe: parse_exe(&exe),
+            unit: fs::read_to_string(dir.join("cgroup"))
+                .ok()
+                .and_then(|c| parse_unit(&c)),
+        });
+    }
+
+    processes.sort_by_key(|p| p.pid);
+    Ok(processes)
+}
+
+/// Strip the ` (deleted)` suffix the kernel appends to the executable of a process once its file
+/// has been removed or replaced.
+fn parse_exe(exe: &Path) -> PathBuf {
+    let exe = exe.to_string_lossy();
+    PathBuf::from(exe.strip_suffix(" (deleted)").unwrap_or(&exe))
+}
+
+/// Find the system service a process belongs to from the contents of `/proc/<pid>/cgroup`.
+/// Processes in user sessions or user services are not attributed to a unit.
+fn parse_unit(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .filter_map(|line| line.rsplit(':').next())
+        .filter_map(|path| path.strip_prefix("/system.slice/"))
+        .flat_map(|path| path.split('/'))
+        .rfind(|component| component.ends_with(".service"))
+        .map(|unit| unit.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exe() {
+        assert_eq!(
+            parse_exe(Path::new("/usr/bin/tail (deleted)")),
+            PathBuf::from("/usr/bin/tail")
+        );
+        assert_eq!(
+            parse_exe(Path::new("/usr/bin/tail")),
+            PathBuf::from("/usr/bin/tail")
+        );
+    }
+
+    #[test]
+    fn test_parse_unit() {
+        assert_eq!(
+            parse_unit("0::/system.slice/cron.service\n"),
+            Some("cron.service".to_string())
+        );
+        assert_eq!(
+            parse_unit("0::/user.slice/user-1000.slice/user@1000.service/app.slice/tail.scope\n"),
+            None
+        );
+        assert_eq!(parse_unit("0::/init.scope\n"), None);
+    }
+}
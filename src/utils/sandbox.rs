@@ -0,0 +1,105 @@
+use super::ErrorCode;
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The directories oxidizr writes to: the system binary directories containing the files it
+/// replaces and their backups, its own state directory, the systemd-tmpfiles configuration
+/// used to schedule replacements at boot, and the systemd units ending trials.
+pub const DEFAULT_WRITE_ROOTS: &[&str] = &[
+    "/usr/bin",
+    "/usr/sbin",
+    "/bin",
+    "/sbin",
+    "/var/lib/oxidizr",
+    "/etc/tmpfiles.d",
+    "/etc/systemd/system",
+];
+
+/// An allowlist of the directories in which oxidizr may create, replace or remove files. Every
+/// file operation performed by the `System` is checked against the allowlist first, so that a
+/// bug in path construction can't write outside of the directories oxidizr manages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteAllowlist {
+    roots: Vec<PathBuf>,
+}
+
+impl Default for WriteAllowlist {
+    fn default() -> Self {
+        Self::new(DEFAULT_WRITE_ROOTS.iter().map(PathBuf::from))
+    }
+}
+
+impl WriteAllowlist {
+    /// Create an allowlist of the specified directories. Symlinked directories are resolved, so
+    /// that `/bin` matches `/usr/bin` on merged-usr systems.
+    pub fn new(roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        let roots = roots
+            .into_iter()
+            .map(|root| fs::canonicalize(&root).unwrap_or(root))
+            .collect();
+        Self { roots }
+    }
+
+    /// Allow writing to further directories as well, such as those configured for the files an
+    /// experiment replaces.
+    pub fn with_roots(mut self, roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        let added = Self::new(roots).roots;
+        for root in added {
+            if !self.roots.contains(&root) {
+                self.roots.push(root);
+            }
+        }
+        self
+    }
+
+    /// Ensure that a file may be written: its path must be absolute, free of `..` components,
+    /// and its parent directory must be inside one of the allowed directories.
+    pub fn check(&self, path: &Path) -> Result<()> {
+        anyhow::ensure!(
+            path.is_absolute() && !path.components().any(|c| c == Component::ParentDir),
+            "refusing to write to '{}': not a normalised absolute path",
+            path.display()
+        );
+
+        let parent = path.parent().unwrap_or(path);
+        let parent = fs::canonicalize(parent).unwrap_or(parent.to_path_buf());
+        if !self.roots.iter().any(|root| parent.starts_with(root)) {
+            return Err(ErrorCode::OutsideManagedDirectories.error(format!(
+                "refusing to write to '{}': outside of the directories managed by oxidizr",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_allowlist() {
+        let root = std::env::temp_dir().join(format!("oxidizr-sandbox-{}", std::process::id()));
+        let managed = root.join("bin");
+        fs::create_dir_all(&managed).unwrap();
+        let link = root.join("link");
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&managed, &link).unwrap();
+
+        let allowlist = WriteAllowlist::new(vec![managed.clone()]);
+        assert!(allowlist.check(&managed.join("date")).is_ok());
+        assert!(allowlist.check(&managed.join(".date.oxidizr.bak")).is_ok());
+        // Paths through a symlink to a managed directory are resolved.
+        assert!(allowlist.check(&link.join("date")).is_ok());
+
+        assert!(allowlist.check(&root.join("date")).is_err());
+        assert!(allowlist.check(&managed.join("../date")).is_err());
+        assert!(allowlist.check(Path::new("bin/date")).is_err());
+        assert!(allowlist.check(Path::new("/etc/passwd")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
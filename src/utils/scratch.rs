@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+
+/// The prefix of the name of every scratch directory, which is followed by the id of the process
+/// that created it and its purpose, such as `oxidizr-scratch-1234-triggers`.
+const SCRATCH_PREFIX: &str = "oxidizr-scratch-";
+
+/// The mode of a scratch directory: accessible by its owner alone, so that other users can't
+/// tamper with what is staged in it.
+const SCRATCH_MODE: u32 = 0o700;
+
+/// A directory for files that are only needed while an operation runs, such as a package being
+/// built, which is removed with everything in it when dropped, including when the operation
+/// fails or panics. Directories left behind by a process that was killed are removed by
+/// `clean_stale_scratch` the next time oxidizr changes the system.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Create an empty scratch directory under `root`, as reported by `Worker::scratch_root`, for
+    /// the specified purpose.
+    pub fn new(root: &Path, purpose: &str) -> Result<Self> {
+        let path = root.join(format!("{SCRATCH_PREFIX}{}-{purpose}", std::process::id()));
+        if fs::exists(&path)? {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)
+            .with_context(|| format!("unable to create scratch directory {}", path.display()))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(SCRATCH_MODE))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            warn!(
+                "Unable to remove scratch directory {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Remove the scratch directories under `root` whose process is no longer running, such as one
+/// killed part way through building a package. Failures are logged rather than returned.
+pub fn clean_stale_scratch(root: &Path) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(SCRATCH_PREFIX))
+            .and_then(|n| n.split('-').next())
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if Path::new("/proc").join(pid.to_string()).exists() {
+            continue;
+        }
+
+        let path = entry.path();
+        match fs::remove_dir_all(&path) {
+            Ok(()) => debug!("Removed stale scratch directory {}", path.display()),
+            Err(e) => warn!(
+                "Unable to remove stale scratch directory {}: {e}",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_dir() {
+        let root =
+            std::env::temp_dir().join(format!("oxidizr-scratch-test-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+
+        let scratch = ScratchDir::new(&root, "triggers").unwrap();
+        let path = scratch.path().to_path_buf();
+        assert!(path.starts_with(&root));
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SCRATCH_MODE);
+        fs::write(path.join("control"), "Package: oxidizr-triggers\n").unwrap();
+        drop(scratch);
+        assert!(!path.exists());
+
+        // Only the directories of processes that are no longer running are cleaned up.
+        let running = ScratchDir::new(&root, "running").unwrap();
+        let stale = root.join(format!("{SCRATCH_PREFIX}{}-crashed", u32::MAX));
+        let unrelated = root.join("unrelated");
+        fs::create_dir_all(stale.join("nested")).unwrap();
+        fs::create_dir_all(&unrelated).unwrap();
+        clean_stale_scratch(&root);
+        assert!(running.path().exists());
+        assert!(!stale.exists());
+        assert!(unrelated.exists());
+
+        drop(running);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
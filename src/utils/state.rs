@@ -0,0 +1,266 @@
+use super::{ErrorCode, STATE_FILE_MODE, Worker};
+use anyhow::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// The field of a state file recording the version of its format.
+const VERSION_FIELD: &str = "schema_version";
+
+/// A record kept as JSON in the state directory, such as the pinned binaries. Each file records
+/// the version of its format, so that the files written by older versions of oxidizr can be
+/// migrated when read, and those written by newer versions aren't misread.
+///
+/// Files are read and written through `read_state`, `write_state` and `update_state`, which hold
+/// an advisory lock on the file while doing so, so that concurrent runs of oxidizr - such as a
+/// timer ending a trial while an administrator enables another experiment - can't lose each
+/// other's changes.
+pub trait StateFile: Serialize + DeserializeOwned {
+    /// The location of the file.
+    const PATH: &'static str;
+
+    /// The version of the format that this version of oxidizr reads and writes. Files written
+    /// before formats were versioned are version 0.
+    const VERSION: u32 = 1;
+
+    /// Migrate the contents of the file from the format of `version` to that of the next
+    /// version. By default, formats differ only in their version.
+    fn migrate(version: u32, contents: Value) -> Result<Value> {
+        let _ = version;
+        Ok(contents)
+    }
+}
+
+/// A state file as written, with the version of its format ahead of its contents.
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    state: &'a T,
+}
+
+/// An advisory lock on a state file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: fs::File,
+}
+
+/// Read a state file, migrating it to the current format, or `None` if the file doesn't exist or
+/// is empty. Fails with `ErrorCode::StateVersionUnsupported` if the file was written by a newer
+/// version of oxidizr.
+pub fn read_state<T: StateFile>(system: &dyn Worker) -> Result<Option<T>> {
+    let _lock = system.lock_file(Path::new(T::PATH), false)?;
+    read_unlocked(system)
+}
+
+/// Write a state file in the current format.
+pub fn write_state<T: StateFile>(system: &dyn Worker, state: &T) -> Result<()> {
+    let _lock = system.lock_file(Path::new(T::PATH), true)?;
+    write_unlocked(system, state)
+}
+
+/// Read a state file, starting from the default if there is none, change it and write it back,
+/// holding the lock on it throughout. The state written is returned.
+pub fn update_state<T: StateFile + Default>(
+    system: &dyn Worker,
+    change: impl FnOnce(&mut T) -> Result<()>,
+) -> Result<T> {
+    let _lock = system.lock_file(Path::new(T::PATH), true)?;
+    let mut state = read_unlocked(system)?.unwrap_or_default();
+    change(&mut state)?;
+    write_unlocked(system, &state)?;
+    Ok(state)
+}
+
+fn read_unlocked<T: StateFile>(system: &dyn Worker) -> Result<Option<T>> {
+    let path = T::PATH;
+    let contents = match system.read_file(Path::new(path))? {
+        Some(contents) if !contents.trim().is_empty() => contents,
+        _ => return Ok(None),
+    };
+    let mut value: Value =
+        serde_json::from_str(&contents).with_context(|| format!("unable to parse {path}"))?;
+    let version = match value.as_object_mut() {
+        Some(object) => match object.remove(VERSION_FIELD) {
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .with_context(|| format!("unable to parse {path}: invalid {VERSION_FIELD}"))?,
+            None => 0,
+        },
+        None => anyhow::bail!("unable to parse {path}: expected a JSON object"),
+    };
+    if version > T::VERSION {
+        return Err(ErrorCode::StateVersionUnsupported.error(format!(
+            "{path} was written by a newer version of oxidizr, in version {version} of its \
+            format, and this version only reads up to version {}",
+            T::VERSION
+        )));
+    }
+    for from in version..T::VERSION {
+        value = T::migrate(from, value).with_context(|| {
+            format!("unable to migrate {path} from version {from} of its format")
+        })?;
+    }
+    serde_json::from_value(value)
+        .map(Some)
+        .with_context(|| format!("unable to parse {path}"))
+}
+
+fn write_unlocked<T: StateFile>(system: &dyn Worker, state: &T) -> Result<()> {
+    let versioned = Versioned {
+        schema_version: T::VERSION,
+        state,
+    };
+    system.write_file(
+        Path::new(T::PATH),
+        &serde_json::to_string_pretty(&versioned)?,
+    )
+}
+
+/// Take an advisory lock on a file, through a lock file alongside it, such as
+/// `/var/lib/oxidizr/.pins.json.lock`, waiting for any other process holding a conflicting lock
+/// to release it. An exclusive lock creates the lock file if needed; a shared lock is only taken
+/// if the lock file exists and can be opened, so that unprivileged users can still read the
+/// state, and `None` is returned otherwise.
+pub fn lock(path: &Path, exclusive: bool) -> Result<Option<FileLock>> {
+    let lock_path = lock_path(path);
+    let file = match exclusive {
+        true => {
+            if let Some(parent) = lock_path.parent() {
+                super::create_dirs(parent)?;
+            }
+            fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .mode(STATE_FILE_MODE)
+                .open(&lock_path)
+                .with_context(|| format!("unable to open {}", lock_path.display()))?
+        }
+        false => match fs::File::open(&lock_path) {
+            Ok(file) => file,
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::PermissionDenied) => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+
+    let acquired = match exclusive {
+        true => file.try_lock(),
+        false => file.try_lock_shared(),
+    };
+    if acquired.is_err() {
+        info!(
+            "Waiting for another oxidizr process to finish with {}",
+            path.display()
+        );
+        match exclusive {
+            true => file.lock(),
+            false => file.lock_shared(),
+        }
+        .with_context(|| format!("unable to lock {}", lock_path.display()))?;
+    }
+    Ok(Some(FileLock { _file: file }))
+}
+
+/// The lock file of a file.
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        names: Vec<String>,
+    }
+
+    impl StateFile for Record {
+        const PATH: &'static str = "/var/lib/oxidizr/record.json";
+        const VERSION: u32 = 2;
+
+        // Until version 2, a single name was recorded.
+        fn migrate(version: u32, mut contents: Value) -> Result<Value> {
+            if version == 1 {
+                let name = contents["name"].take();
+                contents = serde_json::json!({ "names": [name] });
+            }
+            Ok(contents)
+        }
+    }
+
+    #[test]
+    fn test_state_versions() {
+        let runner = MockSystem::default();
+        let path = Path::new(Record::PATH);
+        assert_eq!(read_state::<Record>(&runner).unwrap(), None);
+
+        update_state(&runner, |r: &mut Record| {
+            r.names.push("sed".to_string());
+            Ok(())
+        })
+        .unwrap();
+        let written = runner.read_file(path).unwrap().unwrap();
+        assert!(written.contains("\"schema_version\": 2"));
+        let record: Record = read_state(&runner).unwrap().unwrap();
+        assert_eq!(record.names, ["sed"]);
+
+        // Unversioned files, and those in older formats, are migrated.
+        runner.write_file(path, "{\"name\": \"tar\"}").unwrap();
+        let record: Record = read_state(&runner).unwrap().unwrap();
+        assert_eq!(record.names, ["tar"]);
+        runner
+            .write_file(path, "{\"schema_version\": 1, \"name\": \"gzip\"}")
+            .unwrap();
+        let record: Record = read_state(&runner).unwrap().unwrap();
+        assert_eq!(record.names, ["gzip"]);
+
+        // Files in newer formats aren't misread.
+        runner
+            .write_file(path, "{\"schema_version\": 3, \"names\": []}")
+            .unwrap();
+        let err = read_state::<Record>(&runner).unwrap_err();
+        assert_eq!(
+            ErrorCode::of(&err),
+            Some(ErrorCode::StateVersionUnsupported)
+        );
+    }
+
+    #[test]
+    fn test_lock() {
+        let root = std::env::temp_dir().join(format!("oxidizr-lock-{}", std::process::id()));
+        let path = root.join("pins.json");
+
+        // Without a lock file, nobody has written the file, so there is nothing to wait for.
+        assert!(lock(&path, false).unwrap().is_none());
+
+        let exclusive = lock(&path, true).unwrap().unwrap();
+        assert!(lock_path(&path).exists());
+        let file = fs::File::open(lock_path(&path)).unwrap();
+        assert!(file.try_lock_shared().is_err());
+        drop(exclusive);
+        assert!(file.try_lock_shared().is_ok());
+        drop(file);
+
+        let shared = lock(&path, false).unwrap();
+        assert!(shared.is_some());
+        drop(shared);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+
+/// A simple plain-text table with left-aligned, padded columns.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Create a new `Table` with the specified column headers.
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Append a row to the table. Each row should contain one cell per column.
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Render the table as a GitHub-flavoured Markdown table.
+    pub fn markdown(&self) -> String {
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+
+        let mut markdown = line(&self.headers);
+        markdown.push_str(&line(&vec!["---".to_string(); self.headers.len()]));
+        for row in &self.rows {
+            markdown.push_str(&line(row));
+        }
+        markdown
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in self.rows.iter() {
+            for (i, cell) in row.iter().enumerate() {
+                if let Some(width) = widths.get_mut(i) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+        }
+
+        for row in std::iter::once(&self.headers).chain(self.rows.iter()) {
+            let line = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<String>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Table;
+
+    #[test]
+    fn test_table_display() {
+        let mut table = Table::new(&["NAME", "INSTALLED"]);
+        table.add_row(vec!["coreutils".to_string(), "yes".to_string()]);
+        table.add_row(vec!["sudo-rs".to_string(), "no".to_string()]);
+
+        let expected = "NAME       INSTALLED\ncoreutils  yes\nsudo-rs    no\n";
+        assert_eq!(table.to_string(), expected);
+    }
+}
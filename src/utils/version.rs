@@ -0,0 +1,180 @@
+use anyhow::Result;
+use serde::{Serialize, Serializer};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A Debian package version of the form `[epoch:]upstream_version[-debian_revision]`, ordered
+/// using the same rules as `dpkg --compare-versions`.
+#[derive(Debug, Clone, Eq)]
+pub struct PackageVersion {
+    pub epoch: u64,
+    pub upstream: String,
+    pub revision: String,
+}
+
+impl FromStr for PackageVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        let version = version.trim();
+        anyhow::ensure!(!version.is_empty(), "empty package version");
+
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) => (
+                epoch
+                    .parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("invalid epoch in version '{version}'"))?,
+                rest,
+            ),
+            None => (0, version),
+        };
+
+        let (upstream, revision) = match rest.rsplit_once('-') {
+            Some((upstream, revision)) => (upstream, revision),
+            None => (rest, ""),
+        };
+
+        anyhow::ensure!(
+            upstream.starts_with(|c: char| c.is_ascii_digit()),
+            "invalid package version '{version}': upstream version must start with a digit"
+        );
+
+        Ok(Self {
+            epoch,
+            upstream: upstream.to_string(),
+            revision: revision.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if !self.revision.is_empty() {
+            write!(f, "-{}", self.revision)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for PackageVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Ord for PackageVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| compare_part(&self.upstream, &other.upstream))
+            .then_with(|| compare_part(&self.revision, &other.revision))
+    }
+}
+
+impl PartialOrd for PackageVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PackageVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+/// Compare an upstream version or revision, alternating between non-digit and digit runs as
+/// described in deb-version(7).
+fn compare_part(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    while !a.is_empty() || !b.is_empty() {
+        let (a_text, a_rest) = split_run(a, |c| !c.is_ascii_digit());
+        let (b_text, b_rest) = split_run(b, |c| !c.is_ascii_digit());
+        let ordering = compare_text(a_text, b_text);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+
+        let (a_num, a_rest) = split_run(a_rest, |c| c.is_ascii_digit());
+        let (b_num, b_rest) = split_run(b_rest, |c| c.is_ascii_digit());
+        let ordering = compare_numeric(a_num, b_num);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+
+        (a, b) = (a_rest, b_rest);
+    }
+    Ordering::Equal
+}
+
+/// Split the longest prefix of `s` whose bytes match `pred` from the remainder.
+fn split_run(s: &[u8], pred: impl Fn(u8) -> bool) -> (&[u8], &[u8]) {
+    let end = s.iter().position(|&c| !pred(c)).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// Compare non-digit runs: letters sort before non-letters, and `~` sorts before everything,
+/// even the end of the run.
+fn compare_text(a: &[u8], b: &[u8]) -> Ordering {
+    let weight = |c: Option<&u8>| match c {
+        Some(b'~') => -1,
+        None => 0,
+        Some(c) if c.is_ascii_alphabetic() => *c as i32,
+        Some(c) => *c as i32 + 256,
+    };
+
+    for i in 0..a.len().max(b.len()) {
+        let ordering = weight(a.get(i)).cmp(&weight(b.get(i)));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Compare digit runs numerically, treating an empty run as zero.
+fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
+    let trim = |s: &[u8]| -> Vec<u8> { s.iter().skip_while(|&&c| c == b'0').copied().collect() };
+    let (a, b) = (trim(a), trim(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> PackageVersion {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_package_version() {
+        let version = v("1:0.0.28-1ubuntu1");
+        assert_eq!(version.epoch, 1);
+        assert_eq!(version.upstream, "0.0.28");
+        assert_eq!(version.revision, "1ubuntu1");
+        assert_eq!(version.to_string(), "1:0.0.28-1ubuntu1");
+
+        assert_eq!(v("0.2.5").revision, "");
+        assert_eq!(v("1.0-rc1-2").upstream, "1.0-rc1");
+        assert!("".parse::<PackageVersion>().is_err());
+        assert!("x:1.0".parse::<PackageVersion>().is_err());
+        assert!("rc1".parse::<PackageVersion>().is_err());
+    }
+
+    #[test]
+    fn test_compare_package_versions() {
+        assert!(v("0.2.5-1") < v("0.2.10-1"));
+        assert!(v("0.2.5-1") < v("0.2.5-1ubuntu1"));
+        assert!(v("1.0~rc1") < v("1.0"));
+        assert!(v("1.0") < v("1.0a"));
+        assert!(v("1.0a") < v("1.0+"));
+        assert!(v("9.9") < v("1:0.1"));
+        assert_eq!(v("1.01"), v("1.1"));
+        assert_eq!(v("0:1.0-1"), v("1.0-1"));
+    }
+}
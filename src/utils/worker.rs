@@ -1,17 +1,65 @@
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
     path::{Path, PathBuf},
-    process::Output,
+    process::{Output, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
-use std::fs;
-use tracing::{debug, trace, warn};
-use which::which;
+use crate::experiments::{Directories, Generations, PolicyName};
+use crate::progress::Progress;
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{fmt, fs};
+use tracing::{debug, info, trace, warn};
 
-use super::{Command, Distribution};
+#[cfg(feature = "native-apt")]
+use super::apt;
+use super::{
+    Command, Distribution, ErrorCode, FILE_MODE, FileLock, Filesystem, ManagedTarget,
+    OS_RELEASE_PATH, PackageVersion, Process, RPI_ISSUE_PATH, ReplacementSource, WriteAllowlist,
+    create_dirs, derivative_release, describe_release, detect_wsl, family_release, file_mode,
+    hex_digest, is_windows_path, list_processes, lock, parse_df, parse_os_release,
+    raspberry_pi_os_release, sync_file, sync_parent, warn_or_fail, write_atomic,
+};
+
+/// How long a connection is given to be made by `Worker::can_connect`.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// The interface through which oxidizr inspects and changes the system. Experiments, commands and
+/// the operations on them take a `&dyn Worker`, so that any implementation, such as a
+/// `JournaledWorker` wrapping the `System` or the `MockSystem` used in tests, can be passed in.
 pub trait Worker {
-    /// Report the distribution information for the system.
+    /// Report whether conditions that are normally only warnings should fail the operation.
+    fn strict(&self) -> bool {
+        false
+    }
+
+    /// Report whether changes are only recorded rather than made, as by a `DryRunWorker`.
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    /// Report the compatibility policies selected in addition to the supported releases of each
+    /// experiment.
+    fn compatibility_policies(&self) -> &[PolicyName] {
+        &[]
+    }
+
+    /// Report the directory under which scratch directories are created: the one configured, or
+    /// else `$TMPDIR`, or else `/tmp`.
+    fn scratch_root(&self) -> PathBuf {
+        std::env::temp_dir()
+    }
+
+    /// Report the distribution information for the system. A derivative of a supported
+    /// distribution is reported as the release it is based on, marked as a derivative.
     fn distribution(&self) -> Result<Distribution> {
         let cmd = Command::build("lsb_release", &["-is"]);
         let id = self.run(&cmd)?;
@@ -19,10 +67,31 @@ pub trait Worker {
         let cmd = Command::build("lsb_release", &["-rs"]);
         let release = self.run(&cmd)?;
 
-        Ok(Distribution {
-            id: String::from_utf8(id.stdout)?.trim().to_string(),
-            release: String::from_utf8(release.stdout)?.trim().to_string(),
-        })
+        let mut distribution = Distribution::new(
+            String::from_utf8(id.stdout)?.trim(),
+            String::from_utf8(release.stdout)?.trim(),
+        );
+        let os_release = self
+            .read_file(Path::new(OS_RELEASE_PATH))
+            .ok()
+            .flatten()
+            .map(|contents| parse_os_release(&contents))
+            .unwrap_or_default();
+
+        if distribution.id != "Ubuntu" {
+            let rpi_issue = self
+                .read_file(Path::new(RPI_ISSUE_PATH))
+                .is_ok_and(|f| f.is_some());
+            if let Some(derivative) = derivative_release(&os_release)
+                .or_else(|| raspberry_pi_os_release(&os_release, rpi_issue))
+                .or_else(|| family_release(&os_release))
+            {
+                distribution = derivative;
+            }
+        }
+        describe_release(&mut distribution, &os_release);
+        distribution.architecture = self.architecture().ok().filter(|a| !a.is_empty());
+        Ok(distribution)
     }
 
     /// Run a command and return the output. If the command fails, an error will be returned.
@@ -32,22 +101,79 @@ pub trait Worker {
     fn list_files(&self, directory: PathBuf) -> Result<Vec<PathBuf>>;
 
     /// Find the path to a binary in the system's PATH.
-    fn which(&self, binary_name: &str) -> Result<PathBuf>;
+    fn which(&self, binary_name: &OsStr) -> Result<PathBuf>;
 
     /// Install a package using the system package manager.
     fn install_package(&self, package: &str) -> Result<()> {
-        let cmd = Command::build("apt-get", &["install", "-y", package]);
-        self.run(&cmd)?;
+        self.install_packages(&[package.to_string()])
+    }
+
+    /// Install several packages in a single transaction using the system package manager.
+    fn install_packages(&self, packages: &[String]) -> Result<()> {
+        let mut args = vec!["install", "-y"];
+        args.extend(packages.iter().map(|p| p.as_str()));
+        self.run(&Command::build("apt-get", &args))?;
         Ok(())
     }
 
     /// Remove a package using the system package manager.
     fn remove_package(&self, package: &str) -> Result<()> {
-        let cmd = Command::build("apt-get", &["remove", "-y", package]);
-        self.run(&cmd)?;
+        self.remove_packages(&[package.to_string()])
+    }
+
+    /// Remove several packages in a single transaction using the system package manager.
+    fn remove_packages(&self, packages: &[String]) -> Result<()> {
+        let mut args = vec!["remove", "-y"];
+        args.extend(packages.iter().map(|p| p.as_str()));
+        self.run(&Command::build("apt-get", &args))?;
+        Ok(())
+    }
+
+    /// Install a specific version of a package, downgrading it if a newer version is installed.
+    fn install_package_version(&self, package: &str, version: &str) -> Result<()> {
+        let package = format!("{package}={version}");
+        let args = ["install", "-y", "--allow-downgrades", &package];
+        self.run(&Command::build("apt-get", &args))?;
         Ok(())
     }
 
+    /// Install a package from a package file, such as one downloaded beforehand, downgrading it if
+    /// a newer version is installed. Only its dependencies are fetched from the package archive.
+    fn install_package_file(&self, file: &Path) -> Result<()> {
+        let args = ["install", "-y", "--allow-downgrades"];
+        self.run(&Command::build("apt-get", &args).arg(file))?;
+        Ok(())
+    }
+
+    /// List the versions of a package available from the package archive.
+    fn available_versions(&self, package: &str) -> Result<Vec<String>> {
+        let output = self.run(&Command::build("apt-cache", &["madison", package]))?;
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .filter_map(|line| line.split('|').nth(1))
+            .map(|version| version.trim().to_string())
+            .collect())
+    }
+
+    /// Report the version of a package that would be installed, or `None` if the package archive
+    /// has no version of it.
+    fn candidate_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        let output = self.run(&Command::build("apt-cache", &["policy", package]))?;
+        String::from_utf8(output.stdout)?
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Candidate:"))
+            .map(str::trim)
+            .filter(|version| !version.is_empty() && *version != "(none)")
+            .map(|version| version.parse())
+            .transpose()
+    }
+
+    /// Report the architecture that packages are installed for, such as `amd64` or `armhf`.
+    fn architecture(&self) -> Result<String> {
+        let output = self.run(&Command::build("dpkg", &["--print-architecture"]))?;
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    }
+
     /// Update the package lists using the system package manager.
     fn update_package_lists(&self) -> Result<()> {
         let cmd = Command::build("apt-get", &["update"]);
@@ -64,45 +190,260 @@ pub trait Worker {
         }
     }
 
+    /// Report the installed version of a package, or `None` if the package is not installed.
+    fn installed_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        let cmd = Command::build("dpkg-query", &["-W", "-f=${Version}", package]);
+        match self.run(&cmd) {
+            Ok(output) => {
+                let version = String::from_utf8(output.stdout)?;
+                match version.trim().is_empty() {
+                    true => Ok(None),
+                    false => Ok(Some(version.parse()?)),
+                }
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Report the destination of a symlink, or `None` if the path is not a symlink.
+    fn read_symlink(&self, path: &Path) -> Result<Option<PathBuf>>;
+
+    /// List the processes running on the system, and the executables they were started from.
+    fn processes(&self) -> Result<Vec<Process>>;
+
+    /// Read the contents of a file, or `None` if the file does not exist.
+    fn read_file(&self, path: &Path) -> Result<Option<String>>;
+
+    /// Write the contents of a file, creating its parent directory if needed. Files in the state
+    /// directory are readable by root alone, and others by everyone, whatever the umask.
+    fn write_file(&self, path: &Path, contents: &str) -> Result<()>;
+
+    /// Take an advisory lock on a state file, exclusive or shared, until the returned lock is
+    /// dropped. Workers that don't touch the filesystem take none.
+    fn lock_file(&self, path: &Path, exclusive: bool) -> Result<Option<FileLock>> {
+        let _ = (path, exclusive);
+        Ok(None)
+    }
+
     /// Replace a file with a symlink. If the target file already exists, it will be backed up.
-    fn replace_file_with_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()>;
+    /// An existing symlink is left in place, and the reason it was skipped is returned.
+    fn replace_file_with_symlink(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+    ) -> Result<Option<SkipReason>>;
+
+    /// Replace a file with a root-owned copy of `source` with the specified mode, for binaries
+    /// such as setuid executables that can't be replaced with a symlink. If the target file
+    /// already exists, it will be backed up - unless it is a copy made by a previous call.
+    fn replace_file_with_copy(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+        mode: u32,
+    ) -> Result<()>;
+
+    /// Report the hex-encoded sha256 checksum of a file, or `None` if the file does not exist.
+    fn file_checksum(&self, path: &Path) -> Result<Option<String>>;
+
+    /// Report the size of a regular file in bytes, or `None` if it doesn't exist or isn't a
+    /// regular file. Symlinks aren't followed.
+    fn file_size(&self, path: &Path) -> Result<Option<u64>>;
+
+    /// Report whether a TCP connection can be made to `host` on `port` within
+    /// `CONNECT_TIMEOUT`, such as to check that a package mirror can be reached.
+    fn can_connect(&self, host: &str, port: u16) -> bool {
+        (host, port).to_socket_addrs().is_ok_and(|mut addresses| {
+            addresses.any(|a| TcpStream::connect_timeout(&a, CONNECT_TIMEOUT).is_ok())
+        })
+    }
+
+    /// Report the filesystem holding a file or directory, and the space available on it.
+    fn filesystem(&self, path: &Path) -> Result<Filesystem> {
+        let output = self.run(&Command::build("df", &["-P", "-k"]).arg(path))?;
+        parse_df(&String::from_utf8_lossy(&output.stdout))
+            .with_context(|| format!("unable to parse the output of df for {}", path.display()))
+    }
 
     /// Backup a file by copying it to a new file with a `.oxidizr.bak` extension.
-    fn backup_file(&self, file: PathBuf) -> Result<()>;
+    fn backup_file(&self, file: ManagedTarget) -> Result<()>;
 
-    /// Restore a file from a backup if the backup file exists, warn otherwise.
-    fn restore_file(&self, file: PathBuf) -> Result<()>;
+    /// Restore a file from a backup if the backup file exists, warn otherwise, returning the
+    /// reason the file was skipped.
+    fn restore_file(&self, file: ManagedTarget) -> Result<Option<SkipReason>>;
 
     /// Create a symlink from `source` to `target`. If `target` already exists, it will be removed.
-    fn create_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()>;
+    fn create_symlink(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()>;
+
+    /// Remove a file, if it exists.
+    fn remove_file(&self, file: &Path) -> Result<()>;
+
+    /// Apply a batch of file operations in order, returning the outcome of each operation
+    /// attempted. The operations after the first that fails aren't attempted, as they may depend
+    /// on it. Each is carried out with the method of the Worker it corresponds to, so that a
+    /// Worker journaling or recording those calls does the same for a batch.
+    fn apply(&self, operations: &[FsOperation]) -> Vec<OpResult> {
+        let mut results = Vec::new();
+        for operation in operations {
+            let result = match operation.clone() {
+                FsOperation::Backup { file } => self.backup_file(file).map(|_| None),
+                FsOperation::Symlink { source, target } => {
+                    self.replace_file_with_symlink(source, target)
+                }
+                FsOperation::Restore { file } => self.restore_file(file),
+                FsOperation::Remove { file } => self.remove_file(&file).map(|_| None),
+            };
+            let failed = result.is_err();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        results
+    }
 }
 
 /// A struct representing the system with functions for running commands and manipulating
 /// files on the filesystem.
-#[derive(Clone, Debug)]
-pub struct System {}
+///
+/// Lookups that would otherwise be repeated for every file or package are cached: the binaries
+/// on the PATH are indexed once, and the installed packages are queried with a single invocation
+/// of `dpkg-query`, which is repeated only after packages are installed or removed.
+///
+/// Every file the `System` creates, replaces or removes is checked against an allowlist of the
+/// directories oxidizr manages before it is touched.
+#[derive(Clone, Debug, Default)]
+pub struct System {
+    path_index: RefCell<Option<HashMap<OsString, PathBuf>>>,
+    installed_packages: RefCell<Option<HashMap<String, String>>>,
+    write_allowlist: WriteAllowlist,
+    strict: bool,
+    policies: Vec<PolicyName>,
+    scratch_root: Option<PathBuf>,
+    deadline: Option<Instant>,
+    progress: Progress,
+}
 
 impl System {
-    /// Create a new `System` instance.
+    /// Create a new `System` instance. The directories recorded for the enabled experiments are
+    /// managed as well as the usual ones, so that their files can be restored whatever the
+    /// configuration says now.
     pub fn new() -> Result<Self> {
-        Ok(Self {})
+        let system = Self::default();
+        let recorded: Vec<PathBuf> = Directories::load(&system)
+            .map(|d| d.experiments.into_values().flatten().collect())
+            .unwrap_or_default();
+        Ok(system.with_managed_directories(&recorded))
+    }
+
+    /// Manage `directories` as well as the usual ones, allowing the files in them to be written,
+    /// such as those configured for the files an experiment replaces.
+    pub fn with_managed_directories(mut self, directories: &[PathBuf]) -> Self {
+        self.write_allowlist = self.write_allowlist.with_roots(directories.iter().cloned());
+        self
+    }
+
+    /// Fail operations on conditions that would otherwise only be warned about, such as a
+    /// missing backup during restore.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Apply compatibility policies in addition to the supported releases of each experiment.
+    pub fn with_policies(mut self, policies: &[PolicyName]) -> Self {
+        self.policies = policies.to_vec();
+        self
+    }
+
+    /// Create scratch directories under `root` rather than the temporary directory, if specified.
+    pub fn with_scratch_root(mut self, root: Option<PathBuf>) -> Self {
+        self.scratch_root = root;
+        self
+    }
+
+    /// Stop commands still running at `deadline`, failing them with `ErrorCode::TimedOut`, so
+    /// that a stuck package manager can't hold up an operation indefinitely.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Stream the progress of the operations carried out to `progress`.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Where the progress of operations is streamed.
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Report the installed version of a package, querying all installed packages at once and
+    /// caching the result if that hasn't happened already.
+    fn cached_version(&self, package: &str) -> Result<Option<String>> {
+        let mut cache = self.installed_packages.borrow_mut();
+        if cache.is_none() {
+            let cmd = Command::build(
+                "dpkg-query",
+                &["-W", "-f=${Package}\t${db:Status-Status}\t${Version}\n"],
+            );
+            let output = self.run(&cmd)?;
+            *cache = Some(parse_installed_packages(&String::from_utf8(output.stdout)?));
+        }
+        Ok(cache.as_ref().and_then(|c| c.get(package).cloned()))
+    }
+
+    /// Discard the cached list of installed packages after the package manager has changed it.
+    fn invalidate_installed_packages(&self) {
+        self.installed_packages.borrow_mut().take();
+    }
+
+    /// Report whether oxidizr manages a file, for taking over a backup of it made before backups
+    /// were recorded: a copy made by oxidizr has its checksum recorded, and the latest generation
+    /// records the files it linked.
+    fn is_managed(&self, file: &ManagedTarget) -> bool {
+        file.sibling(CHECKSUM_SUFFIX).exists()
+            || Generations::load(self).is_ok_and(|generations| {
+                generations
+                    .generations
+                    .last()
+                    .is_some_and(|g| g.links.contains_key(file.as_path()))
+            })
     }
 }
 
 impl Worker for System {
+    fn strict(&self) -> bool {
+        self.strict
+    }
+
+    fn compatibility_policies(&self) -> &[PolicyName] {
+        &self.policies
+    }
+
+    fn scratch_root(&self) -> PathBuf {
+        self.scratch_root.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
     /// Run a command and return the output. If the command fails, an error will be returned.
     fn run(&self, cmd: &Command) -> Result<Output> {
         debug!("Running command: {}", cmd.command());
-        let output = std::process::Command::new(&cmd.command)
-            .args(&cmd.args)
-            .output()?;
+        let mut command = std::process::Command::new(&cmd.command);
+        command.args(&cmd.args);
+        let output = match (self.deadline, &cmd.input) {
+            (None, None) => command.output()?,
+            (deadline, input) => output_before(command, input.as_deref(), deadline)
+                .map_err(|e| e.context(format!("'{}' was stopped", cmd.command())))?,
+        };
 
         if !output.status.success() {
-            anyhow::bail!(
+            return Err(ErrorCode::CommandFailed.error(format!(
                 "Failed to run command '{}': {}",
                 &cmd.command(),
                 String::from_utf8_lossy(&output.stderr)
-            );
+            )));
         }
         Ok(output)
     }
@@ -111,7 +452,7 @@ impl Worker for System {
     /// will be returned.
     fn list_files(&self, directory: PathBuf) -> Result<Vec<PathBuf>> {
         if !fs::exists(&directory)? || !fs::metadata(&directory)?.is_dir() {
-            anyhow::bail!("{} is not a directory", directory.to_str().unwrap());
+            anyhow::bail!("{} is not a directory", directory.display());
         }
 
         let entries = fs::read_dir(directory)?;
@@ -127,32 +468,259 @@ impl Worker for System {
         Ok(files)
     }
 
-    /// Find the path to a binary in the system's PATH.
-    fn which(&self, binary_name: &str) -> Result<PathBuf> {
-        Ok(which(binary_name)?)
+    /// Find the path to a binary in the system's PATH. The PATH is indexed on first use.
+    fn which(&self, binary_name: &OsStr) -> Result<PathBuf> {
+        let mut index = self.path_index.borrow_mut();
+        let index = index.get_or_insert_with(|| {
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            match detect_wsl(self) {
+                Some(_) => path_index(&without_windows_paths(&path)),
+                None => path_index(&path),
+            }
+        });
+
+        index
+            .get(binary_name)
+            .cloned()
+            .with_context(|| format!("'{}' not found in PATH", binary_name.display()))
+    }
+
+    /// Install several packages in a single transaction using the system package manager.
+    fn install_packages(&self, packages: &[String]) -> Result<()> {
+        #[cfg(feature = "native-apt")]
+        if let Some(cache) = apt::open_cache() {
+            let result = apt::commit(cache, apt::Transaction::Install, packages);
+            self.invalidate_installed_packages();
+            return result;
+        }
+
+        let mut args = vec!["install", "-y"];
+        args.extend(packages.iter().map(|p| p.as_str()));
+        let result = self.run(&Command::build("apt-get", &args));
+        self.invalidate_installed_packages();
+        result.map(|_| ())
+    }
+
+    /// Remove several packages in a single transaction using the system package manager.
+    fn remove_packages(&self, packages: &[String]) -> Result<()> {
+        #[cfg(feature = "native-apt")]
+        if let Some(cache) = apt::open_cache() {
+            let result = apt::commit(cache, apt::Transaction::Remove, packages);
+            self.invalidate_installed_packages();
+            return result;
+        }
+
+        let mut args = vec!["remove", "-y"];
+        args.extend(packages.iter().map(|p| p.as_str()));
+        let result = self.run(&Command::build("apt-get", &args));
+        self.invalidate_installed_packages();
+        result.map(|_| ())
+    }
+
+    /// Install a specific version of a package with apt-get, which unlike libapt-pkg's cache
+    /// interface accepts `package=version` directly.
+    fn install_package_version(&self, package: &str, version: &str) -> Result<()> {
+        let package = format!("{package}={version}");
+        let args = ["install", "-y", "--allow-downgrades", &package];
+        let result = self.run(&Command::build("apt-get", &args));
+        self.invalidate_installed_packages();
+        result.map(|_| ())
+    }
+
+    fn install_package_file(&self, file: &Path) -> Result<()> {
+        let args = ["install", "-y", "--allow-downgrades"];
+        let result = self.run(&Command::build("apt-get", &args).arg(file));
+        self.invalidate_installed_packages();
+        result.map(|_| ())
+    }
+
+    /// Update the package lists using the system package manager.
+    fn update_package_lists(&self) -> Result<()> {
+        #[cfg(feature = "native-apt")]
+        if let Some(cache) = apt::open_cache() {
+            return apt::update(cache);
+        }
+
+        self.run(&Command::build("apt-get", &["update"]))?;
+        Ok(())
+    }
+
+    /// Check if a package is installed using the cached list of installed packages.
+    fn check_installed(&self, package: &str) -> Result<bool> {
+        Ok(self.cached_version(package)?.is_some())
+    }
+
+    /// Report the installed version of a package using the cached list of installed packages.
+    fn installed_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+        self.cached_version(package)?.map(|v| v.parse()).transpose()
+    }
+
+    /// Report the destination of a symlink, or `None` if the path is not a symlink.
+    fn read_symlink(&self, path: &Path) -> Result<Option<PathBuf>> {
+        if !path.is_symlink() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_link(path)?))
+    }
+
+    /// List the processes running on the system from `/proc`.
+    fn processes(&self) -> Result<Vec<Process>> {
+        list_processes(Path::new("/proc"))
+    }
+
+    /// Read the contents of a file, or `None` if the file does not exist.
+    fn read_file(&self, path: &Path) -> Result<Option<String>> {
+        if !fs::exists(path)? {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?))
+    }
+
+    /// Write the contents of a file, creating its parent directory if needed. The file is
+    /// replaced whole and flushed to disk, so that state such as pins survives a power loss, and
+    /// given the mode for its location.
+    fn write_file(&self, path: &Path, contents: &str) -> Result<()> {
+        self.write_allowlist.check(path)?;
+        if let Some(parent) = path.parent() {
+            create_dirs(parent)?;
+        }
+        write_atomic(path, contents.as_bytes(), file_mode(path))
+    }
+
+    fn lock_file(&self, path: &Path, exclusive: bool) -> Result<Option<FileLock>> {
+        if exclusive {
+            self.write_allowlist.check(path)?;
+        }
+        lock(path, exclusive)
     }
 
     /// Replace a file with a symlink. If the target file already exists, it will be backed up
     /// before being replaced.
-    fn replace_file_with_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()> {
+    fn replace_file_with_symlink(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+    ) -> Result<Option<SkipReason>> {
+        self.write_allowlist.check(target.as_path())?;
         if fs::exists(&target)? {
-            if target.is_symlink() {
-                trace!("Skipping {}, symlink already exists", target.display());
-                return Ok(());
+            if target.as_path().is_symlink() {
+                let existing = fs::read_link(&target)?;
+                if existing != source.as_path() {
+                    warn_or_fail(
+                        self.strict,
+                        format!(
+                            "Skipping {target}, already a symlink to {}",
+                            existing.display()
+                        ),
+                    )?;
+                    return Ok(Some(SkipReason::ForeignSymlink { to: existing }));
+                }
+                trace!("Skipping {target}, symlink already exists");
+                return Ok(Some(SkipReason::AlreadyReplaced));
             }
             self.backup_file(target.clone())?;
             fs::remove_file(&target)?;
         }
 
         self.create_symlink(source, target)?;
-        Ok(())
+        Ok(None)
+    }
+
+    /// Replace a file with a copy of `source`, owned by root and with the specified mode. The
+    /// copy is written alongside the target and renamed over it, so the target is never missing,
+    /// and its checksum is recorded next to the backup. A target matching the recorded checksum
+    /// is a stale copy made by oxidizr, such as after the source package is upgraded, and is
+    /// refreshed without being backed up again.
+    fn replace_file_with_copy(
+        &self,
+        source: ReplacementSource,
+        target: ManagedTarget,
+        mode: u32,
+    ) -> Result<()> {
+        self.write_allowlist.check(target.as_path())?;
+        let checksum_file = target.sibling(CHECKSUM_SUFFIX);
+        self.write_allowlist.check(&checksum_file)?;
+
+        if fs::exists(&target)? {
+            let recorded = fs::read_to_string(&checksum_file).ok();
+            let current = self.file_checksum(target.as_path())?;
+            if recorded.is_none() || recorded.as_deref().map(str::trim) != current.as_deref() {
+                self.backup_file(target.clone())?;
+            }
+        }
+
+        let copy = target.sibling("oxidizr.tmp");
+        self.write_allowlist.check(&copy)?;
+        trace!("Copying {source} -> {target}");
+        fs::copy(&source, &copy)?;
+        // Changing the owner clears the setuid and setgid bits, so the mode is set afterwards.
+        std::os::unix::fs::chown(&copy, Some(0), Some(0))?;
+        fs::set_permissions(&copy, fs::Permissions::from_mode(mode))?;
+        sync_file(&copy)?;
+        fs::rename(&copy, &target)?;
+        sync_parent(target.as_path())?;
+
+        let checksum = self.file_checksum(target.as_path())?.unwrap_or_default();
+        write_atomic(&checksum_file, checksum.as_bytes(), FILE_MODE)
+    }
+
+    /// Report the hex-encoded sha256 checksum of a file, or `None` if the file does not exist.
+    fn file_checksum(&self, path: &Path) -> Result<Option<String>> {
+        if !fs::exists(path)? {
+            return Ok(None);
+        }
+        Ok(Some(hex_digest(&fs::read(path)?)))
+    }
+
+    fn file_size(&self, path: &Path) -> Result<Option<u64>> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.len())),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
     /// Backup a file by copying it to a new file with the same name, but with a `.oxidizr.bak`
-    /// extension.
-    fn backup_file(&self, file: PathBuf) -> Result<()> {
-        let backup_file = backup_filename(&file);
-        trace!("Backing up {} -> {}", file.display(), backup_file.display());
+    /// extension. An existing file in the way of the backup that isn't a backup made by oxidizr,
+    /// as recorded in the backup manifest of the directory, is never overwritten, unless it is
+    /// the unrecorded backup of a file oxidizr manages, made by a version predating the manifest.
+    /// The backup is flushed to disk before returning, as the original may be removed straight
+    /// afterwards.
+    ///
+    /// Backups keep the name they have always had, rather than one namespaced by run or
+    /// content, so that `oxidizr rescue` and the manual recovery instructions find them where
+    /// they expect; the manifest is what tells them apart from unrelated files.
+    fn backup_file(&self, file: ManagedTarget) -> Result<()> {
+        let backup_file = file.backup();
+        let manifest = file.as_path().with_file_name(BACKUP_MANIFEST);
+        self.write_allowlist.check(&backup_file)?;
+        self.write_allowlist.check(&manifest)?;
+
+        let name = manifest_key(backup_file.file_name().unwrap_or_default());
+        let mut backups = read_backup_manifest(&manifest)?;
+        if fs::symlink_metadata(&backup_file).is_ok() {
+            let existing = match backup_file.is_symlink() {
+                true => None,
+                false => self.file_checksum(&backup_file)?,
+            };
+            let recorded = backups.get(&name);
+            let legacy = recorded.is_none() && existing.is_some() && self.is_managed(&file);
+            if legacy {
+                info!(
+                    "Taking over {}, a backup made before backups were recorded",
+                    backup_file.display()
+                );
+            } else if existing.is_none() || recorded != existing.as_ref() {
+                return Err(ErrorCode::BackupCollision.error(format!(
+                    "{} already exists and isn't a backup made by oxidizr, refusing to overwrite \
+                    it with the backup of {file}",
+                    backup_file.display()
+                )));
+            }
+        }
+
+        trace!("Backing up {file} -> {}", backup_file.display());
         fs::copy(&file, &backup_file)?;
 
         // Ensure the same permissions are set on the backup file as on the original file.
@@ -160,47 +728,329 @@ impl Worker for System {
         // preserved by `fs::copy`.
         let metadata = fs::metadata(&file)?;
         fs::set_permissions(&backup_file, metadata.permissions())?;
-        Ok(())
+        sync_file(&backup_file)?;
+        sync_parent(&backup_file)?;
+
+        let checksum = self.file_checksum(&backup_file)?.unwrap_or_default();
+        backups.insert(name, checksum);
+        write_backup_manifest(&manifest, &backups)
     }
 
     /// Restore a file from a backup. If the backup file does not exist, the original file will be
     /// left untouched.
-    fn restore_file(&self, file: PathBuf) -> Result<()> {
-        let backup_file = backup_filename(&file);
+    fn restore_file(&self, file: ManagedTarget) -> Result<Option<SkipReason>> {
+        let backup_file = file.backup();
+        let manifest = file.as_path().with_file_name(BACKUP_MANIFEST);
+        self.write_allowlist.check(file.as_path())?;
+        self.write_allowlist.check(&manifest)?;
 
-        if fs::exists(&backup_file)? {
-            trace!("Restoring {} -> {}", backup_file.display(), file.display());
-            fs::rename(&backup_file, &file)?;
-        } else {
-            warn!("No backup found for '{}', skipping restore", file.display());
+        if !fs::exists(&backup_file)? {
+            warn_or_fail(
+                self.strict,
+                format!("No backup found for '{file}', skipping restore"),
+            )?;
+            return Ok(Some(SkipReason::BackupMissing));
         }
 
-        Ok(())
+        trace!("Restoring {} -> {file}", backup_file.display());
+        fs::rename(&backup_file, &file)?;
+        remove_file_if_exists(&file.sibling(CHECKSUM_SUFFIX))?;
+        sync_parent(file.as_path())?;
+
+        let mut backups = read_backup_manifest(&manifest)?;
+        let name = manifest_key(backup_file.file_name().unwrap_or_default());
+        if backups.remove(&name).is_some() {
+            write_backup_manifest(&manifest, &backups)?;
+        }
+        Ok(None)
     }
 
     /// Create a symlink from `source` to `target`. If `target` already exists, it will be
     /// removed and overwritten with the symlink.
-    fn create_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()> {
-        trace!("Symlinking {} -> {}", source.display(), target.display());
-        remove_file_if_exists(&target)?;
-        std::os::unix::fs::symlink(source, target)?;
-        Ok(())
+    fn create_symlink(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()> {
+        self.write_allowlist.check(target.as_path())?;
+        trace!("Symlinking {source} -> {target}");
+        remove_file_if_exists(target.as_path())?;
+        std::os::unix::fs::symlink(&source, &target)?;
+        sync_parent(target.as_path())
+    }
+
+    fn remove_file(&self, file: &Path) -> Result<()> {
+        self.write_allowlist.check(file)?;
+        trace!("Removing {}", file.display());
+        remove_file_if_exists(file)?;
+        sync_parent(file)
+    }
+}
+
+/// A change to a file, declared so that a batch of them can be applied by `Worker::apply`.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FsOperation {
+    /// Back up a file alongside itself.
+    Backup { file: ManagedTarget },
+    /// Replace a file with a symlink to `source`, backing it up first.
+    Symlink {
+        source: ReplacementSource,
+        target: ManagedTarget,
+    },
+    /// Restore a file from its backup.
+    Restore { file: ManagedTarget },
+    /// Remove a file, if it exists.
+    Remove { file: PathBuf },
+}
+
+impl FsOperation {
+    /// Replace `target` with a symlink to `source`, validating both paths.
+    pub fn symlink(source: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::Symlink {
+            source: ReplacementSource::new(source)?,
+            target: ManagedTarget::new(target)?,
+        })
+    }
+
+    /// Restore `file` from its backup, validating its path.
+    pub fn restore(file: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::Restore {
+            file: ManagedTarget::new(file)?,
+        })
+    }
+}
+
+/// The outcome of an `FsOperation`: the reason the file was left as it is, if it was.
+pub type OpResult = Result<Option<SkipReason>>;
+
+/// Why a file that an operation would replace or restore was left as it is.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The binary is pinned to GNU.
+    Pinned,
+    /// The binary is quarantined, failures having implicated its replacement.
+    Quarantined,
+    /// The replacement has no IMA signature, and IMA appraisal is enforced.
+    Unsigned,
+    /// The file is in use, so it is replaced at the next boot.
+    InUse,
+    /// The file is already a symlink to its replacement.
+    AlreadyReplaced,
+    /// The file is a symlink to something other than its replacement, which oxidizr didn't make.
+    ForeignSymlink { to: PathBuf },
+    /// The file has no backup to restore it from.
+    BackupMissing,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pinned => write!(f, "pinned to GNU"),
+            Self::Quarantined => write!(f, "quarantined after failures"),
+            Self::Unsigned => write!(f, "the replacement has no IMA signature"),
+            Self::InUse => write!(f, "in use, so replaced at the next boot"),
+            Self::AlreadyReplaced => write!(f, "already replaced"),
+            Self::ForeignSymlink { to } => write!(f, "already a symlink to {}", to.display()),
+            Self::BackupMissing => write!(f, "no backup to restore"),
+        }
     }
 }
 
+/// A file that an operation would replace or restore, but that was left as it is.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SkippedFile {
+    pub file: PathBuf,
+    pub reason: SkipReason,
+}
+
 /// Generate a backup filename. For a given file `/path/to/file`, the backup filename will be
-/// `/path/to/.file.oxidizr.bak`.
-fn backup_filename(file: &Path) -> PathBuf {
-    let mut backup_file = file.parent().unwrap_or(&PathBuf::from(".")).to_path_buf();
-    backup_file.push(format!(
-        ".{}.oxidizr.bak",
-        file.file_name().unwrap().to_string_lossy()
-    ));
-    backup_file
+/// `/path/to/.file.oxidizr.bak`. Fails for a path that doesn't name a file, such as `/`.
+pub fn backup_filename(file: &Path) -> Result<PathBuf> {
+    let name = file
+        .file_name()
+        .with_context(|| format!("'{}' doesn't name a file to back up", file.display()))?;
+    let mut backup_name = OsString::from(".");
+    backup_name.push(name);
+    backup_name.push(".oxidizr.bak");
+    Ok(file.with_file_name(backup_name))
+}
+
+/// The file a backup was made of: for a backup `/path/to/.file.oxidizr.bak`, `/path/to/file`.
+/// Returns `None` for a file that isn't named as a backup. The name is taken byte for byte, so
+/// that names that aren't valid UTF-8 are kept as they are.
+pub fn backup_original(backup: &Path) -> Option<PathBuf> {
+    let original = backup
+        .file_name()?
+        .as_bytes()
+        .strip_prefix(b".")?
+        .strip_suffix(b".oxidizr.bak")
+        .filter(|n| !n.is_empty())?;
+    Some(backup.with_file_name(OsStr::from_bytes(original)))
+}
+
+/// The key of a backup in a backup manifest: its file name, or for a name that isn't valid
+/// UTF-8, `hex:` followed by its bytes in hex. Backup names start with a `.`, so the two can't be
+/// confused, and no two names share a key.
+pub fn manifest_key(name: &OsStr) -> String {
+    match name.to_str() {
+        Some(name) => name.to_string(),
+        None => {
+            let hex: String = name.as_bytes().iter().map(|b| format!("{b:02x}")).collect();
+            format!("hex:{hex}")
+        }
+    }
+}
+
+/// The suffix of the file recording the checksum of a copy made by oxidizr. For a given file
+/// `/path/to/file`, the checksum is recorded in `/path/to/.file.oxidizr.sha256`.
+const CHECKSUM_SUFFIX: &str = "oxidizr.sha256";
+
+/// The file recording the backups made by oxidizr in the directory it is in, by the checksum of
+/// each, so that a file that merely has the name of a backup, such as `.sort.oxidizr.bak`, is
+/// never mistaken for one and overwritten.
+pub const BACKUP_MANIFEST: &str = ".oxidizr-backups.json";
+
+/// Read the backups recorded in a backup manifest, by file name. A missing manifest records none.
+fn read_backup_manifest(manifest: &Path) -> Result<BTreeMap<String, String>> {
+    match fs::read_to_string(manifest) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("unable to parse {}", manifest.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a backup manifest, removing it once it records no backups.
+fn write_backup_manifest(manifest: &Path, backups: &BTreeMap<String, String>) -> Result<()> {
+    if backups.is_empty() {
+        remove_file_if_exists(manifest)?;
+        return sync_parent(manifest);
+    }
+    write_atomic(
+        manifest,
+        serde_json::to_string_pretty(backups)?.as_bytes(),
+        FILE_MODE,
+    )
+}
+
+/// Index the executables in each directory of a PATH-style list of directories by filename. As
+/// with a regular PATH lookup, the first directory containing a given filename takes precedence.
+fn path_index(path: &OsStr) -> HashMap<OsString, PathBuf> {
+    let mut index = HashMap::new();
+
+    for dir in std::env::split_paths(path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let executable = fs::metadata(&path)
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+
+            if executable {
+                index.entry(entry.file_name()).or_insert(path);
+            }
+        }
+    }
+
+    index
+}
+
+/// Remove the Windows directories that WSL appends to the PATH, warning about them: resolving a
+/// binary to a Windows program would have oxidizr replace a file on the Windows drive.
+fn without_windows_paths(path: &OsStr) -> OsString {
+    let (windows, linux): (Vec<PathBuf>, Vec<PathBuf>) =
+        std::env::split_paths(path).partition(|dir| is_windows_path(dir));
+    if !windows.is_empty() {
+        warn!(
+            "Ignoring Windows directories in PATH when resolving binaries: {}",
+            windows
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+    std::env::join_paths(linux).unwrap_or_default()
+}
+
+/// Parse the output of `dpkg-query -W -f='${Package}\t${db:Status-Status}\t${Version}\n'`
+/// into a map of installed package names to their versions.
+fn parse_installed_packages(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(package), Some("installed"), Some(version)) => {
+                    Some((package.to_string(), version.to_string()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Run a command and collect its output like `std::process::Command::output`, writing `input` to
+/// its standard input, and killing it if it is still running at `deadline`, if any.
+fn output_before(
+    mut command: std::process::Command,
+    input: Option<&[u8]>,
+    deadline: Option<Instant>,
+) -> Result<Output> {
+    let stdin = match input {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    };
+    let mut child = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // The input is written as the command runs, and closed once written, so that the command
+    // sees the end of it.
+    if let (Some(input), Some(mut pipe)) = (input, child.stdin.take()) {
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = pipe.write_all(&input);
+        });
+    }
+
+    // The pipes are drained as the command runs, so that it can't block on a full pipe.
+    fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    }
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            child.kill()?;
+            child.wait()?;
+            return Err(ErrorCode::TimedOut.error("the operation ran past its deadline"));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
 }
 
 /// Remove a file from the filesystem if it exists.
-fn remove_file_if_exists(file: &PathBuf) -> Result<()> {
+fn remove_file_if_exists(file: &Path) -> Result<()> {
     if fs::exists(file)? {
         fs::remove_file(file)?;
     }
@@ -209,26 +1059,399 @@ fn remove_file_if_exists(file: &PathBuf) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{
+        ffi::OsStr,
+        fs,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{MetadataExt, PermissionsExt},
+        },
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use proptest::prelude::*;
+
+    use crate::utils::worker::{
+        backup_filename, output_before, parse_installed_packages, path_index,
+    };
+    use crate::utils::{
+        Command, ErrorCode, FsOperation, ManagedTarget, ReplacementSource, SkipReason, System,
+        Worker, WriteAllowlist,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_installed_packages() {
+        let output = "\
+coreutils\tinstalled\t9.4-3ubuntu6
+rust-coreutils\tnot-installed\t
+sudo-rs\tconfig-files\t0.2.5-1
+findutils\tinstalled\t4.9.0-5build1
+";
+        let installed = parse_installed_packages(output);
+        assert_eq!(installed.len(), 2);
+        assert_eq!(installed["coreutils"], "9.4-3ubuntu6");
+        assert_eq!(installed["findutils"], "4.9.0-5build1");
+    }
+
+    #[test]
+    fn test_path_index() {
+        let root = std::env::temp_dir().join(format!("oxidizr-path-{}", std::process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir).unwrap();
+            for name in ["date", "notes.txt"] {
+                fs::write(dir.join(name), "").unwrap();
+            }
+            let mode = fs::Permissions::from_mode(0o755);
+            fs::set_permissions(dir.join("date"), mode).unwrap();
+        }
+        fs::write(second.join("sort"), "").unwrap();
+        fs::set_permissions(second.join("sort"), fs::Permissions::from_mode(0o755)).unwrap();
+        // Executables whose names aren't valid UTF-8 are indexed all the same.
+        let invalid = OsStr::from_bytes(b"sort\xff");
+        fs::write(second.join(invalid), "").unwrap();
+        fs::set_permissions(second.join(invalid), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = std::env::join_paths([&first, &second, &root.join("missing")]).unwrap();
+        let index = path_index(&path);
+
+        assert_eq!(index.get(OsStr::new("date")), Some(&first.join("date")));
+        assert_eq!(index.get(OsStr::new("sort")), Some(&second.join("sort")));
+        assert_eq!(index.get(OsStr::new("notes.txt")), None);
+        assert_eq!(index.get(invalid), Some(&second.join(invalid)));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_output_before() {
+        let mut echo = std::process::Command::new("echo");
+        echo.arg("done");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert_eq!(
+            output_before(echo, None, Some(deadline)).unwrap().stdout,
+            b"done\n"
+        );
+
+        // Input is written to the command.
+        let cat = std::process::Command::new("cat");
+        let output = output_before(cat, Some(b"To: root\n"), Some(deadline)).unwrap();
+        assert_eq!(output.stdout, b"To: root\n");
+
+        // A stuck command is stopped at the deadline.
+        let started = Instant::now();
+        let mut sleep = std::process::Command::new("sleep");
+        sleep.arg("10");
+        let err =
+            output_before(sleep, None, Some(started + Duration::from_millis(100))).unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_apply() {
+        let root = std::env::temp_dir().join(format!("oxidizr-apply-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let system = System {
+            write_allowlist: WriteAllowlist::new([root.clone()]),
+            ..System::default()
+        };
+        let source = ReplacementSource::new(root.join("replacement")).unwrap();
+        let target = ManagedTarget::new(root.join("date")).unwrap();
+        fs::write(&source, "").unwrap();
+        fs::write(&target, "original").unwrap();
+
+        let results = system.apply(&[
+            FsOperation::Symlink {
+                source: source.clone(),
+                target: target.clone(),
+            },
+            FsOperation::Restore {
+                file: target.clone(),
+            },
+            FsOperation::Remove {
+                file: source.as_path().to_path_buf(),
+            },
+        ]);
+        assert!(results.iter().all(|r| matches!(r, Ok(None))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+        assert!(!source.as_path().exists());
+
+        // The operations after one that fails aren't attempted.
+        let results = system.apply(&[
+            FsOperation::Remove {
+                file: PathBuf::from("/usr/bin/date"),
+            },
+            FsOperation::Remove {
+                file: target.as_path().to_path_buf(),
+            },
+        ]);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert!(target.as_path().exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_skip_reasons() {
+        let root = std::env::temp_dir().join(format!("oxidizr-skip-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let system = System {
+            write_allowlist: WriteAllowlist::new([root.clone()]),
+            ..System::default()
+        };
+        let source = ReplacementSource::new(root.join("replacement")).unwrap();
+        let target = ManagedTarget::new(root.join("date")).unwrap();
+        fs::write(&source, "").unwrap();
+        fs::write(&target, "").unwrap();
+
+        let replace = || system.replace_file_with_symlink(source.clone(), target.clone());
+        assert_eq!(replace().unwrap(), None);
+        assert_eq!(replace().unwrap(), Some(SkipReason::AlreadyReplaced));
+
+        // A symlink made by something else is left alone.
+        fs::remove_file(&target).unwrap();
+        fs::write(root.join("other"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("other"), &target).unwrap();
+        assert_eq!(
+            replace().unwrap(),
+            Some(SkipReason::ForeignSymlink {
+                to: root.join("other")
+            })
+        );
+
+        assert_eq!(system.restore_file(target.clone()).unwrap(), None);
+        assert_eq!(
+            system.restore_file(target.clone()).unwrap(),
+            Some(SkipReason::BackupMissing)
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
 
-    use crate::utils::worker::backup_filename;
+    #[test]
+    fn test_backup_collision() {
+        let (root, system) = scratch("collision");
+        let target = ManagedTarget::new(root.join("sort")).unwrap();
+        fs::write(&target, "gnu").unwrap();
+
+        // A backup made by oxidizr is refreshed, such as after the original is upgraded.
+        system.backup_file(target.clone()).unwrap();
+        fs::write(&target, "gnu, upgraded").unwrap();
+        system.backup_file(target.clone()).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.backup()).unwrap(),
+            "gnu, upgraded"
+        );
+        assert_eq!(system.restore_file(target.clone()).unwrap(), None);
+        assert!(!root.join(".oxidizr-backups.json").exists());
+
+        // A file that merely has the name of a backup is left alone.
+        fs::write(target.backup(), "unrelated").unwrap();
+        let source = ReplacementSource::new(root.join("replacement")).unwrap();
+        fs::write(&source, "").unwrap();
+        let err = system
+            .replace_file_with_symlink(source, target.clone())
+            .unwrap_err();
+        assert_eq!(ErrorCode::of(&err), Some(ErrorCode::BackupCollision));
+        assert_eq!(fs::read_to_string(target.backup()).unwrap(), "unrelated");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "gnu, upgraded");
+
+        // The unrecorded backup of a copy made by oxidizr was made before backups were recorded,
+        // and is taken over.
+        fs::write(target.sibling("oxidizr.sha256"), "").unwrap();
+        system.backup_file(target.clone()).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.backup()).unwrap(),
+            "gnu, upgraded"
+        );
+        assert!(root.join(".oxidizr-backups.json").exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_managed_directories() {
+        let (root, system) = scratch("managed");
+        let (local, _) = scratch("managed-local");
+        let source = ReplacementSource::new(root.join("replacement")).unwrap();
+        fs::write(&source, "").unwrap();
+        let target = ManagedTarget::new(local.join("date")).unwrap();
+        fs::write(&target, "gnu").unwrap();
+
+        // A directory configured for an experiment is only written to once it is managed.
+        let err = system
+            .replace_file_with_symlink(source.clone(), target.clone())
+            .unwrap_err();
+        assert_eq!(
+            ErrorCode::of(&err),
+            Some(ErrorCode::OutsideManagedDirectories)
+        );
+        let system = system.with_managed_directories(std::slice::from_ref(&local));
+        assert_eq!(
+            system
+                .replace_file_with_symlink(source, target.clone())
+                .unwrap(),
+            None
+        );
+        assert_eq!(fs::read_to_string(target.backup()).unwrap(), "gnu");
+        assert_eq!(system.restore_file(target.clone()).unwrap(), None);
+
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(local).unwrap();
+    }
 
     #[test]
     fn test_backup_filename() {
         let file = PathBuf::from("/home/user/config");
-        let backup = backup_filename(&file);
+        let backup = backup_filename(&file).unwrap();
         assert_eq!(backup, PathBuf::from("/home/user/.config.oxidizr.bak"));
 
         let file = PathBuf::from("config");
-        let backup = backup_filename(&file);
+        let backup = backup_filename(&file).unwrap();
         assert_eq!(backup, PathBuf::from(".config.oxidizr.bak"));
 
         let file = PathBuf::from("/etc/hosts");
-        let backup = backup_filename(&file);
+        let backup = backup_filename(&file).unwrap();
         assert_eq!(backup, PathBuf::from("/etc/.hosts.oxidizr.bak"));
 
         let file = PathBuf::from(".hidden");
-        let backup = backup_filename(&file);
+        let backup = backup_filename(&file).unwrap();
         assert_eq!(backup, PathBuf::from("..hidden.oxidizr.bak"));
+
+        let file = Path::new(OsStr::from_bytes(b"/usr/bin/d\xffte"));
+        let backup = backup_filename(file).unwrap();
+        assert_eq!(
+            backup.as_os_str().as_bytes(),
+            b"/usr/bin/.d\xffte.oxidizr.bak"
+        );
+
+        assert!(backup_filename(Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn test_run_non_utf8_args() {
+        let arg = OsStr::from_bytes(b"caf\xe9");
+        let output = System::default()
+            .run(&Command::build("printf", &["%s"]).arg(arg))
+            .unwrap();
+        assert_eq!(output.stdout, arg.as_bytes());
+    }
+
+    /// A scratch directory for a single case of a property, and a System that may write to it.
+    fn scratch(test: &str) -> (PathBuf, System) {
+        static CASES: AtomicUsize = AtomicUsize::new(0);
+        let case = CASES.fetch_add(1, Ordering::Relaxed);
+        let root =
+            std::env::temp_dir().join(format!("oxidizr-{test}-{}-{case}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let system = System {
+            write_allowlist: WriteAllowlist::new([root.clone()]),
+            ..System::default()
+        };
+        (root, system)
+    }
+
+    /// File names such as `date`, `.hidden` or `x-1`, and those of backups such as
+    /// `.date.oxidizr.bak`.
+    fn file_name() -> impl Strategy<Value = String> {
+        ("[a-z._-]{0,8}[a-z0-9]", any::<bool>()).prop_map(|(name, backup)| match backup {
+            true => format!(".{name}.oxidizr.bak"),
+            false => name,
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_backup_filename_is_unique(a in file_name(), b in file_name()) {
+            let dir = Path::new("/usr/bin");
+            let backup_a = backup_filename(&dir.join(&a)).unwrap();
+            let backup_b = backup_filename(&dir.join(&b)).unwrap();
+            prop_assert_ne!(&backup_a, &dir.join(&a));
+            prop_assert_eq!(backup_a.parent(), Some(dir));
+            prop_assert_eq!(backup_a == backup_b, a == b);
+        }
+
+        #[test]
+        fn prop_backup_restore_round_trip(
+            name in file_name(),
+            contents in prop::collection::vec(any::<u8>(), 0..512),
+            mode in 0u32..0o7777,
+        ) {
+            // The owner can always read and write the file, so that the test can run as anyone.
+            let mode = mode | 0o600;
+            let (root, system) = scratch("round-trip");
+            let source = ReplacementSource::new(root.join("replacement")).unwrap();
+            let target = ManagedTarget::new(root.join(&name)).unwrap();
+            fs::write(&source, "").unwrap();
+            fs::write(&target, &contents).unwrap();
+            fs::set_permissions(&target, fs::Permissions::from_mode(mode)).unwrap();
+            let original = fs::metadata(&target).unwrap();
+
+            let replaced = system.replace_file_with_symlink(source.clone(), target.clone());
+            prop_assert_eq!(replaced.unwrap(), None);
+            prop_assert_eq!(fs::read_link(&target).unwrap(), source.as_path());
+            prop_assert_eq!(system.restore_file(target.clone()).unwrap(), None);
+
+            let restored = fs::symlink_metadata(&target).unwrap();
+            prop_assert!(restored.is_file());
+            prop_assert_eq!(fs::read(&target).unwrap(), contents);
+            prop_assert_eq!(restored.permissions().mode() & 0o7777, mode);
+            prop_assert_eq!(
+                (restored.uid(), restored.gid()),
+                (original.uid(), original.gid())
+            );
+            prop_assert!(!target.backup().exists());
+            fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn prop_replace_existing_file(name in file_name(), existing in 0..4u8) {
+            let (root, system) = scratch("existing");
+            let source = ReplacementSource::new(root.join("replacement")).unwrap();
+            let target = ManagedTarget::new(root.join(&name)).unwrap();
+            let other = root.join("other");
+            fs::write(&source, "").unwrap();
+            fs::write(&other, "").unwrap();
+
+            // The file is missing, a regular file, a symlink to the replacement or a symlink
+            // made by something else.
+            let expected = match existing {
+                0 => None,
+                1 => {
+                    fs::write(&target, "original").unwrap();
+                    None
+                }
+                2 => {
+                    std::os::unix::fs::symlink(&source, &target).unwrap();
+                    Some(SkipReason::AlreadyReplaced)
+                }
+                _ => {
+                    std::os::unix::fs::symlink(&other, &target).unwrap();
+                    Some(SkipReason::ForeignSymlink { to: other.clone() })
+                }
+            };
+            let replace = || system.replace_file_with_symlink(source.clone(), target.clone());
+            prop_assert_eq!(replace().unwrap(), expected.clone());
+
+            // Only a regular file is backed up, and replacing it again changes nothing.
+            prop_assert_eq!(target.backup().exists(), existing == 1);
+            let again = match expected {
+                Some(SkipReason::ForeignSymlink { .. }) => expected,
+                _ => Some(SkipReason::AlreadyReplaced),
+            };
+            prop_assert_eq!(replace().unwrap(), again);
+            match existing {
+                1 => prop_assert_eq!(fs::read_to_string(target.backup()).unwrap(), "original"),
+                3 => prop_assert_eq!(fs::read_link(&target).unwrap(), other),
+                _ => prop_assert_eq!(fs::read_link(&target).unwrap(), source.as_path()),
+            }
+            fs::remove_dir_all(root).unwrap();
+        }
     }
 }
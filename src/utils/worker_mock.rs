@@ -1,9 +1,19 @@
 #[cfg(test)]
 pub mod tests {
-    use crate::utils::{Command, Distribution, Worker};
+    use crate::experiments::PolicyName;
+    use crate::utils::{
+        Command, Distribution, ManagedTarget, PackageVersion, Process, ReplacementSource,
+        SkipReason, Worker, hex_digest,
+    };
 
     use anyhow::Result;
-    use std::{cell::RefCell, collections::HashMap, path::PathBuf, process::Output};
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        ffi::OsStr,
+        path::{Path, PathBuf},
+        process::Output,
+    };
 
     #[derive(Debug, Clone)]
     pub struct MockSystem {
@@ -17,20 +27,27 @@ pub mod tests {
         pub installed_packages: RefCell<Vec<String>>,
         /// List of symlinks created by the worker
         pub created_symlinks: RefCell<Vec<(String, String)>>,
+        /// List of files replaced with copies by the worker, with the mode of each copy
+        pub copied_files: RefCell<Vec<(String, String, u32)>>,
         /// List of files restored by the worker
         pub restored_files: RefCell<Vec<String>>,
         /// List of files backed up by the worker
         pub backed_up_files: RefCell<Vec<String>>,
         /// HashMap of mocked commands and their faked responses
         pub mocked_commands: RefCell<HashMap<String, String>>,
+        /// Whether the Worker reports strict mode.
+        pub strict: bool,
+        /// The compatibility policies the Worker reports as selected.
+        pub policies: Vec<PolicyName>,
+        /// Processes reported as running. Each is reported once, as if it exits straight after.
+        pub processes: RefCell<Vec<Process>>,
+        /// Hosts that connections can't be made to. Any other host is reachable.
+        pub unreachable_hosts: RefCell<Vec<String>>,
     }
 
     impl Default for MockSystem {
         fn default() -> Self {
-            Self::new(Distribution {
-                id: "Ubuntu".to_string(),
-                release: "24.04".to_string(),
-            })
+            Self::new(Distribution::new("Ubuntu", "24.04"))
         }
     }
 
@@ -41,9 +58,14 @@ pub mod tests {
                 files: RefCell::new(HashMap::new()),
                 installed_packages: RefCell::new(Vec::new()),
                 created_symlinks: RefCell::new(Vec::new()),
+                copied_files: RefCell::new(Vec::new()),
                 restored_files: RefCell::new(Vec::new()),
                 backed_up_files: RefCell::new(Vec::new()),
                 mocked_commands: RefCell::new(HashMap::new()),
+                processes: RefCell::new(Vec::new()),
+                unreachable_hosts: RefCell::new(Vec::new()),
+                strict: false,
+                policies: Vec::new(),
             };
 
             s.mock_command("lsb_release -is", distribution.id.as_str());
@@ -51,6 +73,11 @@ pub mod tests {
             s
         }
 
+        pub fn with_policies(mut self, policies: &[PolicyName]) -> Self {
+            self.policies = policies.to_vec();
+            self
+        }
+
         pub fn mock_files(&self, files: Vec<(&str, &str, bool)>) {
             for (path, contents, primary) in files {
                 self.files
@@ -65,6 +92,23 @@ pub mod tests {
                 .push(package.to_string());
         }
 
+        pub fn mock_process(&self, pid: u32, exe: &str, unit: Option<&str>) {
+            self.processes.borrow_mut().push(Process {
+                pid,
+                name: PathBuf::from(exe)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+                exe: PathBuf::from(exe),
+                unit: unit.map(|u| u.to_string()),
+            });
+        }
+
+        pub fn mock_unreachable(&self, host: &str) {
+            self.unreachable_hosts.borrow_mut().push(host.to_string());
+        }
+
         pub fn mock_command(&self, command: &str, stdout: &str) {
             self.mocked_commands
                 .borrow_mut()
@@ -73,6 +117,14 @@ pub mod tests {
     }
 
     impl Worker for MockSystem {
+        fn strict(&self) -> bool {
+            self.strict
+        }
+
+        fn compatibility_policies(&self) -> &[PolicyName] {
+            &self.policies
+        }
+
         fn run(&self, cmd: &Command) -> Result<Output> {
             self.commands.borrow_mut().push(cmd.command());
             let mocked = self.mocked_commands.borrow();
@@ -93,6 +145,18 @@ pub mod tests {
                 .contains(&package.to_string()))
         }
 
+        fn installed_version(&self, package: &str) -> Result<Option<PackageVersion>> {
+            if !self.check_installed(package)? {
+                return Ok(None);
+            }
+            let cmd = format!("dpkg-query -W -f=${{Version}} {package}");
+            self.mocked_commands
+                .borrow()
+                .get(&cmd)
+                .map(|v| v.parse())
+                .transpose()
+        }
+
         fn list_files(&self, directory: PathBuf) -> Result<Vec<PathBuf>> {
             let files: Vec<PathBuf> = self
                 .files
@@ -104,43 +168,110 @@ pub mod tests {
             Ok(files)
         }
 
-        fn which(&self, binary_name: &str) -> Result<PathBuf> {
+        fn which(&self, binary_name: &OsStr) -> Result<PathBuf> {
             for (filename, file) in self.files.borrow().iter() {
-                if filename.file_name().unwrap().to_str().unwrap() == binary_name && file.1 {
+                if filename.file_name() == Some(binary_name) && file.1 {
                     return Ok(filename.clone());
                 }
             }
-            anyhow::bail!("{} not found in mocked filesystem", binary_name);
+            anyhow::bail!("{} not found in mocked filesystem", binary_name.display());
+        }
+
+        fn processes(&self) -> Result<Vec<Process>> {
+            Ok(self.processes.take())
+        }
+
+        fn read_file(&self, path: &Path) -> Result<Option<String>> {
+            Ok(self.files.borrow().get(path).map(|(c, _)| c.clone()))
+        }
+
+        fn write_file(&self, path: &Path, contents: &str) -> Result<()> {
+            self.files
+                .borrow_mut()
+                .insert(path.to_path_buf(), (contents.to_string(), false));
+            Ok(())
         }
 
-        fn replace_file_with_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()> {
-            if self.files.borrow().contains_key(&target) {
+        fn read_symlink(&self, path: &Path) -> Result<Option<PathBuf>> {
+            Ok(self
+                .created_symlinks
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(_, target)| Path::new(target) == path)
+                .map(|(source, _)| PathBuf::from(source)))
+        }
+
+        fn replace_file_with_symlink(
+            &self,
+            source: ReplacementSource,
+            target: ManagedTarget,
+        ) -> Result<Option<SkipReason>> {
+            if self.files.borrow().contains_key(target.as_path()) {
                 self.backup_file(target.clone())?;
             }
 
-            self.create_symlink(source, target.clone())
+            self.create_symlink(source, target.clone())?;
+            Ok(None)
         }
 
-        fn create_symlink(&self, source: PathBuf, target: PathBuf) -> Result<()> {
-            self.created_symlinks.borrow_mut().push((
-                source.into_os_string().into_string().unwrap(),
-                target.into_os_string().into_string().unwrap(),
-            ));
+        fn replace_file_with_copy(
+            &self,
+            source: ReplacementSource,
+            target: ManagedTarget,
+            mode: u32,
+        ) -> Result<()> {
+            // Files previously replaced with a copy are refreshed without another backup.
+            let copied = self
+                .copied_files
+                .borrow()
+                .iter()
+                .any(|(_, t, _)| Path::new(t) == target.as_path());
+            if !copied && self.files.borrow().contains_key(target.as_path()) {
+                self.backup_file(target.clone())?;
+            }
+
+            let contents = self.read_file(source.as_path())?.unwrap_or_default();
+
+            self.write_file(target.as_path(), &contents)?;
+            self.copied_files
+                .borrow_mut()
+                .push((source.to_string(), target.to_string(), mode));
             Ok(())
         }
 
-        fn backup_file(&self, file: PathBuf) -> Result<()> {
-            self.backed_up_files
+        fn can_connect(&self, host: &str, _port: u16) -> bool {
+            !self.unreachable_hosts.borrow().iter().any(|h| h == host)
+        }
+
+        fn file_size(&self, path: &Path) -> Result<Option<u64>> {
+            Ok(self.read_file(path)?.map(|c| c.len() as u64))
+        }
+
+        fn file_checksum(&self, path: &Path) -> Result<Option<String>> {
+            Ok(self.read_file(path)?.map(|c| hex_digest(c.as_bytes())))
+        }
+
+        fn create_symlink(&self, source: ReplacementSource, target: ManagedTarget) -> Result<()> {
+            self.created_symlinks
                 .borrow_mut()
-                .push(file.into_os_string().into_string().unwrap());
+                .push((source.to_string(), target.to_string()));
             Ok(())
         }
 
-        fn restore_file(&self, file: PathBuf) -> Result<()> {
-            self.restored_files
-                .borrow_mut()
-                .push(file.into_os_string().into_string().unwrap());
+        fn backup_file(&self, file: ManagedTarget) -> Result<()> {
+            self.backed_up_files.borrow_mut().push(file.to_string());
+            Ok(())
+        }
+
+        fn remove_file(&self, file: &Path) -> Result<()> {
+            self.files.borrow_mut().remove(file);
             Ok(())
         }
+
+        fn restore_file(&self, file: ManagedTarget) -> Result<Option<SkipReason>> {
+            self.restored_files.borrow_mut().push(file.to_string());
+            Ok(None)
+        }
     }
 }
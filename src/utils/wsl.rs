@@ -0,0 +1,92 @@
+//! Detection of the Windows Subsystem for Linux, which behaves differently to a Linux host.
+
+use super::Worker;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+/// The kernel release, which names the subsystem when running under WSL.
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+/// A version of the Windows Subsystem for Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Wsl {
+    /// WSL 1 translates Linux system calls in the Windows kernel, and never runs systemd.
+    Wsl1,
+    /// WSL 2 runs a real Linux kernel in a lightweight virtual machine.
+    Wsl2,
+}
+
+impl fmt::Display for Wsl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Wsl::Wsl1 => write!(f, "WSL 1"),
+            Wsl::Wsl2 => write!(f, "WSL 2"),
+        }
+    }
+}
+
+/// Detect whether the system is running under WSL, and which version, from the kernel release:
+/// WSL 2 kernels are named like `5.15.153.1-microsoft-standard-WSL2`, while WSL 1 reports the
+/// Windows build, like `4.4.0-19041-Microsoft`.
+pub fn detect_wsl(system: &dyn Worker) -> Option<Wsl> {
+    let release = system
+        .read_file(Path::new(KERNEL_RELEASE_PATH))
+        .ok()
+        .flatten()?
+        .to_lowercase();
+    if !release.contains("microsoft") {
+        return None;
+    }
+    match release.contains("wsl2") || release.contains("microsoft-standard") {
+        true => Some(Wsl::Wsl2),
+        false => Some(Wsl::Wsl1),
+    }
+}
+
+/// Report whether a directory is a Windows drive mounted by WSL, such as `/mnt/c/Windows`. WSL
+/// appends the Windows PATH to the Linux one, so these contain Windows programs rather than
+/// utilities oxidizr manages.
+pub fn is_windows_path(dir: &Path) -> bool {
+    let mut components = dir.components().map(|c| c.as_os_str().to_string_lossy());
+    components.next().as_deref() == Some("/")
+        && components.next().as_deref() == Some("mnt")
+        && components
+            .next()
+            .is_some_and(|drive| drive.len() == 1 && drive.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockSystem;
+
+    #[test]
+    fn test_detect_wsl() {
+        let runner = MockSystem::default();
+        assert_eq!(detect_wsl(&runner), None);
+
+        runner.mock_files(vec![(
+            KERNEL_RELEASE_PATH,
+            "4.4.0-19041-Microsoft\n",
+            false,
+        )]);
+        assert_eq!(detect_wsl(&runner), Some(Wsl::Wsl1));
+
+        runner.mock_files(vec![(
+            KERNEL_RELEASE_PATH,
+            "5.15.153.1-microsoft-standard-WSL2\n",
+            false,
+        )]);
+        assert_eq!(detect_wsl(&runner), Some(Wsl::Wsl2));
+    }
+
+    #[test]
+    fn test_is_windows_path() {
+        assert!(is_windows_path(Path::new("/mnt/c/Windows/system32")));
+        assert!(is_windows_path(Path::new("/mnt/d")));
+        assert!(!is_windows_path(Path::new("/mnt/data/bin")));
+        assert!(!is_windows_path(Path::new("/usr/bin")));
+    }
+}
@@ -3,11 +3,15 @@
 ensure_sudors_installed() {
     apt list sudo-rs | MATCH installed
 
-    ls -la /usr/bin/sudo | MATCH "/usr/bin/sudo -> /usr/lib/cargo/bin/sudo"
+    # The setuid binaries are copies rather than symlinks.
+    stat -c "%a %U" /usr/bin/sudo | MATCH "4755 root"
+    cmp /usr/bin/sudo /usr/lib/cargo/bin/sudo
     ls -la /usr/bin | MATCH ".sudo.oxidizr.bak"
+    ls -la /usr/bin | MATCH ".sudo.oxidizr.sha256"
     /usr/bin/sudo --version 2>&1 | MATCH "sudo-rs"
 
-    ls -la /usr/bin/su | MATCH "/usr/bin/su -> /usr/lib/cargo/bin/su"
+    stat -c "%a %U" /usr/bin/su | MATCH "4755 root"
+    cmp /usr/bin/su /usr/lib/cargo/bin/su
     ls -la /usr/bin | MATCH ".su.oxidizr.bak"
     /usr/bin/su --version 2>&1 | MATCH "su-rs"
 
@@ -20,6 +24,7 @@ ensure_sudors_absent() {
 
     ls -la /usr/bin/sudo | NOMATCH "/usr/bin/sudo -> /usr/lib/cargo/bin/sudo"
     ls -la /usr/bin | NOMATCH ".sudo.oxidizr.bak"
+    ls -la /usr/bin | NOMATCH ".sudo.oxidizr.sha256"
     /usr/bin/sudo --version 2>&1 | NOMATCH "sudo-rs"
 
     ls -la /usr/bin/su | NOMATCH "/usr/bin/su -> /usr/lib/cargo/bin/su"